@@ -0,0 +1,146 @@
+use crate::analytics::{
+    compute_finding_score, get_base_run_name, AnalyticalFinding, Analyze, DataFindings,
+};
+use crate::computations::f64_to_fixed_2;
+use crate::data::common::data_formats::ProcessedData;
+use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
+use crate::profiling::ThreadState;
+use log::debug;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// The maximum number of functions to generate findings for in every run.
+const MAX_FINDINGS_PER_RUN: usize = 5;
+
+/// This rule computes the differential profile between every run and the base run, and generates a
+/// finding for every function whose % samples grew by more than the threshold.
+/// profiler_key  - Only compare the profiles of the profiler with this key if specified, otherwise compare all profilers.
+/// thread_states - For JFR, specify the thread states to include samples for. For other stacks, select None.
+/// total_samples - Use total samples in function if true, otherwise use self samples
+/// threshold     - growth of % samples (in percentage points) to generate finding
+pub struct ProfileStackFrameRunComparisonRule {
+    pub rule_name: &'static str,
+    pub profile_type: &'static str,
+    pub profiler_key: Option<&'static str>,
+    pub thread_states: &'static [ThreadState],
+    pub total_samples: bool,
+    pub threshold: f64,
+    pub score: f64,
+    pub message: &'static str,
+}
+
+macro_rules! profile_stack_frame_run_comparison {
+    (@optional) => { None };
+    (@optional $value:expr) => { Some($value) };
+    {
+        name: $rule_name:literal,
+        profile_type: $profile_type:literal,
+        $(profiler_key: $profiler_key:expr,)?
+        $(thread_states: [$($state:expr),*],)?
+        total_samples: $total_samples:literal,
+        threshold: $threshold:expr,
+        score: $score:expr,
+        message: $message:literal,
+    } => {
+        AnalyticalRule::ProfileStackFrameRunComparisonRule(
+            ProfileStackFrameRunComparisonRule {
+                rule_name: $rule_name,
+                profile_type: $profile_type,
+                profiler_key: profile_stack_frame_run_comparison!(@optional $($profiler_key)?),
+                thread_states: &[$($($state),*)?],
+                total_samples: $total_samples,
+                threshold: $threshold,
+                score: $score.as_f64(),
+                message: $message,
+            }
+        )
+    };
+}
+pub(crate) use profile_stack_frame_run_comparison;
+
+impl fmt::Display for ProfileStackFrameRunComparisonRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ProfileStackFrameRunComparisonRule {} <checking if the % samples of any function in {} grew by {} over the base run>",
+            self.rule_name, self.profile_type, self.threshold
+        )
+    }
+}
+
+impl Analyze for ProfileStackFrameRunComparisonRule {
+    fn analyze(
+        &self,
+        data_findings: &mut DataFindings,
+        processed_data: &mut ProcessedData,
+        processed_data_accessor: &mut ProcessedDataAccessor,
+    ) {
+        let base_run_name = &get_base_run_name();
+        let base_profiler_keys =
+            processed_data_accessor.profiler_keys(processed_data, base_run_name);
+        if base_profiler_keys.is_empty() {
+            debug!("{self} failed to analyze: cannot find the base profile");
+            return;
+        }
+
+        let run_names: Vec<String> = processed_data.runs.keys().cloned().collect();
+        for run_name in &run_names {
+            if base_run_name == run_name {
+                continue;
+            }
+            for key in &base_profiler_keys {
+                if self
+                    .profiler_key
+                    .is_some_and(|profiler_key| profiler_key != key)
+                {
+                    continue;
+                }
+                let diff_profile = match processed_data_accessor.profiler_diff(
+                    processed_data,
+                    base_run_name,
+                    run_name,
+                    key,
+                    self.profile_type,
+                    self.thread_states,
+                ) {
+                    Some(diff_profile) => diff_profile,
+                    None => continue,
+                };
+                if diff_profile.base_total_samples == 0 || diff_profile.current_total_samples == 0 {
+                    continue;
+                }
+
+                for frame_share_delta in diff_profile
+                    .frame_share_deltas(self.total_samples)
+                    .iter()
+                    .take(MAX_FINDINGS_PER_RUN)
+                {
+                    let delta_percentage = frame_share_delta.delta() * 100.0;
+                    if delta_percentage < self.threshold {
+                        break;
+                    }
+                    let finding_description = format!(
+                        "Function {} accounts for {}% of samples in {} of {}, up from {}% in {} (threshold: +{}%)",
+                        frame_share_delta.name,
+                        f64_to_fixed_2(frame_share_delta.current_share * 100.0),
+                        self.profile_type,
+                        run_name,
+                        f64_to_fixed_2(frame_share_delta.base_share * 100.0),
+                        base_run_name,
+                        f64_to_fixed_2(self.threshold),
+                    );
+                    data_findings.insert_finding(
+                        run_name,
+                        key,
+                        AnalyticalFinding::new(
+                            self.rule_name.to_string(),
+                            compute_finding_score(delta_percentage, self.threshold, self.score),
+                            finding_description,
+                            self.message.to_string(),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
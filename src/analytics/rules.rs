@@ -4,9 +4,11 @@ mod cpu_utilization;
 mod diskstats;
 mod efa_stat;
 mod ena_stat;
+mod ext_profile;
 mod hotline;
 mod interrupts;
 mod java_profile;
+mod jvm_stat;
 mod kernel_config;
 mod memalloc;
 mod meminfo;
@@ -0,0 +1,4 @@
+use crate::data::ext_profile::ExtProfile;
+use crate::data::AnalyzeData;
+
+impl AnalyzeData for ExtProfile {}
@@ -1,9 +1,10 @@
 use crate::analytics::rule_templates::profile_metadata_comparison_rule::profile_metadata_comparison;
 use crate::analytics::rule_templates::profile_metadata_expected_rule::profile_metadata_expected;
+use crate::analytics::rule_templates::profile_stack_frame_run_comparison_rule::profile_stack_frame_run_comparison;
 use crate::analytics::rule_templates::profile_stack_frame_threshold_rule::profile_stack_frame_threshold;
 use crate::analytics::{
     AnalyticalRule, ProfileMetadataComparisonRule, ProfileMetadataExpectedRule,
-    ProfileStackFrameThresholdRule, Score,
+    ProfileStackFrameRunComparisonRule, ProfileStackFrameThresholdRule, Score,
 };
 use crate::data::java_profile::JavaProfile;
 use crate::data::AnalyzeData;
@@ -26,6 +27,16 @@ impl AnalyzeData for JavaProfile {
                 score: Score::Critical,
                 message: "We recommend not to use Java exceptions as control flow, and to remove exceptions when they appear in the hot-code path. Overhead can be mitigated some by using the -XX:+OmitStackTraceInFastThrow JVM flag to allow the Java runtime to optimize the exception flow for some hot paths. The best solution is to avoid the exceptions as much as possible.",
             },
+            // Run Comparison Rules
+            profile_stack_frame_run_comparison! {
+                name: "CPU Profile Method Growth",
+                profile_type: "cpu",
+                thread_states: [ThreadState::AsyncDefault],
+                total_samples: false,
+                threshold: 5.0,
+                score: Score::Bad,
+                message: "The method takes a noticeably larger share of CPU time than in the base run. Compare the two runs in the differential flamegraph to find the call paths that grew, and check for code, JVM flag, or dependency changes in them.",
+            },
             // Other Rules
             profile_metadata_expected! {
                 name: "Tiered Compilation Check",
@@ -0,0 +1,30 @@
+use crate::analytics::rule_templates::time_series_stat_threshold_rule::time_series_stat_threshold;
+use crate::analytics::{AnalyticalRule, Score, TimeSeriesStatThresholdRule};
+use crate::computations::{Comparator, Stat};
+use crate::data::jvm_stat::JvmStat;
+use crate::data::AnalyzeData;
+
+impl AnalyzeData for JvmStat {
+    fn get_analytical_rules(&self) -> Vec<AnalyticalRule> {
+        vec![
+            time_series_stat_threshold!(
+                name: "High GC Time Share",
+                metric: "gc_time_percent",
+                stat: Stat::Average,
+                comparator: Comparator::GreaterEqual,
+                threshold: 10.0,
+                score: Score::Poor,
+                message: "The JVM spent at least 10% of the time in garbage collection pauses. Consider increasing the heap size, reducing the allocation rate (see the alloc profile of Java profiling), or tuning the garbage collector.",
+            ),
+            time_series_stat_threshold!(
+                name: "Heap Near Full",
+                metric: "old_gen_used_percent",
+                stat: Stat::Average,
+                comparator: Comparator::GreaterEqual,
+                threshold: 90.0,
+                score: Score::Critical,
+                message: "The old generation of the JVM heap is nearly full, which leads to frequent full garbage collections and eventually OutOfMemoryError. Consider increasing the maximum heap size (-Xmx) or look for memory leaks (see the live profile of Java profiling).",
+            ),
+        ]
+    }
+}
@@ -1,4 +1,20 @@
-use crate::data::perf_profile::PerfProfile;
+use crate::analytics::rule_templates::profile_stack_frame_run_comparison_rule::profile_stack_frame_run_comparison;
+use crate::analytics::{AnalyticalRule, ProfileStackFrameRunComparisonRule, Score};
+use crate::data::perf_profile::{PerfProfile, SYSTEM_PROFILER_KEY};
 use crate::data::AnalyzeData;
+use crate::profiling::ThreadState;
 
-impl AnalyzeData for PerfProfile {}
+impl AnalyzeData for PerfProfile {
+    fn get_analytical_rules(&self) -> Vec<AnalyticalRule> {
+        vec![profile_stack_frame_run_comparison! {
+            name: "CPU Profile Function Growth",
+            profile_type: "cpu",
+            profiler_key: SYSTEM_PROFILER_KEY,
+            thread_states: [ThreadState::None],
+            total_samples: false,
+            threshold: 5.0,
+            score: Score::Bad,
+            message: "The function takes a noticeably larger share of CPU time than in the base run. Compare the two runs in the differential flamegraph to find the call paths that grew, and check for code or configuration changes in them.",
+        }]
+    }
+}
@@ -1,5 +1,10 @@
 use anyhow::Result;
 use aperf::completions::{setup_shell_completions, SetupShellCompletions};
+use aperf::export::{export, Export};
+use aperf::export_profile::{export_profile, ExportProfile};
+use aperf::import::{import, Import};
+use aperf::inspect::{inspect, Inspect};
+use aperf::redact::{redact, Redact};
 use aperf::report::{report, Report};
 #[cfg(feature = "mcp-server")]
 use aperf::server::Server;
@@ -18,6 +23,7 @@ use tempfile::Builder as TempBuilder;
 #[cfg(target_os = "linux")]
 use {
     aperf::record::{record, Record, RECORD_DATA_RECOMMENDATION},
+    aperf::recover::{recover, Recover},
     std::os::unix::fs::PermissionsExt,
 };
 
@@ -46,9 +52,29 @@ enum Commands {
     #[command(after_help = RECORD_DATA_RECOMMENDATION.to_ascii_uppercase())]
     Record(Record),
 
+    #[cfg(target_os = "linux")]
+    /// Finalize and archive a run whose collection was interrupted, e.g. by a crash or a reboot.
+    Recover(Recover),
+
     /// Generate an HTML report based on the data collected.
     Report(Report),
 
+    /// Export the processed data of runs or reports to CSV or JSON lines tables.
+    Export(Export),
+
+    /// Export a profile of a run to the pprof, speedscope or folded stacks format.
+    ExportProfile(ExportProfile),
+
+    /// Import external profiles (perf.data, JFR, folded stacks) into a run.
+    Import(Import),
+
+    /// Inspect the raw data files of a run, or decode the records of a raw data into JSON lines.
+    Inspect(Inspect),
+
+    /// Replace the sensitive data of a run or a report, such as host names and IP addresses,
+    /// with pseudonyms before sharing it.
+    Redact(Redact),
+
     /// Setup shell completions for APerf commands.
     SetupShellCompletions(SetupShellCompletions),
 
@@ -123,9 +149,18 @@ fn main() -> Result<()> {
     match cli.command {
         #[cfg(target_os = "linux")]
         Commands::Record(r) => record(&r, &tmp_dir_path_buf, &runlog),
+        #[cfg(target_os = "linux")]
+        Commands::Recover(r) => recover(&r),
 
         Commands::Report(r) => report(&r, &tmp_dir_path_buf),
 
+        Commands::Export(r) => export(&r, &tmp_dir_path_buf),
+        Commands::ExportProfile(r) => export_profile(&r, &tmp_dir_path_buf),
+
+        Commands::Import(r) => import(&r),
+        Commands::Inspect(r) => inspect(&r, &tmp_dir_path_buf),
+        Commands::Redact(r) => redact(&r, &tmp_dir_path_buf),
+
         Commands::SetupShellCompletions(r) => setup_shell_completions(&r, &mut Cli::command()),
 
         #[cfg(feature = "mcp-server")]
@@ -5,9 +5,11 @@ pub mod cpu_utilization;
 pub mod diskstats;
 pub mod efa_stat;
 pub mod ena_stat;
+pub mod ext_profile;
 pub mod hotline;
 pub mod interrupts;
 pub mod java_profile;
+pub mod jvm_stat;
 pub mod kernel_config;
 pub mod memalloc;
 pub mod meminfo;
@@ -37,14 +39,17 @@ use aperf_runlog::AperfRunlog;
 use aperf_stats::AperfStats;
 use chrono::prelude::*;
 use common::data_formats::AperfData;
+use common::raw_data_file::RawDataSchema;
 use cpu_utilization::{CpuUtilization, CpuUtilizationRaw};
 use diskstats::{Diskstats, DiskstatsRaw};
 use efa_stat::{EfaStat, EfaStatRaw};
 use ena_stat::{EnaStat, EnaStatRaw};
+use ext_profile::{ExtProfile, ExtProfileRaw};
 use hotline::{Hotline, HotlineRaw};
 use include_dir::{include_dir, Dir};
 use interrupts::{InterruptData, InterruptDataRaw};
 use java_profile::{JavaProfile, JavaProfileRaw};
+use jvm_stat::{JvmStat, JvmStatRaw};
 use kernel_config::KernelConfig;
 use memalloc::{MemallocData, MemallocDataRaw};
 use meminfo::{MeminfoData, MeminfoDataRaw};
@@ -88,6 +93,8 @@ impl Sub for TimeEnum {
 /// 2. define the function that instantiates all data structs and adds them
 ///    to the PerformanceData object.
 /// 3. collect the names of data to be collected by default.
+/// 4. dispatch the records of the versioned raw data files to the raw data structs by name.
+/// 5. implement RawDataSchema with the first schema version for every raw data struct.
 macro_rules! data {
     ( $( $data:ident ),* ) => {
 
@@ -100,7 +107,7 @@ macro_rules! data {
         fn get_default_data_names() -> Vec<&'static str> {
             let mut default_data_names: Vec<&'static str> = Vec::new();
             $(
-                if !($data::is_perf_profile() || $data::is_java_profile()) {
+                if !($data::is_perf_profile() || $data::is_java_profile() || $data::is_ext_profile()) {
                     default_data_names.push(get_data_name_from_type::<$data>());
                 }
             )*
@@ -113,6 +120,10 @@ macro_rules! data {
             default_data_names
         }
 
+        $(
+            impl RawDataSchema for $data {}
+        )*
+
         #[derive(Debug, Deserialize, Serialize)]
         pub enum Data {
             $(
@@ -120,6 +131,37 @@ macro_rules! data {
             )*
         }
 
+        impl Data {
+            pub fn schema_version(&self) -> u32 {
+                match self {
+                    $(
+                        Data::$data(_) => $data::SCHEMA_VERSION,
+                    )*
+                }
+            }
+
+            /// Serializes the raw data struct without the enum variant, as a record of the
+            /// versioned raw data file.
+            pub fn serialize_record(&self) -> Result<Vec<u8>> {
+                match self {
+                    $(
+                        Data::$data(ref value) => value.serialize_record(),
+                    )*
+                }
+            }
+
+            /// Decodes a record of the versioned raw data file of the data. The first raw data
+            /// struct of the data is used if a data has more than one, i.e. perf_profile.
+            pub fn deserialize_record(data_name: &str, schema_version: u32, record: &[u8]) -> Result<Self> {
+                $(
+                    if data_name == get_data_name_from_type::<$data>() {
+                        return Ok(Data::$data($data::deserialize_record(schema_version, record)?));
+                    }
+                )*
+                bail!("Unknown raw data {data_name}")
+            }
+        }
+
         #[cfg(target_os = "linux")]
         impl Data {
             pub fn collect_data(&mut self, params: &InitParams) -> Result<()> {
@@ -160,14 +202,22 @@ macro_rules! data {
             pub fn is_profile(&self) -> bool {
                 match self {
                     $(
-                        Data::$data(_) => $data::is_perf_profile() || $data::is_java_profile(),
+                        Data::$data(_) => $data::is_perf_profile() || $data::is_java_profile() || $data::is_ext_profile(),
+                    )*
+                }
+            }
+
+            pub fn is_perf_profile(&self) -> bool {
+                match self {
+                    $(
+                        Data::$data(_) => $data::is_perf_profile(),
                     )*
                 }
             }
         }
 
         #[cfg(target_os = "linux")]
-        pub fn initialize_data_collection_engine(data_collection_engine: &mut DataCollectionEngine, data_names_to_collect: HashSet<String>, perf_profile_enabled: bool, java_profile_enabled: bool) {
+        pub fn initialize_data_collection_engine(data_collection_engine: &mut DataCollectionEngine, data_names_to_collect: HashSet<String>, perf_profile_enabled: bool, java_profile_enabled: bool, ext_profile_enabled: bool) {
             $(
                 let data_name = get_data_name_from_type::<$data>();
 
@@ -179,6 +229,10 @@ macro_rules! data {
                     if java_profile_enabled {
                         data_collection_engine.add_data_collector(data_name, Data::$data($data::new()));
                     }
+                } else if $data::is_ext_profile() {
+                    if ext_profile_enabled {
+                        data_collection_engine.add_data_collector(data_name, Data::$data($data::new()));
+                    }
                 } else {
                     if data_names_to_collect.contains(data_name) {
                         data_collection_engine.add_data_collector(data_name, Data::$data($data::new()));
@@ -255,8 +309,9 @@ macro_rules! report_data {
 }
 
 // IMPORTANT: DO NOT MODIFY THE DATA ORDER HERE. NEW DATA SHOULD BE APPENDED TO THE END.
-// The order decides each data's index within the Data enum, which is used in serialization.
-// Changing the order leads to indices changes and deserialization failures for previous run data.
+// The order decides each data's index within the Data enum, which is used in the serialization
+// of the legacy raw data files. Changing the order leads to indices changes and deserialization
+// failures for the runs recorded before the versioned raw data files.
 data!(
     CpuUtilizationRaw,
     VmstatRaw,
@@ -276,7 +331,9 @@ data!(
     HotlineRaw,
     MemallocDataRaw,
     EnaStatRaw,
-    EfaStatRaw
+    EfaStatRaw,
+    JvmStatRaw,
+    ExtProfileRaw
 );
 
 report_data!(
@@ -299,7 +356,9 @@ report_data!(
     JavaProfile,
     MemallocData,
     EnaStat,
-    EfaStat
+    EfaStat,
+    JvmStat,
+    ExtProfile
 );
 
 #[cfg(target_os = "linux")]
@@ -327,6 +386,10 @@ pub trait CollectData {
     fn is_java_profile() -> bool {
         false
     }
+
+    fn is_ext_profile() -> bool {
+        false
+    }
 }
 
 pub trait ProcessData {
@@ -1,4 +1,5 @@
 use crate::data::common::data_formats::AperfData;
+use crate::data::common::raw_data_file::{RawDataFile, RawDataSchema, RawRecordError};
 use crate::data::common::time_series_data_processor::{
     time_series_data_processor_with_sum_aggregate, TimeSeriesDataProcessor,
 };
@@ -7,14 +8,16 @@ use crate::data_processing::ReportParams;
 use crate::ProcessMetric;
 use anyhow::{bail, Result};
 use chrono::prelude::*;
+use log::error;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
 use strum::IntoEnumIterator;
 #[cfg(target_os = "linux")]
 use {
+    crate::data::common::raw_data_file::{
+        write_raw_data_file_header, write_raw_data_record, RawDataFileHeader,
+    },
     crate::{data_file_path, get_data_name_from_type},
-    log::error,
     std::fs,
     std::path::PathBuf,
 };
@@ -36,6 +39,8 @@ pub struct AperfStats {
     pub stats: HashMap<String, HashMap<String, f64>>,
 }
 
+impl RawDataSchema for AperfStats {}
+
 impl AperfStats {
     pub fn new() -> Self {
         Self {
@@ -115,6 +120,26 @@ impl AperfStatsCollector {
 
     /// Write all saved stats to disk file.
     pub fn flush(&mut self) -> Result<()> {
+        let mut aperf_stats = std::mem::take(&mut self.time_series_aperf_stats);
+        let cur_aperf_stats = std::mem::replace(&mut self.cur_aperf_stats, AperfStats::new());
+        if !cur_aperf_stats.stats.is_empty() {
+            aperf_stats.push(cur_aperf_stats);
+        }
+        self.write_to_file(&aperf_stats)?;
+
+        Ok(())
+    }
+
+    /// Write the saved stats of the past seconds to disk file and sync it, keeping the stats
+    /// of the current second in memory.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let aperf_stats = std::mem::take(&mut self.time_series_aperf_stats);
+        self.write_to_file(&aperf_stats)?.sync_data()?;
+
+        Ok(())
+    }
+
+    fn write_to_file(&self, aperf_stats: &[AperfStats]) -> Result<fs::File> {
         if self.run_data_dir.is_none() {
             bail!("Failed to flush APerf stat since the run data directory path is uninitialized.");
         }
@@ -136,17 +161,18 @@ impl AperfStatsCollector {
             ),
         };
 
-        for aperf_stats in &self.time_series_aperf_stats {
-            bincode::serialize_into(&mut aperf_stats_file, aperf_stats)?;
-        }
-        if !self.cur_aperf_stats.stats.is_empty() {
-            bincode::serialize_into(&mut aperf_stats_file, &self.cur_aperf_stats)?;
+        write_raw_data_file_header(
+            &mut aperf_stats_file,
+            &RawDataFileHeader::new(
+                get_data_name_from_type::<AperfStats>(),
+                AperfStats::SCHEMA_VERSION,
+            ),
+        )?;
+        for aperf_stats in aperf_stats {
+            write_raw_data_record(&mut aperf_stats_file, &aperf_stats.serialize_record()?)?;
         }
 
-        self.time_series_aperf_stats.clear();
-        self.cur_aperf_stats = AperfStats::new();
-
-        Ok(())
+        Ok(aperf_stats_file)
     }
 }
 
@@ -159,23 +185,26 @@ impl Drop for AperfStatsCollector {
     }
 }
 
+/// Logs the records of the APerf stats file that could not be decoded. A truncated last record
+/// is left by a collection that was killed, and is ignored.
+fn log_raw_record_errors(errors: &[RawRecordError]) {
+    for e in errors {
+        if let RawRecordError::Undecodable { .. } = e {
+            error!("Error when Deserializing APerf Stats data: {e}");
+        }
+    }
+}
+
 fn process_legacy_aperf_stats_raw_data(
-    raw_aperf_stats_file: &File,
+    raw_aperf_stats_file: &mut RawDataFile,
     time_series_data_processor: &mut TimeSeriesDataProcessor,
 ) {
-    let mut values = Vec::new();
-    loop {
-        match bincode::deserialize_from::<_, AperfStat>(raw_aperf_stats_file) {
-            Ok(v) => values.push(v),
-            Err(e) => match *e {
-                // EOF
-                bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-                e => panic!("Error when Deserializing APerf Stats data: {}", e),
-            },
-        };
-    }
+    let decoded = raw_aperf_stats_file.decode_records::<AperfStat, _>(|_, _| {
+        bail!("The legacy APerf stats are not written in the versioned format")
+    });
+    log_raw_record_errors(&decoded.errors);
 
-    for value in values {
+    for value in decoded.records {
         time_series_data_processor.proceed_to_time(value.time);
 
         for (stat_key, stat_value) in value.data {
@@ -206,16 +235,18 @@ impl ProcessData for AperfStats {
             time_series_data_processor_with_sum_aggregate!(report_params.collection_start);
         time_series_data_processor.set_aggregate_series_name("total");
 
-        let (raw_aperf_stats_file, _) = match self.get_raw_data_file(&report_params.run_data_dir) {
-            Ok(rs) => rs,
-            Err(e) => bail!("Failed to open raw APerf Stats file: {:?}", e),
-        };
+        let (_, raw_aperf_stats_file_path) =
+            match self.get_raw_data_file(&report_params.run_data_dir) {
+                Ok(rs) => rs,
+                Err(e) => bail!("Failed to open raw APerf Stats file: {:?}", e),
+            };
+        let mut raw_aperf_stats_file = RawDataFile::open(&raw_aperf_stats_file_path)?;
 
         // Version check - APerf process pids started being collected in the same
         // version as the new APerf stats.
-        if report_params.aperf_process_pids.is_empty() {
+        if raw_aperf_stats_file.header.is_none() && report_params.aperf_process_pids.is_empty() {
             process_legacy_aperf_stats_raw_data(
-                &raw_aperf_stats_file,
+                &mut raw_aperf_stats_file,
                 &mut time_series_data_processor,
             );
             return Ok(AperfData::TimeSeries(
@@ -223,22 +254,13 @@ impl ProcessData for AperfStats {
             ));
         }
 
-        let mut values = Vec::new();
-        loop {
-            match bincode::deserialize_from::<_, AperfStats>(&raw_aperf_stats_file) {
-                Ok(v) => values.push(v),
-                Err(e) => match *e {
-                    // EOF
-                    bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                        break
-                    }
-                    e => panic!("Error when Deserializing APerf Stats data: {}", e),
-                },
-            };
-        }
+        let decoded = raw_aperf_stats_file.decode_records(|header, record| {
+            AperfStats::deserialize_record(header.schema_version, record)
+        });
+        log_raw_record_errors(&decoded.errors);
 
         let mut collection_started = false;
-        for value in values {
+        for value in decoded.records {
             // Ignore the time diff for data before the collection started, as time_diff
             // computation would have been corrupted and these data are not time-series anyway.
             if !collection_started
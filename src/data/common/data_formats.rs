@@ -240,6 +240,35 @@ pub struct Profiler {
     pub metadata: KeyValueData,
     /// Profiling type (e.g., "cpu", "wall", "allocation") -> Profile
     pub profiles: HashMap<String, Profile>,
+    /// The disassembly of the hottest functions, annotated with the samples of every instruction
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<AnnotatedFunction>,
+}
+
+/// A function disassembled from its ELF file, with the samples taken at each of its instructions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnotatedFunction {
+    /// The profile type that the samples belong to (e.g., "cpu")
+    pub profile_type: String,
+    /// The name of the function
+    pub name: String,
+    /// The ELF file that the function belongs to
+    pub source: String,
+    /// The total samples taken in the function
+    pub samples: u64,
+    /// The instructions of the function in the address order
+    pub instructions: Vec<AnnotatedInstruction>,
+}
+
+/// A disassembled instruction and the samples taken at it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnotatedInstruction {
+    /// The virtual address of the instruction in the ELF file
+    pub address: u64,
+    /// The disassembled instruction
+    pub instruction: String,
+    /// The samples taken at the instruction
+    pub samples: u64,
 }
 
 impl Default for Profiler {
@@ -249,6 +278,7 @@ impl Default for Profiler {
             block_width_ms: BUCKET_WIDTH_MS,
             metadata: KeyValueData::default(),
             profiles: HashMap::new(),
+            annotations: Vec::new(),
         }
     }
 }
@@ -260,6 +290,7 @@ impl Profiler {
             block_width_ms: BUCKET_WIDTH_MS,
             metadata: KeyValueData::default(),
             profiles: HashMap::new(),
+            annotations: Vec::new(),
         }
     }
 }
@@ -3,5 +3,6 @@
 pub mod common_raw_data;
 pub mod data_formats;
 pub mod processed_data_accessor;
+pub mod raw_data_file;
 pub mod time_series_data_processor;
 pub mod utils;
@@ -4,6 +4,7 @@ use crate::data::common::data_formats::{
     TimeSeriesMetric,
 };
 use crate::data::TimeEnum;
+use crate::profiling::diff::DiffProfile;
 use crate::profiling::{FrameType, ThreadState};
 use regex::Regex;
 use std::collections::HashMap;
@@ -114,6 +115,24 @@ impl ProcessedDataAccessor {
             })
     }
 
+    /// Returns the time_diff and values of a series of the run within the time range.
+    pub fn series_in_time_range<'a>(
+        &self,
+        series: &'a Series,
+        run_name: &str,
+    ) -> (&'a [u64], &'a [f64]) {
+        let (start_idx, end_idx) = compute_time_diff_index(
+            &series.time_diff,
+            self.per_run_from_time.get(run_name).copied(),
+            self.per_run_to_time.get(run_name).copied(),
+            self.run_duration_seconds(run_name),
+        );
+        (
+            &series.time_diff[start_idx..end_idx],
+            &series.values[start_idx..end_idx],
+        )
+    }
+
     /// Returns the stat of a time-series metric within the time range. The computed stat
     /// will be cached.
     pub fn time_series_metric_stats(
@@ -366,6 +385,41 @@ impl ProcessedDataAccessor {
         }
     }
 
+    /// Returns the differential profile of a profile type between the base run and another run,
+    /// each within its time range, or None if either run does not have the profile.
+    pub fn profiler_diff(
+        &mut self,
+        processed_data: &mut ProcessedData,
+        base_run_name: &str,
+        run_name: &str,
+        profiler_key: &str,
+        profile_type: &str,
+        thread_states: &[ThreadState],
+    ) -> Option<DiffProfile> {
+        for time_range_run_name in [base_run_name, run_name] {
+            if let Some(AperfData::Profile(profiling_data)) =
+                processed_data.runs.get_mut(time_range_run_name)
+            {
+                if let Some(profiler) = profiling_data.profilers.get_mut(profiler_key) {
+                    self.apply_time_range_to_profiler(time_range_run_name, profiler_key, profiler);
+                }
+            }
+        }
+        let get_profile = |run_name: &str| match processed_data.runs.get(run_name) {
+            Some(AperfData::Profile(profiling_data)) => profiling_data
+                .profilers
+                .get(profiler_key)?
+                .profiles
+                .get(profile_type),
+            _ => None,
+        };
+        Some(DiffProfile::new(
+            get_profile(base_run_name)?,
+            get_profile(run_name)?,
+            thread_states,
+        ))
+    }
+
     /// Returns a metadata value from a profiler's KeyValueData, searching all groups.
     /// Reuses the same lookup logic as `key_value_value_by_key`.
     pub fn profiler_value_by_key(
@@ -0,0 +1,563 @@
+//! The versioned format of the raw data files. A raw data file starts with a header that
+//! describes the data, followed by the length-prefixed records:
+//!
+//! | "APERFRAW" | header length (u32) | header (JSON) | record length (u64) | record | ...
+//!
+//! where every record is the bincode of a raw data struct, rather than of the Data enum, so
+//! that the records do not depend on the order of the data in the Data enum. The header holds
+//! the schema version of the raw data struct, with which the records written by an older
+//! struct are migrated to the current one. All integers are little-endian.
+//!
+//! The files recorded before the versioned format are bare bincode Data enums, which are still
+//! decoded as the legacy format.
+
+use anyhow::{bail, Result};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The magic bytes at the start of every raw data file of the versioned format.
+pub const RAW_DATA_FILE_MAGIC: &[u8; 8] = b"APERFRAW";
+/// The version of the framing of the raw data files, i.e. the layout of the header and the
+/// records, which is independent of the schema versions of the raw data.
+pub const RAW_DATA_FILE_FORMAT_VERSION: u32 = 1;
+
+/// The schema of the records of a raw data. Bump SCHEMA_VERSION whenever the fields of the
+/// struct change, and implement migrate to decode the records of the older versions. The data!
+/// macro implements it with the defaults for the raw data structs of the Data enum.
+pub trait RawDataSchema: Serialize + DeserializeOwned {
+    const SCHEMA_VERSION: u32 = 1;
+
+    /// Decodes a record written with an older schema version of the struct.
+    fn migrate(schema_version: u32, _record: &[u8]) -> Result<Self> {
+        bail!(
+            "No migration from schema version {schema_version} to {}",
+            Self::SCHEMA_VERSION
+        )
+    }
+
+    fn serialize_record(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    fn deserialize_record(schema_version: u32, record: &[u8]) -> Result<Self> {
+        if schema_version == Self::SCHEMA_VERSION {
+            Ok(bincode::deserialize(record)?)
+        } else if schema_version < Self::SCHEMA_VERSION {
+            Self::migrate(schema_version, record)
+        } else {
+            bail!(
+                "Schema version {schema_version} is newer than the supported version {}, use a newer APerf",
+                Self::SCHEMA_VERSION
+            )
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RawDataFileHeader {
+    pub format_version: u32,
+    pub data_name: String,
+    pub schema_version: u32,
+    /// The version of APerf that wrote the file.
+    pub collector_version: String,
+}
+
+impl RawDataFileHeader {
+    pub fn new(data_name: &str, schema_version: u32) -> Self {
+        RawDataFileHeader {
+            format_version: RAW_DATA_FILE_FORMAT_VERSION,
+            data_name: data_name.to_string(),
+            schema_version,
+            collector_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Writes the header into a raw data file if the file is empty, i.e. it is not being appended
+/// to by a resumed collection.
+pub fn write_raw_data_file_header(file: &mut File, header: &RawDataFileHeader) -> Result<()> {
+    if file.metadata()?.len() > 0 {
+        return Ok(());
+    }
+    let header_json = serde_json::to_vec(header)?;
+    let mut buf = Vec::with_capacity(RAW_DATA_FILE_MAGIC.len() + 4 + header_json.len());
+    buf.extend_from_slice(RAW_DATA_FILE_MAGIC);
+    buf.extend_from_slice(&(header_json.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&header_json);
+    file.write_all(&buf)?;
+    Ok(())
+}
+
+/// Writes a length-prefixed record. The length and the record are written at once, so that an
+/// interrupted write leaves at most one truncated record at the end of the file.
+pub fn write_raw_data_record<W: Write>(writer: &mut W, record: &[u8]) -> Result<()> {
+    let mut buf = Vec::with_capacity(8 + record.len());
+    buf.extend_from_slice(&(record.len() as u64).to_le_bytes());
+    buf.extend_from_slice(record);
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// Truncates the torn record at the end of a versioned raw data file, which is left by a
+/// collection that was killed while writing it, so that records can be appended after the
+/// complete ones. A file with a torn header is emptied. The legacy files are left as they are,
+/// since a torn record cannot be told apart from a record of a different layout. Returns the
+/// number of bytes removed.
+pub fn truncate_torn_record(path: &Path) -> Result<u64> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let prefix = read_prefix(&mut file)?;
+    let complete_len = if !prefix.starts_with(RAW_DATA_FILE_MAGIC) {
+        if !RAW_DATA_FILE_MAGIC.starts_with(&prefix) {
+            return Ok(0);
+        }
+        0
+    } else if header_end(&prefix, size).is_none() {
+        0
+    } else {
+        let decoded: DecodedRecords<()> = RawDataFile::open(path)?.decode_records(|_, _| Ok(()));
+        match decoded.errors.last() {
+            Some(RawRecordError::Truncated { offset, .. }) => *offset,
+            _ => size,
+        }
+    };
+    if complete_len < size {
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete_len)?;
+    }
+    Ok(size - complete_len)
+}
+
+/// Reads the magic bytes and the header length at the start of a raw data file, or fewer bytes
+/// if the file is shorter.
+fn read_prefix<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut prefix = Vec::new();
+    reader
+        .take(RAW_DATA_FILE_MAGIC.len() as u64 + 4)
+        .read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Returns the offset of the first record of a versioned raw data file from the prefix read by
+/// read_prefix and the size of the file, or None if the header is incomplete.
+fn header_end(prefix: &[u8], size: u64) -> Option<u64> {
+    let header_start = RAW_DATA_FILE_MAGIC.len() + 4;
+    let header_len = u32::from_le_bytes(
+        prefix
+            .get(RAW_DATA_FILE_MAGIC.len()..header_start)?
+            .try_into()
+            .unwrap(),
+    ) as u64;
+    let records_start = header_start as u64 + header_len;
+    (size >= records_start).then_some(records_start)
+}
+
+/// Counts the bytes read through it, i.e. the offset in the raw data file of the reader.
+struct CountingReader<'a, R> {
+    inner: &'a mut R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// A record that could not be decoded from a raw data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawRecordError {
+    /// The last record is cut short, e.g. because the collection was killed while writing it.
+    Truncated { offset: u64, trailing_bytes: u64 },
+    /// The record does not match its schema. The undecodable records of the versioned format
+    /// are skipped, while the legacy format cannot be decoded past them.
+    Undecodable { offset: u64, error: String },
+}
+
+impl std::fmt::Display for RawRecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawRecordError::Truncated {
+                offset,
+                trailing_bytes,
+            } => write!(
+                f,
+                "truncated record at offset {offset} ({trailing_bytes} trailing bytes)"
+            ),
+            RawRecordError::Undecodable { offset, error } => {
+                write!(f, "undecodable record at offset {offset}: {error}")
+            }
+        }
+    }
+}
+
+/// The records decoded from a raw data file, and the errors of the records that could not be.
+#[derive(Debug)]
+pub struct DecodedRecords<T> {
+    pub records: Vec<T>,
+    pub errors: Vec<RawRecordError>,
+}
+
+/// A raw data file, whose records are decoded while streaming it rather than read into memory.
+pub struct RawDataFile<R = BufReader<File>> {
+    /// The header of the versioned format. None for the legacy format.
+    pub header: Option<RawDataFileHeader>,
+    reader: R,
+    size: u64,
+    records_start: u64,
+}
+
+impl RawDataFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Self::from_reader(BufReader::new(file), size)
+    }
+}
+
+impl RawDataFile<Cursor<Vec<u8>>> {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let size = bytes.len() as u64;
+        Self::from_reader(Cursor::new(bytes), size)
+    }
+}
+
+impl<R: Read + Seek> RawDataFile<R> {
+    /// Reads the header of a raw data file of the given size from the start of the reader.
+    pub fn from_reader(mut reader: R, size: u64) -> Result<Self> {
+        let prefix = read_prefix(&mut reader)?;
+        if !prefix.starts_with(RAW_DATA_FILE_MAGIC) {
+            return Ok(RawDataFile {
+                header: None,
+                reader,
+                size,
+                records_start: 0,
+            });
+        }
+        let records_start = match header_end(&prefix, size) {
+            Some(records_start) => records_start,
+            None => bail!("The raw data file header is truncated"),
+        };
+        let mut header_json = vec![0; (records_start - prefix.len() as u64) as usize];
+        reader.read_exact(&mut header_json)?;
+        let header: RawDataFileHeader = serde_json::from_slice(&header_json)?;
+        if header.format_version > RAW_DATA_FILE_FORMAT_VERSION {
+            bail!(
+                "Raw data file format version {} is newer than the supported version {}, use a newer APerf",
+                header.format_version,
+                RAW_DATA_FILE_FORMAT_VERSION
+            );
+        }
+        Ok(RawDataFile {
+            header: Some(header),
+            reader,
+            size,
+            records_start,
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Decodes all records of the file. The records of the versioned format are decoded by
+    /// decode_record with the header of the file, and the records of the legacy format are
+    /// decoded as bare bincode of T.
+    pub fn decode_records<T, F>(&mut self, mut decode_record: F) -> DecodedRecords<T>
+    where
+        T: DeserializeOwned,
+        F: FnMut(&RawDataFileHeader, &[u8]) -> Result<T>,
+    {
+        let mut decoded = DecodedRecords {
+            records: Vec::new(),
+            errors: Vec::new(),
+        };
+        let size = self.size;
+        let mut offset = self.records_start;
+        if let Err(e) = self.reader.seek(SeekFrom::Start(offset)) {
+            decoded.errors.push(RawRecordError::Undecodable {
+                offset,
+                error: e.to_string(),
+            });
+            return decoded;
+        }
+
+        let header = match &self.header {
+            Some(header) => header,
+            None => {
+                let mut reader = CountingReader {
+                    inner: &mut self.reader,
+                    count: offset,
+                };
+                while offset < size {
+                    // The same encoding as bincode::deserialize_from, limited to the remaining
+                    // bytes so that the lengths decoded from corrupt bytes cannot exhaust the
+                    // memory.
+                    let options = bincode::DefaultOptions::new()
+                        .with_fixint_encoding()
+                        .allow_trailing_bytes()
+                        .with_limit(size - offset);
+                    match options.deserialize_from::<_, T>(&mut reader) {
+                        Ok(record) => decoded.records.push(record),
+                        Err(e) => {
+                            decoded.errors.push(match *e {
+                                bincode::ErrorKind::SizeLimit => RawRecordError::Truncated {
+                                    offset,
+                                    trailing_bytes: size - offset,
+                                },
+                                bincode::ErrorKind::Io(ref io_error)
+                                    if io_error.kind() == io::ErrorKind::UnexpectedEof =>
+                                {
+                                    RawRecordError::Truncated {
+                                        offset,
+                                        trailing_bytes: size - offset,
+                                    }
+                                }
+                                e => RawRecordError::Undecodable {
+                                    offset,
+                                    error: e.to_string(),
+                                },
+                            });
+                            break;
+                        }
+                    }
+                    offset = reader.count;
+                }
+                return decoded;
+            }
+        };
+
+        while offset < size {
+            let record_start = offset + 8;
+            let mut len_bytes = [0; 8];
+            let record_len = match self.reader.read_exact(&mut len_bytes) {
+                Ok(()) => u64::from_le_bytes(len_bytes),
+                Err(_) => u64::MAX,
+            };
+            let record_end = record_start.saturating_add(record_len);
+            let mut record = Vec::new();
+            if record_end > size
+                || (&mut self.reader)
+                    .take(record_len)
+                    .read_to_end(&mut record)
+                    .map_or(true, |read| read as u64 != record_len)
+            {
+                decoded.errors.push(RawRecordError::Truncated {
+                    offset,
+                    trailing_bytes: size - offset,
+                });
+                break;
+            }
+            match decode_record(header, &record) {
+                Ok(record) => decoded.records.push(record),
+                Err(e) => decoded.errors.push(RawRecordError::Undecodable {
+                    offset,
+                    error: e.to_string(),
+                }),
+            }
+            offset = record_end;
+        }
+        decoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct TestRaw {
+        time: u64,
+        data: String,
+    }
+
+    impl RawDataSchema for TestRaw {
+        const SCHEMA_VERSION: u32 = 2;
+
+        fn migrate(schema_version: u32, record: &[u8]) -> Result<Self> {
+            #[derive(Deserialize)]
+            struct TestRawV1 {
+                data: String,
+            }
+            match schema_version {
+                1 => {
+                    let v1: TestRawV1 = bincode::deserialize(record)?;
+                    Ok(TestRaw {
+                        time: 0,
+                        data: v1.data,
+                    })
+                }
+                _ => bail!("No migration from schema version {schema_version}"),
+            }
+        }
+    }
+
+    fn write_file(path: &Path, schema_version: u32, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = File::create(path).unwrap();
+        write_raw_data_file_header(&mut file, &RawDataFileHeader::new("test", schema_version))
+            .unwrap();
+        for record in records {
+            write_raw_data_record(&mut file, record).unwrap();
+        }
+        fs::read(path).unwrap()
+    }
+
+    fn decode(bytes: Vec<u8>) -> DecodedRecords<TestRaw> {
+        RawDataFile::from_bytes(bytes)
+            .unwrap()
+            .decode_records(|header, record| {
+                TestRaw::deserialize_record(header.schema_version, record)
+            })
+    }
+
+    #[test]
+    fn test_versioned_records() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.bin");
+        let records: Vec<Vec<u8>> = (0..3)
+            .map(|i| {
+                TestRaw {
+                    time: i,
+                    data: format!("record {i}"),
+                }
+                .serialize_record()
+                .unwrap()
+            })
+            .collect();
+        let bytes = write_file(&path, 2, &records);
+
+        let file = RawDataFile::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(file.header, Some(RawDataFileHeader::new("test", 2)));
+        let decoded = decode(bytes.clone());
+        assert_eq!(decoded.records.len(), 3);
+        assert_eq!(decoded.records[2].data, "record 2");
+        assert!(decoded.errors.is_empty());
+
+        // The header is not written again when appending to the file
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write_raw_data_file_header(&mut file, &RawDataFileHeader::new("test", 2)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        // An undecodable record is skipped, and the truncated record ends the decoding
+        let record_size = 8 + records[0].len();
+        let second_record = bytes.len() - 2 * record_size;
+        let third_record = bytes.len() - record_size;
+        let mut damaged = bytes.clone();
+        // The length of the string in the second record
+        damaged[second_record + 16..second_record + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+        damaged.truncate(bytes.len() - 3);
+        let decoded = decode(damaged);
+        assert_eq!(decoded.records.len(), 1);
+        assert!(matches!(
+            decoded.errors[0],
+            RawRecordError::Undecodable { offset, .. } if offset == second_record as u64
+        ));
+        assert_eq!(
+            decoded.errors[1],
+            RawRecordError::Truncated {
+                offset: third_record as u64,
+                trailing_bytes: record_size as u64 - 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_records() {
+        #[derive(Serialize)]
+        struct TestRawV1 {
+            data: String,
+        }
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.bin");
+        let record = bincode::serialize(&TestRawV1 {
+            data: "old".to_string(),
+        })
+        .unwrap();
+        let decoded = decode(write_file(&path, 1, &[record.clone()]));
+        assert_eq!(
+            decoded.records,
+            vec![TestRaw {
+                time: 0,
+                data: "old".to_string()
+            }]
+        );
+
+        let decoded = decode(write_file(&path, 3, &[record]));
+        assert!(decoded.records.is_empty());
+        assert!(matches!(
+            &decoded.errors[0],
+            RawRecordError::Undecodable { error, .. } if error.contains("newer")
+        ));
+    }
+
+    #[test]
+    fn test_legacy_records() {
+        let mut bytes = Vec::new();
+        for i in 0..2 {
+            bincode::serialize_into(
+                &mut bytes,
+                &TestRaw {
+                    time: i,
+                    data: "legacy".to_string(),
+                },
+            )
+            .unwrap();
+        }
+        // A corrupt string length
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        let mut file = RawDataFile::from_bytes(bytes).unwrap();
+        assert!(file.header.is_none());
+        let decoded: DecodedRecords<TestRaw> =
+            file.decode_records(|_, _| bail!("Not a versioned file"));
+        assert_eq!(decoded.records.len(), 2);
+        assert!(matches!(
+            decoded.errors[..],
+            [RawRecordError::Truncated { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_truncate_torn_record() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.bin");
+        let record = TestRaw {
+            time: 0,
+            data: "record".to_string(),
+        }
+        .serialize_record()
+        .unwrap();
+        let bytes = write_file(&path, 2, &[record.clone(), record.clone()]);
+
+        assert_eq!(truncate_torn_record(&path).unwrap(), 0);
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        assert_eq!(
+            truncate_torn_record(&path).unwrap(),
+            (8 + record.len() - 3) as u64
+        );
+        let decoded = decode(fs::read(&path).unwrap());
+        assert_eq!(decoded.records.len(), 1);
+        assert!(decoded.errors.is_empty());
+
+        // A torn header
+        fs::write(&path, &bytes[..RAW_DATA_FILE_MAGIC.len() + 6]).unwrap();
+        assert_eq!(
+            truncate_torn_record(&path).unwrap(),
+            RAW_DATA_FILE_MAGIC.len() as u64 + 6
+        );
+        assert!(fs::read(&path).unwrap().is_empty());
+
+        // A legacy file is left as it is
+        fs::write(&path, [1, 2, 3]).unwrap();
+        assert_eq!(truncate_torn_record(&path).unwrap(), 0);
+    }
+}
@@ -0,0 +1,585 @@
+use crate::data::common::data_formats::{AperfData, ProfilingData};
+use crate::data::{Data, ProcessData};
+use crate::data_processing::ReportParams;
+use crate::profiling::ingest::ProfileFormat;
+use anyhow::{bail, Result};
+use log::error;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use {
+    crate::data::common::utils::get_sub_process_duration_seconds,
+    crate::data::CollectData,
+    crate::data_collection::InitParams,
+    crate::profiling::ingest::ingest_profile,
+    crate::register_sub_process_pid,
+    crate::PDError,
+    chrono::Utc,
+    log::{debug, warn},
+    nix::{sys::signal, unistd::Pid},
+    std::fs::File,
+    std::io::{Read, Write},
+    std::net::TcpStream,
+    std::os::unix::process::CommandExt,
+    std::process::{Child, Command, Stdio},
+    std::sync::mpsc::{self, Receiver, RecvTimeoutError},
+    std::sync::Mutex,
+    std::thread,
+    std::time::{Duration, Instant},
+};
+
+/// The profile type of the samples of an external profiler, unless configured otherwise.
+const DEFAULT_EXT_PROFILE_TYPE: &str = "cpu";
+
+/// How long to wait for an external profiler to exit or respond once the collection ends, so that
+/// a profiler that hangs does not hold up the end of the recording.
+#[cfg(target_os = "linux")]
+const PLUGIN_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The placeholder in the command or URL of an external profiler that is replaced with the
+/// duration of the collection in seconds.
+const DURATION_PLACEHOLDER: &str = "{duration}";
+/// The placeholder in the command of an external profiler that is replaced with the path of the
+/// file to write the profile to. Without it, the profile is read from the standard output.
+const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+/// An external profiler declared through --profile-ext, which is either a command, or a local
+/// HTTP endpoint that profiles for the requested duration, such as Go's /debug/pprof/profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExtProfilerConfig {
+    /// The name of the profiler, which is the key of its Profiler.
+    pub name: String,
+    /// The command to run through the shell, or the loopback URL (http://localhost...) to request.
+    pub source: String,
+    /// The format of the generated profile.
+    pub format: ProfileFormat,
+    /// The profile type of the samples.
+    pub profile_type: String,
+}
+
+impl ExtProfilerConfig {
+    /// Parse the declaration of an external profiler, "<name>=<command|URL>" followed by the
+    /// optional ",format=<folded|pprof|speedscope>" (folded by default) and ",type=<profile type>"
+    /// (cpu by default). The command or URL may contain commas.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((name, mut source)) = spec.split_once('=') else {
+            bail!("Invalid external profiler '{spec}', expected <name>=<command|URL>[,format=<format>][,type=<type>]");
+        };
+        let name = name.trim();
+        if !Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap().is_match(name) {
+            bail!("Invalid external profiler name '{name}', which can only contain letters, digits, '_', '.' and '-'");
+        }
+
+        let mut format = None;
+        let mut profile_type = None;
+        while let Some((rest, option)) = source.rsplit_once(',') {
+            match option.trim().split_once('=') {
+                Some(("format", value)) if format.is_none() => format = Some(value.parse()?),
+                Some(("type", value)) if profile_type.is_none() && !value.is_empty() => {
+                    profile_type = Some(value.to_string())
+                }
+                _ => break,
+            }
+            source = rest;
+        }
+        let source = source.trim();
+        if source.is_empty() {
+            bail!("The external profiler '{name}' has no command or URL");
+        }
+        if source.starts_with("https://")
+            || (source.starts_with("http://") && !is_loopback_url(source))
+        {
+            bail!(
+                "The external profiler '{name}' can only request a local HTTP endpoint (http://localhost, http://127.0.0.1 or http://[::1]), not '{source}'"
+            );
+        }
+
+        Ok(ExtProfilerConfig {
+            name: name.to_string(),
+            source: source.to_string(),
+            format: format.unwrap_or(ProfileFormat::Folded),
+            profile_type: profile_type.unwrap_or_else(|| DEFAULT_EXT_PROFILE_TYPE.to_string()),
+        })
+    }
+
+    fn is_http(&self) -> bool {
+        self.source.starts_with("http://")
+    }
+}
+
+/// Whether a http:// URL is on the loopback interface, i.e. its host is localhost or a loopback
+/// IP address.
+fn is_loopback_url(url: &str) -> bool {
+    let address = url.strip_prefix("http://").unwrap_or(url);
+    let host_port = address.split(['/', '?']).next().unwrap_or_default();
+    let host = match host_port.strip_prefix('[') {
+        Some(ipv6_host_port) => ipv6_host_port.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Parse the external profilers of --profile-ext, whose names must be unique.
+pub fn parse_ext_profilers(specs: &[String]) -> Result<Vec<ExtProfilerConfig>> {
+    let mut configs: Vec<ExtProfilerConfig> = Vec::new();
+    for spec in specs {
+        let config = ExtProfilerConfig::parse(spec)?;
+        if configs.iter().any(|other| other.name == config.name) {
+            bail!(
+                "The external profiler '{}' is declared more than once",
+                config.name
+            );
+        }
+        configs.push(config);
+    }
+    Ok(configs)
+}
+
+fn ext_profiler_data_path(run_data_dir: &Path) -> PathBuf {
+    run_data_dir.join("ext_profiler_data.json")
+}
+
+/// A profiler that runs alongside the collection: it is started at the beginning of the
+/// collection window, and stopped at its end to return the profile it generated, which is
+/// ingested into a Profiler.
+#[cfg(target_os = "linux")]
+pub trait ProfilerPlugin: Send {
+    /// Start profiling for the given duration in seconds.
+    fn start(&mut self, duration: u64) -> Result<()>;
+
+    /// Stop profiling if it is still running and return the generated profile.
+    fn stop(&mut self) -> Result<Vec<u8>>;
+}
+
+/// Create the plugin of an external profiler, which writes its profile to output_path if it is a
+/// command.
+#[cfg(target_os = "linux")]
+pub fn new_profiler_plugin(
+    config: &ExtProfilerConfig,
+    output_path: PathBuf,
+) -> Box<dyn ProfilerPlugin> {
+    if config.is_http() {
+        Box::new(HttpProfilerPlugin {
+            url: config.source.clone(),
+            request: None,
+        })
+    } else {
+        Box::new(CommandProfilerPlugin {
+            command: config.source.clone(),
+            output_path,
+            child: None,
+            stop_timeout: PLUGIN_STOP_TIMEOUT,
+        })
+    }
+}
+
+/// A profiler command run through the shell, whose process group is interrupted with SIGINT at
+/// the end of the collection if it has not exited by then, and killed if it has not exited
+/// stop_timeout after that.
+#[cfg(target_os = "linux")]
+struct CommandProfilerPlugin {
+    command: String,
+    output_path: PathBuf,
+    child: Option<Child>,
+    stop_timeout: Duration,
+}
+
+#[cfg(target_os = "linux")]
+impl ProfilerPlugin for CommandProfilerPlugin {
+    fn start(&mut self, duration: u64) -> Result<()> {
+        let command = self
+            .command
+            .replace(DURATION_PLACEHOLDER, &duration.to_string())
+            .replace(OUTPUT_PLACEHOLDER, &self.output_path.to_string_lossy());
+        let stdout = if self.command.contains(OUTPUT_PLACEHOLDER) {
+            Stdio::null()
+        } else {
+            Stdio::from(File::create(&self.output_path)?)
+        };
+        // Run the shell in its own process group, so that the signal to stop reaches every
+        // process of the command.
+        let child = Command::new("sh")
+            .args(["-c", &command])
+            .stdout(stdout)
+            .stderr(Stdio::inherit())
+            .process_group(0)
+            .spawn()?;
+        register_sub_process_pid(child.id());
+        self.child = Some(child);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<Vec<u8>> {
+        let Some(mut child) = self.child.take() else {
+            bail!("The profiler was not started");
+        };
+        let process_group = Pid::from_raw(child.id() as i32);
+        if child.try_wait()?.is_none() {
+            signal::killpg(process_group, signal::Signal::SIGINT)?;
+        }
+        let deadline = Instant::now() + self.stop_timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "'{}' did not exit within {}s of SIGINT, killing it",
+                    self.command,
+                    self.stop_timeout.as_secs()
+                );
+                signal::killpg(process_group, signal::Signal::SIGKILL)?;
+                break child.wait()?;
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+        if !status.success() {
+            warn!("'{}' exited with {}", self.command, status);
+        }
+        Ok(fs::read(&self.output_path)?)
+    }
+}
+
+/// A local HTTP endpoint requested in the background, whose response is the profile.
+#[cfg(target_os = "linux")]
+struct HttpProfilerPlugin {
+    url: String,
+    request: Option<Receiver<Result<Vec<u8>>>>,
+}
+
+#[cfg(target_os = "linux")]
+impl ProfilerPlugin for HttpProfilerPlugin {
+    fn start(&mut self, duration: u64) -> Result<()> {
+        let url = self
+            .url
+            .replace(DURATION_PLACEHOLDER, &duration.to_string());
+        // Allow the endpoint some time to respond after profiling for the duration.
+        let timeout = Duration::from_secs(duration + 60);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(http_get(&url, timeout)));
+        self.request = Some(receiver);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<Vec<u8>> {
+        let Some(request) = self.request.take() else {
+            bail!("The profiler was not started");
+        };
+        match request.recv_timeout(PLUGIN_STOP_TIMEOUT) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => bail!(
+                "{} did not respond within {}s after the collection",
+                self.url,
+                PLUGIN_STOP_TIMEOUT.as_secs()
+            ),
+            Err(RecvTimeoutError::Disconnected) => bail!("The request to {} panicked", self.url),
+        }
+    }
+}
+
+/// Send an HTTP/1.0 GET request to a http:// URL and return the body of the response, which
+/// must have status 200.
+#[cfg(target_os = "linux")]
+fn http_get(url: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let Some(address) = url.strip_prefix("http://") else {
+        bail!("Unsupported URL {url}");
+    };
+    let (host_port, path) = match address.find('/') {
+        Some(idx) => address.split_at(idx),
+        None => (address, "/"),
+    };
+    let has_port = host_port
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    let host_port = if has_port {
+        host_port.to_string()
+    } else {
+        format!("{host_port}:80")
+    };
+
+    let mut stream = TcpStream::connect(&host_port)?;
+    stream.set_read_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "GET {path} HTTP/1.0\r\nHost: {host_port}\r\nUser-Agent: aperf\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let Some(header_end) = response.windows(4).position(|window| window == b"\r\n\r\n") else {
+        bail!("Invalid HTTP response from {url}");
+    };
+    let header = String::from_utf8_lossy(&response[..header_end]);
+    let status_line = header.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        bail!("{url} responded with '{status_line}'");
+    }
+    Ok(response.split_off(header_end + 4))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtProfileRaw {
+    pub data: String,
+}
+
+#[cfg(target_os = "linux")]
+lazy_static! {
+    static ref EXT_PROFILER_PLUGINS: Mutex<Vec<(ExtProfilerConfig, Box<dyn ProfilerPlugin>)>> =
+        Mutex::new(Vec::new());
+    static ref EXT_PROFILE_START_TIME_MS: Mutex<i64> = Mutex::new(0);
+}
+
+#[cfg(target_os = "linux")]
+impl ExtProfileRaw {
+    pub fn new() -> Self {
+        ExtProfileRaw {
+            data: String::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CollectData for ExtProfileRaw {
+    fn prepare_data_collector(&mut self, init_params: &InitParams) -> Result<()> {
+        *EXT_PROFILE_START_TIME_MS.lock().unwrap() = Utc::now().timestamp_millis();
+        let duration = get_sub_process_duration_seconds(init_params);
+
+        let mut plugins = EXT_PROFILER_PLUGINS.lock().unwrap();
+        for config in &init_params.ext_profilers {
+            let output_path = init_params.tmp_dir.join(format!(
+                "{}-ext-profile-{}.{}",
+                init_params.run_name,
+                config.name,
+                config.format.file_extension()
+            ));
+            let mut plugin = new_profiler_plugin(config, output_path);
+            match plugin.start(duration) {
+                Ok(()) => {
+                    debug!("Started the external profiler '{}'", config.name);
+                    plugins.push((config.clone(), plugin));
+                }
+                Err(e) => error!(
+                    "Failed to start the external profiler '{}': {}",
+                    config.name, e
+                ),
+            }
+        }
+        if plugins.is_empty() {
+            return Err(PDError::DependencyError(
+                "None of the external profilers could be started".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn collect_data(&mut self, _init_params: &InitParams) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish_data_collection(&mut self, init_params: &InitParams) -> Result<()> {
+        let start_time_ms = *EXT_PROFILE_START_TIME_MS.lock().unwrap();
+        let mut profiling_data = ProfilingData::default();
+
+        for (config, mut plugin) in EXT_PROFILER_PLUGINS.lock().unwrap().drain(..) {
+            let profile = match plugin.stop() {
+                Ok(profile) => profile,
+                Err(e) => {
+                    error!(
+                        "Failed to get the profile of the external profiler '{}': {}",
+                        config.name, e
+                    );
+                    continue;
+                }
+            };
+            // Keep the original profile, which can be opened with the tools of the format.
+            fs::write(
+                init_params.run_data_dir.join(format!(
+                    "ext-profile-{}.{}",
+                    config.name,
+                    config.format.file_extension()
+                )),
+                &profile,
+            )?;
+            match ingest_profile(&profile, config.format, &config.profile_type, start_time_ms) {
+                Ok(profiler) => {
+                    profiling_data
+                        .profilers
+                        .insert(config.name.clone(), profiler);
+                }
+                Err(e) => error!(
+                    "Failed to ingest the {} profile of the external profiler '{}': {}",
+                    config.format, config.name, e
+                ),
+            }
+        }
+
+        fs::write(
+            ext_profiler_data_path(&init_params.run_data_dir),
+            serde_json::to_string(&profiling_data)?,
+        )?;
+        Ok(())
+    }
+
+    fn is_ext_profile() -> bool {
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtProfile;
+
+impl ExtProfile {
+    pub fn new() -> Self {
+        ExtProfile
+    }
+}
+
+impl ProcessData for ExtProfile {
+    fn process_raw_data(
+        &mut self,
+        report_params: &ReportParams,
+        _raw_data: Vec<Data>,
+    ) -> Result<AperfData> {
+        let profiling_data =
+            match fs::read_to_string(ext_profiler_data_path(&report_params.run_data_dir)) {
+                Ok(json) => serde_json::from_str(&json)?,
+                Err(e) => {
+                    error!("Failed to read the external profiles: {e}");
+                    ProfilingData::default()
+                }
+            };
+        Ok(AperfData::Profile(profiling_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ext_profiler() {
+        assert_eq!(
+            ExtProfilerConfig::parse(
+                "py=py-spy record -p 1234 -f raw -o {output} -d {duration},format=folded"
+            )
+            .unwrap(),
+            ExtProfilerConfig {
+                name: "py".to_string(),
+                source: "py-spy record -p 1234 -f raw -o {output} -d {duration}".to_string(),
+                format: ProfileFormat::Folded,
+                profile_type: "cpu".to_string(),
+            }
+        );
+        assert_eq!(
+            ExtProfilerConfig::parse(
+                "go-api=http://localhost:6060/debug/pprof/profile?seconds={duration},type=cpu,format=pprof"
+            )
+            .unwrap(),
+            ExtProfilerConfig {
+                name: "go-api".to_string(),
+                source: "http://localhost:6060/debug/pprof/profile?seconds={duration}"
+                    .to_string(),
+                format: ProfileFormat::Pprof,
+                profile_type: "cpu".to_string(),
+            }
+        );
+        // The commas of the command are kept, and the format defaults to folded stacks
+        let config = ExtProfilerConfig::parse("node=cat a,b,type=wall").unwrap();
+        assert_eq!(config.source, "cat a,b");
+        assert_eq!(config.format, ProfileFormat::Folded);
+        assert_eq!(config.profile_type, "wall");
+
+        assert!(ExtProfilerConfig::parse("py-spy record").is_err());
+        assert!(ExtProfilerConfig::parse("py spy=py-spy record").is_err());
+        assert!(ExtProfilerConfig::parse("py=,format=folded").is_err());
+        assert!(ExtProfilerConfig::parse("py=py-spy record,format=svg").is_err());
+        assert!(ExtProfilerConfig::parse("go=https://localhost/debug/pprof/profile").is_err());
+        assert!(ExtProfilerConfig::parse("go=http://example.com:6060/profile").is_err());
+        assert!(ExtProfilerConfig::parse("go=http://127.0.0.1.example.com/profile").is_err());
+        assert!(ExtProfilerConfig::parse("go=http://127.0.0.2:6060/profile").is_ok());
+        assert!(ExtProfilerConfig::parse("go=http://[::1]:6060/profile").is_ok());
+        assert!(parse_ext_profilers(&["a=true".to_string(), "a=false".to_string()]).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_command_profiler_plugin() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+
+        // The profile is read from the standard output
+        let config = ExtProfilerConfig::parse("echo=echo 'main;work {duration}'").unwrap();
+        let mut plugin = new_profiler_plugin(&config, tmp_dir.path().join("echo.folded"));
+        plugin.start(3).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(plugin.stop().unwrap(), b"main;work 3\n");
+
+        // The profile is written to the output file, and the profiler is interrupted
+        let config = ExtProfilerConfig::parse(
+            "sleep=trap 'echo main 1 > {output}; exit 0' INT; sleep {duration} & wait",
+        )
+        .unwrap();
+        let mut plugin = new_profiler_plugin(&config, tmp_dir.path().join("sleep.folded"));
+        plugin.start(30).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(plugin.stop().unwrap(), b"main 1\n");
+
+        // A profiler that ignores SIGINT is killed after the stop timeout, keeping its output
+        let mut plugin = CommandProfilerPlugin {
+            command: "echo main 2; trap '' INT; sleep 1000".to_string(),
+            output_path: tmp_dir.path().join("trap.folded"),
+            child: None,
+            stop_timeout: Duration::from_millis(500),
+        };
+        plugin.start(30).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        assert_eq!(plugin.stop().unwrap(), b"main 2\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_http_profiler_plugin() {
+        use std::io::BufRead;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            for body in ["main;work 7\n", ""] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Read the headers up to the empty line
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let status = if body.is_empty() {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                write!(stream, "HTTP/1.0 {status}\r\n\r\n{body}").unwrap();
+                assert_eq!(request_line, "GET /profile?seconds=5 HTTP/1.0\r\n");
+            }
+        });
+
+        let config = ExtProfilerConfig::parse(&format!(
+            "go=http://127.0.0.1:{port}/profile?seconds={{duration}}"
+        ))
+        .unwrap();
+        for expected in [Some(b"main;work 7\n".to_vec()), None] {
+            let mut plugin = new_profiler_plugin(&config, PathBuf::new());
+            plugin.start(5).unwrap();
+            assert_eq!(plugin.stop().ok(), expected);
+        }
+        server.join().unwrap();
+    }
+}
@@ -6,25 +6,26 @@ use crate::data::{Data, ProcessData};
 use crate::data_processing::ReportParams;
 use crate::find_file;
 use crate::profiling::Profile;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::path::PathBuf;
 #[cfg(target_os = "linux")]
 use {
     crate::data::common::utils::get_sub_process_duration_seconds,
     crate::data::CollectData,
     crate::data_collection::InitParams,
-    crate::profiling::jfr,
+    crate::profiling::{jfr, jvm},
     crate::{get_data_name_from_type, PDError},
     crate::{run_command, run_command_and_wait},
-    log::debug,
+    log::{debug, warn},
     nix::{sys::signal, unistd::Pid},
     serde_json::Value,
     std::fs::File,
     std::io::Write,
-    std::path::PathBuf,
+    std::path::Path,
     std::process::{Child, Stdio},
     std::str::FromStr,
     std::sync::Mutex,
@@ -32,18 +33,342 @@ use {
 
 const PROFILE_METRICS: &[&str] = &["cpu", "alloc", "wall"];
 
+/// The asprof options that are managed by APerf or set through --java-profile-events, which
+/// cannot be passed through --asprof-args.
+const MANAGED_ASPROF_OPTIONS: &[&str] = &[
+    "-d",
+    "--duration",
+    "-o",
+    "-f",
+    "--file",
+    "--clock",
+    "-e",
+    "--event",
+    "-i",
+    "--interval",
+    "--alloc",
+    "--wall",
+    "--lock",
+    "--nativemem",
+    "--live",
+];
+
+/// The async-profiler settings used to profile a JVM.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AsprofOptions {
+    /// The execution event to sample, such as cpu, itimer, ctimer or cycles. None if only the
+    /// other events are profiled.
+    pub event: Option<String>,
+    /// The sampling interval of the execution event. None to use the default of async-profiler.
+    pub interval: Option<String>,
+    /// The sampling interval (in bytes) of the heap allocations, if allocation profiling is enabled.
+    pub alloc_interval: Option<String>,
+    /// The sampling interval of the wall-clock profiling, if it is enabled.
+    pub wall_interval: Option<String>,
+    /// The duration threshold of the contended locks, if lock profiling is enabled.
+    pub lock_threshold: Option<String>,
+    /// The sampling interval (in bytes) of the native memory allocations, if native memory
+    /// profiling is enabled.
+    pub nativemem_interval: Option<String>,
+    /// Whether the sampled heap allocations that are still alive are recorded.
+    pub live: bool,
+    /// Additional asprof arguments, such as thread filters and include/exclude patterns.
+    pub extra_args: Vec<String>,
+}
+
+impl Default for AsprofOptions {
+    fn default() -> Self {
+        AsprofOptions {
+            event: Some("cpu".to_string()),
+            interval: None,
+            alloc_interval: Some("2m".to_string()),
+            wall_interval: Some("100ms".to_string()),
+            lock_threshold: None,
+            nativemem_interval: None,
+            live: false,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl AsprofOptions {
+    /// Parse comma separated events with optional intervals, such as "itimer=5ms,alloc=512k".
+    /// The events that are not listed are not profiled.
+    pub fn from_events(events: &str) -> Result<Self> {
+        let mut options = AsprofOptions {
+            event: None,
+            alloc_interval: None,
+            wall_interval: None,
+            ..Default::default()
+        };
+        for event in events.split(',').map(str::trim) {
+            let (name, interval) = match event.split_once('=') {
+                Some((name, interval)) => (name, Some(interval.to_string())),
+                None => (event, None),
+            };
+            if name.is_empty() || interval.as_ref().is_some_and(|i| i.is_empty()) {
+                bail!("Invalid Java profile event '{event}' in '{events}'.");
+            }
+            match name {
+                "alloc" => options.alloc_interval = interval.or(Some("2m".to_string())),
+                "wall" => options.wall_interval = interval.or(Some("100ms".to_string())),
+                "lock" => options.lock_threshold = interval.or(Some("10ms".to_string())),
+                "nativemem" => options.nativemem_interval = interval.or(Some("2m".to_string())),
+                "live" if interval.is_none() => options.live = true,
+                "live" => bail!("The Java profile event 'live' does not take an interval."),
+                _ => {
+                    if let Some(other) = &options.event {
+                        bail!(
+                            "Only one execution event can be profiled, got '{other}' and '{name}'."
+                        );
+                    }
+                    options.event = Some(name.to_string());
+                    options.interval = interval;
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Add the arguments of --asprof-args, which are split like a shell command line, so that
+    /// quoted arguments can contain spaces.
+    pub fn add_extra_args(&mut self, args: &str) -> Result<()> {
+        let args = match shell_words::split(args) {
+            Ok(args) => args,
+            Err(e) => bail!("Invalid asprof arguments '{args}': {e}."),
+        };
+        for arg in args {
+            let option = arg
+                .split_once('=')
+                .map_or(arg.as_str(), |(option, _)| option);
+            if MANAGED_ASPROF_OPTIONS.contains(&option) {
+                bail!(
+                    "The asprof option '{option}' is managed by APerf and cannot be set through --asprof-args. Use --java-profile-events to choose the profiled events."
+                );
+            }
+            self.extra_args.push(arg);
+        }
+        Ok(())
+    }
+
+    /// The comma separated events with intervals, in the format of --java-profile-events.
+    pub fn events(&self) -> String {
+        let mut events = Vec::new();
+        if let Some(event) = &self.event {
+            events.push(match &self.interval {
+                Some(interval) => format!("{event}={interval}"),
+                None => event.clone(),
+            });
+        }
+        for (name, interval) in [
+            ("alloc", &self.alloc_interval),
+            ("wall", &self.wall_interval),
+            ("lock", &self.lock_threshold),
+            ("nativemem", &self.nativemem_interval),
+        ] {
+            if let Some(interval) = interval {
+                events.push(format!("{name}={interval}"));
+            }
+        }
+        if self.live {
+            events.push("live".to_string());
+        }
+        events.join(",")
+    }
+
+    /// The asprof arguments choosing the profiled events and filters.
+    pub fn asprof_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(event) = &self.event {
+            args.extend(["-e".to_string(), event.clone()]);
+        }
+        for (option, value) in [
+            ("-i", &self.interval),
+            ("--alloc", &self.alloc_interval),
+            ("--wall", &self.wall_interval),
+            ("--lock", &self.lock_threshold),
+            ("--nativemem", &self.nativemem_interval),
+        ] {
+            if let Some(value) = value {
+                args.extend([option.to_string(), value.clone()]);
+            }
+        }
+        if self.live {
+            args.push("--live".to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+
+    /// The profile metrics of the jfrconv heatmaps that are recorded with these options.
+    fn profile_metrics(&self) -> Vec<&'static str> {
+        let mut metrics = Vec::new();
+        if self.event.is_some() {
+            metrics.push("cpu");
+        }
+        if self.alloc_interval.is_some() {
+            metrics.push("alloc");
+        }
+        if self.wall_interval.is_some() {
+            metrics.push("wall");
+        }
+        metrics
+    }
+}
+
+/// Split the optional "<PID/Name>:" prefix, which applies the value to specific JVMs, from the
+/// value of --java-profile-events or --asprof-args.
+fn split_jvm_prefix(value: &str) -> (Option<&str>, &str) {
+    match value.split_once(':') {
+        Some((jvm, rest))
+            if !jvm.is_empty()
+                && !jvm.starts_with('-')
+                && !jvm.contains(|c: char| c == '=' || c == ',' || c.is_whitespace()) =>
+        {
+            (Some(jvm), rest)
+        }
+        _ => (None, value),
+    }
+}
+
+/// Build the async-profiler options of all JVMs, and of the JVMs with specific names or PIDs,
+/// from the values of --java-profile-events and --asprof-args.
+pub fn parse_asprof_options(
+    java_profile_events: &[String],
+    asprof_args: &[String],
+) -> Result<(AsprofOptions, HashMap<String, AsprofOptions>)> {
+    let mut asprof_options = AsprofOptions::default();
+    let mut jvm_asprof_options: HashMap<String, AsprofOptions> = HashMap::new();
+    for value in java_profile_events {
+        match split_jvm_prefix(value) {
+            (Some(jvm), events) => {
+                jvm_asprof_options.insert(jvm.to_string(), AsprofOptions::from_events(events)?);
+            }
+            (None, events) => asprof_options = AsprofOptions::from_events(events)?,
+        }
+    }
+    // The arguments of all JVMs apply to the JVMs with their own events as well.
+    for value in asprof_args {
+        if let (None, args) = split_jvm_prefix(value) {
+            for options in
+                std::iter::once(&mut asprof_options).chain(jvm_asprof_options.values_mut())
+            {
+                options.add_extra_args(args)?;
+            }
+        }
+    }
+    for value in asprof_args {
+        if let (Some(jvm), args) = split_jvm_prefix(value) {
+            jvm_asprof_options
+                .entry(jvm.to_string())
+                .or_insert_with(|| asprof_options.clone())
+                .add_extra_args(args)?;
+        }
+    }
+    Ok((asprof_options, jvm_asprof_options))
+}
+
 #[cfg(target_os = "linux")]
 lazy_static! {
     pub static ref ASPROF_CHILDREN: Mutex<Vec<Child>> = Mutex::new(Vec::new());
 }
 
+/// Extract the metadata events of a JFR file, such as the JVM information, as JSON using the
+/// jfr tool of the JDK. Returns Value::Null if the tool is unavailable or fails.
+#[cfg(target_os = "linux")]
+pub(crate) fn read_jfr_metadata(jfr_path: &Path, key: &str) -> Value {
+    let metadata_events = [
+        "jdk.ActiveRecording",
+        "jdk.ActiveSetting",
+        "jdk.CheckPoint",
+        "jdk.Metadata",
+        "jdk.JVMInformation",
+        "jdk.NativeLibrary",
+    ];
+    match run_command_and_wait(
+        "jfr",
+        [
+            "print",
+            "--json",
+            "--events",
+            &metadata_events.join(","),
+            &jfr_path.to_string_lossy(),
+        ],
+        "jfr-print",
+        None,
+    ) {
+        Err(e) => {
+            error!("'jfr' metadata extraction failed for {}: {}", key, e);
+            Value::Null
+        }
+        Ok(output) => {
+            if !output.status.success() {
+                error!(
+                    "'jfr' metadata extraction failed for {}: {}",
+                    key,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                Value::Null
+            } else {
+                serde_json::from_slice(&output.stdout).unwrap_or(Value::Null)
+            }
+        }
+    }
+}
+
 fn java_profiler_data_filename(pid: &str) -> String {
     format!("java_profiler_data_{}.json", pid)
 }
 
+/// Write the Profiler of a JVM into the run directory and add the JVM to the run's jps map.
+/// If the key is already used by another JVM, a suffix is appended to it. Returns the key that
+/// the Profiler is stored under.
+pub(crate) fn save_java_profiler_data(
+    run_data_dir: &PathBuf,
+    key: &str,
+    jvm_name: &str,
+    profiler: &Profiler,
+) -> Result<String> {
+    let jps_map_filename = find_file(run_data_dir, r"jps-map\.json$", None)
+        .unwrap_or_else(|_| "jps-map.json".to_string());
+    let jps_map_path = run_data_dir.join(jps_map_filename);
+    let mut process_map: HashMap<String, Vec<String>> = match fs::read_to_string(&jps_map_path) {
+        Ok(json) => serde_json::from_str(&json)?,
+        Err(_) => HashMap::new(),
+    };
+
+    let mut deduped_key = key.to_string();
+    let mut suffix = 1;
+    while process_map.contains_key(&deduped_key) {
+        deduped_key = format!("{}-{}", key, suffix);
+        suffix += 1;
+    }
+
+    fs::write(
+        run_data_dir.join(java_profiler_data_filename(&deduped_key)),
+        serde_json::to_string(profiler)?,
+    )?;
+    process_map.insert(deduped_key.clone(), vec![jvm_name.to_string()]);
+    fs::write(jps_map_path, serde_json::to_string(&process_map)?)?;
+
+    Ok(deduped_key)
+}
+
+/// Parse the major version of async-profiler from the output of asprof --version, such as
+/// "Async-profiler 3.0 built on May 10 2024".
+fn asprof_major_version(version_output: &str) -> Option<u32> {
+    version_output
+        .split_whitespace()
+        .find_map(|word| word.split('.').next()?.parse().ok())
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JavaProfileRaw {
     process_map: HashMap<String, Vec<String>>,
+    // Whether asprof timestamps the samples with CLOCK_MONOTONIC, which is decided when the
+    // collection is prepared, so it needs to skip serialization
+    #[serde(skip)]
+    monotonic_clock: bool,
 }
 
 #[cfg(target_os = "linux")]
@@ -58,43 +383,35 @@ impl JavaProfileRaw {
     pub fn new() -> Self {
         JavaProfileRaw {
             process_map: HashMap::new(),
+            monotonic_clock: false,
         }
     }
 
     fn launch_asprof(
         &self,
         jids: Vec<String>,
-        run_name: &str,
+        init_params: &InitParams,
         duration: u64,
-        tmp_dir: &PathBuf,
     ) -> Result<()> {
         for jid in &jids {
-            match run_command(
-                "asprof",
-                [
-                    "-d",
-                    &duration.to_string(),
-                    "-o",
-                    "jfr",
-                    "-e",
-                    "cpu",
-                    "--alloc",
-                    "2m",
-                    "--wall",
-                    "100ms",
-                    "--cstack",
-                    "vm",
-                    "-F",
-                    "vtable",
-                    "-f",
-                    &tmp_dir
-                        .join(format!("{}-java-profile-{}.jfr", run_name, jid))
-                        .to_string_lossy(),
-                    jid.as_str(),
-                ],
-                Stdio::inherit(),
-                Stdio::inherit(),
-            ) {
+            let jfr_path = init_params
+                .tmp_dir
+                .join(format!("{}-java-profile-{}.jfr", init_params.run_name, jid));
+            let mut asprof_args: Vec<String> = vec!["-d".to_string(), duration.to_string()];
+            asprof_args.extend(["-o", "jfr", "--cstack", "vm", "-F", "vtable"].map(String::from));
+            // Timestamp the samples with the clock of the Perf samples (perf record -k 1), so
+            // that they can be correlated in the mixed-mode Java profiles of perf_profile.
+            if self.monotonic_clock {
+                asprof_args.extend(["--clock", "monotonic"].map(String::from));
+            }
+            asprof_args.extend(self.asprof_options(jid, init_params).asprof_args());
+            asprof_args.extend([
+                "-f".to_string(),
+                jfr_path.to_string_lossy().to_string(),
+                jid.clone(),
+            ]);
+
+            match run_command("asprof", asprof_args, Stdio::inherit(), Stdio::inherit()) {
                 Err(e) => {
                     return Err(PDError::DependencyError(format!(
                         "'asprof' command failed. {}",
@@ -117,6 +434,20 @@ impl JavaProfileRaw {
         Ok(())
     }
 
+    /// The async-profiler options of a JVM, which are the options of its PID or name if set.
+    fn asprof_options<'a>(&self, jid: &str, init_params: &'a InitParams) -> &'a AsprofOptions {
+        init_params
+            .jvm_asprof_options
+            .get(jid)
+            .or_else(|| {
+                self.process_map
+                    .get(jid)
+                    .and_then(|names| names.first())
+                    .and_then(|name| init_params.jvm_asprof_options.get(name))
+            })
+            .unwrap_or(&init_params.asprof_options)
+    }
+
     fn get_jids(&mut self, arg: &str) -> Vec<String> {
         let mut jids: Vec<String> = Vec::new();
         for (key, value) in self.process_map.clone().into_iter() {
@@ -127,51 +458,16 @@ impl JavaProfileRaw {
         jids
     }
 
-    fn update_process_map(&mut self) -> Result<String, PDError> {
-        debug!("Running jps (may incur utilization spike)...");
-        let jps_cmd = run_command_and_wait("jps", std::iter::empty::<&str>(), "jps", None);
-        /*
-        Output of jps:
-        lvmid [ classname | JARfilename | "Unknown"]
-        lvmid [ classname | JARfilename | "Unknown"]
-        .
-        .
-        lvmid [ classname | JARfilename | "Unknown"]
-        */
-        match jps_cmd {
-            Ok(jps_out) => {
-                let jps_str = String::from_utf8(jps_out.stdout).unwrap_or_default();
-                let jps: Vec<&str> = jps_str.split_whitespace().collect();
-                for i in (0..jps.len()).step_by(2) {
-                    if jps[i + 1] != "Jps" {
-                        self.process_map
-                            .insert(String::from(jps[i]), vec![String::from(jps[i + 1])]);
-                    }
-                }
-                Ok(jps_str)
+    /// Discover the running JVMs, add the new ones to the process map, and return their PIDs.
+    fn update_process_map(&mut self) -> Vec<String> {
+        let mut new_jids = Vec::new();
+        for (pid, jvm) in jvm::discover_jvms() {
+            if !self.process_map.contains_key(&pid) {
+                self.process_map.insert(pid.clone(), vec![jvm.name]);
+                new_jids.push(pid);
             }
-            Err(e) => Err(PDError::DependencyError(format!(
-                "jps command failed. Ensure JDK is installed to use Java profiling. Error msg: {}",
-                e
-            ))),
-        }
-    }
-
-    fn launch_pgrep(&mut self) -> Result<Vec<String>, PDError> {
-        let pgrep_cmd = run_command_and_wait("pgrep", ["java"], "pgrep", None);
-        match pgrep_cmd {
-            Ok(pgrep_out) => {
-                let pgrep_str = String::from_utf8(pgrep_out.stdout).unwrap();
-                return Ok(pgrep_str
-                    .split_whitespace()
-                    .map(|s| s.to_string())
-                    .collect());
-            }
-            Err(e) => Err(PDError::DependencyError(format!(
-                "pgrep command failed. Error msg: {}",
-                e
-            ))),
         }
+        new_jids
     }
 }
 
@@ -179,42 +475,43 @@ impl JavaProfileRaw {
 impl CollectData for JavaProfileRaw {
     fn prepare_data_collector(&mut self, init_params: &InitParams) -> Result<()> {
         // Check if asprof is installed
-        match run_command_and_wait("asprof", ["--version"], "asprof", None) {
-            Ok(_) => {},
+        let asprof_version = match run_command_and_wait("asprof", ["--version"], "asprof", None) {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
             Err(e) => return Err(PDError::DependencyError(format!(
                 "'asprof' command failed. Ensure it is installed and refer to DEPENDENCIES documentation for more info. Error msg: {}",
                 e
             )).into()),
-        }
-
-        let mut jids: Vec<String> = Vec::new();
-        let pgrep: Vec<String> = self.launch_pgrep()?;
-        for pid in pgrep {
-            if self.process_map.contains_key(&pid) {
-                continue;
+        };
+        // The Java samples are only merged into the Perf profile if asprof supports --clock,
+        // which was added in async-profiler 3.0.
+        if init_params.perf_profile {
+            self.monotonic_clock =
+                asprof_major_version(&asprof_version).is_some_and(|major| major >= 3);
+            if !self.monotonic_clock {
+                warn!(
+                    "'{}' does not support --clock monotonic, which requires async-profiler 3.0 or later, so the Java frames will not be merged into the Perf profile.",
+                    asprof_version
+                );
             }
-            self.process_map
-                .insert(pid.clone(), vec![format!("Unknown_JVM_{pid}")]);
         }
 
-        let jps_str = self.update_process_map()?;
-        let jps: Vec<&str> = jps_str.split_whitespace().collect();
+        let mut jids: Vec<String> = Vec::new();
+        let discovered_jids = self.update_process_map();
 
         let jprofile_value = init_params.profile.get(get_data_name_from_type::<Self>());
         if let Some(value) = jprofile_value {
             match value.as_str() {
                 "jps" => {
-                    jids = self.process_map.clone().into_keys().collect();
-                    debug!("Jps will be run if new JVM is started during aperf record to resolve process names.",);
+                    jids = discovered_jids;
+                    debug!("New JVMs started during aperf record will be discovered and profiled.");
                 }
                 _ => {
-                    let args: Vec<&str> = value.split(',').collect();
-                    for arg in args {
-                        if !jps.contains(&arg) {
+                    for arg in value.split(',') {
+                        let mut arg_jids = self.get_jids(arg);
+                        if arg_jids.is_empty() {
                             error!("No JVM with name/PID '{}'.", arg);
-                            continue;
                         }
-                        jids.append(&mut self.get_jids(arg));
+                        jids.append(&mut arg_jids);
                     }
                 }
             }
@@ -224,9 +521,8 @@ impl CollectData for JavaProfileRaw {
 
         self.launch_asprof(
             jids,
-            &init_params.run_name,
+            init_params,
             get_sub_process_duration_seconds(init_params),
-            &init_params.tmp_dir,
         )
     }
 
@@ -240,25 +536,13 @@ impl CollectData for JavaProfileRaw {
             return Ok(());
         }
 
-        let pgrep_pids: Vec<String> = self.launch_pgrep()?;
-
-        let mut jids: Vec<String> = Vec::new();
-        for pid in pgrep_pids {
-            if self.process_map.contains_key(pid.as_str()) {
-                continue;
-            }
-            self.process_map
-                .insert(pid.clone(), vec![format!("Unknown_JVM_{pid}")]);
-            jids.push(pid.clone());
-        }
-
+        let jids = self.update_process_map();
         let duration = get_sub_process_duration_seconds(init_params);
         if jids.is_empty() || duration == 0 {
             return Ok(());
         }
 
-        self.update_process_map()?;
-        self.launch_asprof(jids, &init_params.run_name, duration, &init_params.tmp_dir)
+        self.launch_asprof(jids, init_params, duration)
     }
 
     fn finish_data_collection(&mut self, init_params: &InitParams) -> Result<()> {
@@ -285,47 +569,11 @@ impl CollectData for JavaProfileRaw {
                 .join(format!("{}-java-profile-{}.jfr", init_params.run_name, key));
 
             if fs::exists(&jfr_path).expect("Can't check existence of jfr file") {
-                // Extract metadata JSON string from JFR
-                let metadata_events = [
-                    "jdk.ActiveRecording",
-                    "jdk.ActiveSetting",
-                    "jdk.CheckPoint",
-                    "jdk.Metadata",
-                    "jdk.JVMInformation",
-                    "jdk.NativeLibrary",
-                ];
-                let metadata_json = match run_command_and_wait(
-                    "jfr",
-                    [
-                        "print",
-                        "--json",
-                        "--events",
-                        &metadata_events.join(","),
-                        &jfr_path.to_string_lossy(),
-                    ],
-                    "jfr-print",
-                    None,
-                ) {
-                    Err(e) => {
-                        error!("'jfr' metadata extraction failed for {}: {}", key, e);
-                        Value::Null
-                    }
-                    Ok(output) => {
-                        if !output.status.success() {
-                            error!(
-                                "'jfr' metadata extraction failed for {}: {}",
-                                key,
-                                String::from_utf8_lossy(&output.stderr)
-                            );
-                            Value::Null
-                        } else {
-                            serde_json::from_slice(&output.stdout).unwrap_or(Value::Null)
-                        }
-                    }
-                };
+                let metadata_json = read_jfr_metadata(&jfr_path, key);
+                let asprof_options = self.asprof_options(key, init_params);
 
-                // Generate heatmaps for each profiling type
-                for metric in PROFILE_METRICS {
+                // Generate heatmaps for each profiled type
+                for metric in asprof_options.profile_metrics() {
                     let html_path = init_params
                         .run_data_dir
                         .join(format!("java-profile-{}-{}.html", key, metric));
@@ -384,6 +632,18 @@ impl CollectData for JavaProfileRaw {
                     match jfr::build_java_profiler_data(&jfr_path, events_out_path) {
                         Ok(mut profiler) => {
                             profiler.metadata = jfr::parse_jfr_metadata(&metadata_json);
+                            let asprof_group = profiler
+                                .metadata
+                                .key_value_groups
+                                .entry("async-profiler".to_string())
+                                .or_default();
+                            asprof_group
+                                .key_values
+                                .insert("events".to_string(), asprof_options.events());
+                            asprof_group.key_values.insert(
+                                "arguments".to_string(),
+                                asprof_options.asprof_args().join(" "),
+                            );
                             if let Ok(json) = serde_json::to_string(&profiler) {
                                 fs::write(
                                     init_params
@@ -538,3 +798,89 @@ impl ProcessData for JavaProfile {
         Ok(AperfData::Profile(profiling_data))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asprof_options_from_events() {
+        let options = AsprofOptions::from_events("itimer=5ms,alloc=512k,lock,live").unwrap();
+        assert_eq!(options.event.as_deref(), Some("itimer"));
+        assert_eq!(options.interval.as_deref(), Some("5ms"));
+        assert_eq!(options.alloc_interval.as_deref(), Some("512k"));
+        assert_eq!(options.wall_interval, None);
+        assert_eq!(options.lock_threshold.as_deref(), Some("10ms"));
+        assert!(options.live);
+        assert_eq!(options.events(), "itimer=5ms,alloc=512k,lock=10ms,live");
+        assert_eq!(
+            options.asprof_args(),
+            vec!["-e", "itimer", "-i", "5ms", "--alloc", "512k", "--lock", "10ms", "--live"]
+        );
+
+        assert!(AsprofOptions::from_events("cpu,cycles").is_err());
+        assert!(AsprofOptions::from_events("cpu,alloc=").is_err());
+        assert!(AsprofOptions::from_events("live=1").is_err());
+    }
+
+    #[test]
+    fn test_parse_asprof_options() {
+        let (asprof_options, jvm_asprof_options) = parse_asprof_options(
+            &["cpu,wall=200ms".to_string(), "kafka:ctimer".to_string()],
+            &[
+                "-t --filter 120-130".to_string(),
+                "1234:-I 'com/example/*'".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            asprof_options.asprof_args(),
+            vec!["-e", "cpu", "--wall", "200ms", "-t", "--filter", "120-130"]
+        );
+        assert_eq!(
+            jvm_asprof_options["kafka"].asprof_args(),
+            vec!["-e", "ctimer", "-t", "--filter", "120-130"]
+        );
+        assert_eq!(
+            jvm_asprof_options["1234"].asprof_args(),
+            vec![
+                "-e",
+                "cpu",
+                "--wall",
+                "200ms",
+                "-t",
+                "--filter",
+                "120-130",
+                "-I",
+                "com/example/*"
+            ]
+        );
+
+        let (asprof_options, _) = parse_asprof_options(
+            &[],
+            &["--title \"GC pauses\" -X '*Unsafe.park*'".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            asprof_options.extra_args,
+            vec!["--title", "GC pauses", "-X", "*Unsafe.park*"]
+        );
+
+        assert!(parse_asprof_options(&[], &["-I 'com/example/*".to_string()]).is_err());
+        assert!(parse_asprof_options(&[], &["--alloc 1k".to_string()]).is_err());
+        assert!(parse_asprof_options(&[], &["-e=cpu".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_asprof_major_version() {
+        assert_eq!(
+            asprof_major_version("Async-profiler 3.0 built on May 10 2024"),
+            Some(3)
+        );
+        assert_eq!(
+            asprof_major_version("Async-profiler 2.9 built on May  8 2023"),
+            Some(2)
+        );
+        assert_eq!(asprof_major_version(""), None);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::data::common::common_raw_data::parse_common_raw_time_series_data;
+use crate::data::common::data_formats::AperfData;
+use crate::data::common::time_series_data_processor::time_series_data_processor_with_max_series_aggregate;
+use crate::data::{Data, ProcessData, TimeEnum};
+use crate::data_processing::ReportParams;
+use crate::profiling::jvm::PerfDataValue;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+#[cfg(target_os = "linux")]
+use {
+    crate::data::common::common_raw_data::TimeSeriesDataBuilder,
+    crate::data::CollectData,
+    crate::data_collection::InitParams,
+    crate::profiling::jvm::{discover_hsperfdata_files, hsperfdata_jvm_name, parse_hsperfdata},
+    crate::PDError,
+    chrono::Utc,
+    log::debug,
+    std::fs,
+    std::time::Duration,
+};
+
+/// How often to scan /proc for new JVMs, since a scan costs far more than reading the
+/// hsperfdata files of the known ones.
+#[cfg(target_os = "linux")]
+const JVM_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The stats that accumulate since the start of a JVM, which are processed into rates.
+const CUMULATIVE_STATS: &[&str] = &[
+    "gc_count",
+    "gc_time",
+    "full_gc_count",
+    "full_gc_time",
+    "safepoint_count",
+    "safepoint_time",
+    "safepoint_sync_time",
+    "class_loads",
+    "jit_compiles",
+    "jit_compile_time",
+    "thread_starts",
+];
+
+/// Compute the stats of a JVM from its hsperfdata counters: the counts and times (in ms)
+/// accumulated since the JVM started, and the current memory usage (in bytes), classes and
+/// threads. The stats of the counters that the JVM does not publish, such as the old generation
+/// of a non-generational garbage collector, are omitted.
+pub fn jvm_stat_values(counters: &HashMap<String, PerfDataValue>) -> Vec<(&'static str, f64)> {
+    let long = |name: &str| match counters.get(name) {
+        Some(PerfDataValue::Long(value)) => Some(*value as f64),
+        _ => None,
+    };
+    // Sum the counters of all indexed components, such as sun.gc.collector.<N>.time
+    let sum = |prefix: &str, suffix: &str| {
+        let values: Vec<f64> = counters
+            .iter()
+            .filter_map(|(name, value)| match value {
+                PerfDataValue::Long(value)
+                    if name
+                        .strip_prefix(prefix)
+                        .and_then(|name| name.strip_suffix(suffix))
+                        .is_some_and(|index| index.parse::<u32>().is_ok()) =>
+                {
+                    Some(*value as f64)
+                }
+                _ => None,
+            })
+            .collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f64>())
+    };
+    // The times are counted in the ticks of the high-resolution timer
+    let ticks_to_ms = |ticks: Option<f64>| {
+        long("sun.os.hrt.frequency")
+            .filter(|frequency| *frequency > 0.0)
+            .zip(ticks)
+            .map(|(frequency, ticks)| ticks * 1000.0 / frequency)
+    };
+
+    let young_gen_used = sum("sun.gc.generation.0.space.", ".used");
+    let old_gen_used = sum("sun.gc.generation.1.space.", ".used");
+    let heap_used = match (young_gen_used, old_gen_used) {
+        (None, None) => None,
+        (young, old) => Some(young.unwrap_or(0.0) + old.unwrap_or(0.0)),
+    };
+    let loaded_classes = long("java.cls.loadedClasses").map(|loaded| {
+        loaded + long("java.cls.sharedLoadedClasses").unwrap_or(0.0)
+            - long("java.cls.unloadedClasses").unwrap_or(0.0)
+            - long("java.cls.sharedUnloadedClasses").unwrap_or(0.0)
+    });
+    let class_loads = long("java.cls.loadedClasses")
+        .map(|loaded| loaded + long("java.cls.sharedLoadedClasses").unwrap_or(0.0));
+
+    [
+        ("gc_count", sum("sun.gc.collector.", ".invocations")),
+        ("gc_time", ticks_to_ms(sum("sun.gc.collector.", ".time"))),
+        ("full_gc_count", long("sun.gc.collector.1.invocations")),
+        ("full_gc_time", ticks_to_ms(long("sun.gc.collector.1.time"))),
+        ("safepoint_count", long("sun.rt.safepoints")),
+        ("safepoint_time", ticks_to_ms(long("sun.rt.safepointTime"))),
+        (
+            "safepoint_sync_time",
+            ticks_to_ms(long("sun.rt.safepointSyncTime")),
+        ),
+        ("heap_used", heap_used),
+        ("heap_committed", sum("sun.gc.generation.", ".capacity")),
+        ("young_gen_used", young_gen_used),
+        ("old_gen_used", old_gen_used),
+        (
+            "old_gen_max_capacity",
+            long("sun.gc.generation.1.maxCapacity"),
+        ),
+        ("metaspace_used", long("sun.gc.metaspace.used")),
+        ("metaspace_committed", long("sun.gc.metaspace.capacity")),
+        ("loaded_classes", loaded_classes),
+        ("class_loads", class_loads),
+        ("jit_compiles", long("sun.ci.totalCompiles")),
+        ("jit_compile_time", ticks_to_ms(long("java.ci.totalTime"))),
+        ("live_threads", long("java.threads.live")),
+        ("daemon_threads", long("java.threads.daemon")),
+        ("thread_starts", long("java.threads.started")),
+    ]
+    .into_iter()
+    .filter_map(|(stat, value)| value.map(|value| (stat, value)))
+    .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JvmStatRaw {
+    // The hsperfdata files of the JVMs by their PIDs, which are rediscovered periodically during
+    // the collection, so they need to skip serialization
+    #[serde(skip)]
+    pub hsperfdata_paths: HashMap<String, PathBuf>,
+    #[serde(skip)]
+    pub last_discovery: Option<Instant>,
+    pub time: TimeEnum,
+    pub data: String,
+}
+
+#[cfg(target_os = "linux")]
+impl JvmStatRaw {
+    pub fn new() -> Self {
+        JvmStatRaw {
+            hsperfdata_paths: HashMap::new(),
+            last_discovery: None,
+            time: TimeEnum::DateTime(Utc::now()),
+            data: String::new(),
+        }
+    }
+
+    /// Discover the JVMs with an hsperfdata file, unless they were discovered less than
+    /// JVM_DISCOVERY_INTERVAL ago.
+    fn update_hsperfdata_paths(&mut self) {
+        if self
+            .last_discovery
+            .is_some_and(|last_discovery| last_discovery.elapsed() < JVM_DISCOVERY_INTERVAL)
+        {
+            return;
+        }
+        self.hsperfdata_paths = discover_hsperfdata_files();
+        self.last_discovery = Some(Instant::now());
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CollectData for JvmStatRaw {
+    fn prepare_data_collector(&mut self, _init_params: &InitParams) -> Result<()> {
+        self.update_hsperfdata_paths();
+        if self.hsperfdata_paths.is_empty() {
+            return Err(PDError::IgnoredDataPreparationError(
+                "No JVM with hsperfdata available".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn collect_data(&mut self, _init_params: &InitParams) -> Result<()> {
+        self.time = TimeEnum::DateTime(Utc::now());
+        self.update_hsperfdata_paths();
+
+        let mut common_raw_data_builder = TimeSeriesDataBuilder::new();
+        let mut exited_jvms = Vec::new();
+        for (pid, hsperfdata_path) in &self.hsperfdata_paths {
+            // The JVM may exit at any time, removing its hsperfdata file
+            let counters = match fs::read(hsperfdata_path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| parse_hsperfdata(&data))
+            {
+                Ok(counters) => counters,
+                Err(e) => {
+                    debug!("Failed to read the hsperfdata of JVM {}: {}", pid, e);
+                    exited_jvms.push(pid.clone());
+                    continue;
+                }
+            };
+            // A JVM is named after its main class or JAR file and its PID
+            let jvm = format!(
+                "{} ({})",
+                hsperfdata_jvm_name(&counters).unwrap_or_else(|| format!("Unknown_JVM_{pid}")),
+                pid
+            );
+            common_raw_data_builder.add_component_line(&jvm);
+            for (stat, value) in jvm_stat_values(&counters) {
+                common_raw_data_builder.add_metric_line(&stat.to_string(), &value.to_string());
+            }
+        }
+        self.data = common_raw_data_builder.get_data();
+        for pid in exited_jvms {
+            self.hsperfdata_paths.remove(&pid);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JvmStat;
+
+impl JvmStat {
+    pub fn new() -> Self {
+        JvmStat
+    }
+}
+
+impl ProcessData for JvmStat {
+    fn process_raw_data(
+        &mut self,
+        report_params: &ReportParams,
+        raw_data: Vec<Data>,
+    ) -> Result<AperfData> {
+        // Every series is a JVM, and the aggregate is the JVM with the highest average, so that
+        // the analytical rules find the worst JVM rather than the average of all.
+        let mut time_series_data_processor =
+            time_series_data_processor_with_max_series_aggregate!(report_params.collection_start);
+
+        for buffer in raw_data {
+            let raw_value = match buffer {
+                Data::JvmStatRaw(ref value) => value,
+                _ => panic!("Invalid Data type in raw file"),
+            };
+            time_series_data_processor.proceed_to_time(raw_value.time);
+
+            let jvm_data = parse_common_raw_time_series_data(&raw_value.data);
+            for (stat, per_jvm_value) in &jvm_data {
+                for (jvm, value) in per_jvm_value {
+                    if !CUMULATIVE_STATS.contains(&stat.as_str()) {
+                        time_series_data_processor.add_data_point(stat, jvm, *value);
+                        continue;
+                    }
+                    let Some(rate) =
+                        time_series_data_processor.add_accumulative_data_point(stat, jvm, *value)
+                    else {
+                        continue;
+                    };
+                    // The GC time in ms per second is the share of time in GC pauses per mille
+                    if stat == "gc_time" {
+                        time_series_data_processor.add_data_point(
+                            "gc_time_percent",
+                            jvm,
+                            (rate / 10.0).min(100.0),
+                        );
+                    }
+                }
+            }
+
+            if let (Some(old_gen_used), Some(old_gen_max_capacity)) = (
+                jvm_data.get("old_gen_used"),
+                jvm_data.get("old_gen_max_capacity"),
+            ) {
+                for (jvm, used) in old_gen_used {
+                    if let Some(max_capacity) =
+                        old_gen_max_capacity.get(jvm).filter(|max| **max > 0.0)
+                    {
+                        time_series_data_processor.add_data_point(
+                            "old_gen_used_percent",
+                            jvm,
+                            used / max_capacity * 100.0,
+                        );
+                    }
+                }
+            }
+        }
+
+        let time_series_data = time_series_data_processor
+            .get_time_series_data_with_metric_name_order(vec![
+                "gc_time_percent",
+                "gc_count",
+                "gc_time",
+                "full_gc_count",
+                "full_gc_time",
+                "heap_used",
+                "heap_committed",
+                "young_gen_used",
+                "old_gen_used",
+                "old_gen_used_percent",
+                "old_gen_max_capacity",
+                "metaspace_used",
+                "metaspace_committed",
+                "safepoint_count",
+                "safepoint_time",
+                "safepoint_sync_time",
+                "loaded_classes",
+                "class_loads",
+                "jit_compiles",
+                "jit_compile_time",
+                "live_threads",
+                "daemon_threads",
+                "thread_starts",
+            ]);
+
+        Ok(AperfData::TimeSeries(time_series_data))
+    }
+}
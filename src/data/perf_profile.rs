@@ -12,20 +12,25 @@ use std::path::PathBuf;
 #[cfg(target_os = "linux")]
 use {
     crate::data::common::utils::get_sub_process_duration_seconds,
-    crate::data::CollectData,
+    crate::data::{CollectData, TimeEnum},
     crate::data_collection::InitParams,
-    crate::profiling::perf::parser::build_perf_profiler_data,
+    crate::profiling::export::write_flamegraph_svg,
+    crate::profiling::perf::parser::{build_perf_profiler_data, perf_data_maps_jitdump},
+    crate::profiling::perf::perf_event_with_period,
+    crate::profiling::symbols::symbol_bundle::{create_symbol_bundle, SYMBOL_BUNDLE_DIR_NAME},
     crate::PDError,
     crate::{run_command, run_command_and_wait},
     chrono::Utc,
-    inferno::collapse::{perf::Folder, Collapse},
-    inferno::flamegraph::{self, Direction, Options},
     log::{debug, error, warn},
     nix::{sys::signal, unistd, unistd::Pid},
+    regex::Regex,
+    std::collections::HashMap,
     std::fs::File,
     std::io::Write,
+    std::path::Path,
     std::process::Stdio,
     std::str::FromStr,
+    std::time::{Duration, UNIX_EPOCH},
     std::{process::Child, sync::Mutex},
 };
 
@@ -36,6 +41,7 @@ use {
 pub struct FlamegraphRaw {
     pub data: String,
 }
+
 #[cfg(target_os = "linux")]
 impl FlamegraphRaw {
     pub fn new() -> Self {
@@ -54,6 +60,7 @@ impl CollectData for FlamegraphRaw {
 #[cfg(target_os = "linux")]
 lazy_static! {
     pub static ref PERF_CHILD: Mutex<Option<Child>> = Mutex::new(None);
+    pub static ref PERF_OFF_CPU_CHILD: Mutex<Option<Child>> = Mutex::new(None);
     pub static ref PROFILE_START_TIME_MS: Mutex<i64> = Mutex::new(0);
 }
 
@@ -65,10 +72,157 @@ fn raw_perf_on_cpu_profile_path(run_data_dir: &PathBuf) -> PathBuf {
     raw_perf_profile_path(run_data_dir, "cpu")
 }
 
+fn raw_perf_off_cpu_profile_path(run_data_dir: &PathBuf) -> PathBuf {
+    raw_perf_profile_path(run_data_dir, "offcpu")
+}
+
+/// The key of the Profiler with the samples of the whole system. The Profilers of processes and
+/// CPUs are keyed by their names.
+pub const SYSTEM_PROFILER_KEY: &str = "cpu";
+
 fn perf_profiler_data_path(run_data_dir: &PathBuf) -> PathBuf {
     run_data_dir.join("perf_profiler_data.json")
 }
 
+/// Write the Profilers of perf_profile into the run directory, replacing any existing ones.
+pub(crate) fn save_perf_profiler_data(
+    run_data_dir: &PathBuf,
+    profiling_data: &ProfilingData,
+) -> Result<()> {
+    fs::write(
+        perf_profiler_data_path(run_data_dir),
+        serde_json::to_string(profiling_data)?,
+    )?;
+    Ok(())
+}
+
+/// Read the Profilers of perf_profile from the run directory. Older versions saved the single
+/// Profiler of the whole system.
+fn read_perf_profiler_data(run_data_dir: &PathBuf) -> Option<ProfilingData> {
+    let json = fs::read_to_string(perf_profiler_data_path(run_data_dir)).ok()?;
+    serde_json::from_str::<ProfilingData>(&json)
+        .ok()
+        .or_else(|| {
+            let profiler = serde_json::from_str::<Profiler>(&json).ok()?;
+            let mut profiling_data = ProfilingData::default();
+            profiling_data
+                .profilers
+                .insert(SYSTEM_PROFILER_KEY.to_string(), profiler);
+            Some(profiling_data)
+        })
+}
+
+#[cfg(target_os = "linux")]
+fn symbol_bundle_path(run_data_dir: &Path) -> PathBuf {
+    run_data_dir.join(SYMBOL_BUNDLE_DIR_NAME)
+}
+
+/// The JFRs recorded by java_profile in the run directory, by the PID of their JVM.
+#[cfg(target_os = "linux")]
+fn java_profile_jfr_paths(run_data_dir: &Path) -> HashMap<i32, PathBuf> {
+    let jfr_file_name = Regex::new(r"^java-profile-(\d+)\.jfr$").unwrap();
+    fs::read_dir(run_data_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let pid = jfr_file_name
+                .captures(&entry.file_name().to_string_lossy())?
+                .get(1)?
+                .as_str()
+                .parse()
+                .ok()?;
+            Some((pid, entry.path()))
+        })
+        .collect()
+}
+
+/// JIT runtimes that write jitdump files, such as the JVMs with the perf JVMTI agent or Node.js
+/// with --perf-prof, only describe their JIT-compiled code in those files. If the raw on-CPU
+/// Perf profile maps any, replace it with the output of perf inject -j, which maps the code from
+/// ELF files generated out of the jitdump files, so that the JIT-compiled frames are symbolized.
+#[cfg(target_os = "linux")]
+fn inject_jitdump(run_data_dir: &PathBuf) {
+    let perf_data_path = raw_perf_on_cpu_profile_path(run_data_dir);
+    match perf_data_maps_jitdump(&perf_data_path) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Failed to look for jitdump files in the Perf profile: {e}");
+            return;
+        }
+    }
+
+    debug!("Running Perf inject...");
+    let injected_perf_data_path = run_data_dir.join("perf.data.jit");
+    let out = run_command_and_wait(
+        "perf",
+        [
+            "inject",
+            "-j",
+            "-i",
+            &perf_data_path.to_string_lossy(),
+            "-o",
+            &injected_perf_data_path.to_string_lossy(),
+        ],
+        "perf-inject",
+        None,
+    );
+    let error = match out {
+        Ok(output) if output.status.success() => {
+            match fs::rename(&injected_perf_data_path, &perf_data_path) {
+                Ok(_) => return,
+                Err(e) => e.to_string(),
+            }
+        }
+        Ok(output) => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        Err(e) => e.to_string(),
+    };
+    warn!("Perf inject failed, so the JIT-compiled frames of the jitdump files will not be symbolized: {error}");
+    fs::remove_file(&injected_perf_data_path).ok();
+}
+
+/// Parse the raw on-CPU Perf profile, and the off-CPU one if it was recorded, into the same
+/// Profilers, resolving symbols against the symbol bundle of the run if one exists. The JFRs of
+/// the JVMs profiled by java_profile complete the Java frames of their mixed-mode Profilers.
+#[cfg(target_os = "linux")]
+fn build_run_perf_profiler_data(
+    run_data_dir: &PathBuf,
+    profile_start_time_ms: i64,
+    events_out_path: Option<&Path>,
+) -> ProfilingData {
+    let symbol_bundle_dir = symbol_bundle_path(run_data_dir);
+    let java_jfr_paths = java_profile_jfr_paths(run_data_dir);
+    let mut perf_profiler_data = build_perf_profiler_data(
+        &raw_perf_on_cpu_profile_path(run_data_dir),
+        profile_start_time_ms,
+        events_out_path,
+        Some(&symbol_bundle_dir),
+        &java_jfr_paths,
+    );
+    let off_cpu_profile_path = raw_perf_off_cpu_profile_path(run_data_dir);
+    if off_cpu_profile_path.exists() {
+        let off_cpu_profiler_data = build_perf_profiler_data(
+            &off_cpu_profile_path,
+            profile_start_time_ms,
+            None,
+            Some(&symbol_bundle_dir),
+            &java_jfr_paths,
+        );
+        for (profiler_key, off_cpu_profiler) in off_cpu_profiler_data.profilers {
+            match perf_profiler_data.profilers.get_mut(&profiler_key) {
+                Some(profiler) => profiler.profiles.extend(off_cpu_profiler.profiles),
+                None => {
+                    perf_profiler_data
+                        .profilers
+                        .insert(profiler_key, off_cpu_profiler);
+                }
+            }
+        }
+    }
+    perf_profiler_data
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PerfProfileRaw {
     pub data: String,
@@ -122,28 +276,30 @@ impl CollectData for PerfProfileRaw {
 
         *PROFILE_START_TIME_MS.lock().unwrap() = Utc::now().timestamp_millis();
 
-        match run_command(
-            "perf",
-            [
-                "record",
-                "-a",
-                "-q",
-                "-g",
-                "-k",
-                "1",
-                "-F",
-                &init_params.perf_frequency.to_string(),
-                "-e",
-                "cpu-clock:pppH",
-                "-o",
-                &raw_perf_on_cpu_profile_path(&init_params.run_data_dir).to_string_lossy(),
-                "--",
-                "sleep",
-                &get_sub_process_duration_seconds(init_params).to_string(),
-            ],
-            Stdio::null(),
-            Stdio::inherit(),
-        ) {
+        let perf_frequency = init_params.perf_frequency.to_string();
+        let perf_events = perf_events_to_sample(init_params);
+        let perf_data_path = raw_perf_on_cpu_profile_path(&init_params.run_data_dir);
+        let perf_data_path = perf_data_path.to_string_lossy();
+        let duration = get_sub_process_duration_seconds(init_params).to_string();
+        let mut perf_args = vec![
+            "record",
+            "-a",
+            "-q",
+            "--call-graph",
+            &init_params.perf_call_graph,
+            "-k",
+            "1",
+            "-F",
+            &perf_frequency,
+            "-e",
+            "cpu-clock:pppH",
+        ];
+        for perf_event in &perf_events {
+            perf_args.extend(["-e", perf_event]);
+        }
+        perf_args.extend(["-o", &perf_data_path, "--", "sleep", &duration]);
+
+        match run_command("perf", perf_args, Stdio::null(), Stdio::inherit()) {
             Err(e) => Err(PDError::DependencyError(format!(
                 "Skipping Perf profile collection due to: {}",
                 e
@@ -152,6 +308,9 @@ impl CollectData for PerfProfileRaw {
             Ok(child) => {
                 debug!("Recording Perf profiling data.");
                 *PERF_CHILD.lock().unwrap() = Some(child);
+                if init_params.profile_offcpu {
+                    launch_perf_off_cpu_record(init_params);
+                }
                 Ok(())
             }
         }
@@ -173,6 +332,28 @@ impl CollectData for PerfProfileRaw {
             signal::Signal::from_str(&init_params.end_signal).unwrap_or(signal::Signal::SIGTERM),
         )?;
 
+        // The off-CPU profile is optional, so failing to stop it should not affect the
+        // on-CPU profile.
+        let mut off_cpu_recorded = false;
+        if let Some(mut off_cpu_child) = PERF_OFF_CPU_CHILD.lock().unwrap().take() {
+            let _ = signal::kill(
+                Pid::from_raw(off_cpu_child.id() as i32),
+                signal::Signal::from_str(&init_params.end_signal)
+                    .unwrap_or(signal::Signal::SIGTERM),
+            );
+            debug!("Waiting for perf off-CPU profile collection to complete...");
+            match off_cpu_child.wait() {
+                Err(e) => error!(
+                    "'perf' for off-CPU profile did not exit successfully: {}",
+                    e
+                ),
+                Ok(_) => {
+                    debug!("'perf record' for off-CPU profile executed successfully.");
+                    off_cpu_recorded = true;
+                }
+            }
+        }
+
         debug!("Waiting for perf profile collection to complete...");
         match child.as_mut().unwrap().wait() {
             Err(e) => {
@@ -182,77 +363,31 @@ impl CollectData for PerfProfileRaw {
             Ok(_) => debug!("'perf record' executed successfully."),
         }
 
-        debug!("Running Perf inject...");
-        let perf_jit_loc = init_params.run_data_dir.join("perf.data.jit");
-        let out_jit = run_command_and_wait(
-            "perf",
-            [
-                "inject",
-                "-j",
-                "-i",
-                &raw_perf_on_cpu_profile_path(&init_params.run_data_dir).to_string_lossy(),
-                "-o",
-                perf_jit_loc.to_str().unwrap(),
-            ],
-            "perf-inject",
-            None,
-        );
-
-        let fg_out = File::create(init_params.run_data_dir.join("flamegraph.svg"))?;
-        let reverse_fg_out = File::create(init_params.run_data_dir.join("reverse-flamegraph.svg"))?;
+        inject_jitdump(&init_params.run_data_dir);
 
-        match out_jit {
-            Err(e) => {
-                let out = format!("Perf inject failed due to: {e}");
-                error!("{}", out);
-                write_msg_to_svg(fg_out, out)?;
+        // Capture the symbol files of the sampled ELF files while they are still present, so
+        // that the profile can be symbolized when the report is generated on another system.
+        if init_params.symbol_bundle_size_mb > 0 {
+            debug!("Creating symbol bundle...");
+            let mut perf_data_paths = vec![raw_perf_on_cpu_profile_path(&init_params.run_data_dir)];
+            if off_cpu_recorded {
+                perf_data_paths.push(raw_perf_off_cpu_profile_path(&init_params.run_data_dir));
             }
-            Ok(_) => {
-                debug!("Creating flamegraph...");
-                let script_loc = init_params.run_data_dir.join("script.out");
-                let out = run_command_and_wait(
-                    "perf",
-                    ["script", "-f", "-i", perf_jit_loc.to_str().unwrap()],
-                    "perf-script",
-                    Some(&script_loc),
-                );
-                match out {
-                    Err(e) => {
-                        let out = format!("Perf script failed due to: {}", e);
-                        error!("{}", out);
-                        write_msg_to_svg(fg_out, out)?;
-                    }
-                    Ok(_) => {
-                        let collapse_loc = init_params.run_data_dir.join("collapse.out");
-                        Folder::default().collapse_file(
-                            Some(script_loc.clone()),
-                            File::create(&collapse_loc)?,
-                        )?;
-
-                        // Generate icicle graph as default
-                        let mut reverse_options = Options::default();
-                        reverse_options.direction = Direction::Inverted;
-                        reverse_options.reverse_stack_order = false;
-                        flamegraph::from_files(
-                            &mut reverse_options,
-                            &[collapse_loc.to_path_buf()],
-                            fg_out,
-                        )?;
-
-                        // Generate reverse icicle graph
-                        reverse_options.reverse_stack_order = true;
-                        flamegraph::from_files(
-                            &mut reverse_options,
-                            &[collapse_loc.to_path_buf()],
-                            reverse_fg_out,
-                        )?;
-
-                        // Clean up intermediate files after creating flamegraphs and saving
-                        for file in [&script_loc, &perf_jit_loc, &collapse_loc] {
-                            fs::remove_file(file).ok();
-                        }
-                    }
-                }
+            let recording_start =
+                UNIX_EPOCH + Duration::from_millis(*PROFILE_START_TIME_MS.lock().unwrap() as u64);
+            match create_symbol_bundle(
+                &perf_data_paths,
+                &symbol_bundle_path(&init_params.run_data_dir),
+                init_params.symbol_bundle_size_mb * 1024 * 1024,
+                recording_start,
+            ) {
+                Ok(manifest) => debug!(
+                    "Bundled {} ELF files and {} JIT maps ({} ELF files not bundled)",
+                    manifest.elf_files.len(),
+                    manifest.jit_map_pids.len(),
+                    manifest.missing_elf_files.len()
+                ),
+                Err(e) => warn!("Failed to create symbol bundle: {e}"),
             }
         }
 
@@ -263,18 +398,32 @@ impl CollectData for PerfProfileRaw {
             None
         };
 
-        // TODO: Guard the new profile processing logic by the save_profile_events flag,
-        //       so that the new flow is only executed in tests. Remove the guardrail
-        //       after the feature is ready to launch.
-        if init_params.save_profile_events {
-            // Parse raw Perf profile and build ProfilingData
-            let perf_profiler_data = build_perf_profiler_data(
-                &raw_perf_on_cpu_profile_path(&init_params.run_data_dir),
-                *PROFILE_START_TIME_MS.lock().unwrap(),
-                events_out_path,
-            );
-            if let Ok(json) = serde_json::to_string(&perf_profiler_data) {
-                fs::write(perf_profiler_data_path(&init_params.run_data_dir), json)?;
+        // Parse raw Perf profile and build ProfilingData
+        debug!("Parsing Perf profile...");
+        let perf_profiler_data = build_run_perf_profiler_data(
+            &init_params.run_data_dir,
+            *PROFILE_START_TIME_MS.lock().unwrap(),
+            events_out_path,
+        );
+        save_perf_profiler_data(&init_params.run_data_dir, &perf_profiler_data)?;
+
+        // Render the flamegraphs of the on-CPU profile for the runs viewed with older reports.
+        debug!("Creating flamegraphs...");
+        for (filename, reverse_stack_order) in
+            [("flamegraph.svg", false), ("reverse-flamegraph.svg", true)]
+        {
+            let fg_out = File::create(init_params.run_data_dir.join(filename))?;
+            match perf_profiler_data
+                .profilers
+                .get(SYSTEM_PROFILER_KEY)
+                .and_then(|profiler| profiler.profiles.get("cpu"))
+            {
+                Some(profile) => {
+                    if let Err(e) = write_flamegraph_svg(profile, reverse_stack_order, fg_out) {
+                        error!("Failed to create {}: {}", filename, e);
+                    }
+                }
+                None => write_msg_to_svg(fg_out, "No Perf samples were recorded.".to_string())?,
             }
         }
 
@@ -286,6 +435,69 @@ impl CollectData for PerfProfileRaw {
     }
 }
 
+/// The PMU events to sample along with cpu-clock, with their sampling period. Since perf record
+/// fails as a whole if any of its events cannot be opened, the events that cannot be counted on
+/// this system (e.g. hardware events in most VMs) are skipped.
+#[cfg(target_os = "linux")]
+fn perf_events_to_sample(init_params: &InitParams) -> Vec<String> {
+    init_params
+        .perf_events
+        .iter()
+        .filter(|perf_event| {
+            let supported = run_command_and_wait(
+                "perf",
+                ["stat", "-x", ",", "-e", perf_event, "--", "true"],
+                "perf",
+                None,
+            )
+            .is_ok_and(|output| {
+                output.status.success()
+                    && !String::from_utf8_lossy(&output.stderr).contains("<not supported>")
+            });
+            if !supported {
+                warn!("Skipping the sampling of PMU event {perf_event}, which is not supported on this system.");
+            }
+            supported
+        })
+        .map(|perf_event| perf_event_with_period(perf_event, init_params.perf_event_period))
+        .collect()
+}
+
+/// Launch a separate Perf record of the scheduler tracepoints for off-CPU profiling. Every
+/// event needs to be recorded (-c 1) to account for all off-CPU time, so it is not combined
+/// with the sampled cpu-clock event.
+#[cfg(target_os = "linux")]
+fn launch_perf_off_cpu_record(init_params: &InitParams) {
+    match run_command(
+        "perf",
+        [
+            "record",
+            "-a",
+            "-q",
+            "-g",
+            "-c",
+            "1",
+            "-e",
+            "sched:sched_switch",
+            "-e",
+            "sched:sched_wakeup",
+            "-o",
+            &raw_perf_off_cpu_profile_path(&init_params.run_data_dir).to_string_lossy(),
+            "--",
+            "sleep",
+            &get_sub_process_duration_seconds(init_params).to_string(),
+        ],
+        Stdio::null(),
+        Stdio::inherit(),
+    ) {
+        Err(e) => warn!("Skipping Perf off-CPU profile collection due to: {}", e),
+        Ok(child) => {
+            debug!("Recording Perf off-CPU profiling data.");
+            *PERF_OFF_CPU_CHILD.lock().unwrap() = Some(child);
+        }
+    }
+}
+
 #[cfg(target_os = "linux")]
 fn write_msg_to_svg(mut file: File, msg: String) -> Result<()> {
     write!(
@@ -296,6 +508,33 @@ fn write_msg_to_svg(mut file: File, msg: String) -> Result<()> {
     Ok(())
 }
 
+/// Symbolize the raw Perf profile of the run when generating the report, which is only done
+/// for runs that captured a symbol bundle, so that the result does not depend on the ELF files
+/// present on the system generating the report.
+#[cfg(target_os = "linux")]
+fn build_report_time_perf_profiler_data(report_params: &ReportParams) -> Option<ProfilingData> {
+    let run_data_dir = &report_params.run_data_dir;
+    if !symbol_bundle_path(run_data_dir).exists()
+        || !raw_perf_on_cpu_profile_path(run_data_dir).exists()
+    {
+        return None;
+    }
+    let profile_start_time_ms = match &report_params.collection_start {
+        Some(TimeEnum::DateTime(collection_start)) => collection_start.timestamp_millis(),
+        _ => 0,
+    };
+    Some(build_run_perf_profiler_data(
+        run_data_dir,
+        profile_start_time_ms,
+        None,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn build_report_time_perf_profiler_data(_report_params: &ReportParams) -> Option<ProfilingData> {
+    None
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PerfProfile;
 
@@ -311,8 +550,8 @@ impl ProcessData for PerfProfile {
         report_params: &ReportParams,
         _raw_data: Vec<Data>,
     ) -> Result<AperfData> {
-        // Still attempt to process perf script + inferno generated flamegraphs for
-        // backward compatibility
+        // Still attempt to process the flamegraph SVGs for backward compatibility, which
+        // older versions generated through perf script
         let mut graph_data = GraphData::default();
         graph_data.graph_groups.push(GraphGroup::new("default"));
         graph_data.graph_groups.push(GraphGroup::new("reverse"));
@@ -345,23 +584,15 @@ impl ProcessData for PerfProfile {
             }
         });
 
-        // Deserialize the ProfilerData generated at the end of record.
-        let perf_profiler_data =
-            match fs::read_to_string(perf_profiler_data_path(&report_params.run_data_dir))
-                .ok()
-                .and_then(|json| serde_json::from_str::<Profiler>(&json).ok())
-            {
-                Some(perf_profiler_data) => perf_profiler_data,
-                // If ProfilerData could not be read, chaces are this run was created before the
-                // introduction of ProfilingData, so fall back to the old GraphData.
-                None => return Ok(AperfData::Graph(graph_data)),
-            };
-
-        let mut profiling_data = ProfilingData::default();
-        profiling_data
-            .profilers
-            .insert(String::from("cpu"), perf_profiler_data);
-
-        Ok(AperfData::Profile(profiling_data))
+        // Deserialize the ProfilerData generated at the end of record. If it was not generated,
+        // but the run has a symbol bundle, parse the raw Perf profile now using the bundle.
+        match read_perf_profiler_data(&report_params.run_data_dir)
+            .or_else(|| build_report_time_perf_profiler_data(report_params))
+        {
+            Some(profiling_data) => Ok(AperfData::Profile(profiling_data)),
+            // If ProfilerData could not be read, chaces are this run was created before the
+            // introduction of ProfilingData, so fall back to the old GraphData.
+            None => Ok(AperfData::Graph(graph_data)),
+        }
     }
 }
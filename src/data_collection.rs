@@ -1,3 +1,5 @@
+use crate::data::ext_profile::ExtProfilerConfig;
+use crate::data::java_profile::AsprofOptions;
 use crate::data::TimeEnum;
 use crate::PDError;
 use crate::{APERF_TMP, GROUPED_PMU_MODE};
@@ -7,15 +9,22 @@ use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 use std::time::Instant;
 #[cfg(target_os = "linux")]
 use {
+    crate::data::common::raw_data_file::{
+        write_raw_data_file_header, write_raw_data_record, RawDataFileHeader,
+    },
     crate::data::processes::ProcessesRaw,
     crate::data::Data,
     crate::sub_process_pids,
     crate::{aperf_runlog_file_path, data_file_path, get_data_name_from_type},
-    crate::{aperf_stats_add, aperf_stats_measure, aperf_stats_proceed_to_next_stats},
+    crate::{
+        aperf_stats_add, aperf_stats_checkpoint, aperf_stats_measure,
+        aperf_stats_proceed_to_next_stats,
+    },
     nix::poll::{poll, PollFd, PollFlags, PollTimeout},
     nix::sys::{
         signal,
@@ -143,6 +152,8 @@ impl DataCollectionEngine {
         let mut end_signal = String::new();
 
         let mut current_time = start_time;
+        let checkpoint_interval = time::Duration::from_secs(self.init_params.checkpoint_interval);
+        let mut last_checkpoint_time: Option<time::Instant> = None;
 
         while current_time <= end_time {
             if poll(&mut poll_fds, PollTimeout::NONE)? <= 0 {
@@ -176,6 +187,16 @@ impl DataCollectionEngine {
                     );
                     debug!("Collection time: {:?}", cur_collection_time);
 
+                    // Checkpoint after the first collection, so that an interrupted run has
+                    // its metadata as early as possible.
+                    if last_checkpoint_time.is_none()
+                        || (!checkpoint_interval.is_zero()
+                            && last_checkpoint_time.unwrap().elapsed() >= checkpoint_interval)
+                    {
+                        self.checkpoint();
+                        last_checkpoint_time = Some(time::Instant::now());
+                    }
+
                     current_time = cur_collection_end;
                 }
             }
@@ -206,8 +227,35 @@ impl DataCollectionEngine {
         Ok(())
     }
 
-    pub fn finish_data_collection(&mut self) -> Result<()> {
+    /// Syncs the data files to disk and saves the metadata of the collection so far, so that
+    /// the run can be recovered if the collection is killed or the host reboots.
+    fn checkpoint(&mut self) {
         for data_collector in self.data_collectors.values_mut() {
+            if let Err(e) = data_collector.sync() {
+                error!("Failed to sync {} data: {e}", data_collector.data_name);
+            }
+        }
+        if let Err(e) = aperf_stats_checkpoint() {
+            error!("Failed to checkpoint APerf stats: {e}");
+        }
+
+        let mut metadata = self.init_params.clone();
+        metadata.checkpoint = true;
+        metadata.collection_end = Some(TimeEnum::DateTime(Utc::now()));
+        metadata.sub_process_pids = sub_process_pids();
+        if let Err(e) = metadata.save_to_json() {
+            error!("Failed to checkpoint run metadata: {e}");
+        }
+        debug!("Checkpointed the collection");
+    }
+
+    pub fn finish_data_collection(&mut self) -> Result<()> {
+        // Finish the Perf profile last, so that the JFRs of java_profile are in the run
+        // directory when building the mixed-mode Java profiles.
+        let mut data_collectors: Vec<&mut DataCollector> =
+            self.data_collectors.values_mut().collect();
+        data_collectors.sort_by_key(|data_collector| data_collector.is_perf_profile());
+        for data_collector in data_collectors {
             data_collector.finish_data_collection(&self.init_params)?;
         }
 
@@ -253,7 +301,7 @@ pub struct DataCollector {
 impl DataCollector {
     pub fn new(data_name: &'static str, data: Data, run_data_dir: &PathBuf) -> Self {
         let data_file_path = data_file_path(data_name, run_data_dir);
-        let data_file = match OpenOptions::new()
+        let mut data_file = match OpenOptions::new()
             .read(true)
             .create(true)
             .append(true)
@@ -266,6 +314,14 @@ impl DataCollector {
                 e
             ),
         };
+        let header = RawDataFileHeader::new(data_name, data.schema_version());
+        if let Err(e) = write_raw_data_file_header(&mut data_file, &header) {
+            panic!(
+                "Failed to write the header of data file at {}: {:?}",
+                data_file_path.display(),
+                e
+            );
+        }
 
         DataCollector {
             data_name,
@@ -282,6 +338,10 @@ impl DataCollector {
         self.data.is_profile()
     }
 
+    pub fn is_perf_profile(&self) -> bool {
+        self.data.is_perf_profile()
+    }
+
     pub fn prepare_data_collector(&mut self, init_params: &InitParams) -> Result<()> {
         aperf_stats_measure(
             "prepare".to_string(),
@@ -325,13 +385,19 @@ impl DataCollector {
             self.data_name.to_string(),
             operation_name,
             || -> Result<()> {
-                bincode::serialize_into(&mut self.data_file, &self.data)?;
+                write_raw_data_record(&mut self.data_file, &self.data.serialize_record()?)?;
                 Ok(())
             },
         )?;
         Ok(())
     }
 
+    /// Syncs the written records to disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.data_file.sync_data()?;
+        Ok(())
+    }
+
     pub fn finish_data_collection(&mut self, init_params: &InitParams) -> Result<()> {
         aperf_stats_measure(
             "finish".to_string(),
@@ -359,6 +425,17 @@ pub struct InitParams {
     #[serde(default)]
     pub pmu_counter_mode: String,
     pub interval: u64,
+    /// Interval (in seconds) at which the metadata is checkpointed and the data files are
+    /// synced to disk during the collection. 0 means only at the end of the collection.
+    #[serde(default)]
+    pub checkpoint_interval: u64,
+    /// Whether the metadata is a checkpoint of a collection in progress. The run of a checkpoint
+    /// was interrupted before the collection finished, and can be finalized by aperf recover.
+    #[serde(default)]
+    pub checkpoint: bool,
+    /// Whether the run was finalized by aperf recover after the collection was interrupted.
+    #[serde(default)]
+    pub recovered: bool,
     /// The version of APerf that performed the collection.
     pub collector_version: String,
     /// The short commit SHA of APerf that performed the collection.
@@ -366,6 +443,35 @@ pub struct InitParams {
     pub tmp_dir: PathBuf,
     pub runlog: PathBuf,
     pub perf_frequency: u32,
+    /// Whether the Perf profile is collected, into which the samples of the JVMs profiled by
+    /// java_profile are merged.
+    #[serde(default)]
+    pub perf_profile: bool,
+    /// The call graph mode of the Perf profile, "fp" or "dwarf".
+    pub perf_call_graph: String,
+    /// Whether to record an off-CPU profile from scheduler tracepoints along with the
+    /// Perf profile.
+    pub profile_offcpu: bool,
+    /// The PMU events sampled by the Perf profile along with cpu-clock, each of which is a
+    /// profile type of the Perf profile.
+    #[serde(default)]
+    pub perf_events: Vec<String>,
+    /// The number of occurrences of a PMU event in perf_events between two samples.
+    #[serde(default)]
+    pub perf_event_period: u64,
+    /// The size budget (in MB) of the symbol files copied into the run at the end of the Perf
+    /// profile collection. 0 disables the symbol bundle.
+    pub symbol_bundle_size_mb: u64,
+    /// The async-profiler options used to profile the JVMs.
+    #[serde(default)]
+    pub asprof_options: AsprofOptions,
+    /// The async-profiler options of the JVMs with the given names or PIDs, which replace
+    /// asprof_options for those JVMs.
+    #[serde(default)]
+    pub jvm_asprof_options: HashMap<String, AsprofOptions>,
+    /// The external profilers run along with the collection.
+    #[serde(default)]
+    pub ext_profilers: Vec<ExtProfilerConfig>,
     pub save_profile_events: bool,
     pub hotline_frequency: u32,
     pub num_to_report: u32,
@@ -407,11 +513,23 @@ impl InitParams {
             pmu_config: Option::None,
             pmu_counter_mode: GROUPED_PMU_MODE.to_string(),
             interval: 0,
+            checkpoint_interval: 0,
+            checkpoint: false,
+            recovered: false,
             collector_version: env!("CARGO_PKG_VERSION").to_string(),
             collector_commit_sha: env!("VERGEN_GIT_SHA").to_string(),
             tmp_dir: PathBuf::from(APERF_TMP),
             runlog: PathBuf::new(),
             perf_frequency: 99,
+            perf_profile: false,
+            perf_call_graph: String::from("fp"),
+            profile_offcpu: false,
+            perf_events: Vec::new(),
+            perf_event_period: 0,
+            symbol_bundle_size_mb: 0,
+            asprof_options: AsprofOptions::default(),
+            jvm_asprof_options: HashMap::new(),
+            ext_profilers: Vec::new(),
             save_profile_events: false,
             hotline_frequency: 1000,
             num_to_report: 5000,
@@ -425,11 +543,15 @@ impl InitParams {
         }
     }
 
+    /// Writes the metadata to a temporary file and renames it, so that a collection killed
+    /// while saving a checkpoint still leaves the previous metadata.
     pub fn save_to_json(&self) -> Result<()> {
-        fs::write(
-            self.run_data_dir.join(Self::json_file_name()),
-            serde_json::to_string(self)?,
-        )?;
+        let json_path = self.run_data_dir.join(Self::json_file_name());
+        let tmp_json_path = json_path.with_extension("json.tmp");
+        let mut tmp_json_file = fs::File::create(&tmp_json_path)?;
+        tmp_json_file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        tmp_json_file.sync_all()?;
+        fs::rename(&tmp_json_path, &json_path)?;
 
         Ok(())
     }
@@ -456,6 +578,7 @@ mod tests {
     #[cfg(target_os = "linux")]
     use {
         super::{DataCollectionEngine, DataCollector, InitParams},
+        crate::data::common::raw_data_file::RawDataFile,
         crate::data::cpu_utilization::CpuUtilizationRaw,
         crate::data::Data,
         crate::data_file_path,
@@ -514,22 +637,24 @@ mod tests {
 
         dc.write_to_file().unwrap();
 
+        dc.write_to_file().unwrap();
+
         // Re-open the file to read back what was serialized (the collector's own handle is in
         // append mode).
-        let read_handle = std::fs::File::open(&data_file_path).unwrap();
-        loop {
-            match bincode::deserialize_from::<_, Data>(&read_handle) {
-                Ok(v) => match v {
-                    Data::CpuUtilizationRaw(ref value) => assert!(value.data.is_empty()),
-                    _ => unreachable!(),
-                },
-                Err(e) => match *e {
-                    bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                        break
-                    }
-                    _ => unreachable!(),
-                },
-            };
+        let mut raw_data_file = RawDataFile::open(&data_file_path).unwrap();
+        let header = raw_data_file.header.as_ref().unwrap();
+        assert_eq!(header.data_name, "cpu_utilization");
+        assert_eq!(header.schema_version, 1);
+        let decoded = raw_data_file.decode_records(|header, record| {
+            Data::deserialize_record(&header.data_name, header.schema_version, record)
+        });
+        assert!(decoded.errors.is_empty());
+        assert_eq!(decoded.records.len(), 2);
+        for record in decoded.records {
+            match record {
+                Data::CpuUtilizationRaw(ref value) => assert!(value.data.is_empty()),
+                _ => unreachable!(),
+            }
         }
     }
 }
@@ -5,15 +5,16 @@ use crate::data::common::data_formats::{
     AperfData, DataFormat, ProcessedData, Series, TimeSeriesMetric,
 };
 use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
+use crate::data::common::raw_data_file::{RawDataFile, RawRecordError};
 use crate::data::common::utils::{combine_value_ranges, topological_sort};
 use crate::data::processes::Processes;
 use crate::data::TimeEnum;
+use crate::profiling::transform::ProfileTransforms;
 use crate::{data::Data, data::ReportData};
 use crate::{get_data_name_from_type, ProcessMetric};
 use anyhow::{bail, Result};
 use log::{debug, error, info};
 use std::collections::{BTreeMap, HashMap};
-use std::io::{Seek, SeekFrom};
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -34,6 +35,8 @@ pub struct ReportParams {
     pub aperf_process_pids: Vec<u32>,
     /// System page size in bytes at collection time
     pub page_size: u64,
+    /// The transforms applied to the stacks of the profiles of the run.
+    pub profile_transforms: ProfileTransforms,
 }
 
 impl ReportParams {
@@ -47,6 +50,7 @@ impl ReportParams {
             pmu_counter_mode: String::new(),
             aperf_process_pids: Vec::new(),
             page_size: 0,
+            profile_transforms: ProfileTransforms::default(),
         }
     }
 }
@@ -332,7 +336,14 @@ impl DataProcessor {
             None => return Ok(()),
         };
 
-        let processed_data = self.data.process_raw_data(report_params, raw_data)?;
+        let mut processed_data = self.data.process_raw_data(report_params, raw_data)?;
+        if let AperfData::Profile(profiling_data) = &mut processed_data {
+            if !report_params.profile_transforms.is_empty() {
+                for profiler in profiling_data.profilers.values_mut() {
+                    profiler.apply_transforms(&report_params.profile_transforms)?;
+                }
+            }
+        }
         self.processed_data.data_format = processed_data.get_format_name();
         self.processed_data
             .runs
@@ -353,53 +364,54 @@ impl DataProcessor {
             return Some(raw_data);
         }
 
-        let (mut raw_data_file, raw_data_file_path) =
-            match self.data.get_raw_data_file(&report_params.run_data_dir) {
-                Ok(raw_data_file) => raw_data_file,
-                Err(e) => {
-                    debug!(
-                        "Raw {} data unavailable in run {}: {e}",
-                        self.data_name, report_params.run_name
-                    );
-                    return None;
-                }
-            };
-
-        if let Err(e) = raw_data_file.seek(SeekFrom::Start(0)) {
-            error!(
-                "Failed to reset seek position to zero for raw data file {}: {e}",
-                raw_data_file_path.display()
-            );
-            return None;
-        }
+        let (_, raw_data_file_path) = match self.data.get_raw_data_file(&report_params.run_data_dir)
+        {
+            Ok(raw_data_file) => raw_data_file,
+            Err(e) => {
+                debug!(
+                    "Raw {} data unavailable in run {}: {e}",
+                    self.data_name, report_params.run_name
+                );
+                return None;
+            }
+        };
 
-        loop {
-            match bincode::deserialize_from::<_, Data>(&raw_data_file) {
-                Ok(v) => raw_data.push(v),
-                Err(e) => match *e {
-                    // EOF
-                    bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                        break
-                    }
-                    // Ignore invalid enum variant errors, raw data wont be used by self.data
-                    bincode::ErrorKind::Custom(ref msg)
-                        if msg.contains("expected variant index") =>
-                    {
-                        break
-                    }
-                    e => {
-                        error!(
-                            "Error when deserializing raw {} data for run {} at {}: {}",
-                            self.data_name,
-                            report_params.run_name,
-                            raw_data_file_path.display(),
-                            e
-                        );
-                        break;
-                    }
-                },
-            };
+        let mut raw_data_file = match RawDataFile::open(&raw_data_file_path) {
+            Ok(raw_data_file) => raw_data_file,
+            Err(e) => {
+                error!(
+                    "Failed to read raw data file {}: {e}",
+                    raw_data_file_path.display()
+                );
+                return None;
+            }
+        };
+        let decoded = raw_data_file.decode_records(|header, record| {
+            Data::deserialize_record(&header.data_name, header.schema_version, record)
+        });
+        for e in decoded.errors {
+            match e {
+                // The last record of a collection that was killed
+                RawRecordError::Truncated { .. } => debug!(
+                    "Ignoring the {e} in raw {} data for run {} at {}",
+                    self.data_name,
+                    report_params.run_name,
+                    raw_data_file_path.display()
+                ),
+                // Ignore invalid enum variant errors of the legacy format, raw data wont be
+                // used by self.data
+                RawRecordError::Undecodable { ref error, .. }
+                    if raw_data_file.header.is_none()
+                        && error.contains("expected variant index") => {}
+                RawRecordError::Undecodable { .. } => error!(
+                    "Error when deserializing raw {} data for run {} at {}: {e}",
+                    self.data_name,
+                    report_params.run_name,
+                    raw_data_file_path.display()
+                ),
+            }
         }
+        raw_data.extend(decoded.records);
 
         Some(raw_data)
     }
@@ -0,0 +1,319 @@
+use crate::analytics::{DataFindings, BASE_RUN_NAME};
+use crate::data;
+use crate::data::common::data_formats::{AperfData, ProcessedData};
+use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
+use crate::data_processing::DataProcessingEngine;
+use crate::report::{parse_time_range, RunsInfo};
+use crate::PDError;
+use anyhow::Result;
+use chrono::Utc;
+use clap::{builder::PossibleValuesParser, Args};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The possible formats of the exported tables.
+pub const EXPORT_FORMATS: [&str; 2] = ["csv", "jsonl"];
+
+const TIME_SERIES_COLUMNS: [&str; 6] = ["run", "data", "metric", "series", "time", "value"];
+const KEY_VALUE_COLUMNS: [&str; 5] = ["run", "data", "group", "key", "value"];
+const TEXT_COLUMNS: [&str; 4] = ["run", "data", "line_number", "line"];
+
+#[derive(Clone, Args, Debug)]
+pub struct Export {
+    /// The paths to the directories or archives of the recorded runs, or of the reports whose
+    /// runs are all exported.
+    #[clap(help_heading = "Basic Options", verbatim_doc_comment, short, long, value_parser, required = true, value_names = &["RUN_NAME> <RUN_NAME"], num_args = 1..)]
+    pub run: Vec<String>,
+
+    /// The directory to write the exported files to. Defaults to aperf_export_<TIMESTAMP>.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub output: Option<String>,
+
+    /// The format of the exported tables: CSV with a header line, or JSON lines with one
+    /// object per row. The analytical findings are always exported as JSON.
+    #[clap(
+        help_heading = "Basic Options",
+        short,
+        long,
+        value_parser = PossibleValuesParser::new(EXPORT_FORMATS),
+        default_value = "csv"
+    )]
+    pub format: String,
+
+    /// The time range of the time-series data to export and analyze, in the same format as the
+    /// --time-range option of the report command.
+    /// Example: --time-range first_run=10:60 --time-range 20:150
+    #[clap(
+        help_heading = "Basic Options",
+        verbatim_doc_comment,
+        long,
+        value_parser = parse_time_range,
+        value_name = "RUN=FROM:TO",
+        allow_hyphen_values = true,
+        num_args = 1
+    )]
+    pub time_range: Vec<(String, Option<i64>, Option<i64>)>,
+}
+
+/// Writes the rows of a table in the format of the export.
+struct TableWriter {
+    writer: BufWriter<File>,
+    columns: &'static [&'static str],
+    is_csv: bool,
+}
+
+impl TableWriter {
+    fn new(
+        output_dir: &Path,
+        name: &str,
+        format: &str,
+        columns: &'static [&'static str],
+    ) -> Result<Self> {
+        let path = output_dir.join(format!("{name}.{format}"));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let is_csv = format == "csv";
+        if is_csv {
+            writeln!(writer, "{}", columns.join(","))?;
+        }
+        debug!("Writing {:?}", path);
+        Ok(TableWriter {
+            writer,
+            columns,
+            is_csv,
+        })
+    }
+
+    fn write_row(&mut self, values: &[Value]) -> Result<()> {
+        if self.is_csv {
+            let fields: Vec<String> = values.iter().map(csv_field).collect();
+            writeln!(self.writer, "{}", fields.join(","))?;
+        } else {
+            let row: serde_json::Map<String, Value> = self
+                .columns
+                .iter()
+                .map(|column| column.to_string())
+                .zip(values.iter().cloned())
+                .collect();
+            writeln!(self.writer, "{}", Value::Object(row))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Formats a value as a CSV field, quoting the strings that contain a separator, a quote or
+/// a line break. Null values, such as the non-finite numbers, are empty fields.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+pub fn export(export: &Export, tmp_dir: &Path) -> Result<()> {
+    let output_dir = match &export.output {
+        Some(output) => PathBuf::from(output),
+        None => {
+            let time_str = Utc::now().format("%Y-%m-%d_%H_%M_%S").to_string();
+            let default_output = format!("aperf_export_{}", time_str);
+            info!("Output directory not given. Using {}", default_output);
+            PathBuf::from(default_output)
+        }
+    };
+    if output_dir.exists() {
+        return Err(PDError::ExportExists(output_dir).into());
+    }
+
+    // Legacy flamegraphs are copied into the report directory while processing the data, so
+    // point it to the temporary directory.
+    let report_dir = tmp_dir.join("export");
+    fs::create_dir_all(report_dir.join("data").join("js"))?;
+    let mut runs_info = RunsInfo::new(report_dir, tmp_dir.to_path_buf());
+    runs_info.add_runs(&export.run)?;
+    runs_info.process_per_run_time_range(&export.time_range)?;
+    {
+        let mut base_run_name = BASE_RUN_NAME.lock().unwrap();
+        *base_run_name = runs_info.run_names[0].clone();
+    }
+
+    info!("Processing collected data...");
+    let mut data_processing_engine = DataProcessingEngine::new(runs_info.per_run_report_params);
+    data::initialize_data_processing_engine(&mut data_processing_engine);
+    for run_name in &runs_info.run_names {
+        data_processing_engine.process_raw_data(run_name)?;
+    }
+    data_processing_engine.post_process_data();
+
+    let mut processed_data_accessor = ProcessedDataAccessor::from_time_ranges(
+        runs_info.per_run_from_time,
+        runs_info.per_run_to_time,
+        runs_info.per_run_start_time,
+        runs_info.per_run_end_time,
+    );
+    let analytical_findings = data_processing_engine.run_analytics(&mut processed_data_accessor);
+
+    fs::create_dir_all(&output_dir)?;
+    let mut time_series_writer = TableWriter::new(
+        &output_dir,
+        "time_series",
+        &export.format,
+        &TIME_SERIES_COLUMNS,
+    )?;
+    let mut key_value_writer = TableWriter::new(
+        &output_dir,
+        "key_values",
+        &export.format,
+        &KEY_VALUE_COLUMNS,
+    )?;
+    let mut text_writer = TableWriter::new(&output_dir, "text", &export.format, &TEXT_COLUMNS)?;
+
+    let mut data_names: Vec<&String> = data_processing_engine.all_data_names().collect();
+    data_names.sort();
+    for data_name in data_names {
+        let processed_data = match data_processing_engine.get_processed_data(data_name) {
+            Some(processed_data) => processed_data,
+            None => continue,
+        };
+        for run_name in &runs_info.run_names {
+            match processed_data.runs.get(run_name) {
+                Some(AperfData::TimeSeries(_)) => write_time_series_rows(
+                    &mut time_series_writer,
+                    &processed_data_accessor,
+                    processed_data,
+                    run_name,
+                )?,
+                Some(AperfData::KeyValue(_)) => {
+                    write_key_value_rows(&mut key_value_writer, processed_data, run_name)?
+                }
+                Some(AperfData::Text(_)) => {
+                    write_text_rows(&mut text_writer, processed_data, run_name)?
+                }
+                Some(AperfData::Profile(_)) => warn!(
+                    "Skipping the {data_name} data of run {run_name}, use export-profile to export profiles."
+                ),
+                Some(_) => debug!("Skipping the {data_name} data of run {run_name}"),
+                None => {}
+            }
+        }
+    }
+    time_series_writer.finish()?;
+    key_value_writer.finish()?;
+    text_writer.finish()?;
+
+    let findings: BTreeMap<&String, &DataFindings> = analytical_findings.iter().collect();
+    fs::write(
+        output_dir.join("findings.json"),
+        serde_json::to_string_pretty(&findings)?,
+    )?;
+
+    info!("Exported the data to {:?}", output_dir);
+    Ok(())
+}
+
+fn write_time_series_rows(
+    writer: &mut TableWriter,
+    processed_data_accessor: &ProcessedDataAccessor,
+    processed_data: &ProcessedData,
+    run_name: &str,
+) -> Result<()> {
+    let time_series_data = match processed_data.runs.get(run_name) {
+        Some(AperfData::TimeSeries(time_series_data)) => time_series_data,
+        _ => return Ok(()),
+    };
+    for metric_name in &time_series_data.sorted_metric_names {
+        let metric = match time_series_data.metrics.get(metric_name) {
+            Some(metric) => metric,
+            None => continue,
+        };
+        for series in &metric.series {
+            let (time_diff, values) =
+                processed_data_accessor.series_in_time_range(series, run_name);
+            for (time, value) in time_diff.iter().zip(values) {
+                writer.write_row(&[
+                    json!(run_name),
+                    json!(processed_data.data_name),
+                    json!(metric_name),
+                    json!(series.series_name),
+                    json!(time),
+                    // Non-finite values are serialized as null.
+                    json!(value),
+                ])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_key_value_rows(
+    writer: &mut TableWriter,
+    processed_data: &ProcessedData,
+    run_name: &str,
+) -> Result<()> {
+    let key_value_data = match processed_data.runs.get(run_name) {
+        Some(AperfData::KeyValue(key_value_data)) => key_value_data,
+        _ => return Ok(()),
+    };
+    let groups: BTreeMap<&String, BTreeMap<&String, &String>> = key_value_data
+        .key_value_groups
+        .iter()
+        .map(|(group_name, group)| (group_name, group.key_values.iter().collect()))
+        .collect();
+    for (group_name, key_values) in groups {
+        for (key, value) in key_values {
+            writer.write_row(&[
+                json!(run_name),
+                json!(processed_data.data_name),
+                json!(group_name),
+                json!(key),
+                json!(value),
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+fn write_text_rows(
+    writer: &mut TableWriter,
+    processed_data: &ProcessedData,
+    run_name: &str,
+) -> Result<()> {
+    let text_data = match processed_data.runs.get(run_name) {
+        Some(AperfData::Text(text_data)) => text_data,
+        _ => return Ok(()),
+    };
+    for (line_idx, line) in text_data.lines.iter().enumerate() {
+        writer.write_row(&[
+            json!(run_name),
+            json!(processed_data.data_name),
+            json!(line_idx + 1),
+            json!(line),
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field(&json!("cpu0")), "cpu0");
+        assert_eq!(csv_field(&json!("a,b")), "\"a,b\"");
+        assert_eq!(csv_field(&json!("say \"hi\"")), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(&json!(1.5)), "1.5");
+        assert_eq!(csv_field(&json!(f64::NAN)), "");
+    }
+}
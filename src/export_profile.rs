@@ -0,0 +1,254 @@
+use crate::data::common::data_formats::AperfData;
+use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
+use crate::data::ext_profile::ExtProfile;
+use crate::data::java_profile::JavaProfile;
+use crate::data::perf_profile::{PerfProfile, SYSTEM_PROFILER_KEY};
+use crate::data::ReportData;
+use crate::data_collection::InitParams;
+use crate::data_processing::{DataProcessor, ReportParams};
+use crate::profiling::export::{self, export_file_extension, PROFILE_EXPORT_FORMATS};
+use crate::report::{extract_archive, parse_time_range};
+use crate::{get_data_name_from_type, no_tar_gz_file_name, PDError};
+use anyhow::Result;
+use clap::{builder::PossibleValuesParser, Args};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Args, Debug)]
+pub struct ExportProfile {
+    /// The path to the directory or archive of the recorded run.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub run: String,
+
+    /// The data that contains the profile.
+    #[clap(
+        help_heading = "Basic Options",
+        long,
+        value_parser = PossibleValuesParser::new(["perf_profile", "java_profile", "ext_profile"]),
+        default_value = "perf_profile"
+    )]
+    pub data: String,
+
+    /// The profiler of the data to export, such as the name of a JVM in java_profile, of an
+    /// external profiler in ext_profile, or a process or CPU in perf_profile. Can be omitted if the data has a single profiler, and
+    /// defaults to the whole system in perf_profile.
+    #[clap(help_heading = "Basic Options", long, value_parser)]
+    pub profiler: Option<String>,
+
+    /// The profile type to export, such as cpu or offcpu for perf_profile, and cpu, alloc or
+    /// wall for java_profile.
+    #[clap(
+        help_heading = "Basic Options",
+        long,
+        value_parser,
+        default_value = "cpu"
+    )]
+    pub profile_type: String,
+
+    /// The format of the exported profile: gzipped pprof protobuf, speedscope JSON, or folded
+    /// stacks (one "frame1;frame2;... count" line per stack).
+    #[clap(
+        help_heading = "Basic Options",
+        short,
+        long,
+        value_parser = PossibleValuesParser::new(PROFILE_EXPORT_FORMATS),
+        default_value = "pprof"
+    )]
+    pub format: String,
+
+    /// The path of the exported file. Defaults to <RUN>-<PROFILER>-<PROFILE_TYPE> with the
+    /// extension of the format in the current directory.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub output: Option<String>,
+
+    /// The time range of the samples to export, in the format of FROM:TO, where FROM and TO are
+    /// in seconds from the start of the run. Either bound can be omitted or negative.
+    /// Example: --time-range 10:60
+    ///          --time-range -10:
+    #[clap(
+        help_heading = "Basic Options",
+        verbatim_doc_comment,
+        long,
+        value_parser = parse_time_range,
+        value_name = "FROM:TO",
+        allow_hyphen_values = true
+    )]
+    pub time_range: Option<(String, Option<i64>, Option<i64>)>,
+}
+
+pub fn export_profile(export_profile: &ExportProfile, tmp_dir: &Path) -> Result<()> {
+    // The extracted run and the processed data are only needed until the profile is exported.
+    let export_dir = tmp_dir.join("export_profile");
+    let result = export_profile_in(export_profile, &export_dir);
+    if export_dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&export_dir) {
+            warn!("Failed to remove {}: {e}", export_dir.display());
+        }
+    }
+    result
+}
+
+fn export_profile_in(export_profile: &ExportProfile, export_dir: &PathBuf) -> Result<()> {
+    let run_path = PathBuf::from(&export_profile.run);
+    if !run_path.exists() {
+        return Err(PDError::RunNotFound(run_path).into());
+    }
+    let run_name = no_tar_gz_file_name(&run_path).unwrap();
+    fs::create_dir_all(export_dir)?;
+    let run_dir_path = if run_path.is_dir() {
+        run_path.clone()
+    } else {
+        extract_archive(&run_path, export_dir)?
+    };
+
+    let mut per_run_from_time = HashMap::new();
+    let mut per_run_to_time = HashMap::new();
+    if let Some((time_range_run_name, from_time, to_time)) = &export_profile.time_range {
+        if !time_range_run_name.is_empty() {
+            return Err(PDError::InvalidRunTimeRangeOption(format!(
+                "The time range of a profile export cannot specify a run name ({}).",
+                time_range_run_name
+            ))
+            .into());
+        }
+        if let (Some(from_time), Some(to_time)) = (from_time, to_time) {
+            if (*from_time ^ *to_time) >= 0 && *from_time > *to_time {
+                return Err(PDError::InvalidRunTimeRangeOption(format!(
+                    "The specified from_time {} is larger than to_time {}.",
+                    from_time, to_time
+                ))
+                .into());
+            }
+        }
+        if let Some(from_time) = from_time {
+            per_run_from_time.insert(run_name.clone(), *from_time);
+        }
+        if let Some(to_time) = to_time {
+            per_run_to_time.insert(run_name.clone(), *to_time);
+        }
+    }
+
+    let mut report_params = ReportParams::new();
+    report_params.run_name = run_name.clone();
+    report_params.run_data_dir = run_dir_path.clone();
+    report_params.tmp_dir = export_dir.clone();
+    // Legacy flamegraphs are copied into the report directory while processing the data, so
+    // point it to the temporary directory.
+    report_params.report_dir = export_dir.join("report");
+    fs::create_dir_all(report_params.report_dir.join("data").join("js"))?;
+
+    let mut per_run_start_time = HashMap::new();
+    let mut per_run_end_time = HashMap::new();
+    match InitParams::from_json(&run_dir_path) {
+        Ok(meta_data) => {
+            report_params.collection_start = meta_data.collection_start;
+            if let Some(collection_start) = meta_data.collection_start {
+                per_run_start_time.insert(run_name.clone(), collection_start);
+            }
+            if let Some(collection_end) = meta_data.collection_end {
+                per_run_end_time.insert(run_name.clone(), collection_end);
+            }
+        }
+        Err(e) => warn!("Failed to parse run meta data: {e}"),
+    }
+    if export_profile.time_range.is_some() && per_run_end_time.is_empty() {
+        warn!("The run does not have its collection times, so the time range is ignored.");
+    }
+
+    let mut data_processor = match export_profile.data.as_str() {
+        "java_profile" => DataProcessor::new(
+            get_data_name_from_type::<JavaProfile>(),
+            ReportData::JavaProfile(JavaProfile::new()),
+        ),
+        "ext_profile" => DataProcessor::new(
+            get_data_name_from_type::<ExtProfile>(),
+            ReportData::ExtProfile(ExtProfile::new()),
+        ),
+        _ => DataProcessor::new(
+            get_data_name_from_type::<PerfProfile>(),
+            ReportData::PerfProfile(PerfProfile::new()),
+        ),
+    };
+    data_processor.process_raw_data(&report_params)?;
+
+    let profiling_data = match data_processor.processed_data.runs.get_mut(&run_name) {
+        Some(AperfData::Profile(profiling_data)) => profiling_data,
+        _ => {
+            return Err(PDError::ProfileNotFound(format!(
+                "run {} does not have any {} data",
+                run_name, export_profile.data
+            ))
+            .into())
+        }
+    };
+
+    let profiler_key = match &export_profile.profiler {
+        Some(profiler_key) => profiler_key.clone(),
+        None if profiling_data.profilers.len() == 1 => {
+            profiling_data.profilers.keys().next().unwrap().clone()
+        }
+        None if export_profile.data == "perf_profile"
+            && profiling_data.profilers.contains_key(SYSTEM_PROFILER_KEY) =>
+        {
+            SYSTEM_PROFILER_KEY.to_string()
+        }
+        None => {
+            let mut profiler_keys: Vec<&String> = profiling_data.profilers.keys().collect();
+            profiler_keys.sort();
+            return Err(PDError::ProfileNotFound(format!(
+                "specify one of the profilers {:?} with --profiler",
+                profiler_keys
+            ))
+            .into());
+        }
+    };
+    let profiler = profiling_data
+        .profilers
+        .get_mut(&profiler_key)
+        .ok_or_else(|| {
+            PDError::ProfileNotFound(format!(
+                "run {} does not have the profiler {}",
+                run_name, profiler_key
+            ))
+        })?;
+
+    let mut processed_data_accessor = ProcessedDataAccessor::from_time_ranges(
+        per_run_from_time,
+        per_run_to_time,
+        per_run_start_time,
+        per_run_end_time,
+    );
+    processed_data_accessor.apply_time_range_to_profiler(&run_name, &profiler_key, profiler);
+
+    let profile_name = format!("{run_name}-{profiler_key}-{}", export_profile.profile_type);
+    let exported_profile = export::export_profile(
+        profiler,
+        &export_profile.profile_type,
+        &export_profile.format,
+        &profile_name,
+    )
+    .ok_or_else(|| {
+        PDError::ProfileNotFound(format!(
+            "profiler {} does not have a {} profile",
+            profiler_key, export_profile.profile_type
+        ))
+    })??;
+
+    let output_path = match &export_profile.output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(format!(
+            "{}.{}",
+            profile_name.replace(
+                |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_',
+                "_"
+            ),
+            export_file_extension(&export_profile.format)
+        )),
+    };
+    fs::write(&output_path, exported_profile)?;
+    info!("Profile exported to {}", output_path.display());
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use crate::data::common::data_formats::{Profiler, ProfilingData};
+use crate::data::java_profile::{save_java_profiler_data, JavaProfile};
+use crate::data::perf_profile::{save_perf_profiler_data, PerfProfile, SYSTEM_PROFILER_KEY};
+use crate::data::TimeEnum;
+use crate::data_collection::InitParams;
+use crate::profiling::jfr;
+#[cfg(target_os = "linux")]
+use crate::{data::java_profile::read_jfr_metadata, profiling::perf::parser};
+use crate::{data_file_path, find_file, get_data_name_from_type, PDError};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::{ArgGroup, Args};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Clone, Args, Debug)]
+#[clap(group(ArgGroup::new("profiles").required(true).multiple(true).args(["perf", "jfr", "folded"])))]
+pub struct Import {
+    /// The directory of the run to import the profiles into. A new run is created if the
+    /// directory does not exist.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub run: String,
+
+    /// The perf.data recorded by "perf record -g", which is imported as the perf_profile of the
+    /// run. The symbols are resolved against the files on this system.
+    #[clap(help_heading = "Basic Options", long, value_parser)]
+    pub perf: Option<String>,
+
+    /// The JFR recorded by async-profiler or the JDK, which is imported as a JVM in the
+    /// java_profile of the run. Can be specified multiple times.
+    #[clap(help_heading = "Basic Options", long, value_parser)]
+    pub jfr: Vec<String>,
+
+    /// The folded stacks (one "frame1;frame2;... count" line per stack), which are imported as
+    /// the cpu profile in the perf_profile of the run.
+    #[clap(
+        help_heading = "Basic Options",
+        long,
+        value_parser,
+        conflicts_with = "perf"
+    )]
+    pub folded: Option<String>,
+}
+
+/// The modification time of a file in milliseconds since epoch, used as the end of the recording
+/// of an imported profile.
+fn file_modified_time_ms(path: &Path) -> Result<i64> {
+    Ok(fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?
+        .as_millis() as i64)
+}
+
+fn check_input_file(path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    if !path.is_file() {
+        bail!("Could not find the file to import: {}", path.display());
+    }
+    Ok(path)
+}
+
+/// The time range covered by the samples of a Profiler, in milliseconds since epoch.
+fn profiler_time_range_ms(profiler: &Profiler) -> (i64, i64) {
+    let num_blocks = profiler
+        .profiles
+        .values()
+        .map(|profile| profile.blocks.len())
+        .max()
+        .unwrap_or(0);
+    (
+        profiler.start_time_ms,
+        profiler.start_time_ms + (num_blocks as u64 * profiler.block_width_ms) as i64,
+    )
+}
+
+/// Create an empty raw data file for the data if the run does not have one, since the report
+/// only processes the data types that have a raw data file.
+fn ensure_raw_data_file(run_data_dir: &PathBuf, data_name: &str) -> Result<()> {
+    let pattern = format!("^{}(_.*)?\\.bin$", regex::escape(data_name));
+    if find_file(run_data_dir, &pattern, None).is_err() {
+        fs::write(data_file_path(data_name, run_data_dir), [])?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn build_perf_profiling_data(perf_data_path: &PathBuf) -> Result<ProfilingData> {
+    parser::build_imported_perf_profiler_data(
+        perf_data_path,
+        file_modified_time_ms(perf_data_path)?,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn build_perf_profiling_data(_perf_data_path: &PathBuf) -> Result<ProfilingData> {
+    bail!("Importing perf.data is only supported on Linux.")
+}
+
+fn build_folded_profiling_data(folded_path: &PathBuf) -> Result<ProfilingData> {
+    // Folded stacks have no timestamps, so all samples are placed at the time of the file.
+    let sample_time_ms = file_modified_time_ms(folded_path)?;
+    let mut profiler = Profiler::new(sample_time_ms);
+    profiler.insert_collapsed("cpu", sample_time_ms, &fs::read_to_string(folded_path)?)?;
+    let mut profiling_data = ProfilingData::default();
+    profiling_data
+        .profilers
+        .insert(SYSTEM_PROFILER_KEY.to_string(), profiler);
+    Ok(profiling_data)
+}
+
+fn build_jfr_profiler(jfr_path: &Path, _key: &str) -> Result<Profiler> {
+    #[allow(unused_mut)]
+    let mut profiler = jfr::build_java_profiler_data(jfr_path, None)?;
+    #[cfg(target_os = "linux")]
+    {
+        profiler.metadata = jfr::parse_jfr_metadata(&read_jfr_metadata(jfr_path, _key));
+    }
+    Ok(profiler)
+}
+
+pub fn import(import: &Import) -> Result<()> {
+    let run_data_dir = PathBuf::from(&import.run);
+    if run_data_dir.exists() && !run_data_dir.is_dir() {
+        return Err(PDError::InvalidDirectory(run_data_dir).into());
+    }
+
+    let perf_profiling_data = match (&import.perf, &import.folded) {
+        (Some(perf), _) => Some(build_perf_profiling_data(&check_input_file(perf)?)?),
+        (None, Some(folded)) => Some(build_folded_profiling_data(&check_input_file(folded)?)?),
+        (None, None) => None,
+    };
+    let mut java_profilers = Vec::new();
+    for jfr in &import.jfr {
+        let jfr_path = check_input_file(jfr)?;
+        let key = jfr_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "jfr".to_string());
+        java_profilers.push((key.clone(), build_jfr_profiler(&jfr_path, &key)?));
+    }
+
+    fs::create_dir_all(&run_data_dir)?;
+
+    let mut time_ranges = Vec::new();
+    if let Some(profiling_data) = perf_profiling_data {
+        save_perf_profiler_data(&run_data_dir, &profiling_data)?;
+        ensure_raw_data_file(&run_data_dir, get_data_name_from_type::<PerfProfile>())?;
+        time_ranges.extend(
+            profiling_data
+                .profilers
+                .values()
+                .map(profiler_time_range_ms),
+        );
+        info!("Imported the perf profile into {}", run_data_dir.display());
+    }
+    for (key, profiler) in &java_profilers {
+        let key = save_java_profiler_data(&run_data_dir, key, key, profiler)?;
+        ensure_raw_data_file(&run_data_dir, get_data_name_from_type::<JavaProfile>())?;
+        time_ranges.push(profiler_time_range_ms(profiler));
+        info!(
+            "Imported the JFR of {} into {}",
+            key,
+            run_data_dir.display()
+        );
+    }
+
+    // The collection times of the run are used to align the time ranges of the data in the
+    // report, so a run without them gets the time range of the imported profiles.
+    if InitParams::from_json(&run_data_dir).is_err() {
+        let run_name = run_data_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut init_params = InitParams::new(run_name, run_data_dir.clone());
+        init_params.pid = None;
+        let start_ms = time_ranges.iter().map(|(start, _)| *start).min();
+        let end_ms = time_ranges.iter().map(|(_, end)| *end).max();
+        init_params.collection_start = start_ms
+            .and_then(DateTime::<Utc>::from_timestamp_millis)
+            .map(TimeEnum::DateTime);
+        init_params.collection_end = end_ms
+            .and_then(DateTime::<Utc>::from_timestamp_millis)
+            .map(TimeEnum::DateTime);
+        init_params.save_to_json()?;
+    } else if !time_ranges.is_empty() {
+        warn!(
+            "The run already has its collection times, which may not cover the imported profiles."
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,559 @@
+use crate::data::aperf_stats::{AperfStat, AperfStats};
+use crate::data::common::raw_data_file::{
+    DecodedRecords, RawDataFile, RawDataFileHeader, RawDataSchema, RawRecordError,
+};
+use crate::data::Data;
+use crate::report::extract_archive;
+use crate::{find_file, get_data_name_from_type, no_tar_gz_file_name, PDError, APERF_FILE_FORMAT};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Args, Debug)]
+pub struct Inspect {
+    /// The path to the directory or archive of the recorded run.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub run: String,
+
+    /// Decode every record of the raw data into JSON lines, one record per line.
+    #[clap(
+        help_heading = "Basic Options",
+        long,
+        value_parser,
+        value_name = "DATA_NAME"
+    )]
+    pub dump: Option<String>,
+
+    /// The path of the file to write the dumped records to. Defaults to <RUN>-<DATA_NAME>.jsonl
+    /// in the current directory.
+    #[clap(
+        help_heading = "Basic Options",
+        short,
+        long,
+        value_parser,
+        requires = "dump"
+    )]
+    pub output: Option<String>,
+}
+
+/// Whether the records of a raw data file could all be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawDataFileStatus {
+    Complete,
+    /// Some records could not be decoded, e.g. because the collection was killed while writing
+    /// the last record, or the records were written by a version of APerf with a different
+    /// layout of the raw data.
+    Damaged(Vec<RawRecordError>),
+    /// The file does not contain records of the raw data, such as the metadata of legacy runs.
+    NotRawData,
+}
+
+impl std::fmt::Display for RawDataFileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawDataFileStatus::Complete => write!(f, "ok"),
+            RawDataFileStatus::Damaged(errors) => match errors.len() {
+                0 | 1 => write!(
+                    f,
+                    "{}",
+                    errors.first().map_or(String::new(), |e| e.to_string())
+                ),
+                num_errors => write!(f, "{} (and {} more)", errors[0], num_errors - 1),
+            },
+            RawDataFileStatus::NotRawData => write!(f, "not raw data"),
+        }
+    }
+}
+
+/// The summary of the records of a raw data file.
+#[derive(Debug, Clone)]
+pub struct RawDataFileInfo {
+    pub file_name: String,
+    pub size: u64,
+    /// The header of the versioned raw data file. None for the legacy format.
+    pub header: Option<RawDataFileHeader>,
+    pub num_records: usize,
+    /// The record types, i.e. the variants of the raw data, in the order they first appear.
+    pub record_types: Vec<String>,
+    pub first_time: Option<DateTime<Utc>>,
+    pub last_time: Option<DateTime<Utc>>,
+    pub status: RawDataFileStatus,
+}
+
+impl RawDataFileInfo {
+    fn new(file_name: String, size: u64) -> Self {
+        RawDataFileInfo {
+            file_name,
+            size,
+            header: None,
+            num_records: 0,
+            record_types: Vec::new(),
+            first_time: None,
+            last_time: None,
+            status: RawDataFileStatus::Complete,
+        }
+    }
+
+    /// The format of the file, i.e. the schema version of its records, or legacy.
+    pub fn format(&self) -> String {
+        match (&self.header, &self.status) {
+            (Some(header), _) => format!(
+                "v{} schema {} (aperf {})",
+                header.format_version, header.schema_version, header.collector_version
+            ),
+            (None, RawDataFileStatus::NotRawData) => "-".to_string(),
+            (None, _) => "legacy".to_string(),
+        }
+    }
+}
+
+/// The types that the records of a legacy raw data file are serialized from.
+enum RecordType {
+    Data,
+    AperfStats,
+    LegacyAperfStats,
+}
+
+impl RecordType {
+    /// The candidate record types of a legacy raw data file by its name, in the order of
+    /// preference.
+    fn candidates(file_name: &str) -> Vec<Self> {
+        if file_name == format!("meta_data.{}", APERF_FILE_FORMAT) {
+            Vec::new()
+        } else if file_name.starts_with("aperf_stats") {
+            // The APerf stats of the runs before the APerf process stats are of the legacy type.
+            vec![RecordType::AperfStats, RecordType::LegacyAperfStats]
+        } else if file_name.starts_with("aperf_run_stats") {
+            vec![RecordType::LegacyAperfStats]
+        } else {
+            vec![RecordType::Data]
+        }
+    }
+
+    fn decode(&self, file: &mut RawDataFile) -> DecodedRecords<Value> {
+        // The legacy records are decoded without the header, so decode_record is never called.
+        fn no_header<T>(_: &RawDataFileHeader, _: &[u8]) -> Result<T> {
+            bail!("Not a versioned raw data file")
+        }
+        match self {
+            RecordType::Data => to_values(file.decode_records::<Data, _>(no_header)),
+            RecordType::AperfStats => to_values(file.decode_records::<AperfStats, _>(no_header)),
+            RecordType::LegacyAperfStats => {
+                to_values(file.decode_records::<AperfStat, _>(no_header))
+            }
+        }
+    }
+}
+
+fn to_values<T: Serialize>(decoded: DecodedRecords<T>) -> DecodedRecords<Value> {
+    // The JSON conversion only fails for the map keys that are not strings or numbers, which
+    // none of the raw data have.
+    DecodedRecords {
+        records: decoded
+            .records
+            .iter()
+            .map(|record| serde_json::to_value(record).unwrap_or(Value::Null))
+            .collect(),
+        errors: decoded.errors,
+    }
+}
+
+/// Returns the name of the variant and the collection time of a decoded record.
+fn record_type_and_time(record: &Value) -> (Option<&str>, Option<DateTime<Utc>>) {
+    // The variants of Data are serialized as {"<variant>": {...}}.
+    let (record_type, fields) = match record.as_object() {
+        Some(object) if object.len() == 1 && !object.contains_key("time") => {
+            let (variant, fields) = object.iter().next().unwrap();
+            (Some(variant.as_str()), fields)
+        }
+        _ => (None, record),
+    };
+    let time = fields
+        .get("time")
+        .and_then(|time| time.get("DateTime"))
+        .and_then(|time| time.as_str())
+        .and_then(|time| time.parse::<DateTime<Utc>>().ok());
+    (record_type, time)
+}
+
+/// Decodes all records of a raw data file, passing every record to the callback, and returns
+/// the summary of the records.
+pub fn decode_raw_data_file<F>(path: &Path, mut on_record: F) -> Result<RawDataFileInfo>
+where
+    F: FnMut(&Value) -> Result<()>,
+{
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut info = RawDataFileInfo::new(file_name, fs::metadata(path)?.len());
+    let mut file = match RawDataFile::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            info.status = RawDataFileStatus::Damaged(vec![RawRecordError::Undecodable {
+                offset: 0,
+                error: e.to_string(),
+            }]);
+            return Ok(info);
+        }
+    };
+
+    let decoded = match &file.header {
+        Some(header) if header.data_name == get_data_name_from_type::<AperfStats>() => {
+            to_values(file.decode_records(|header, record| {
+                AperfStats::deserialize_record(header.schema_version, record)
+            }))
+        }
+        Some(_) => to_values(file.decode_records(|header, record| {
+            Data::deserialize_record(&header.data_name, header.schema_version, record)
+        })),
+        None => {
+            let candidates = RecordType::candidates(&info.file_name);
+            if candidates.is_empty() {
+                info.status = RawDataFileStatus::NotRawData;
+                return Ok(info);
+            }
+            // Use the first candidate that decodes all records, or else the one that decodes
+            // the most records.
+            let mut best: Option<DecodedRecords<Value>> = None;
+            for candidate in &candidates {
+                let decoded = candidate.decode(&mut file);
+                if decoded.errors.is_empty() {
+                    best = Some(decoded);
+                    break;
+                }
+                if best
+                    .as_ref()
+                    .is_none_or(|best| decoded.records.len() > best.records.len())
+                {
+                    best = Some(decoded);
+                }
+            }
+            best.unwrap()
+        }
+    };
+
+    info.header = file.header.clone();
+    for record in &decoded.records {
+        info.num_records += 1;
+        let (record_type, time) = record_type_and_time(record);
+        if let Some(record_type) = record_type {
+            if !info.record_types.iter().any(|t| t == record_type) {
+                info.record_types.push(record_type.to_string());
+            }
+        }
+        if let Some(time) = time {
+            info.first_time = Some(info.first_time.map_or(time, |t| t.min(time)));
+            info.last_time = Some(info.last_time.map_or(time, |t| t.max(time)));
+        }
+        on_record(record)?;
+    }
+    if !decoded.errors.is_empty() {
+        info.status = RawDataFileStatus::Damaged(decoded.errors);
+    }
+
+    Ok(info)
+}
+
+/// Returns the paths to all raw data files of a run, sorted by their names.
+pub(crate) fn raw_data_file_paths(run_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(run_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == APERF_FILE_FORMAT)
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or("-".to_string(), |time| {
+        time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+    })
+}
+
+/// Returns the printable collector and collection times of the run's metadata. The metadata is
+/// read as plain JSON, so that the metadata of runs recorded by other versions can be shown.
+fn metadata_lines(run_dir: &Path) -> Vec<String> {
+    let metadata: Value = match fs::read_to_string(run_dir.join("metadata.json"))
+        .ok()
+        .and_then(|json_str| serde_json::from_str(&json_str).ok())
+    {
+        Some(metadata) => metadata,
+        None => return vec!["Metadata: unavailable".to_string()],
+    };
+    let field = |name: &str| {
+        let value = &metadata[name];
+        value["DateTime"]
+            .as_str()
+            .or(value.as_str())
+            .filter(|value| !value.is_empty())
+            .unwrap_or("-")
+            .to_string()
+    };
+    vec![
+        format!(
+            "Collector version: {} ({})",
+            field("collector_version"),
+            field("collector_commit_sha")
+        ),
+        format!(
+            "Collection time: {} - {}",
+            field("collection_start"),
+            field("collection_end")
+        ),
+    ]
+}
+
+pub fn inspect(inspect: &Inspect, tmp_dir: &PathBuf) -> Result<()> {
+    let run_path = PathBuf::from(&inspect.run);
+    if !run_path.exists() {
+        return Err(PDError::RunNotFound(run_path).into());
+    }
+    let run_dir = if run_path.is_dir() {
+        run_path.clone()
+    } else {
+        extract_archive(&run_path, tmp_dir)?
+    };
+
+    if let Some(data_name) = &inspect.dump {
+        let file_name = find_file(
+            &run_dir,
+            &format!(
+                "^{}(_.*)?\\.{}$",
+                regex::escape(data_name),
+                APERF_FILE_FORMAT
+            ),
+            None,
+        )
+        .map_err(|_| {
+            PDError::RawDataNotFound(format!(
+                "run {} does not have any raw {} data",
+                no_tar_gz_file_name(&run_path).unwrap_or_default(),
+                data_name
+            ))
+        })?;
+        let output_path = match &inspect.output {
+            Some(output) => PathBuf::from(output),
+            None => PathBuf::from(format!(
+                "{}-{}.jsonl",
+                no_tar_gz_file_name(&run_path).unwrap_or_default(),
+                data_name
+            )),
+        };
+        let mut writer = BufWriter::new(File::create(&output_path)?);
+        let info = decode_raw_data_file(&run_dir.join(&file_name), |record| {
+            writeln!(writer, "{record}")?;
+            Ok(())
+        })?;
+        writer.flush()?;
+        if info.status == RawDataFileStatus::Complete {
+            info!(
+                "Dumped {} records of {} to {:?}",
+                info.num_records, file_name, output_path
+            );
+        } else {
+            warn!(
+                "Dumped {} records of {} to {:?}, skipped {}",
+                info.num_records, file_name, output_path, info.status
+            );
+        }
+        return Ok(());
+    }
+
+    println!(
+        "Run: {}",
+        no_tar_gz_file_name(&run_path).unwrap_or_default()
+    );
+    for line in metadata_lines(&run_dir) {
+        println!("{line}");
+    }
+    println!();
+
+    let infos = raw_data_file_paths(&run_dir)?
+        .iter()
+        .map(|path| decode_raw_data_file(path, |_| Ok(())))
+        .collect::<Result<Vec<RawDataFileInfo>>>()?;
+    let record_types: Vec<String> = infos
+        .iter()
+        .map(|info| match info.record_types.is_empty() {
+            true => "-".to_string(),
+            false => info.record_types.join(","),
+        })
+        .collect();
+    let name_width = infos
+        .iter()
+        .map(|info| info.file_name.len())
+        .max()
+        .unwrap_or(0)
+        .max("FILE".len());
+    let type_width = record_types
+        .iter()
+        .map(|record_type| record_type.len())
+        .max()
+        .unwrap_or(0)
+        .max("RECORD TYPE".len());
+    let formats: Vec<String> = infos.iter().map(|info| info.format()).collect();
+    let format_width = formats
+        .iter()
+        .map(|format| format.len())
+        .max()
+        .unwrap_or(0)
+        .max("FORMAT".len());
+    println!(
+        "{:<name_width$}  {:<format_width$}  {:<type_width$}  {:>10}  {:>7}  {:<20}  {:<20}  STATUS",
+        "FILE", "FORMAT", "RECORD TYPE", "SIZE", "RECORDS", "FIRST RECORD", "LAST RECORD"
+    );
+    for ((info, record_type), format) in infos.iter().zip(&record_types).zip(&formats) {
+        println!(
+            "{:<name_width$}  {:<format_width$}  {:<type_width$}  {:>10}  {:>7}  {:<20}  {:<20}  {}",
+            info.file_name,
+            format,
+            record_type,
+            info.size,
+            info.num_records,
+            format_time(info.first_time),
+            format_time(info.last_time),
+            info.status
+        );
+    }
+
+    let num_damaged = infos
+        .iter()
+        .filter(|info| matches!(info.status, RawDataFileStatus::Damaged(_)))
+        .count();
+    if num_damaged > 0 {
+        warn!("{num_damaged} raw data files have truncated or undecodable records.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::common::raw_data_file::{write_raw_data_file_header, write_raw_data_record};
+    use crate::data::cpu_utilization::CpuUtilizationRaw;
+    use crate::data::TimeEnum;
+
+    fn cpu_utilization_record(i: usize) -> Data {
+        Data::CpuUtilizationRaw(CpuUtilizationRaw {
+            time: TimeEnum::DateTime(
+                format!("2026-01-30T08:25:3{i}Z")
+                    .parse::<DateTime<Utc>>()
+                    .unwrap(),
+            ),
+            data: format!("cpu {i}"),
+        })
+    }
+
+    fn write_legacy_records(path: &Path, num_records: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for i in 0..num_records {
+            bincode::serialize_into(&mut bytes, &cpu_utilization_record(i)).unwrap();
+        }
+        fs::write(path, &bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_decode_raw_data_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir
+            .path()
+            .join("cpu_utilization_2026-01-30_08_25_32.bin");
+        let mut file = File::create(&path).unwrap();
+        let header = RawDataFileHeader::new("cpu_utilization", 1);
+        write_raw_data_file_header(&mut file, &header).unwrap();
+        for i in 0..3 {
+            let record = cpu_utilization_record(i).serialize_record().unwrap();
+            write_raw_data_record(&mut file, &record).unwrap();
+        }
+        let bytes = fs::read(&path).unwrap();
+
+        let mut records = Vec::new();
+        let info = decode_raw_data_file(&path, |record| {
+            records.push(record.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(info.header, Some(header));
+        assert_eq!(info.num_records, 3);
+        assert_eq!(info.record_types, vec!["CpuUtilizationRaw"]);
+        assert_eq!(info.status, RawDataFileStatus::Complete);
+        assert_eq!(format_time(info.first_time), "2026-01-30T08:25:30Z");
+        assert_eq!(format_time(info.last_time), "2026-01-30T08:25:32Z");
+        assert_eq!(records[1]["CpuUtilizationRaw"]["data"], "cpu 1");
+
+        // A record cut short by a killed collection
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        let info = decode_raw_data_file(&path, |_| Ok(())).unwrap();
+        assert_eq!(info.num_records, 2);
+        assert!(matches!(
+            &info.status,
+            RawDataFileStatus::Damaged(errors)
+                if matches!(errors[..], [RawRecordError::Truncated { .. }])
+        ));
+    }
+
+    #[test]
+    fn test_decode_legacy_raw_data_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir
+            .path()
+            .join("cpu_utilization_2026-01-30_08_25_32.bin");
+        let bytes = write_legacy_records(&path, 3);
+
+        let info = decode_raw_data_file(&path, |_| Ok(())).unwrap();
+        assert_eq!(info.header, None);
+        assert_eq!(info.format(), "legacy");
+        assert_eq!(info.num_records, 3);
+        assert_eq!(info.status, RawDataFileStatus::Complete);
+
+        // A record cut short by a killed collection
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        let info = decode_raw_data_file(&path, |_| Ok(())).unwrap();
+        assert_eq!(info.num_records, 2);
+        let record_size = bytes.len() as u64 / 3;
+        assert_eq!(
+            info.status,
+            RawDataFileStatus::Damaged(vec![RawRecordError::Truncated {
+                offset: 2 * record_size,
+                trailing_bytes: record_size - 5
+            }])
+        );
+
+        // A record of an unknown type
+        let mut corrupt_bytes = bytes.clone();
+        corrupt_bytes[record_size as usize..record_size as usize + 4]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &corrupt_bytes).unwrap();
+        let info = decode_raw_data_file(&path, |_| Ok(())).unwrap();
+        assert_eq!(info.num_records, 1);
+        assert!(matches!(
+            &info.status,
+            RawDataFileStatus::Damaged(errors)
+                if matches!(errors[..], [RawRecordError::Undecodable { offset, .. }] if offset == record_size)
+        ));
+    }
+
+    #[test]
+    fn test_not_raw_data_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("meta_data.bin");
+        fs::write(&path, [1, 2, 3]).unwrap();
+        let info = decode_raw_data_file(&path, |_| Ok(())).unwrap();
+        assert_eq!(info.num_records, 0);
+        assert_eq!(info.status, RawDataFileStatus::NotRawData);
+    }
+}
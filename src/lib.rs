@@ -7,9 +7,16 @@ pub mod computations;
 pub mod data;
 pub mod data_collection;
 pub mod data_processing;
+pub mod export;
+pub mod export_profile;
+pub mod import;
+pub mod inspect;
 pub mod profiling;
 #[cfg(target_os = "linux")]
 pub mod record;
+#[cfg(target_os = "linux")]
+pub mod recover;
+pub mod redact;
 pub mod report;
 #[cfg(feature = "mcp-server")]
 pub mod server;
@@ -67,12 +74,21 @@ pub enum PDError {
     #[error("The run {0:?} does not exist.")]
     RunNotFound(PathBuf),
 
+    #[error("The collection of the run {0:?} is still running (PID {1}).")]
+    CollectionRunning(PathBuf, u32),
+
     #[error("The run {0:?} was specified more than once.")]
     DuplicateRunPath(PathBuf),
 
     #[error("The report {0} already exists in current directory.")]
     ReportExists(String),
 
+    #[error("The export directory {0:?} already exists.")]
+    ExportExists(PathBuf),
+
+    #[error("The redacted output {0:?} already exists.")]
+    RedactedOutputExists(PathBuf),
+
     #[error("Invalid directory {0:?}")]
     InvalidDirectory(PathBuf),
 
@@ -99,6 +115,12 @@ pub enum PDError {
 
     #[error("Ignored data preparation: {}", .0)]
     IgnoredDataPreparationError(String),
+
+    #[error("Profile not found: {}", .0)]
+    ProfileNotFound(String),
+
+    #[error("Raw data not found: {}", .0)]
+    RawDataNotFound(String),
 }
 
 /// Use the module name (file name) of a data as its unique identifier in APerf.
@@ -266,6 +288,15 @@ pub fn aperf_stats_flush() -> Result<()> {
     Ok(())
 }
 
+/// Write the APerf stats of the past seconds to disk, keeping the stats of the current second.
+#[cfg(target_os = "linux")]
+pub fn aperf_stats_checkpoint() -> Result<()> {
+    APERF_STATS_COLLECTOR
+        .with(|aperf_stats_collector| aperf_stats_collector.borrow_mut().checkpoint())?;
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 pub fn sub_process_pids() -> HashSet<u32> {
     SUB_PROCESS_PIDS.with(|pids| pids.borrow().clone())
@@ -0,0 +1,321 @@
+//! Differential profiles between two profiles of the same type, such as the profiles of the base
+//! run and another run.
+
+use crate::profiling::{Profile, ThreadState};
+use std::collections::{BTreeMap, HashMap};
+
+/// A differential profile, whose tree merges the call paths of a base profile and a current
+/// profile by frame names. Sample counts are normalized by the total samples of each profile, so
+/// that profiles of different durations or sampling frequencies can be compared.
+#[derive(Debug, Clone)]
+pub struct DiffProfile {
+    /// Tree nodes, index 0 is the root (index is node_id)
+    pub nodes: Vec<DiffTreeNode>,
+    /// Total samples of the base profile
+    pub base_total_samples: u64,
+    /// Total samples of the current profile
+    pub current_total_samples: u64,
+}
+
+/// A node in the differential call tree, representing a call path that exists in either profile.
+#[derive(Debug, Clone)]
+pub struct DiffTreeNode {
+    pub name: String,
+    /// Index of parent node in the nodes vec, None for root
+    pub parent: Option<usize>,
+    /// Map from child frame name -> child node_id, ordered by name
+    pub children: BTreeMap<String, usize>,
+    /// Share (0.0 - 1.0) of the base profile's samples in this call path, including children
+    pub base_total_share: f64,
+    /// Share (0.0 - 1.0) of the base profile's samples in this call path, excluding children
+    pub base_self_share: f64,
+    /// Share (0.0 - 1.0) of the current profile's samples in this call path, including children
+    pub current_total_share: f64,
+    /// Share (0.0 - 1.0) of the current profile's samples in this call path, excluding children
+    pub current_self_share: f64,
+}
+
+impl DiffTreeNode {
+    fn new(name: &str, parent: Option<usize>) -> Self {
+        DiffTreeNode {
+            name: name.to_string(),
+            parent,
+            children: BTreeMap::new(),
+            base_total_share: 0.0,
+            base_self_share: 0.0,
+            current_total_share: 0.0,
+            current_self_share: 0.0,
+        }
+    }
+
+    /// The change of the share of samples in this call path, including children.
+    pub fn total_delta(&self) -> f64 {
+        self.current_total_share - self.base_total_share
+    }
+
+    /// The change of the share of samples in this call path, excluding children.
+    pub fn self_delta(&self) -> f64 {
+        self.current_self_share - self.base_self_share
+    }
+}
+
+/// The share of samples in a function across all of its call paths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameShareDelta {
+    pub name: String,
+    pub base_share: f64,
+    pub current_share: f64,
+}
+
+impl FrameShareDelta {
+    pub fn delta(&self) -> f64 {
+        self.current_share - self.base_share
+    }
+}
+
+impl DiffProfile {
+    /// Compute the differential profile from the sample stats of the two profiles, which are
+    /// aggregated for their current time ranges. Only the samples in the thread_states are
+    /// counted, or all thread states if it is empty.
+    pub fn new(base: &Profile, current: &Profile, thread_states: &[ThreadState]) -> Self {
+        let mut diff_profile = DiffProfile {
+            nodes: vec![DiffTreeNode::new("[root]", None)],
+            base_total_samples: base.get_total_samples(thread_states),
+            current_total_samples: current.get_total_samples(thread_states),
+        };
+        let thread_state_ids = base.resolve_thread_states(thread_states);
+        diff_profile.merge_profile(base, &thread_state_ids, true);
+        diff_profile.merge_profile(current, &thread_state_ids, false);
+        diff_profile
+    }
+
+    /// Walk the context tree of a profile and add its normalized sample counts to the nodes with
+    /// the same call paths.
+    fn merge_profile(&mut self, profile: &Profile, thread_state_ids: &[u8], is_base: bool) {
+        let total_samples = if is_base {
+            self.base_total_samples
+        } else {
+            self.current_total_samples
+        };
+        if total_samples == 0 || profile.context_tree.is_empty() {
+            return;
+        }
+        // (context tree node_id, diff tree node_id)
+        let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+        while let Some((node_id, diff_node_id)) = stack.pop() {
+            let node = &profile.context_tree[node_id];
+            for (&child_frame_id, &child_node_id) in &node.children {
+                let child_stats = &profile.context_tree[child_node_id].sample_stats;
+                let (child_total, child_self) = thread_state_ids
+                    .iter()
+                    .filter_map(|thread_state_id| child_stats.get(thread_state_id))
+                    .fold((0u64, 0u64), |(total, self_samples), stats| {
+                        (
+                            total + stats.total_samples,
+                            self_samples + stats.self_samples,
+                        )
+                    });
+                if child_total == 0 {
+                    continue;
+                }
+                let child_diff_node_id =
+                    self.get_or_insert_child(diff_node_id, profile.frame_map.name(child_frame_id));
+                let child_diff_node = &mut self.nodes[child_diff_node_id];
+                let total_share = child_total as f64 / total_samples as f64;
+                let self_share = child_self as f64 / total_samples as f64;
+                if is_base {
+                    child_diff_node.base_total_share += total_share;
+                    child_diff_node.base_self_share += self_share;
+                } else {
+                    child_diff_node.current_total_share += total_share;
+                    child_diff_node.current_self_share += self_share;
+                }
+                stack.push((child_node_id, child_diff_node_id));
+            }
+        }
+        let root = &mut self.nodes[0];
+        if is_base {
+            root.base_total_share = 1.0;
+        } else {
+            root.current_total_share = 1.0;
+        }
+    }
+
+    fn get_or_insert_child(&mut self, node_id: usize, name: &str) -> usize {
+        if let Some(&child_node_id) = self.nodes[node_id].children.get(name) {
+            return child_node_id;
+        }
+        let child_node_id = self.nodes.len();
+        self.nodes.push(DiffTreeNode::new(name, Some(node_id)));
+        self.nodes[node_id]
+            .children
+            .insert(name.to_string(), child_node_id);
+        child_node_id
+    }
+
+    /// The shares of samples in every function of either profile, summed across all of its call
+    /// paths. Recursive calls are only counted once, at the outermost call. If total_samples is
+    /// false, only the samples in the function itself (excluding its callees) are counted.
+    /// Ordered by the largest growth first.
+    pub fn frame_share_deltas(&self, total_samples: bool) -> Vec<FrameShareDelta> {
+        let mut frame_shares: HashMap<&str, (f64, f64)> = HashMap::new();
+        let mut path: Vec<&str> = Vec::new();
+        self.collect_frame_shares(0, total_samples, &mut path, &mut frame_shares);
+        let mut frame_share_deltas: Vec<FrameShareDelta> = frame_shares
+            .into_iter()
+            .map(|(name, (base_share, current_share))| FrameShareDelta {
+                name: name.to_string(),
+                base_share,
+                current_share,
+            })
+            .collect();
+        frame_share_deltas.sort_by(|a, b| {
+            b.delta()
+                .total_cmp(&a.delta())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        frame_share_deltas
+    }
+
+    fn collect_frame_shares<'a>(
+        &'a self,
+        node_id: usize,
+        total_samples: bool,
+        path: &mut Vec<&'a str>,
+        frame_shares: &mut HashMap<&'a str, (f64, f64)>,
+    ) {
+        for &child_node_id in self.nodes[node_id].children.values() {
+            let child = &self.nodes[child_node_id];
+            let shares = frame_shares.entry(&child.name).or_default();
+            if !total_samples {
+                shares.0 += child.base_self_share;
+                shares.1 += child.current_self_share;
+            } else if !path.contains(&child.name.as_str()) {
+                shares.0 += child.base_total_share;
+                shares.1 += child.current_total_share;
+            }
+            path.push(&child.name);
+            self.collect_frame_shares(child_node_id, total_samples, path, frame_shares);
+            path.pop();
+        }
+    }
+
+    /// Generate the differential collapsed format, where every line has a call stack followed by
+    /// its self samples in the base profile and in the current profile, scaled to the total
+    /// samples of the current profile:
+    ///
+    /// frame1;frame2 10 20
+    /// frame1;frame3 20 5
+    pub fn generate_differential_collapsed(&self) -> String {
+        let mut result = String::new();
+        let mut path: Vec<&str> = Vec::new();
+        self.dfs_differential_collapsed(0, &mut path, &mut result);
+        result
+    }
+
+    fn dfs_differential_collapsed<'a>(
+        &'a self,
+        node_id: usize,
+        path: &mut Vec<&'a str>,
+        result: &mut String,
+    ) {
+        let node = &self.nodes[node_id];
+        if !path.is_empty() {
+            let scale = self.current_total_samples as f64;
+            let base_samples = (node.base_self_share * scale).round() as u64;
+            let current_samples = (node.current_self_share * scale).round() as u64;
+            if base_samples > 0 || current_samples > 0 {
+                result.push_str(&format!(
+                    "{} {} {}\n",
+                    path.join(";"),
+                    base_samples,
+                    current_samples
+                ));
+            }
+        }
+        for &child_node_id in node.children.values() {
+            path.push(&self.nodes[child_node_id].name);
+            self.dfs_differential_collapsed(child_node_id, path, result);
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_profile(stacks: &[(&[&str], u64)]) -> Profile {
+        let mut profile = Profile::new();
+        for (frames, count) in stacks {
+            let frames: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+            profile.insert_stack(0, 0, 20, ThreadState::None, &frames, *count);
+        }
+        profile
+    }
+
+    fn find_node<'a>(diff_profile: &'a DiffProfile, path: &[&str]) -> &'a DiffTreeNode {
+        let mut node_id = 0;
+        for name in path {
+            node_id = diff_profile.nodes[node_id].children[*name];
+        }
+        &diff_profile.nodes[node_id]
+    }
+
+    #[test]
+    fn test_diff_profile() {
+        // The current profile has twice as many samples, but main;parse doubled its share.
+        let base = create_profile(&[(&["main", "parse"], 25), (&["main", "compute"], 75)]);
+        let current = create_profile(&[
+            (&["main", "parse"], 100),
+            (&["main", "compute"], 90),
+            (&["main", "io"], 10),
+        ]);
+        let diff_profile = DiffProfile::new(&base, &current, &[ThreadState::None]);
+
+        let parse = find_node(&diff_profile, &["main", "parse"]);
+        assert_eq!(parse.base_total_share, 0.25);
+        assert_eq!(parse.current_total_share, 0.5);
+        assert_eq!(parse.total_delta(), 0.25);
+        let compute = find_node(&diff_profile, &["main", "compute"]);
+        assert_eq!(compute.self_delta(), 0.45 - 0.75);
+        let io = find_node(&diff_profile, &["main", "io"]);
+        assert_eq!(io.base_total_share, 0.0);
+        assert_eq!(io.current_total_share, 0.05);
+        let main = find_node(&diff_profile, &["main"]);
+        assert_eq!(main.total_delta(), 0.0);
+
+        let frame_share_deltas = diff_profile.frame_share_deltas(true);
+        assert_eq!(frame_share_deltas[0].name, "parse");
+        assert_eq!(frame_share_deltas[0].delta(), 0.25);
+        assert_eq!(frame_share_deltas.last().unwrap().name, "compute");
+
+        assert_eq!(
+            diff_profile.generate_differential_collapsed(),
+            "main;compute 150 90\nmain;io 0 10\nmain;parse 50 100\n"
+        );
+    }
+
+    #[test]
+    fn test_frame_share_deltas_recursion() {
+        let base = create_profile(&[(&["main", "walk"], 50), (&["main", "idle"], 50)]);
+        let current = create_profile(&[
+            (&["main", "walk", "walk", "walk"], 80),
+            (&["main", "idle"], 20),
+        ]);
+        let diff_profile = DiffProfile::new(&base, &current, &[ThreadState::None]);
+        let walk = diff_profile
+            .frame_share_deltas(true)
+            .into_iter()
+            .find(|frame_share_delta| frame_share_delta.name == "walk")
+            .unwrap();
+        assert_eq!(walk.base_share, 0.5);
+        assert_eq!(walk.current_share, 0.8);
+        let walk_self = diff_profile
+            .frame_share_deltas(false)
+            .into_iter()
+            .find(|frame_share_delta| frame_share_delta.name == "walk")
+            .unwrap();
+        assert_eq!(walk_self.current_share, 0.8);
+    }
+}
@@ -0,0 +1,482 @@
+//! Export profiles to the formats of other profiling tools: pprof, speedscope and the folded
+//! stacks of Brendan Gregg's FlameGraph, and render profiles as flamegraph SVGs.
+
+use crate::data::common::data_formats::Profiler;
+use crate::profiling::{Profile, ThreadState};
+use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
+use inferno::flamegraph::{self, Direction, Options};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+
+/// The names of the supported export formats.
+pub const PROFILE_EXPORT_FORMATS: [&str; 3] = ["pprof", "speedscope", "folded"];
+
+/// The file extension of every export format.
+pub fn export_file_extension(format: &str) -> &'static str {
+    match format {
+        "pprof" => "pb.gz",
+        "speedscope" => "speedscope.json",
+        _ => "folded",
+    }
+}
+
+/// Export a profile of the profiler to one of PROFILE_EXPORT_FORMATS. The samples of all thread
+/// states are included, aggregated for the current time range of the profile.
+pub fn export_profile(
+    profiler: &Profiler,
+    profile_type: &str,
+    format: &str,
+    name: &str,
+) -> Option<Result<Vec<u8>>> {
+    let profile = profiler.profiles.get(profile_type)?;
+    Some(match format {
+        "pprof" => generate_pprof(profiler, profile, profile_type),
+        "speedscope" => generate_speedscope(profile, profile_type, name),
+        _ => Ok(profile
+            .generate_collapsed(&all_thread_states())
+            .into_bytes()),
+    })
+}
+
+/// Render a profile as an icicle graph SVG, with the samples of all thread states aggregated for
+/// the current time range of the profile. If reverse_stack_order is set, the stacks are rooted at
+/// the leaf frames, which shows the functions with the most self samples at the top.
+pub fn write_flamegraph_svg(
+    profile: &Profile,
+    reverse_stack_order: bool,
+    writer: impl Write,
+) -> Result<()> {
+    let collapsed = profile.generate_collapsed(&all_thread_states());
+    let mut options = Options::default();
+    options.direction = Direction::Inverted;
+    options.reverse_stack_order = reverse_stack_order;
+    flamegraph::from_lines(&mut options, collapsed.lines(), writer)?;
+    Ok(())
+}
+
+fn all_thread_states() -> Vec<ThreadState> {
+    std::iter::once(ThreadState::None)
+        .chain(ThreadState::ALL)
+        .collect()
+}
+
+/// The unit of the sample counts of a profile type. Off-CPU profiles are weighted by the time
+/// spent off-CPU, while all the other profiles count samples or events.
+fn sample_unit(profile_type: &str) -> &'static str {
+    match profile_type {
+        "offcpu" => "microseconds",
+        _ => "count",
+    }
+}
+
+/// The start time (in milliseconds since epoch) and duration (in milliseconds) of the current
+/// time range of a profile, or of all of its blocks if no time range was applied.
+fn profile_time_range_ms(profiler: &Profiler, profile: &Profile) -> (i64, u64) {
+    let block_width_ms = profiler.block_width_ms.max(1);
+    let (start_idx, end_idx) = match profile.time_range {
+        (start_idx, end_idx) if end_idx > start_idx => {
+            (start_idx, end_idx.min(profile.blocks.len()))
+        }
+        _ => (0, profile.blocks.len()),
+    };
+    // Blocks are aligned by their width, see Profile::insert_stack.
+    let start_block_ms = profiler.start_time_ms - profiler.start_time_ms % block_width_ms as i64;
+    (
+        start_block_ms + (start_idx as u64 * block_width_ms) as i64,
+        end_idx.saturating_sub(start_idx) as u64 * block_width_ms,
+    )
+}
+
+/// Generate the speedscope file format (https://www.speedscope.app/file-format-schema.json),
+/// where every call stack is a sample weighted by its sample count.
+fn generate_speedscope(profile: &Profile, profile_type: &str, name: &str) -> Result<Vec<u8>> {
+    let frames: Vec<serde_json::Value> = (0..profile.frame_map.len())
+        .map(|frame_id| match profile.frame_map.location(frame_id) {
+            Some(location) => json!({
+                "name": profile.frame_map.name(frame_id),
+                "file": location.file,
+                "line": location.line,
+            }),
+            None => json!({ "name": profile.frame_map.name(frame_id) }),
+        })
+        .collect();
+    let mut samples: Vec<Vec<usize>> = Vec::new();
+    let mut weights: Vec<u64> = Vec::new();
+    profile.for_each_stack(&all_thread_states(), |frame_ids, count| {
+        samples.push(frame_ids.to_vec());
+        weights.push(count);
+    });
+    let unit = match sample_unit(profile_type) {
+        "count" => "none",
+        unit => unit,
+    };
+    let speedscope = json!({
+        "$schema": "https://www.speedscope.app/file-format-schema.json",
+        "shared": { "frames": frames },
+        "profiles": [{
+            "type": "sampled",
+            "name": format!("{name} ({profile_type})"),
+            "unit": unit,
+            "startValue": 0,
+            "endValue": weights.iter().sum::<u64>(),
+            "samples": samples,
+            "weights": weights,
+        }],
+        "name": name,
+        "exporter": format!("aperf {}", env!("CARGO_PKG_VERSION")),
+    });
+    Ok(serde_json::to_vec(&speedscope)?)
+}
+
+/// Generate the gzipped pprof protobuf (https://github.com/google/pprof/blob/main/proto/profile.proto).
+/// Every frame becomes a function with a single location, which both use the frame ID as their ID.
+/// The source file and line of a frame, if known, are the filename and start line of its function.
+fn generate_pprof(profiler: &Profiler, profile: &Profile, profile_type: &str) -> Result<Vec<u8>> {
+    let mut string_table = PprofStringTable::new();
+    let mut message = Vec::new();
+
+    // Profile.sample_type
+    let mut value_type = Vec::new();
+    write_varint_field(&mut value_type, 1, string_table.index(profile_type));
+    write_varint_field(
+        &mut value_type,
+        2,
+        string_table.index(sample_unit(profile_type)),
+    );
+    write_bytes_field(&mut message, 1, &value_type);
+
+    // Profile.sample, whose location IDs are ordered from leaf to root
+    let mut frame_ids_in_use: BTreeSet<usize> = BTreeSet::new();
+    profile.for_each_stack(&all_thread_states(), |frame_ids, count| {
+        let mut sample = Vec::new();
+        write_packed_field(
+            &mut sample,
+            1,
+            frame_ids.iter().rev().map(|&frame_id| frame_id as u64),
+        );
+        write_packed_field(&mut sample, 2, std::iter::once(count));
+        write_bytes_field(&mut message, 2, &sample);
+        frame_ids_in_use.extend(frame_ids);
+    });
+
+    for &frame_id in &frame_ids_in_use {
+        let source_location = profile.frame_map.location(frame_id);
+
+        // Profile.location, with a single Location.line
+        let mut line = Vec::new();
+        write_varint_field(&mut line, 1, frame_id as u64);
+        if let Some(source_location) = source_location {
+            write_varint_field(&mut line, 2, source_location.line as u64);
+        }
+        let mut location = Vec::new();
+        write_varint_field(&mut location, 1, frame_id as u64);
+        write_bytes_field(&mut location, 4, &line);
+        write_bytes_field(&mut message, 4, &location);
+
+        // Profile.function
+        let name_index = string_table.index(profile.frame_map.name(frame_id));
+        let mut function = Vec::new();
+        write_varint_field(&mut function, 1, frame_id as u64);
+        write_varint_field(&mut function, 2, name_index);
+        write_varint_field(&mut function, 3, name_index);
+        if let Some(source_location) = source_location {
+            write_varint_field(&mut function, 4, string_table.index(&source_location.file));
+            write_varint_field(&mut function, 5, source_location.line as u64);
+        }
+        write_bytes_field(&mut message, 5, &function);
+    }
+
+    // Profile.time_nanos and Profile.duration_nanos
+    let (start_time_ms, duration_ms) = profile_time_range_ms(profiler, profile);
+    write_varint_field(&mut message, 9, (start_time_ms.max(0) as u64) * 1_000_000);
+    write_varint_field(&mut message, 10, duration_ms * 1_000_000);
+
+    // Profile.string_table
+    for string in &string_table.strings {
+        write_bytes_field(&mut message, 6, string.as_bytes());
+    }
+
+    let mut gz_encoder = GzEncoder::new(Vec::new(), Compression::default());
+    gz_encoder.write_all(&message)?;
+    Ok(gz_encoder.finish()?)
+}
+
+/// The deduplicated strings of a pprof profile, where the first string must be empty.
+struct PprofStringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, u64>,
+}
+
+impl PprofStringTable {
+    fn new() -> Self {
+        PprofStringTable {
+            strings: vec![String::new()],
+            indices: HashMap::from([(String::new(), 0)]),
+        }
+    }
+
+    fn index(&mut self, string: &str) -> u64 {
+        if let Some(&index) = self.indices.get(string) {
+            return index;
+        }
+        let index = self.strings.len() as u64;
+        self.strings.push(string.to_string());
+        self.indices.insert(string.to_string(), index);
+        index
+    }
+}
+
+// The protobuf wire format: https://protobuf.dev/programming-guides/encoding/
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_LEN: u64 = 2;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buf, (field << 3) | WIRE_TYPE_VARINT);
+    write_varint(buf, value);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, (field << 3) | WIRE_TYPE_LEN);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed_field(buf: &mut Vec<u8>, field: u64, values: impl Iterator<Item = u64>) {
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, value);
+    }
+    write_bytes_field(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling::SourceLocation;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn create_profiler() -> Profiler {
+        let mut profiler = Profiler::new(1_000);
+        for (frames, count) in [(vec!["main", "parse"], 3), (vec!["main", "compute"], 7)] {
+            let frames: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+            profiler.insert_stack("cpu", 1_000, ThreadState::None, &frames, count);
+        }
+        profiler
+    }
+
+    fn read_varint(bytes: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[*pos];
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    /// Decode the fields of a protobuf message as (field, varint value or bytes) pairs.
+    fn decode_fields(bytes: &[u8]) -> Vec<(u64, Result<u64, Vec<u8>>)> {
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let key = read_varint(bytes, &mut pos);
+            if key & 0x7 == WIRE_TYPE_VARINT {
+                fields.push((key >> 3, Ok(read_varint(bytes, &mut pos))));
+            } else {
+                let len = read_varint(bytes, &mut pos) as usize;
+                fields.push((key >> 3, Err(bytes[pos..pos + len].to_vec())));
+                pos += len;
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn test_export_pprof() {
+        let profiler = create_profiler();
+        let pprof = export_profile(&profiler, "cpu", "pprof", "run")
+            .unwrap()
+            .unwrap();
+        let mut message = Vec::new();
+        GzDecoder::new(pprof.as_slice())
+            .read_to_end(&mut message)
+            .unwrap();
+        let fields = decode_fields(&message);
+
+        let strings: Vec<String> = fields
+            .iter()
+            .filter_map(|(field, value)| match (field, value) {
+                (6, Err(bytes)) => Some(String::from_utf8(bytes.clone()).unwrap()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strings[0], "");
+        for string in ["cpu", "count", "main", "parse", "compute"] {
+            assert!(strings.contains(&string.to_string()));
+        }
+
+        let mut total_samples = 0;
+        let mut num_samples = 0;
+        for (_, sample) in fields.iter().filter(|(field, _)| *field == 2) {
+            let sample_fields = decode_fields(sample.as_ref().unwrap_err());
+            // Two locations (leaf first) and a single value
+            let location_ids = sample_fields[0].1.as_ref().unwrap_err();
+            assert_eq!(location_ids.len(), 2);
+            let main_frame_id = 1;
+            assert_eq!(location_ids[1], main_frame_id);
+            let value = sample_fields[1].1.as_ref().unwrap_err();
+            total_samples += read_varint(value, &mut 0);
+            num_samples += 1;
+        }
+        assert_eq!(num_samples, 2);
+        assert_eq!(total_samples, 10);
+        assert_eq!(fields.iter().filter(|(field, _)| *field == 4).count(), 3);
+        assert_eq!(fields.iter().filter(|(field, _)| *field == 5).count(), 3);
+        assert!(fields.contains(&(9, Ok(1_000_000_000))));
+        assert!(fields.contains(&(10, Ok(20_000_000))));
+    }
+
+    #[test]
+    fn test_export_speedscope() {
+        let profiler = create_profiler();
+        let speedscope = export_profile(&profiler, "cpu", "speedscope", "run")
+            .unwrap()
+            .unwrap();
+        let speedscope: serde_json::Value = serde_json::from_slice(&speedscope).unwrap();
+        let frames = speedscope["shared"]["frames"].as_array().unwrap();
+        let profile = &speedscope["profiles"][0];
+        assert_eq!(profile["type"], "sampled");
+        assert_eq!(profile["unit"], "none");
+        assert_eq!(profile["endValue"], 10);
+        let samples = profile["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        for (sample, weight) in samples.iter().zip(profile["weights"].as_array().unwrap()) {
+            let names: Vec<&str> = sample
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|frame| {
+                    frames[frame.as_u64().unwrap() as usize]["name"]
+                        .as_str()
+                        .unwrap()
+                })
+                .collect();
+            match names.as_slice() {
+                ["main", "parse"] => assert_eq!(weight, 3),
+                ["main", "compute"] => assert_eq!(weight, 7),
+                _ => panic!("Unexpected stack {names:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_source_locations() {
+        let mut profiler = Profiler::new(1_000);
+        let frames: Vec<String> = vec!["main".to_string(), "parse".to_string()];
+        for line in [12, 10, 14] {
+            let location = SourceLocation {
+                file: "parse.c".to_string(),
+                line,
+            };
+            profiler.insert_stack_with_locations(
+                "cpu",
+                1_000,
+                ThreadState::None,
+                &frames,
+                &[None, Some(&location)],
+                1,
+            );
+        }
+
+        let speedscope = export_profile(&profiler, "cpu", "speedscope", "run")
+            .unwrap()
+            .unwrap();
+        let speedscope: serde_json::Value = serde_json::from_slice(&speedscope).unwrap();
+        let frames = speedscope["shared"]["frames"].as_array().unwrap();
+        let parse_frame = frames
+            .iter()
+            .find(|frame| frame["name"] == "parse")
+            .unwrap();
+        assert_eq!(parse_frame["file"], "parse.c");
+        assert_eq!(parse_frame["line"], 10);
+        let main_frame = frames.iter().find(|frame| frame["name"] == "main").unwrap();
+        assert!(main_frame.get("file").is_none());
+
+        let pprof = export_profile(&profiler, "cpu", "pprof", "run")
+            .unwrap()
+            .unwrap();
+        let mut message = Vec::new();
+        GzDecoder::new(pprof.as_slice())
+            .read_to_end(&mut message)
+            .unwrap();
+        let fields = decode_fields(&message);
+        let strings: Vec<String> = fields
+            .iter()
+            .filter_map(|(field, value)| match (field, value) {
+                (6, Err(bytes)) => Some(String::from_utf8(bytes.clone()).unwrap()),
+                _ => None,
+            })
+            .collect();
+        let functions: Vec<Vec<(u64, Result<u64, Vec<u8>>)>> = fields
+            .iter()
+            .filter(|(field, _)| *field == 5)
+            .map(|(_, function)| decode_fields(function.as_ref().unwrap_err()))
+            .collect();
+        let parse_function = functions
+            .iter()
+            .find(|function| function.contains(&(1, Ok(2))))
+            .unwrap();
+        let filename_index = parse_function
+            .iter()
+            .find_map(|(field, value)| (*field == 4).then(|| *value.as_ref().unwrap()))
+            .unwrap();
+        assert_eq!(strings[filename_index as usize], "parse.c");
+        assert!(parse_function.contains(&(5, Ok(10))));
+    }
+
+    #[test]
+    fn test_export_folded() {
+        let profiler = create_profiler();
+        let folded = export_profile(&profiler, "cpu", "folded", "run")
+            .unwrap()
+            .unwrap();
+        let mut lines: Vec<&str> = std::str::from_utf8(&folded).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, vec!["main;compute 7", "main;parse 3"]);
+        assert!(export_profile(&profiler, "wall", "folded", "run").is_none());
+    }
+
+    #[test]
+    fn test_write_flamegraph_svg() {
+        let profiler = create_profiler();
+        let profile = profiler.profiles.get("cpu").unwrap();
+        for reverse_stack_order in [false, true] {
+            let mut svg = Vec::new();
+            write_flamegraph_svg(profile, reverse_stack_order, &mut svg).unwrap();
+            let svg = String::from_utf8(svg).unwrap();
+            assert!(svg.starts_with("<?xml"));
+            for frame in ["main", "parse", "compute"] {
+                assert!(
+                    svg.contains(&format!("<title>{frame} (")),
+                    "{frame} not in SVG"
+                );
+            }
+        }
+    }
+}
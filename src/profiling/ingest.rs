@@ -0,0 +1,581 @@
+//! Ingest the profiles of other profiling tools in the folded stacks, pprof and speedscope
+//! formats into Profilers. Every sample is placed at the same time, since none of the formats
+//! reliably has the times of the samples.
+
+use crate::data::common::data_formats::Profiler;
+use crate::profiling::ThreadState;
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+/// The format of a profile generated by another profiling tool.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileFormat {
+    /// The folded stacks of Brendan Gregg's FlameGraph: one "frame1;frame2;... count" line per
+    /// stack, from the root to the leaf.
+    Folded,
+    /// The (optionally gzipped) pprof protobuf of Go and other tools.
+    Pprof,
+    /// The JSON format of speedscope, with sampled or evented profiles.
+    Speedscope,
+}
+
+/// The names of the supported ingest formats.
+pub const PROFILE_INGEST_FORMATS: [&str; 3] = ["folded", "pprof", "speedscope"];
+
+impl FromStr for ProfileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Ok(match name {
+            "folded" => ProfileFormat::Folded,
+            "pprof" => ProfileFormat::Pprof,
+            "speedscope" => ProfileFormat::Speedscope,
+            _ => bail!(
+                "Unsupported profile format '{name}', expected one of: {}",
+                PROFILE_INGEST_FORMATS.join(", ")
+            ),
+        })
+    }
+}
+
+impl fmt::Display for ProfileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ProfileFormat::Folded => "folded",
+            ProfileFormat::Pprof => "pprof",
+            ProfileFormat::Speedscope => "speedscope",
+        })
+    }
+}
+
+impl ProfileFormat {
+    /// The file extension of a profile in the format.
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ProfileFormat::Folded => "folded",
+            ProfileFormat::Pprof => "pb.gz",
+            ProfileFormat::Speedscope => "speedscope.json",
+        }
+    }
+}
+
+/// Ingest a profile in the given format as the profile_type profile of a new Profiler, with all
+/// samples placed at sample_time_ms.
+pub fn ingest_profile(
+    data: &[u8],
+    format: ProfileFormat,
+    profile_type: &str,
+    sample_time_ms: i64,
+) -> Result<Profiler> {
+    let mut profiler = Profiler::new(sample_time_ms);
+    let stacks = match format {
+        ProfileFormat::Folded => {
+            profiler.insert_collapsed(
+                profile_type,
+                sample_time_ms,
+                &String::from_utf8_lossy(data),
+            )?;
+            Vec::new()
+        }
+        ProfileFormat::Pprof => read_pprof_stacks(data)?,
+        ProfileFormat::Speedscope => read_speedscope_stacks(data)?,
+    };
+    for (frames, count) in stacks {
+        if !frames.is_empty() && count > 0 {
+            profiler.insert_stack(
+                profile_type,
+                sample_time_ms,
+                ThreadState::None,
+                &frames,
+                count,
+            );
+        }
+    }
+    Ok(profiler)
+}
+
+/// The stacks (from the root to the leaf) and sample counts of a profile.
+type Stacks = Vec<(Vec<String>, u64)>;
+
+/// Read the samples of a pprof profile (https://github.com/google/pprof/blob/main/proto/profile.proto)
+/// with the value of its default sample type, which is the last one unless specified.
+fn read_pprof_stacks(data: &[u8]) -> Result<Stacks> {
+    let mut message = Vec::new();
+    if data.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(data).read_to_end(&mut message)?;
+    } else {
+        message = data.to_vec();
+    }
+
+    let mut string_table: Vec<String> = Vec::new();
+    let mut sample_types: Vec<u64> = Vec::new();
+    let mut default_sample_type: Option<u64> = None;
+    // Every sample as its location IDs (leaf first) and values
+    let mut samples: Vec<(Vec<u64>, Vec<u64>)> = Vec::new();
+    // Every location as its function IDs (innermost inlined function first) and address
+    let mut locations: HashMap<u64, (Vec<u64>, u64)> = HashMap::new();
+    let mut function_names: HashMap<u64, u64> = HashMap::new();
+
+    for field in ProtobufFields::new(&message) {
+        match field? {
+            (1, ProtobufValue::Bytes(value_type)) => {
+                for field in ProtobufFields::new(value_type) {
+                    if let (1, ProtobufValue::Varint(type_index)) = field? {
+                        sample_types.push(type_index);
+                    }
+                }
+            }
+            (2, ProtobufValue::Bytes(sample)) => {
+                let mut location_ids = Vec::new();
+                let mut values = Vec::new();
+                for field in ProtobufFields::new(sample) {
+                    match field? {
+                        (1, value) => location_ids.extend(value.repeated_varints()?),
+                        (2, value) => values.extend(value.repeated_varints()?),
+                        _ => {}
+                    }
+                }
+                samples.push((location_ids, values));
+            }
+            (4, ProtobufValue::Bytes(location)) => {
+                let mut id = 0;
+                let mut address = 0;
+                let mut function_ids = Vec::new();
+                for field in ProtobufFields::new(location) {
+                    match field? {
+                        (1, ProtobufValue::Varint(value)) => id = value,
+                        (3, ProtobufValue::Varint(value)) => address = value,
+                        (4, ProtobufValue::Bytes(line)) => {
+                            for field in ProtobufFields::new(line) {
+                                if let (1, ProtobufValue::Varint(function_id)) = field? {
+                                    function_ids.push(function_id);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                locations.insert(id, (function_ids, address));
+            }
+            (5, ProtobufValue::Bytes(function)) => {
+                let mut id = 0;
+                let mut name_index = 0;
+                for field in ProtobufFields::new(function) {
+                    match field? {
+                        (1, ProtobufValue::Varint(value)) => id = value,
+                        (2, ProtobufValue::Varint(value)) => name_index = value,
+                        _ => {}
+                    }
+                }
+                function_names.insert(id, name_index);
+            }
+            (6, ProtobufValue::Bytes(string)) => {
+                string_table.push(String::from_utf8_lossy(string).to_string())
+            }
+            (14, ProtobufValue::Varint(type_index)) => default_sample_type = Some(type_index),
+            _ => {}
+        }
+    }
+
+    let value_idx = default_sample_type
+        .and_then(|type_index| sample_types.iter().position(|&t| t == type_index))
+        .unwrap_or(sample_types.len().saturating_sub(1));
+    let string = |index: u64| string_table.get(index as usize).map_or("", String::as_str);
+    let mut stacks = Stacks::new();
+    for (location_ids, values) in samples {
+        let Some(&value) = values.get(value_idx) else {
+            continue;
+        };
+        let mut frames: Vec<String> = Vec::new();
+        for location_id in &location_ids {
+            match locations.get(location_id) {
+                Some((function_ids, _)) if !function_ids.is_empty() => {
+                    frames.extend(function_ids.iter().map(|function_id| {
+                        match function_names.get(function_id).map(|&index| string(index)) {
+                            Some(name) if !name.is_empty() => name.to_string(),
+                            _ => "[unknown]".to_string(),
+                        }
+                    }))
+                }
+                Some((_, address)) => frames.push(format!("{:#x}", address)),
+                None => frames.push("[unknown]".to_string()),
+            }
+        }
+        frames.reverse();
+        stacks.push((frames, value));
+    }
+    Ok(stacks)
+}
+
+/// Read the profiles of a speedscope file (https://www.speedscope.app/file-format-schema.json).
+/// The weights of the time units are converted to microseconds. If the file has more than one
+/// profile, such as one per thread, the stacks are rooted at the frames named after the profiles.
+fn read_speedscope_stacks(data: &[u8]) -> Result<Stacks> {
+    let speedscope: Value = serde_json::from_slice(data)?;
+    let frame_names: Vec<String> = speedscope["shared"]["frames"]
+        .as_array()
+        .map(|frames| {
+            frames
+                .iter()
+                .map(|frame| frame["name"].as_str().unwrap_or("[unknown]").to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let Some(profiles) = speedscope["profiles"].as_array() else {
+        bail!("The speedscope file has no profiles");
+    };
+    let frame_name = |frame_idx: &Value| {
+        frame_idx
+            .as_u64()
+            .and_then(|frame_idx| frame_names.get(frame_idx as usize))
+            .cloned()
+            .unwrap_or_else(|| "[unknown]".to_string())
+    };
+
+    let mut stacks = Stacks::new();
+    for (profile_idx, profile) in profiles.iter().enumerate() {
+        let root_frames: Vec<String> = if profiles.len() > 1 {
+            vec![profile["name"]
+                .as_str()
+                .map_or_else(|| format!("profile {profile_idx}"), String::from)]
+        } else {
+            Vec::new()
+        };
+        let scale = match profile["unit"].as_str() {
+            Some("nanoseconds") => 0.001,
+            Some("milliseconds") => 1_000.0,
+            Some("seconds") => 1_000_000.0,
+            _ => 1.0,
+        };
+        let weight = |value: f64| (value * scale).round() as u64;
+
+        match profile["type"].as_str() {
+            Some("sampled") => {
+                let samples = profile["samples"].as_array().cloned().unwrap_or_default();
+                let weights = profile["weights"].as_array().cloned().unwrap_or_default();
+                for (sample_idx, sample) in samples.iter().enumerate() {
+                    let frames: Vec<String> = root_frames
+                        .iter()
+                        .cloned()
+                        .chain(sample.as_array().into_iter().flatten().map(frame_name))
+                        .collect();
+                    let value = weights
+                        .get(sample_idx)
+                        .and_then(Value::as_f64)
+                        .unwrap_or(1.0);
+                    stacks.push((frames, weight(value)));
+                }
+            }
+            // The time between two events is attributed to the stack of the open frames
+            Some("evented") => {
+                let mut frames = root_frames.clone();
+                let mut last_at: Option<f64> = None;
+                for event in profile["events"].as_array().into_iter().flatten() {
+                    let at = event["at"].as_f64().unwrap_or(0.0);
+                    if let Some(last_at) = last_at {
+                        if frames.len() > root_frames.len() {
+                            stacks.push((frames.clone(), weight(at - last_at)));
+                        }
+                    }
+                    last_at = Some(at);
+                    match event["type"].as_str() {
+                        Some("O") => frames.push(frame_name(&event["frame"])),
+                        Some("C") if frames.len() > root_frames.len() => {
+                            frames.pop();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            other => bail!("Unsupported speedscope profile type {:?}", other),
+        }
+    }
+    Ok(stacks)
+}
+
+// The protobuf wire format: https://protobuf.dev/programming-guides/encoding/
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_I64: u64 = 1;
+const WIRE_TYPE_LEN: u64 = 2;
+const WIRE_TYPE_I32: u64 = 5;
+
+/// The value of a field of a protobuf message.
+enum ProtobufValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+impl ProtobufValue<'_> {
+    /// The values of a repeated varint field, which is either packed or a single value.
+    fn repeated_varints(&self) -> Result<Vec<u64>> {
+        match self {
+            ProtobufValue::Varint(value) => Ok(vec![*value]),
+            ProtobufValue::Bytes(packed) => {
+                let mut values = Vec::new();
+                let mut pos = 0;
+                while pos < packed.len() {
+                    values.push(read_varint(packed, &mut pos)?);
+                }
+                Ok(values)
+            }
+            ProtobufValue::Fixed => bail!("Unexpected fixed-size protobuf value"),
+        }
+    }
+}
+
+/// An iterator over the (field number, value) pairs of a protobuf message.
+struct ProtobufFields<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtobufFields<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        ProtobufFields { bytes, pos: 0 }
+    }
+
+    fn read_field(&mut self) -> Result<(u64, ProtobufValue<'a>)> {
+        let key = read_varint(self.bytes, &mut self.pos)?;
+        let value = match key & 0x7 {
+            WIRE_TYPE_VARINT => ProtobufValue::Varint(read_varint(self.bytes, &mut self.pos)?),
+            WIRE_TYPE_LEN => {
+                let len = read_varint(self.bytes, &mut self.pos)? as usize;
+                let Some(bytes) = self.bytes.get(self.pos..self.pos + len) else {
+                    bail!("Truncated protobuf message");
+                };
+                self.pos += len;
+                ProtobufValue::Bytes(bytes)
+            }
+            WIRE_TYPE_I64 => {
+                self.pos += 8;
+                ProtobufValue::Fixed
+            }
+            WIRE_TYPE_I32 => {
+                self.pos += 4;
+                ProtobufValue::Fixed
+            }
+            wire_type => bail!("Unsupported protobuf wire type {wire_type}"),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for ProtobufFields<'a> {
+    type Item = Result<(u64, ProtobufValue<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.bytes.len() {
+            return None;
+        }
+        let field = self.read_field();
+        if field.is_err() {
+            // Stop at the first malformed field
+            self.pos = self.bytes.len();
+        }
+        Some(field)
+    }
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let Some(&byte) = bytes.get(*pos) else {
+            bail!("Truncated protobuf varint");
+        };
+        *pos += 1;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        if byte < 0x80 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling::export::export_profile;
+
+    fn create_profiler() -> Profiler {
+        let mut profiler = Profiler::new(1_000);
+        for (frames, count) in [(vec!["main", "parse"], 3), (vec!["main", "compute"], 7)] {
+            let frames: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+            profiler.insert_stack("cpu", 1_000, ThreadState::None, &frames, count);
+        }
+        profiler
+    }
+
+    fn sorted_collapsed(profiler: &Profiler, profile_type: &str) -> Vec<String> {
+        let mut lines: Vec<String> = profiler
+            .generate_collapsed(profile_type, &[ThreadState::None])
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_ingest_exported_profiles() {
+        let profiler = create_profiler();
+        for format in PROFILE_INGEST_FORMATS {
+            let exported = export_profile(&profiler, "cpu", format, "run")
+                .unwrap()
+                .unwrap();
+            let ingested =
+                ingest_profile(&exported, format.parse().unwrap(), "wall", 5_000).unwrap();
+            assert_eq!(ingested.start_time_ms, 5_000);
+            assert_eq!(
+                sorted_collapsed(&ingested, "wall"),
+                vec!["main;compute 7", "main;parse 3"],
+                "{format}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ingest_pprof_default_sample_type() {
+        fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+            while value >= 0x80 {
+                buf.push((value as u8) | 0x80);
+                value >>= 7;
+            }
+            buf.push(value as u8);
+        }
+        fn write_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+            write_varint(buf, (field << 3) | WIRE_TYPE_LEN);
+            write_varint(buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+        }
+        fn write_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+            write_varint(buf, (field << 3) | WIRE_TYPE_VARINT);
+            write_varint(buf, value);
+        }
+
+        // A Go CPU profile: [samples/count, cpu/nanoseconds], with a location of an inlined
+        // function and an unpacked location ID
+        let mut message = Vec::new();
+        for (type_index, unit_index) in [(1, 2), (3, 4)] {
+            let mut value_type = Vec::new();
+            write_varint_field(&mut value_type, 1, type_index);
+            write_varint_field(&mut value_type, 2, unit_index);
+            write_field(&mut message, 1, &value_type);
+        }
+        let mut sample = Vec::new();
+        write_varint_field(&mut sample, 1, 2);
+        write_varint_field(&mut sample, 1, 1);
+        write_field(&mut sample, 2, &[5, 0xc0, 0x84, 0x3d]);
+        write_field(&mut message, 2, &sample);
+        for (location_id, function_ids) in [(1, vec![1]), (2, vec![3, 2])] {
+            let mut location = Vec::new();
+            write_varint_field(&mut location, 1, location_id);
+            for function_id in function_ids {
+                let mut line = Vec::new();
+                write_varint_field(&mut line, 1, function_id);
+                write_field(&mut location, 4, &line);
+            }
+            write_field(&mut message, 4, &location);
+        }
+        for (function_id, name_index) in [(1, 5), (2, 6), (3, 7)] {
+            let mut function = Vec::new();
+            write_varint_field(&mut function, 1, function_id);
+            write_varint_field(&mut function, 2, name_index);
+            write_field(&mut message, 5, &function);
+        }
+        for string in [
+            "",
+            "samples",
+            "count",
+            "cpu",
+            "nanoseconds",
+            "main.main",
+            "main.compute",
+            "main.add",
+        ] {
+            write_field(&mut message, 6, string.as_bytes());
+        }
+
+        let profiler = ingest_profile(&message, ProfileFormat::Pprof, "cpu", 0).unwrap();
+        assert_eq!(
+            sorted_collapsed(&profiler, "cpu"),
+            vec!["main.main;main.compute;main.add 1000000"]
+        );
+
+        // The default sample type overrides the last one
+        write_varint_field(&mut message, 14, 1);
+        let profiler = ingest_profile(&message, ProfileFormat::Pprof, "cpu", 0).unwrap();
+        assert_eq!(
+            sorted_collapsed(&profiler, "cpu"),
+            vec!["main.main;main.compute;main.add 5"]
+        );
+    }
+
+    #[test]
+    fn test_ingest_speedscope() {
+        let speedscope = serde_json::json!({
+            "shared": { "frames": [{ "name": "main" }, { "name": "poll" }, { "name": "work" }] },
+            "profiles": [
+                {
+                    "type": "sampled",
+                    "name": "MainThread",
+                    "unit": "seconds",
+                    "startValue": 0,
+                    "endValue": 0.03,
+                    "samples": [[0, 1], [0, 2], [0, 2]],
+                    "weights": [0.01, 0.01, 0.01],
+                },
+                {
+                    "type": "evented",
+                    "name": "worker",
+                    "unit": "milliseconds",
+                    "startValue": 0,
+                    "endValue": 10,
+                    "events": [
+                        { "type": "O", "frame": 0, "at": 0 },
+                        { "type": "O", "frame": 2, "at": 2 },
+                        { "type": "C", "frame": 2, "at": 8 },
+                        { "type": "C", "frame": 0, "at": 10 },
+                    ],
+                },
+            ],
+        });
+        let profiler = ingest_profile(
+            &serde_json::to_vec(&speedscope).unwrap(),
+            ProfileFormat::Speedscope,
+            "cpu",
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            sorted_collapsed(&profiler, "cpu"),
+            vec![
+                "MainThread;main;poll 10000",
+                "MainThread;main;work 20000",
+                "worker;main 4000",
+                "worker;main;work 6000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_profile_format() {
+        assert_eq!(
+            "speedscope".parse::<ProfileFormat>().unwrap(),
+            ProfileFormat::Speedscope
+        );
+        assert_eq!(ProfileFormat::Pprof.to_string(), "pprof");
+        assert!("collapsed".parse::<ProfileFormat>().is_err());
+    }
+}
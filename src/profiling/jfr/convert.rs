@@ -36,15 +36,10 @@ pub fn build_java_profiler_data(
         None
     };
 
-    let frame_type_suffix: HashMap<u8, &str> = reader
-        .enums
-        .get("jdk.types.FrameType")
-        .map(|m| {
-            m.iter()
-                .map(|(&k, v)| (k as u8, FrameType::from_jfr_name(v).literal_suffix()))
-                .collect()
-        })
-        .unwrap_or_default();
+    let frame_type_suffix: HashMap<u8, &str> = jfr_frame_types(&reader)
+        .into_iter()
+        .map(|(k, frame_type)| (k, frame_type.literal_suffix()))
+        .collect();
     let thread_state_map = reader
         .enums
         .get("jdk.types.ThreadState")
@@ -77,22 +72,12 @@ pub fn build_java_profiler_data(
                         write_jfr_out_event(file, &event, &mut humanized_class_name_cache, &reader);
                 }
 
-                let (profile_type, thread_state, samples) = match &event {
-                    JfrEvent::ExecutionSample(e) => {
-                        let ptype = match e.sample_type {
-                            ExecSampleType::Execution
-                            | ExecSampleType::NativeMethod
-                            | ExecSampleType::CpuTime => "cpu",
-                            ExecSampleType::WallClock => "wall",
-                        };
-                        let state_name = thread_state_map
-                            .get(&e.thread_state)
-                            .map(|s| s.as_str())
-                            .unwrap_or("");
-                        (ptype, ThreadState::from_str(state_name), e.samples as u64)
-                    }
-                    JfrEvent::AllocationSample(_) => ("alloc", ThreadState::None, 1u64),
-                    _ => continue,
+                let Some((profile_type, thread_state, samples)) = event_profile_sample(
+                    &event,
+                    &thread_state_map,
+                    reader.chunk_info.ticks_per_sec,
+                ) else {
+                    continue;
                 };
 
                 num_samples += 1;
@@ -109,33 +94,24 @@ pub fn build_java_profiler_data(
                                 .or_insert_with(|| {
                                     let suffix =
                                         frame_type_suffix.get(&frame_type).copied().unwrap_or("");
-                                    if let Some((cls, method, _)) = reader.resolve_method(method_id)
-                                    {
-                                        if frame_type == 3 || frame_type == 4 || cls.is_empty() {
-                                            format!("{}{}", method, suffix)
-                                        } else if method.is_empty() {
-                                            format!("{}{}", cls.replace('/', "."), suffix)
-                                        } else {
-                                            format!(
-                                                "{}.{}{}",
-                                                cls.replace('/', "."),
-                                                method,
-                                                suffix
-                                            )
-                                        }
-                                    } else {
-                                        format!("[unknown:{}]{}", method_id, suffix)
-                                    }
+                                    jfr_frame_name(&reader, method_id, frame_type, suffix)
                                 })
                                 .clone()
                         })
                         .collect();
 
-                    if let JfrEvent::AllocationSample(e) = &event {
+                    // The allocated or locked class is the leaf frame of the stack.
+                    let class_id = match &event {
+                        JfrEvent::AllocationSample(e) => Some(e.class_id),
+                        JfrEvent::ContendedLock(e) => Some(e.class_id),
+                        JfrEvent::LiveObject(e) => Some(e.class_id),
+                        _ => None,
+                    };
+                    if let Some(class_id) = class_id {
                         let cls = humanized_class_name_cache
-                            .entry(e.class_id as i64)
+                            .entry(class_id as i64)
                             .or_insert_with(|| {
-                                jvm_type_to_human(&reader.resolve_class(e.class_id as i64))
+                                jvm_type_to_human(&reader.resolve_class(class_id as i64))
                             });
                         let frame = format!("{}{}", cls, FrameType::Inlined.literal_suffix());
                         frames.push(frame);
@@ -164,6 +140,146 @@ pub fn build_java_profiler_data(
     Ok(profiler)
 }
 
+/// The Java part of the stack of a JFR execution sample, used to correlate the sample with the
+/// Perf samples of the same thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JavaStack {
+    /// The TID of the sampled thread.
+    pub tid: i32,
+    /// The timestamp of the sample in nanoseconds of CLOCK_MONOTONIC.
+    pub timestamp: u64,
+    /// The frames from the root down to the innermost Java frame. The native frames below it
+    /// are dropped, since the Perf samples have them.
+    pub frames: Vec<String>,
+}
+
+/// Read the Java stacks of the execution samples (cpu and wall) in a JFR recorded by
+/// async-profiler. Only the chunks timestamped with CLOCK_MONOTONIC (async-profiler's
+/// `--clock monotonic`), which is the clock of the Perf samples, are read.
+pub fn read_java_stacks(jfr_path: &Path) -> Result<Vec<JavaStack>> {
+    let mut reader = JfrReader::open(jfr_path.to_str().unwrap())?;
+    let frame_types = jfr_frame_types(&reader);
+    let mut formatted_frame_name_cache: HashMap<(i64, u8), String> = HashMap::new();
+
+    let mut java_stacks: Vec<JavaStack> = Vec::new();
+    loop {
+        match reader.read_event()? {
+            JfrEvent::EndOfChunk if !reader.has_more_chunks().unwrap_or(false) => break,
+            JfrEvent::ExecutionSample(e) if reader.chunk_info.ticks_per_sec == 1_000_000_000 => {
+                let Some(trace) = reader.stack_traces.get(&(e.stack_trace_id as i64)) else {
+                    continue;
+                };
+                let frames: Vec<(i64, u8)> = trace
+                    .methods
+                    .iter()
+                    .copied()
+                    .zip(trace.types.iter().copied())
+                    .rev()
+                    .collect();
+                let Some(innermost_java_frame) = frames.iter().rposition(|(_, frame_type)| {
+                    frame_types
+                        .get(frame_type)
+                        .is_some_and(|frame_type| frame_type.is_java())
+                }) else {
+                    continue;
+                };
+                java_stacks.push(JavaStack {
+                    tid: e.tid,
+                    timestamp: e.time as u64,
+                    frames: frames[..=innermost_java_frame]
+                        .iter()
+                        .map(|&(method_id, frame_type)| {
+                            formatted_frame_name_cache
+                                .entry((method_id, frame_type))
+                                .or_insert_with(|| {
+                                    let suffix = frame_types
+                                        .get(&frame_type)
+                                        .map_or("", |frame_type| frame_type.literal_suffix());
+                                    jfr_frame_name(&reader, method_id, frame_type, suffix)
+                                })
+                                .clone()
+                        })
+                        .collect(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(java_stacks)
+}
+
+/// The FrameType of every frame type ID in the jdk.types.FrameType enum of the JFR.
+fn jfr_frame_types(reader: &JfrReader) -> HashMap<u8, FrameType> {
+    reader
+        .enums
+        .get("jdk.types.FrameType")
+        .map(|m| {
+            m.iter()
+                .map(|(&k, v)| (k as u8, FrameType::from_jfr_name(v)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The name of a frame in a JFR stack trace: the Java class and method, or only the function
+/// of native (3) and C++ (4) frames, followed by the suffix of the frame type.
+fn jfr_frame_name(reader: &JfrReader, method_id: i64, frame_type: u8, suffix: &str) -> String {
+    if let Some((cls, method, _)) = reader.resolve_method(method_id) {
+        if frame_type == 3 || frame_type == 4 || cls.is_empty() {
+            format!("{}{}", method, suffix)
+        } else if method.is_empty() {
+            format!("{}{}", cls.replace('/', "."), suffix)
+        } else {
+            format!("{}.{}{}", cls.replace('/', "."), method, suffix)
+        }
+    } else {
+        format!("[unknown:{}]{}", method_id, suffix)
+    }
+}
+
+/// The profile type, thread state and weight of the sample of a JFR event, or None if the event
+/// is not profiled:
+///   - cpu and wall: the number of samples
+///   - alloc: the number of sampled allocations
+///   - lock: the time (in nanoseconds) spent waiting for Java monitors and native mutexes
+///   - nativemem: the bytes allocated through malloc (frees are not profiled)
+///   - live: the bytes of the sampled allocations that are still alive at the end of profiling
+fn event_profile_sample(
+    event: &JfrEvent,
+    thread_state_map: &HashMap<i32, String>,
+    ticks_per_sec: i64,
+) -> Option<(&'static str, ThreadState, u64)> {
+    let ticks_to_nanos =
+        |ticks: i64| (ticks as f64 * 1_000_000_000.0 / ticks_per_sec.max(1) as f64) as u64;
+    Some(match event {
+        JfrEvent::ExecutionSample(e) => {
+            let ptype = match e.sample_type {
+                ExecSampleType::Execution
+                | ExecSampleType::NativeMethod
+                | ExecSampleType::CpuTime => "cpu",
+                ExecSampleType::WallClock => "wall",
+            };
+            let state_name = thread_state_map
+                .get(&e.thread_state)
+                .map(|s| s.as_str())
+                .unwrap_or("");
+            (ptype, ThreadState::from_str(state_name), e.samples as u64)
+        }
+        JfrEvent::AllocationSample(_) => ("alloc", ThreadState::None, 1u64),
+        JfrEvent::ContendedLock(e) if e.duration > 0 => {
+            ("lock", ThreadState::None, ticks_to_nanos(e.duration))
+        }
+        JfrEvent::NativeLock(e) if e.duration > 0 => {
+            ("lock", ThreadState::None, ticks_to_nanos(e.duration))
+        }
+        JfrEvent::MallocEvent(e) if e.size > 0 => ("nativemem", ThreadState::None, e.size as u64),
+        JfrEvent::LiveObject(e) if e.allocation_size > 0 => {
+            ("live", ThreadState::None, e.allocation_size as u64)
+        }
+        _ => return None,
+    })
+}
+
 fn write_jfr_out_header(out_file: &mut File, reader: &JfrReader) -> Result<()> {
     writeln!(out_file, "start_nanos: {}", reader.start_nanos)?;
     writeln!(out_file, "end_nanos: {}", reader.end_nanos)?;
@@ -540,3 +656,63 @@ pub fn parse_jfr_metadata(metadata_json: &Value) -> KeyValueData {
 
     key_value_data
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling::jfr::{ContendedLock, LiveObject, MallocEvent};
+
+    #[test]
+    fn test_event_profile_sample() {
+        let thread_state_map = HashMap::new();
+        let lock = JfrEvent::ContendedLock(ContendedLock {
+            time: 0,
+            tid: 1,
+            stack_trace_id: 1,
+            duration: 2_000,
+            class_id: 1,
+        });
+        // 2000 ticks at 1MHz is 2ms.
+        assert_eq!(
+            event_profile_sample(&lock, &thread_state_map, 1_000_000),
+            Some(("lock", ThreadState::None, 2_000_000))
+        );
+
+        let malloc = JfrEvent::MallocEvent(MallocEvent {
+            time: 0,
+            tid: 1,
+            stack_trace_id: 1,
+            address: 0x1000,
+            size: 4096,
+        });
+        assert_eq!(
+            event_profile_sample(&malloc, &thread_state_map, 1_000_000),
+            Some(("nativemem", ThreadState::None, 4096))
+        );
+        // Frees have no size and are not profiled.
+        let free = JfrEvent::MallocEvent(MallocEvent {
+            time: 0,
+            tid: 1,
+            stack_trace_id: 1,
+            address: 0x1000,
+            size: 0,
+        });
+        assert_eq!(
+            event_profile_sample(&free, &thread_state_map, 1_000_000),
+            None
+        );
+
+        let live_object = JfrEvent::LiveObject(LiveObject {
+            time: 0,
+            tid: 1,
+            stack_trace_id: 1,
+            class_id: 1,
+            allocation_size: 512,
+            allocation_time: 0,
+        });
+        assert_eq!(
+            event_profile_sample(&live_object, &thread_state_map, 1_000_000),
+            Some(("live", ThreadState::None, 512))
+        );
+    }
+}
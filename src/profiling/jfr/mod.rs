@@ -8,7 +8,7 @@ mod convert;
 mod reader;
 mod types;
 
-pub use convert::{build_java_profiler_data, parse_jfr_metadata};
+pub use convert::{build_java_profiler_data, parse_jfr_metadata, read_java_stacks, JavaStack};
 
 pub use reader::JfrReader;
 pub use types::*;
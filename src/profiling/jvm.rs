@@ -0,0 +1,417 @@
+//! JVM discovery without the JDK tools.
+//!
+//! HotSpot JVMs publish their performance counters in the hsperfdata file
+//! `<tmp>/hsperfdata_<user>/<pid>`, which is what `jps` and `jstat` read. [`discover_jvms`] finds
+//! the JVMs by scanning `/proc` and the hsperfdata files reachable through `/proc/<pid>/root`,
+//! which works with only a JRE and for JVMs in other containers, namespaces or of other users.
+//! [`discover_hsperfdata_files`] only finds their hsperfdata files, without reading them.
+//! [`parse_hsperfdata`] parses the counters of an hsperfdata file.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use {
+    std::fs,
+    std::path::{Path, PathBuf},
+};
+
+/// The magic number at the start of every hsperfdata file, stored in big-endian.
+const HSPERFDATA_MAGIC: u32 = 0xcafec0c0;
+/// The size of the prologue of an hsperfdata file, before the first entry.
+const HSPERFDATA_PROLOGUE_SIZE: usize = 32;
+/// The hsperfdata counter holding the main class or JAR file and the arguments of the JVM.
+pub const JAVA_COMMAND_COUNTER: &str = "sun.rt.javaCommand";
+
+/// The java launcher options that take the following argument as their value.
+const JAVA_OPTIONS_WITH_VALUE: &[&str] = &[
+    "-cp",
+    "-classpath",
+    "--class-path",
+    "-p",
+    "--module-path",
+    "--upgrade-module-path",
+    "--add-modules",
+    "--limit-modules",
+    "--add-reads",
+    "--add-exports",
+    "--add-opens",
+    "--patch-module",
+    "--enable-native-access",
+];
+
+/// A running JVM found by [`discover_jvms`].
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredJvm {
+    /// The main class or JAR file of the JVM, or "Unknown_JVM_<pid>".
+    pub name: String,
+    /// The hsperfdata file of the JVM, which is None if it disabled the performance counters
+    /// (-XX:-UsePerfData) or is not accessible.
+    pub hsperfdata_path: Option<PathBuf>,
+}
+
+/// The value of an hsperfdata counter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PerfDataValue {
+    Long(i64),
+    String(String),
+}
+
+/// Parse the counters of an hsperfdata file by their names, such as "sun.gc.collector.0.time".
+pub fn parse_hsperfdata(data: &[u8]) -> Result<HashMap<String, PerfDataValue>> {
+    if data.len() < HSPERFDATA_PROLOGUE_SIZE
+        || u32::from_be_bytes(data[0..4].try_into()?) != HSPERFDATA_MAGIC
+    {
+        bail!("Not an hsperfdata file");
+    }
+    let little_endian = data[4] == 1;
+    let read_bytes = |offset: usize, len: usize| {
+        data.get(offset..offset + len)
+            .ok_or_else(|| anyhow!("Truncated hsperfdata entry at offset {offset}"))
+    };
+    let read_i32 = |offset: usize| -> Result<i32> {
+        let bytes = read_bytes(offset, 4)?.try_into()?;
+        Ok(match little_endian {
+            true => i32::from_le_bytes(bytes),
+            false => i32::from_be_bytes(bytes),
+        })
+    };
+    let read_i64 = |offset: usize| -> Result<i64> {
+        let bytes = read_bytes(offset, 8)?.try_into()?;
+        Ok(match little_endian {
+            true => i64::from_le_bytes(bytes),
+            false => i64::from_be_bytes(bytes),
+        })
+    };
+    let read_string = |bytes: &[u8]| {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).to_string()
+    };
+
+    let mut counters = HashMap::new();
+    let mut entry_offset = usize::try_from(read_i32(24)?)?;
+    let num_entries = read_i32(28)?;
+    for _ in 0..num_entries {
+        let entry_length = usize::try_from(read_i32(entry_offset)?)?;
+        let name_offset = usize::try_from(read_i32(entry_offset + 4)?)?;
+        let vector_length = usize::try_from(read_i32(entry_offset + 8)?)?;
+        let data_type = read_bytes(entry_offset + 12, 1)?[0];
+        let data_offset = usize::try_from(read_i32(entry_offset + 16)?)?;
+        if entry_length == 0 {
+            bail!("Invalid hsperfdata entry at offset {entry_offset}");
+        }
+
+        let name_start = entry_offset + name_offset;
+        let name = read_string(read_bytes(
+            name_start,
+            data.len().saturating_sub(name_start),
+        )?);
+        let value_offset = entry_offset + data_offset;
+        let value = match (data_type, vector_length) {
+            (b'J', 0) => Some(PerfDataValue::Long(read_i64(value_offset)?)),
+            (b'B', 1..) => Some(PerfDataValue::String(read_string(read_bytes(
+                value_offset,
+                vector_length,
+            )?))),
+            _ => None,
+        };
+        if let Some(value) = value {
+            counters.insert(name, value);
+        }
+        entry_offset += entry_length;
+    }
+    Ok(counters)
+}
+
+/// Get the main class or JAR file name from a java command, the way jps does: the package of a
+/// main class and the directory of a JAR file are removed, e.g. "org.apache.kafka.Kafka" becomes
+/// "Kafka" and "/opt/app/app.jar" becomes "app.jar".
+pub fn main_class(java_command: &str) -> String {
+    let main = java_command.split(' ').next().unwrap_or_default();
+    let main = main.rsplit(['/', '\\']).next().unwrap_or(main);
+    match main.rsplit_once('.') {
+        Some((_, "jar")) => main.to_string(),
+        Some((_, class)) if !class.is_empty() => class.to_string(),
+        _ => main.to_string(),
+    }
+}
+
+/// Get the main class or JAR file name from the command line of a java launcher, for the JVMs
+/// without an hsperfdata file (e.g. started with -XX:-UsePerfData).
+pub fn main_class_from_cmdline(cmdline: &[String]) -> Option<String> {
+    let mut args = cmdline.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-jar" | "-m" | "--module" => return args.next().map(|main| main_class(main)),
+            _ if arg.starts_with("--module=") => {
+                return Some(main_class(arg.trim_start_matches("--module=")))
+            }
+            _ if JAVA_OPTIONS_WITH_VALUE.contains(&arg.as_str()) => {
+                args.next();
+            }
+            _ if arg.starts_with('-') => {}
+            _ => return Some(main_class(arg)),
+        }
+    }
+    None
+}
+
+/// A JVM process found in /proc, before reading its hsperfdata file.
+#[cfg(target_os = "linux")]
+struct JvmProcess {
+    pid: String,
+    cmdline: Vec<String>,
+    hsperfdata_path: Option<PathBuf>,
+}
+
+/// Scan /proc for the processes that have an hsperfdata file or are java launchers.
+#[cfg(target_os = "linux")]
+fn scan_jvm_processes() -> Vec<JvmProcess> {
+    let mut jvm_processes = Vec::new();
+    let Ok(proc_entries) = fs::read_dir("/proc") else {
+        return jvm_processes;
+    };
+    // The hsperfdata files of every root directory, by the namespace PIDs of the JVMs.
+    let mut hsperfdata_files: HashMap<(PathBuf, PathBuf), HashMap<String, PathBuf>> =
+        HashMap::new();
+
+    for proc_entry in proc_entries.flatten() {
+        let pid = proc_entry.file_name().to_string_lossy().to_string();
+        if pid.parse::<u32>().is_err() {
+            continue;
+        }
+        let proc_dir = proc_entry.path();
+        let cmdline: Vec<String> = fs::read(proc_dir.join("cmdline"))
+            .unwrap_or_default()
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).to_string())
+            .collect();
+        // Skip the kernel threads and the exited processes
+        if cmdline.is_empty() {
+            continue;
+        }
+
+        // The hsperfdata files are reachable through the root of the process even if it is in
+        // another mount namespace, and are named after the PID in its own PID namespace.
+        let hsperfdata_path = fs::read_link(proc_dir.join("ns/mnt"))
+            .ok()
+            .zip(fs::read_link(proc_dir.join("root")).ok())
+            .and_then(|root_key| {
+                hsperfdata_files
+                    .entry(root_key)
+                    .or_insert_with(|| read_hsperfdata_files(&proc_dir.join("root/tmp")))
+                    .get(&namespace_pid(&proc_dir).unwrap_or_else(|| pid.clone()))
+                    .cloned()
+            });
+        let is_java_launcher = Path::new(&cmdline[0])
+            .file_name()
+            .is_some_and(|name| name == "java")
+            || fs::read_to_string(proc_dir.join("comm")).is_ok_and(|comm| comm.trim() == "java");
+        if hsperfdata_path.is_none() && !is_java_launcher {
+            continue;
+        }
+        jvm_processes.push(JvmProcess {
+            pid,
+            cmdline,
+            hsperfdata_path,
+        });
+    }
+    jvm_processes
+}
+
+/// Get the name of a JVM from the java command in its hsperfdata counters, if any.
+pub fn hsperfdata_jvm_name(counters: &HashMap<String, PerfDataValue>) -> Option<String> {
+    match counters.get(JAVA_COMMAND_COUNTER) {
+        Some(PerfDataValue::String(java_command)) => {
+            Some(main_class(java_command)).filter(|name| !name.is_empty())
+        }
+        _ => None,
+    }
+}
+
+/// Discover the running JVMs by their PIDs. A process is a JVM if it has an hsperfdata file or
+/// is a java launcher. The names are read from the hsperfdata files, falling back to the command
+/// lines, and to "Unknown_JVM_<pid>".
+#[cfg(target_os = "linux")]
+pub fn discover_jvms() -> HashMap<String, DiscoveredJvm> {
+    scan_jvm_processes()
+        .into_iter()
+        .map(|jvm_process| {
+            let name = jvm_process
+                .hsperfdata_path
+                .as_ref()
+                .and_then(|path| fs::read(path).ok())
+                .and_then(|data| parse_hsperfdata(&data).ok())
+                .and_then(|counters| hsperfdata_jvm_name(&counters))
+                .or_else(|| main_class_from_cmdline(&jvm_process.cmdline))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("Unknown_JVM_{}", jvm_process.pid));
+            (
+                jvm_process.pid,
+                DiscoveredJvm {
+                    name,
+                    hsperfdata_path: jvm_process.hsperfdata_path,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Discover the hsperfdata files of the running JVMs by their PIDs, without reading them.
+#[cfg(target_os = "linux")]
+pub fn discover_hsperfdata_files() -> HashMap<String, PathBuf> {
+    scan_jvm_processes()
+        .into_iter()
+        .filter_map(|jvm_process| Some((jvm_process.pid, jvm_process.hsperfdata_path?)))
+        .collect()
+}
+
+/// Find the hsperfdata files of the JVMs of all users in a tmp directory, by their PIDs.
+#[cfg(target_os = "linux")]
+fn read_hsperfdata_files(tmp_dir: &Path) -> HashMap<String, PathBuf> {
+    let mut hsperfdata_files = HashMap::new();
+    let Ok(tmp_entries) = fs::read_dir(tmp_dir) else {
+        return hsperfdata_files;
+    };
+    for user_dir in tmp_entries.flatten().filter(|entry| {
+        entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with("hsperfdata_")
+    }) {
+        let Ok(user_entries) = fs::read_dir(user_dir.path()) else {
+            continue;
+        };
+        for entry in user_entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if file_name.parse::<u32>().is_ok() {
+                hsperfdata_files.insert(file_name, entry.path());
+            }
+        }
+    }
+    hsperfdata_files
+}
+
+/// Get the PID of a process in its innermost PID namespace, from the NSpid line of its status.
+#[cfg(target_os = "linux")]
+fn namespace_pid(proc_dir: &Path) -> Option<String> {
+    fs::read_to_string(proc_dir.join("status"))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))
+        .and_then(|pids| pids.split_whitespace().last())
+        .map(|pid| pid.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an hsperfdata file of the given byte order with the long and string counters.
+    fn build_hsperfdata(little_endian: bool, counters: &[(&str, PerfDataValue)]) -> Vec<u8> {
+        let i32_bytes = |v: i32| match little_endian {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        };
+        let mut data = HSPERFDATA_MAGIC.to_be_bytes().to_vec();
+        data.extend([little_endian as u8, 2, 0, 1]);
+        data.extend([0; 16]);
+        data.extend(i32_bytes(HSPERFDATA_PROLOGUE_SIZE as i32));
+        data.extend(i32_bytes(counters.len() as i32));
+        for (name, value) in counters {
+            let mut name_bytes = name.as_bytes().to_vec();
+            name_bytes.push(0);
+            let (data_type, vector_length, value_bytes) = match value {
+                PerfDataValue::Long(v) => (
+                    b'J',
+                    0,
+                    match little_endian {
+                        true => v.to_le_bytes().to_vec(),
+                        false => v.to_be_bytes().to_vec(),
+                    },
+                ),
+                PerfDataValue::String(s) => {
+                    let mut bytes = s.as_bytes().to_vec();
+                    bytes.resize(s.len() + 8, 0);
+                    (b'B', bytes.len() as i32, bytes)
+                }
+            };
+            let name_offset = 20;
+            let data_offset = name_offset + name_bytes.len();
+            let entry_length = data_offset + value_bytes.len();
+            data.extend(i32_bytes(entry_length as i32));
+            data.extend(i32_bytes(name_offset as i32));
+            data.extend(i32_bytes(vector_length));
+            data.extend([data_type, 0, 1, 1]);
+            data.extend(i32_bytes(data_offset as i32));
+            data.extend(name_bytes);
+            data.extend(value_bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_hsperfdata() {
+        let counters = [
+            ("sun.gc.collector.0.invocations", PerfDataValue::Long(42)),
+            (
+                JAVA_COMMAND_COUNTER,
+                PerfDataValue::String("org.apache.kafka.Kafka config/server.properties".into()),
+            ),
+        ];
+        for little_endian in [true, false] {
+            let parsed = parse_hsperfdata(&build_hsperfdata(little_endian, &counters)).unwrap();
+            assert_eq!(parsed.len(), 2);
+            for (name, value) in &counters {
+                assert_eq!(&parsed[*name], value);
+            }
+        }
+
+        assert!(parse_hsperfdata(b"not an hsperfdata file").is_err());
+        let mut truncated = build_hsperfdata(true, &counters);
+        truncated.truncate(60);
+        assert!(parse_hsperfdata(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_main_class() {
+        assert_eq!(
+            main_class("org.apache.kafka.Kafka config/server.properties"),
+            "Kafka"
+        );
+        assert_eq!(main_class("/opt/app/app.jar --port 8080"), "app.jar");
+        assert_eq!(main_class("jdk.compiler/com.sun.tools.javac.Main"), "Main");
+        assert_eq!(main_class("Main"), "Main");
+        assert_eq!(main_class(""), "");
+    }
+
+    #[test]
+    fn test_main_class_from_cmdline() {
+        let cmdline = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            main_class_from_cmdline(&cmdline(&[
+                "/usr/bin/java",
+                "-Xmx1g",
+                "-cp",
+                "lib/*",
+                "org.example.Server",
+                "--port",
+                "8080"
+            ])),
+            Some("Server".to_string())
+        );
+        assert_eq!(
+            main_class_from_cmdline(&cmdline(&["java", "-XX:+UseG1GC", "-jar", "/opt/app.jar"])),
+            Some("app.jar".to_string())
+        );
+        assert_eq!(
+            main_class_from_cmdline(&cmdline(&["java", "--module=app/org.example.App"])),
+            Some("App".to_string())
+        );
+        assert_eq!(
+            main_class_from_cmdline(&cmdline(&["java", "-version"])),
+            None
+        );
+    }
+}
@@ -3,15 +3,25 @@
 //! This module contains:
 //! - Core profile data structures ([`Profile`], [`CCTree`], [`ThreadState`], etc.)
 //! - [`jfr`] — JFR (Java Flight Recorder) binary format parser for async-profiler output.
-
+//! - [`jvm`] — JVM discovery through `/proc` and the hsperfdata files, without the JDK tools.
+//! - [`diff`] — Differential profiles between the profiles of two runs.
+//! - [`export`] — Exporters of profiles to the pprof, speedscope and folded stacks formats.
+//! - [`ingest`] — Ingestion of the profiles of other tools in the same formats.
+//! - [`transform`] — Report-time transforms of the stacks of profiles, such as dropping frames.
+
+pub mod diff;
+pub mod export;
+pub mod ingest;
 pub mod jfr;
+pub mod jvm;
 pub mod perf;
 pub mod symbols;
+pub mod transform;
 
 pub const BUCKET_WIDTH_MS: u64 = 20;
 
 use crate::data::common::data_formats::Profiler;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::cell::OnceCell;
@@ -67,6 +77,22 @@ pub struct FrameMap {
 pub struct Frame {
     pub name: String,
     pub node_ids: Vec<usize>,
+    /// The source file of the function and its lowest sampled line, if debug info is available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+}
+
+/// A location in the source code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
 }
 
 impl FrameMap {
@@ -86,6 +112,7 @@ impl FrameMap {
         self.frame_id_to_frame.push(Frame {
             name: name.to_string(),
             node_ids: Vec::new(),
+            location: None,
         });
         self.frame_name_to_frame_id.insert(name.to_string(), id);
         id
@@ -101,6 +128,38 @@ impl FrameMap {
         &self.frame_id_to_frame[id].node_ids
     }
 
+    /// Get the source location of a frame, if any.
+    pub fn location(&self, id: usize) -> Option<&SourceLocation> {
+        self.frame_id_to_frame[id].location.as_ref()
+    }
+
+    /// Record a source location of a frame. A function is sampled at many lines, so the lowest
+    /// line in the file of the first location is kept, which is the closest to its definition.
+    pub fn add_location(&mut self, id: usize, location: &SourceLocation) {
+        let frame = &mut self.frame_id_to_frame[id];
+        match frame.location.as_mut() {
+            Some(frame_location) if frame_location.file == location.file => {
+                frame_location.line = frame_location.line.min(location.line);
+            }
+            Some(_) => {}
+            None => frame.location = Some(location.clone()),
+        }
+    }
+
+    /// Copy the source locations of the frames of another frame map with the same names.
+    pub fn copy_locations(&mut self, other: &FrameMap) {
+        let locations: HashMap<&str, &SourceLocation> = other
+            .frame_id_to_frame
+            .iter()
+            .filter_map(|frame| Some((frame.name.as_str(), frame.location.as_ref()?)))
+            .collect();
+        for frame in &mut self.frame_id_to_frame {
+            if let Some(&location) = locations.get(frame.name.as_str()) {
+                frame.location = Some(location.clone());
+            }
+        }
+    }
+
     /// Register a node_id under a frame.
     pub fn add_node(&mut self, frame_id: usize, node_id: usize) {
         self.frame_id_to_frame[frame_id].node_ids.push(node_id);
@@ -124,6 +183,27 @@ impl Profiler {
         thread_state: ThreadState,
         frames: &[String],
         count: u64,
+    ) {
+        self.insert_stack_with_locations(
+            profile_type,
+            sample_time_ms,
+            thread_state,
+            frames,
+            &[],
+            count,
+        );
+    }
+
+    /// Insert a stack sample like [`Profiler::insert_stack`], along with the source locations
+    /// of its frames (see [`Profile::add_frame_locations`]).
+    pub fn insert_stack_with_locations(
+        &mut self,
+        profile_type: &str,
+        sample_time_ms: i64,
+        thread_state: ThreadState,
+        frames: &[String],
+        locations: &[Option<&SourceLocation>],
+        count: u64,
     ) {
         let profile = self
             .profiles
@@ -137,6 +217,7 @@ impl Profiler {
             frames,
             count,
         );
+        profile.add_frame_locations(frames, locations);
     }
 
     /// Get sample count for a stack pattern in a specific profile type.
@@ -171,6 +252,38 @@ impl Profiler {
         }
     }
 
+    /// Insert every stack in the collapsed format (see [`Profile::generate_collapsed`]), such as
+    /// the folded stacks generated by other profilers, with the same sample time.
+    pub fn insert_collapsed(
+        &mut self,
+        profile_type: &str,
+        sample_time_ms: i64,
+        collapsed: &str,
+    ) -> Result<()> {
+        for (line_idx, line) in collapsed.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (stack, count) = match line
+                .rsplit_once(' ')
+                .and_then(|(stack, count)| Some((stack, count.parse::<u64>().ok()?)))
+            {
+                Some((stack, count)) => (stack, count),
+                None => bail!("Invalid collapsed stack at line {}: {}", line_idx + 1, line),
+            };
+            let frames: Vec<String> = stack.split(';').map(|frame| frame.to_string()).collect();
+            self.insert_stack(
+                profile_type,
+                sample_time_ms,
+                ThreadState::None,
+                &frames,
+                count,
+            );
+        }
+        Ok(())
+    }
+
     /// Update context_tree sample_counts to contain the aggregate of samples between the specified
     /// start and end times.
     pub fn set_time_range(
@@ -328,13 +441,37 @@ impl Profile {
         frames: &[String],
         count: u64,
     ) {
-        // Calculate block index and extend blocks vec if necessary
-        let thread_state_id = thread_state.id();
-        // Align blocks by width
+        // Calculate block index, aligning blocks by width
         let start_block_ms = start_time_ms - (start_time_ms % (bucket_width_ms as i64));
         let offset_ms = (sample_time_ms - start_block_ms).max(0) as u64;
         let block_idx = (offset_ms / bucket_width_ms) as usize;
+        self.insert_stack_in_block(block_idx, thread_state.id(), frames, count);
+    }
+
+    /// Record the source locations of the frames of a stack, where locations has the location
+    /// of every frame, if known.
+    pub fn add_frame_locations(
+        &mut self,
+        frames: &[String],
+        locations: &[Option<&SourceLocation>],
+    ) {
+        for (frame_name, location) in frames.iter().zip(locations) {
+            if let Some(location) = location {
+                let frame_id = self.frame_map.get_or_insert(frame_name);
+                self.frame_map.add_location(frame_id, location);
+            }
+        }
+    }
 
+    /// Insert a stack frame with count into the time block of the given index, extending the
+    /// blocks vec if necessary.
+    fn insert_stack_in_block(
+        &mut self,
+        block_idx: usize,
+        thread_state_id: u8,
+        frames: &[String],
+        count: u64,
+    ) {
         while self.blocks.len() <= block_idx {
             self.blocks.push(HashMap::new());
         }
@@ -414,42 +551,48 @@ impl Profile {
     /// frame1;frame2;frame3 10
     /// frame1;frame4 20
     pub fn generate_collapsed(&self, thread_states: &[ThreadState]) -> String {
-        let thread_state_ids = self.resolve_thread_states(thread_states);
         let mut result = String::new();
-        let mut path: Vec<usize> = Vec::new();
-        self.dfs_collapsed(0, &thread_state_ids, &mut path, &mut result);
+        self.for_each_stack(thread_states, |frame_ids, self_samples| {
+            let stack: String = frame_ids
+                .iter()
+                .map(|&frame_id| self.frame_map.name(frame_id))
+                .collect::<Vec<_>>()
+                .join(";");
+            result.push_str(&format!("{} {}\n", stack, self_samples));
+        });
         result
     }
 
-    fn dfs_collapsed(
+    /// Call f with the frame IDs (from root to leaf) and the self samples of every call stack
+    /// that has self samples for the specified thread states, in depth-first order.
+    pub fn for_each_stack(&self, thread_states: &[ThreadState], mut f: impl FnMut(&[usize], u64)) {
+        let thread_state_ids = self.resolve_thread_states(thread_states);
+        let mut frame_ids: Vec<usize> = Vec::new();
+        self.dfs_stacks(0, &thread_state_ids, &mut frame_ids, &mut f);
+    }
+
+    fn dfs_stacks(
         &self,
         node_id: usize,
         thread_state_ids: &[u8],
-        path: &mut Vec<usize>,
-        result: &mut String,
+        frame_ids: &mut Vec<usize>,
+        f: &mut impl FnMut(&[usize], u64),
     ) {
         let node = &self.context_tree[node_id];
-
-        // Emit line if this node has self_samples for any requested thread state
-        if !path.is_empty() {
+        if !frame_ids.is_empty() {
             let self_samples: u64 = thread_state_ids
                 .iter()
                 .filter_map(|ts| node.sample_stats.get(ts).map(|s| s.self_samples))
                 .sum();
             if self_samples > 0 {
-                let stack: String = path
-                    .iter()
-                    .map(|&nid| self.frame_map.name(self.context_tree[nid].frame_id))
-                    .collect::<Vec<_>>()
-                    .join(";");
-                result.push_str(&format!("{} {}\n", stack, self_samples));
+                f(frame_ids, self_samples);
             }
         }
 
-        for (&_frame_id, &child_node_id) in &node.children {
-            path.push(child_node_id);
-            self.dfs_collapsed(child_node_id, thread_state_ids, path, result);
-            path.pop();
+        for (&frame_id, &child_node_id) in &node.children {
+            frame_ids.push(frame_id);
+            self.dfs_stacks(child_node_id, thread_state_ids, frame_ids, f);
+            frame_ids.pop();
         }
     }
 
@@ -546,6 +689,14 @@ impl FrameType {
         "(_\\[(0|j|i|k|1|v)\\])?"
     }
 
+    /// Whether the frame is of a Java method, in the frame types of JFR stacks.
+    pub(crate) fn is_java(&self) -> bool {
+        matches!(
+            self,
+            FrameType::Jit | FrameType::Inlined | FrameType::Interpreted | FrameType::C1
+        )
+    }
+
     pub(crate) fn from_jfr_name(name: &str) -> Self {
         match name {
             "Interpreted" => FrameType::Interpreted,
@@ -565,13 +716,22 @@ pub enum ThreadState {
     AsyncRunnable = 1,
     AsyncSleeping = 2,
     AsyncDefault = 3,
+    /// Off-CPU time spent waiting for a CPU after being preempted or woken up.
+    SchedRunnable = 4,
+    /// Off-CPU time spent in interruptible sleep (e.g. futex, epoll or nanosleep).
+    SchedSleeping = 5,
+    /// Off-CPU time spent in uninterruptible sleep, which is typically blocking I/O.
+    SchedUninterruptible = 6,
 }
 
 impl ThreadState {
-    pub const ALL: [ThreadState; 3] = [
+    pub const ALL: [ThreadState; 6] = [
         ThreadState::AsyncRunnable,
         ThreadState::AsyncSleeping,
         ThreadState::AsyncDefault,
+        ThreadState::SchedRunnable,
+        ThreadState::SchedSleeping,
+        ThreadState::SchedUninterruptible,
     ];
 
     pub fn from_str(name: &str) -> Self {
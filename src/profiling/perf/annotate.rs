@@ -0,0 +1,207 @@
+use crate::data::common::data_formats::{AnnotatedFunction, AnnotatedInstruction};
+use crate::profiling::symbols::ElfFunction;
+use crate::run_command_and_wait;
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// The maximum number of the hottest functions of every profile type to annotate.
+const MAX_ANNOTATED_FUNCTIONS: usize = 10;
+
+/// The samples taken at the instructions of the functions in ELF files, by the attribute index
+/// of the sampled event, which are used to annotate the disassembly of the hottest functions.
+#[derive(Debug, Default)]
+pub struct InstructionSamples {
+    functions: HashMap<(usize, ElfFunction), HashMap<u64, u64>>,
+}
+
+impl InstructionSamples {
+    /// Add the weight of a sample taken at the instruction address (the virtual address in the
+    /// ELF file) of a function.
+    pub fn add(&mut self, attr_index: usize, function: ElfFunction, address: u64, weight: u64) {
+        *self
+            .functions
+            .entry((attr_index, function))
+            .or_default()
+            .entry(address)
+            .or_default() += weight;
+    }
+
+    /// Disassemble the hottest functions of every event through objdump, and annotate their
+    /// instructions with the samples taken at them. Functions that cannot be disassembled, such
+    /// as the ones in an ELF file of another architecture, are skipped.
+    pub fn annotate(&self, event_profile_types: &[String]) -> Vec<AnnotatedFunction> {
+        let annotate_start_time = Instant::now();
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_cached_key(|((attr_index, function), instruction_samples)| {
+            (
+                *attr_index,
+                Reverse(instruction_samples.values().sum::<u64>()),
+                function.name.clone(),
+            )
+        });
+
+        let mut annotated_functions = Vec::new();
+        let mut num_functions_per_event: HashMap<usize, usize> = HashMap::new();
+        for ((attr_index, function), instruction_samples) in functions {
+            let Some(profile_type) = event_profile_types.get(*attr_index) else {
+                continue;
+            };
+            let num_functions = num_functions_per_event.entry(*attr_index).or_default();
+            if *num_functions >= MAX_ANNOTATED_FUNCTIONS {
+                continue;
+            }
+            *num_functions += 1;
+            match disassemble(function) {
+                Ok(instructions) => annotated_functions.push(annotate_function(
+                    profile_type,
+                    function,
+                    instruction_samples,
+                    instructions,
+                )),
+                Err(e) => {
+                    warn!("Skipping the annotation of {}: {e}", function.name);
+                    // Every other function would fail in the same way without objdump.
+                    if e.downcast_ref::<std::io::Error>().is_some() {
+                        break;
+                    }
+                }
+            }
+        }
+        annotated_functions.sort_by(|a, b| {
+            a.profile_type
+                .cmp(&b.profile_type)
+                .then(b.samples.cmp(&a.samples))
+        });
+        debug!(
+            "Annotated {} functions in {:?}",
+            annotated_functions.len(),
+            annotate_start_time.elapsed()
+        );
+        annotated_functions
+    }
+}
+
+/// Disassemble a function from its ELF file through objdump.
+fn disassemble(function: &ElfFunction) -> Result<Vec<(u64, String)>> {
+    let output = run_command_and_wait(
+        "objdump",
+        [
+            "-d",
+            "-C",
+            "--no-show-raw-insn",
+            &format!("--start-address={:#x}", function.start),
+            &format!("--stop-address={:#x}", function.end),
+            &function.elf_data_path,
+        ],
+        "objdump",
+        None,
+    )?;
+    if !output.status.success() {
+        bail!(
+            "objdump failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_objdump_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Parse the instruction lines of the objdump output, such as "  401126:\tpush   %rbp", into the
+/// addresses and the instructions.
+fn parse_objdump_output(output: &str) -> Vec<(u64, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (address, instruction) = line.trim_start().split_once(":\t")?;
+            let address = u64::from_str_radix(address, 16).ok()?;
+            Some((address, instruction.trim_end().replace('\t', " ")))
+        })
+        .collect()
+}
+
+fn annotate_function(
+    profile_type: &str,
+    function: &ElfFunction,
+    instruction_samples: &HashMap<u64, u64>,
+    instructions: Vec<(u64, String)>,
+) -> AnnotatedFunction {
+    AnnotatedFunction {
+        profile_type: profile_type.to_string(),
+        name: function.name.clone(),
+        source: function.source.clone(),
+        samples: instruction_samples.values().sum(),
+        instructions: instructions
+            .into_iter()
+            .map(|(address, instruction)| AnnotatedInstruction {
+                address,
+                instruction,
+                samples: instruction_samples.get(&address).copied().unwrap_or(0),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_objdump_output() {
+        let output = "
+/usr/bin/app:     file format elf64-x86-64
+
+
+Disassembly of section .text:
+
+0000000000401126 <main>:
+  401126:\tpush   %rbp
+  401127:\tmov    %rsp,%rbp
+  40112a:\tvaddps\t%ymm1,%ymm0,%ymm0
+";
+        assert_eq!(
+            parse_objdump_output(output),
+            vec![
+                (0x401126, "push   %rbp".to_string()),
+                (0x401127, "mov    %rsp,%rbp".to_string()),
+                (0x40112a, "vaddps %ymm1,%ymm0,%ymm0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotate_function() {
+        let function = ElfFunction {
+            name: "main".to_string(),
+            source: "/usr/bin/app".to_string(),
+            elf_data_path: "/usr/bin/app".to_string(),
+            start: 0x401126,
+            end: 0x40112e,
+        };
+        let mut instruction_samples = InstructionSamples::default();
+        instruction_samples.add(0, function.clone(), 0x40112a, 3);
+        instruction_samples.add(0, function.clone(), 0x40112a, 2);
+        instruction_samples.add(0, function.clone(), 0x401126, 1);
+
+        let annotated_function = annotate_function(
+            "cpu",
+            &function,
+            &instruction_samples.functions[&(0, function.clone())],
+            vec![
+                (0x401126, "push   %rbp".to_string()),
+                (0x401127, "mov    %rsp,%rbp".to_string()),
+                (0x40112a, "vaddps %ymm1,%ymm0,%ymm0".to_string()),
+            ],
+        );
+        assert_eq!(annotated_function.samples, 6);
+        let samples: Vec<u64> = annotated_function
+            .instructions
+            .iter()
+            .map(|instruction| instruction.samples)
+            .collect();
+        assert_eq!(samples, vec![1, 0, 5]);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::profiling::jfr::{read_java_stacks, JavaStack};
+use crate::profiling::symbols::ResolvedSymbol;
+use crate::profiling::FrameType;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The maximum distance between the timestamps of a Perf sample and a JFR sample of the same
+/// thread for the Java stack of the latter to be used by the former. It is the default interval
+/// of the async-profiler cpu event.
+const MAX_CORRELATION_DISTANCE_NS: u64 = 10_000_000;
+
+/// The symbol of the HotSpot template interpreter in the perf map of a JVM, which covers all
+/// interpreted Java methods.
+const HOTSPOT_INTERPRETER_SYMBOL: &str = "Interpreter";
+
+/// The Java stacks sampled by async-profiler, by the PID and TID of the sampled thread, used to
+/// fill in the Java frames of the Perf samples of the JVMs that could not be resolved through
+/// their perf maps. The stacks of a thread are sorted by timestamp.
+#[derive(Debug, Default)]
+pub struct JavaStacks {
+    stacks: HashMap<(i32, i32), Vec<JavaStack>>,
+}
+
+impl JavaStacks {
+    /// Read the Java stacks from the JFR recorded for every JVM PID. A JFR that cannot be read
+    /// is skipped, leaving the Java frames of the JVM to its perf map.
+    pub fn from_jfr_files(jfr_paths: &HashMap<i32, PathBuf>) -> Self {
+        let mut java_stacks = JavaStacks::default();
+        for (&pid, jfr_path) in jfr_paths {
+            match read_java_stacks(jfr_path) {
+                Ok(stacks) => {
+                    debug!("Read {} Java stacks of JVM {pid}", stacks.len());
+                    for stack in stacks {
+                        java_stacks.insert(pid, stack);
+                    }
+                }
+                Err(e) => warn!("Failed to read the Java stacks of JVM {pid}: {e}"),
+            }
+        }
+        java_stacks
+    }
+
+    /// Add the Java stack of a thread of the JVM, keeping the stacks of the thread sorted.
+    pub fn insert(&mut self, pid: i32, stack: JavaStack) {
+        let stacks = self.stacks.entry((pid, stack.tid)).or_default();
+        let idx = stacks.partition_point(|other| other.timestamp <= stack.timestamp);
+        stacks.insert(idx, stack);
+    }
+
+    /// The frames of the Java stack of the thread sampled closest to the timestamp (in
+    /// nanoseconds of CLOCK_MONOTONIC), if it is within MAX_CORRELATION_DISTANCE_NS.
+    pub fn find(&self, pid: i32, tid: i32, timestamp: u64) -> Option<&[String]> {
+        let stacks = self.stacks.get(&(pid, tid))?;
+        let idx = stacks.partition_point(|stack| stack.timestamp < timestamp);
+        [idx.checked_sub(1), Some(idx)]
+            .into_iter()
+            .flatten()
+            .filter_map(|idx| stacks.get(idx))
+            .map(|stack| (stack.timestamp.abs_diff(timestamp), stack))
+            .filter(|(distance, _)| *distance <= MAX_CORRELATION_DISTANCE_NS)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, stack)| stack.frames.as_slice())
+    }
+}
+
+/// Build the mixed-mode stack of a Perf sample of a JVM, from the root to the leaf. The Java
+/// frames that Perf could not resolve, i.e. the unknown frames and the frames of the template
+/// interpreter, are replaced with the Java stack of the thread sampled by async-profiler at the
+/// same time, which is kept above the native and kernel frames that Perf sampled below the
+/// innermost of them, such as JNI, epoll or syscall frames. Without a Java stack, or if Perf
+/// resolved every Java frame through the perf map, the frames are kept as they are.
+pub fn mixed_frames(
+    frames: &[String],
+    resolved_frames: &[Option<&ResolvedSymbol>],
+    java_frames: Option<&[String]>,
+) -> Vec<String> {
+    let Some(java_frames) = java_frames else {
+        return frames.to_vec();
+    };
+    // The kernel frames are always below the userspace ones.
+    let num_user_frames = resolved_frames
+        .iter()
+        .position(|resolved_symbol| {
+            resolved_symbol.is_some_and(|s| matches!(s.frame_type, FrameType::Kernel))
+        })
+        .unwrap_or(resolved_frames.len());
+    let innermost_unresolved_java_frame = resolved_frames[..num_user_frames].iter().rposition(
+        |resolved_symbol| match resolved_symbol {
+            None => true,
+            Some(s) => {
+                matches!(s.frame_type, FrameType::Jit) && s.name == HOTSPOT_INTERPRETER_SYMBOL
+            }
+        },
+    );
+    match innermost_unresolved_java_frame {
+        Some(idx) => java_frames
+            .iter()
+            .chain(&frames[idx + 1..])
+            .cloned()
+            .collect(),
+        None => frames.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved_symbol(name: &str, frame_type: FrameType) -> ResolvedSymbol {
+        ResolvedSymbol {
+            name: name.to_string(),
+            offset: 0,
+            source: String::new(),
+            frame_type,
+            location: None,
+            inlined_frames: Vec::new(),
+        }
+    }
+
+    fn java_stack(tid: i32, timestamp: u64, frames: &[&str]) -> JavaStack {
+        JavaStack {
+            tid,
+            timestamp,
+            frames: frames.iter().map(|frame| frame.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_java_stacks_find() {
+        let mut java_stacks = JavaStacks::default();
+        java_stacks.insert(100, java_stack(101, 120_000_000, &["B.run_[j]"]));
+        java_stacks.insert(100, java_stack(101, 100_000_000, &["A.run_[j]"]));
+        java_stacks.insert(100, java_stack(102, 100_000_000, &["C.run_[j]"]));
+
+        assert_eq!(
+            java_stacks.find(100, 101, 108_000_000),
+            Some(&["A.run_[j]".to_string()][..])
+        );
+        assert_eq!(
+            java_stacks.find(100, 101, 112_000_000),
+            Some(&["B.run_[j]".to_string()][..])
+        );
+        assert_eq!(
+            java_stacks.find(100, 101, 125_000_000),
+            Some(&["B.run_[j]".to_string()][..])
+        );
+        // Too far from any sample of the thread
+        assert_eq!(java_stacks.find(100, 101, 140_000_000), None);
+        assert_eq!(java_stacks.find(100, 103, 100_000_000), None);
+        assert_eq!(java_stacks.find(200, 101, 100_000_000), None);
+    }
+
+    #[test]
+    fn test_mixed_frames() {
+        let start_thread = resolved_symbol("start_thread", FrameType::Native);
+        let call_stub = resolved_symbol("JavaCalls::call_helper", FrameType::Native);
+        let interpreter = resolved_symbol(HOTSPOT_INTERPRETER_SYMBOL, FrameType::Jit);
+        let jit_method =
+            resolved_symbol("io.netty.channel.epoll.Native::epollWait", FrameType::Jit);
+        let epoll_wait = resolved_symbol("epoll_wait", FrameType::Native);
+        let do_syscall = resolved_symbol("do_syscall_64", FrameType::Kernel);
+        let frame = |resolved_symbol: &Option<&ResolvedSymbol>| {
+            resolved_symbol.map_or("[unknown]".to_string(), |s| {
+                format!("{}{}", s.name, s.frame_type.literal_suffix())
+            })
+        };
+        let java_frames: Vec<String> = vec![
+            "start_thread".to_string(),
+            "io.netty.channel.nio.EventLoop.run_[j]".to_string(),
+            "io.netty.channel.epoll.Native.epollWait_[j]".to_string(),
+        ];
+
+        // The unknown JIT frames are replaced, keeping the JNI and kernel frames below them
+        let resolved_frames = vec![
+            Some(&start_thread),
+            Some(&call_stub),
+            None,
+            None,
+            Some(&epoll_wait),
+            Some(&do_syscall),
+        ];
+        let frames: Vec<String> = resolved_frames.iter().map(frame).collect();
+        assert_eq!(
+            mixed_frames(&frames, &resolved_frames, Some(&java_frames)),
+            vec![
+                "start_thread",
+                "io.netty.channel.nio.EventLoop.run_[j]",
+                "io.netty.channel.epoll.Native.epollWait_[j]",
+                "epoll_wait",
+                "do_syscall_64_[k]",
+            ]
+        );
+        // Without a Java stack, the frames are kept
+        assert_eq!(mixed_frames(&frames, &resolved_frames, None), frames);
+
+        // The frames of the interpreter are replaced as well
+        let resolved_frames = vec![Some(&start_thread), Some(&interpreter), Some(&epoll_wait)];
+        let frames: Vec<String> = resolved_frames.iter().map(frame).collect();
+        assert_eq!(
+            mixed_frames(&frames, &resolved_frames, Some(&java_frames))[3..],
+            ["epoll_wait".to_string()]
+        );
+
+        // The frames resolved through the perf map are kept
+        let resolved_frames = vec![
+            Some(&start_thread),
+            Some(&jit_method),
+            Some(&epoll_wait),
+            Some(&do_syscall),
+        ];
+        let frames: Vec<String> = resolved_frames.iter().map(frame).collect();
+        assert_eq!(
+            mixed_frames(&frames, &resolved_frames, Some(&java_frames)),
+            vec![
+                "start_thread",
+                "io.netty.channel.epoll.Native::epollWait_[j]",
+                "epoll_wait",
+                "do_syscall_64_[k]",
+            ]
+        );
+    }
+}
@@ -1,19 +1,92 @@
 #![cfg(target_os = "linux")]
 
+mod annotate;
+mod mixed;
+mod offcpu;
 pub mod parser;
 
+use crate::profiling::perf::annotate::InstructionSamples;
 use crate::profiling::symbols::ResolvedSymbol;
+use crate::profiling::ThreadState;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+/// The profile type of on-CPU samples collected through the cpu-clock event.
+pub const ON_CPU_PROFILE_TYPE: &str = "cpu";
+/// The profile type of off-CPU time collected through the scheduler tracepoints.
+pub const OFF_CPU_PROFILE_TYPE: &str = "offcpu";
 
 /// The information of a parsed Perf sample.
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct PerfSample {
     /// The PID that this sample belongs to.
     pid: i32,
+    /// The TID of the thread that this sample belongs to.
+    tid: i32,
+    /// The CPU that the sample was taken on, if recorded.
+    cpu: Option<u32>,
     /// The timestamp of the sample in nanoseconds from the EPOCH.
     timestamp: u64,
     /// The symbolicated call chain of the sample.
     /// The order is from leaf to root.
     call_chain: Vec<Option<ResolvedSymbol>>,
+    /// The state of the thread, which is ThreadState::None for on-CPU samples.
+    thread_state: ThreadState,
+    /// The index of the sampled event in the attribute table of the profile.
+    attr_index: usize,
+    /// The weight of the sample: 1 for cpu-clock samples, the number of occurrences for the
+    /// samples of other PMU events, and the off-CPU time in microseconds for off-CPU samples.
+    weight: u64,
+}
+
+/// All samples parsed from a raw Perf profile, along with the names of the threads.
+#[derive(Debug, Default)]
+struct ParsedPerfData {
+    samples: Vec<PerfSample>,
+    /// The latest name (comm) of every TID. The name of a process is the one of its main thread.
+    thread_names: HashMap<i32, String>,
+    /// The profile type of the on-CPU samples of every event, indexed by the attribute index.
+    event_profile_types: Vec<String>,
+    /// The samples taken at the instructions of the functions in ELF files.
+    instruction_samples: InstructionSamples,
+    /// The PIDs of the sampled processes that are HotSpot JVMs.
+    hotspot_jvm_pids: HashSet<i32>,
+}
+
+/// Add the sampling period to the terms of a PMU event, such as "cache-misses/period=N/" or
+/// "cpu/event=0x3c,period=N/u". Events that already set their period are kept as they are.
+pub fn perf_event_with_period(event: &str, period: u64) -> String {
+    if event.contains("period=") {
+        return event.to_string();
+    }
+    if let Some(terms_end) = event.rfind('/') {
+        return format!(
+            "{},period={}{}",
+            &event[..terms_end],
+            period,
+            &event[terms_end..]
+        );
+    }
+    match event.split_once(':') {
+        Some((name, modifiers)) => format!("{name}/period={period}/{modifiers}"),
+        None => format!("{event}/period={period}/"),
+    }
+}
+
+/// The profile type of the samples of a PMU event, which is the event as specified by the user,
+/// i.e. without the period term added by perf_event_with_period.
+fn event_profile_type(event_name: &str) -> String {
+    let period_only_terms = Regex::new(r"^([^/]+)/period=\d+/(.*)$").unwrap();
+    if let Some(captures) = period_only_terms.captures(event_name) {
+        return match &captures[2] {
+            "" => captures[1].to_string(),
+            modifiers => format!("{}:{}", &captures[1], modifiers),
+        };
+    }
+    Regex::new(r",period=\d+")
+        .unwrap()
+        .replace_all(event_name, "")
+        .to_string()
 }
 
 // See below constants in https://github.com/torvalds/linux/blob/master/include/uapi/linux/perf_event.h
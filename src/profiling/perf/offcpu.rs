@@ -0,0 +1,372 @@
+use crate::profiling::ThreadState;
+use std::collections::HashMap;
+
+/// The name of the tracepoint that fires whenever a CPU switches from one task to another.
+pub const SCHED_SWITCH_EVENT: &str = "sched:sched_switch";
+/// The name of the tracepoint that fires whenever a sleeping task is woken up.
+pub const SCHED_WAKEUP_EVENT: &str = "sched:sched_wakeup";
+
+// See the TASK_* constants in https://github.com/torvalds/linux/blob/master/include/linux/sched.h
+const TASK_INTERRUPTIBLE: u64 = 0x1;
+const TASK_UNINTERRUPTIBLE: u64 = 0x2;
+
+/// The location of a field within the raw data of a tracepoint sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TracepointField {
+    offset: usize,
+    size: usize,
+}
+
+impl TracepointField {
+    fn read(&self, raw: &[u8]) -> Option<u64> {
+        let bytes = raw.get(self.offset..self.offset + self.size)?;
+        // Tracepoint data is always recorded in the native endianness of the profiled system.
+        Some(match self.size {
+            1 => bytes[0] as u64,
+            2 => u16::from_ne_bytes(bytes.try_into().ok()?) as u64,
+            4 => u32::from_ne_bytes(bytes.try_into().ok()?) as u64,
+            8 => u64::from_ne_bytes(bytes.try_into().ok()?),
+            _ => return None,
+        })
+    }
+}
+
+/// The layout of the sched_switch and sched_wakeup tracepoint fields that are needed for
+/// off-CPU profiling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedTracepointFormat {
+    prev_pid: TracepointField,
+    prev_state: TracepointField,
+    next_pid: TracepointField,
+    wakeup_pid: TracepointField,
+}
+
+impl Default for SchedTracepointFormat {
+    /// The layout used by 64-bit kernels, where every tracepoint starts with 8 bytes of common
+    /// fields followed by the event-specific fields.
+    fn default() -> Self {
+        SchedTracepointFormat {
+            prev_pid: TracepointField {
+                offset: 24,
+                size: 4,
+            },
+            prev_state: TracepointField {
+                offset: 32,
+                size: 8,
+            },
+            next_pid: TracepointField {
+                offset: 56,
+                size: 4,
+            },
+            wakeup_pid: TracepointField {
+                offset: 24,
+                size: 4,
+            },
+        }
+    }
+}
+
+impl SchedTracepointFormat {
+    /// Build the layout from the tracing data saved in the Perf profile, which embeds the
+    /// tracefs format file of every recorded tracepoint. Fields that cannot be found fall
+    /// back to the default layout.
+    pub fn from_tracing_data(tracing_data: &[u8]) -> Self {
+        let tracing_data = String::from_utf8_lossy(tracing_data);
+        let mut format = SchedTracepointFormat::default();
+
+        let sched_switch_fields = parse_tracepoint_format(&tracing_data, "sched_switch");
+        if let Some(field) = sched_switch_fields.get("prev_pid") {
+            format.prev_pid = *field;
+        }
+        if let Some(field) = sched_switch_fields.get("prev_state") {
+            format.prev_state = *field;
+        }
+        if let Some(field) = sched_switch_fields.get("next_pid") {
+            format.next_pid = *field;
+        }
+        if let Some(field) = parse_tracepoint_format(&tracing_data, "sched_wakeup").get("pid") {
+            format.wakeup_pid = *field;
+        }
+
+        format
+    }
+
+    /// Parse the raw data of a sched_switch sample into (prev_pid, prev_state, next_pid).
+    pub fn parse_sched_switch(&self, raw: &[u8]) -> Option<(i32, u64, i32)> {
+        Some((
+            self.prev_pid.read(raw)? as i32,
+            self.prev_state.read(raw)?,
+            self.next_pid.read(raw)? as i32,
+        ))
+    }
+
+    /// Parse the raw data of a sched_wakeup sample into the PID of the woken task.
+    pub fn parse_sched_wakeup(&self, raw: &[u8]) -> Option<i32> {
+        Some(self.wakeup_pid.read(raw)? as i32)
+    }
+}
+
+/// Find the format description of the tracepoint and collect the offset and size of all of its
+/// fields. A format description looks like:
+///
+/// name: sched_switch
+/// ID: 316
+/// format:
+///     field:unsigned short common_type;  offset:0;  size:2;  signed:0;
+///     field:char prev_comm[16];  offset:8;  size:16;  signed:0;
+///     ...
+///
+/// print fmt: ...
+fn parse_tracepoint_format(
+    tracing_data: &str,
+    event_name: &str,
+) -> HashMap<String, TracepointField> {
+    let mut fields = HashMap::new();
+    let header = format!("name: {event_name}\n");
+    let start = match tracing_data.find(&header) {
+        Some(start) => start + header.len(),
+        None => return fields,
+    };
+
+    for line in tracing_data[start..].lines() {
+        let line = line.trim();
+        if line.starts_with("print fmt:") || line.starts_with("name:") {
+            break;
+        }
+        let mut field_name: Option<&str> = None;
+        let mut offset: Option<usize> = None;
+        let mut size: Option<usize> = None;
+        for part in line.split(';') {
+            let part = part.trim();
+            if let Some(declaration) = part.strip_prefix("field:") {
+                // The name is the last token of the declaration, without any array suffix.
+                field_name = declaration
+                    .split_whitespace()
+                    .last()
+                    .map(|name| name.split('[').next().unwrap_or(name));
+            } else if let Some(value) = part.strip_prefix("offset:") {
+                offset = value.parse().ok();
+            } else if let Some(value) = part.strip_prefix("size:") {
+                size = value.parse().ok();
+            }
+        }
+        if let (Some(field_name), Some(offset), Some(size)) = (field_name, offset, size) {
+            fields.insert(field_name.to_string(), TracepointField { offset, size });
+        }
+    }
+
+    fields
+}
+
+/// Map the prev_state of a sched_switch event to the state that the task is in until
+/// it is woken up.
+pub fn switched_out_thread_state(prev_state: u64) -> ThreadState {
+    if prev_state & TASK_UNINTERRUPTIBLE != 0 {
+        ThreadState::SchedUninterruptible
+    } else if prev_state & TASK_INTERRUPTIBLE != 0 {
+        ThreadState::SchedSleeping
+    } else if prev_state == 0 || (prev_state.is_power_of_two() && prev_state >= 0x100) {
+        // A task that is still running when switched out was preempted, which the kernel
+        // reports either as 0 or TASK_REPORT_MAX depending on the version.
+        ThreadState::SchedRunnable
+    } else {
+        // The remaining states (stopped, traced, parked, idle, etc.) are not waiting for a CPU.
+        ThreadState::SchedSleeping
+    }
+}
+
+/// The time that a task spent off-CPU between being switched out and switched back in.
+#[derive(Debug, PartialEq)]
+pub struct OffCpuInterval<T> {
+    /// The timestamp at which the task was switched out.
+    pub switch_out_timestamp: u64,
+    /// The timestamp at which the task was woken up, if it was blocked.
+    pub wakeup_timestamp: Option<u64>,
+    /// The state of the task before it was woken up.
+    pub blocked_thread_state: ThreadState,
+    /// The time spent in blocked_thread_state.
+    pub blocked_duration: u64,
+    /// The time spent waiting for a CPU after the task was woken up or preempted.
+    pub runnable_duration: u64,
+    /// The stack of the task when it was switched out.
+    pub stack: T,
+}
+
+struct SwitchedOutTask<T> {
+    timestamp: u64,
+    thread_state: ThreadState,
+    wakeup_timestamp: Option<u64>,
+    stack: T,
+}
+
+/// Tracks every task that is currently switched out, so that the time it spends off-CPU can be
+/// computed when it is switched back in. Tasks that are still off-CPU when the profile ends are
+/// not reported, since the end of their off-CPU interval is unknown.
+pub struct OffCpuTracker<T> {
+    switched_out_tasks: HashMap<i32, SwitchedOutTask<T>>,
+}
+
+impl<T> OffCpuTracker<T> {
+    pub fn new() -> Self {
+        OffCpuTracker {
+            switched_out_tasks: HashMap::new(),
+        }
+    }
+
+    /// Handle a task being switched out of a CPU.
+    pub fn switch_out(&mut self, tid: i32, timestamp: u64, prev_state: u64, stack: T) {
+        self.switched_out_tasks.insert(
+            tid,
+            SwitchedOutTask {
+                timestamp,
+                thread_state: switched_out_thread_state(prev_state),
+                wakeup_timestamp: None,
+                stack,
+            },
+        );
+    }
+
+    /// Handle a task being woken up. Only the first wakeup after a task blocks is relevant.
+    pub fn wakeup(&mut self, tid: i32, timestamp: u64) {
+        if let Some(task) = self.switched_out_tasks.get_mut(&tid) {
+            if task.thread_state != ThreadState::SchedRunnable && task.wakeup_timestamp.is_none() {
+                task.wakeup_timestamp = Some(timestamp.max(task.timestamp));
+            }
+        }
+    }
+
+    /// Handle a task being switched back in to a CPU, and return the interval it spent
+    /// off-CPU if the task was seen being switched out.
+    pub fn switch_in(&mut self, tid: i32, timestamp: u64) -> Option<OffCpuInterval<T>> {
+        let task = self.switched_out_tasks.remove(&tid)?;
+        let timestamp = timestamp.max(task.timestamp);
+        let (blocked_duration, runnable_duration) =
+            if task.thread_state == ThreadState::SchedRunnable {
+                (0, timestamp - task.timestamp)
+            } else {
+                match task.wakeup_timestamp {
+                    Some(wakeup_timestamp) => {
+                        let wakeup_timestamp = wakeup_timestamp.min(timestamp);
+                        (
+                            wakeup_timestamp - task.timestamp,
+                            timestamp - wakeup_timestamp,
+                        )
+                    }
+                    // The wakeup was not observed, so account the entire interval as blocked.
+                    None => (timestamp - task.timestamp, 0),
+                }
+            };
+
+        Some(OffCpuInterval {
+            switch_out_timestamp: task.timestamp,
+            wakeup_timestamp: task.wakeup_timestamp,
+            blocked_thread_state: task.thread_state,
+            blocked_duration,
+            runnable_duration,
+            stack: task.stack,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHED_SWITCH_FORMAT: &str = "name: sched_switch
+ID: 316
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+\tfield:unsigned char common_preempt_lazy_count;\toffset:8;\tsize:1;\tsigned:0;
+
+\tfield:char prev_comm[16];\toffset:12;\tsize:16;\tsigned:0;
+\tfield:pid_t prev_pid;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:int prev_prio;\toffset:32;\tsize:4;\tsigned:1;
+\tfield:long prev_state;\toffset:40;\tsize:8;\tsigned:1;
+\tfield:char next_comm[16];\toffset:48;\tsize:16;\tsigned:0;
+\tfield:pid_t next_pid;\toffset:64;\tsize:4;\tsigned:1;
+\tfield:int next_prio;\toffset:68;\tsize:4;\tsigned:1;
+
+print fmt: \"prev_comm=%s prev_pid=%d\"
+";
+
+    #[test]
+    fn test_parse_sched_switch_format() {
+        let format = SchedTracepointFormat::from_tracing_data(SCHED_SWITCH_FORMAT.as_bytes());
+        let mut raw = vec![0u8; 72];
+        raw[28..32].copy_from_slice(&1234i32.to_ne_bytes());
+        raw[40..48].copy_from_slice(&2u64.to_ne_bytes());
+        raw[64..68].copy_from_slice(&5678i32.to_ne_bytes());
+
+        assert_eq!(format.parse_sched_switch(&raw), Some((1234, 2, 5678)));
+        // The sched_wakeup format is missing, so the default layout is used.
+        assert_eq!(
+            format.wakeup_pid,
+            SchedTracepointFormat::default().wakeup_pid
+        );
+        // Raw data that is too short cannot be parsed.
+        assert_eq!(format.parse_sched_switch(&raw[..60]), None);
+    }
+
+    #[test]
+    fn test_switched_out_thread_state() {
+        assert_eq!(switched_out_thread_state(0), ThreadState::SchedRunnable);
+        assert_eq!(switched_out_thread_state(0x100), ThreadState::SchedRunnable);
+        assert_eq!(switched_out_thread_state(0x1), ThreadState::SchedSleeping);
+        assert_eq!(
+            switched_out_thread_state(0x2),
+            ThreadState::SchedUninterruptible
+        );
+        // TASK_IDLE is reported as TASK_UNINTERRUPTIBLE | TASK_NOLOAD.
+        assert_eq!(
+            switched_out_thread_state(0x402),
+            ThreadState::SchedUninterruptible
+        );
+        assert_eq!(switched_out_thread_state(0x80), ThreadState::SchedSleeping);
+    }
+
+    #[test]
+    fn test_off_cpu_tracker() {
+        let mut tracker: OffCpuTracker<&str> = OffCpuTracker::new();
+
+        // A sleeping task that is woken up and then waits for a CPU.
+        tracker.switch_out(1, 100, 0x1, "futex_wait");
+        tracker.wakeup(1, 400);
+        tracker.wakeup(1, 450);
+        assert_eq!(
+            tracker.switch_in(1, 500),
+            Some(OffCpuInterval {
+                switch_out_timestamp: 100,
+                wakeup_timestamp: Some(400),
+                blocked_thread_state: ThreadState::SchedSleeping,
+                blocked_duration: 300,
+                runnable_duration: 100,
+                stack: "futex_wait",
+            })
+        );
+        // The task was already switched in.
+        assert_eq!(tracker.switch_in(1, 600), None);
+
+        // A preempted task is runnable for the entire interval.
+        tracker.switch_out(2, 100, 0, "compute");
+        tracker.wakeup(2, 150);
+        let interval = tracker.switch_in(2, 200).unwrap();
+        assert_eq!(interval.blocked_duration, 0);
+        assert_eq!(interval.runnable_duration, 100);
+
+        // A task blocked on I/O without an observed wakeup.
+        tracker.switch_out(3, 100, 0x2, "io_schedule");
+        let interval = tracker.switch_in(3, 1100).unwrap();
+        assert_eq!(
+            interval.blocked_thread_state,
+            ThreadState::SchedUninterruptible
+        );
+        assert_eq!(interval.blocked_duration, 1000);
+        assert_eq!(interval.runnable_duration, 0);
+
+        // A task that was never seen being switched out.
+        assert_eq!(tracker.switch_in(4, 100), None);
+    }
+}
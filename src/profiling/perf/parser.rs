@@ -1,53 +1,113 @@
-use crate::data::common::data_formats::Profiler;
+use crate::data::common::data_formats::{Profiler, ProfilingData};
+use crate::data::perf_profile::SYSTEM_PROFILER_KEY;
+use crate::profiling::perf::annotate::InstructionSamples;
+use crate::profiling::perf::mixed::{mixed_frames, JavaStacks};
+use crate::profiling::perf::offcpu::{
+    OffCpuInterval, OffCpuTracker, SchedTracepointFormat, SCHED_SWITCH_EVENT, SCHED_WAKEUP_EVENT,
+};
 use crate::profiling::perf::{
-    PerfSample, PERF_CONTEXT_MAX, PERF_CONTEXT_USER, PERF_RECORD_MISC_FORK_EXEC,
+    event_profile_type, ParsedPerfData, PerfSample, OFF_CPU_PROFILE_TYPE, ON_CPU_PROFILE_TYPE,
+    PERF_CONTEXT_MAX, PERF_CONTEXT_USER, PERF_RECORD_MISC_FORK_EXEC,
 };
+use crate::profiling::symbols::stack_unwinder::{StackMemory, UnwindArch, UnwindRegisters};
+use crate::profiling::symbols::symbol_bundle::SymbolBundle;
 use crate::profiling::symbols::symbol_resolver::SymbolResolver;
 use crate::profiling::symbols::ResolvedSymbol;
-use crate::profiling::ThreadState;
+use crate::profiling::{SourceLocation, ThreadState};
 use anyhow::Result;
-use linux_perf_data::{PerfFileReader, PerfFileRecord};
-use linux_perf_event_reader::{EventRecord, RawData, SampleRecord};
+use linux_perf_data::{Feature, PerfFileReader, PerfFileRecord};
+use linux_perf_event_reader::{
+    EventRecord, RawData, RecordType, SampleFormat, SampleRecord, SamplingPolicy,
+};
 use log::{debug, error, warn};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-/// Parse the raw Perf profile and build the Profiler Data.
+/// The maximum number of processes, by number of samples, that get their own Profiler.
+const MAX_PROCESS_PROFILERS: usize = 16;
+
+/// The maximum number of CPUs, by number of on-CPU samples, that get their own Profiler.
+const MAX_CPU_PROFILERS: usize = 16;
+
+/// The key of the Profiler with the samples of a process.
+fn process_profiler_key(process_name: &str, pid: i32) -> String {
+    format!("process: {} [{}]", process_name, pid)
+}
+
+/// The key of the Profiler with the mixed-mode Java and native samples of a JVM.
+fn java_profiler_key(process_name: &str, pid: i32) -> String {
+    format!("java: {} [{}]", process_name, pid)
+}
+
+/// The key of the Profiler with the on-CPU samples taken on a CPU.
+fn cpu_profiler_key(cpu: u32) -> String {
+    format!("CPU {}", cpu)
+}
+
+/// Parse the raw Perf profile and build the Profiler Data. On-CPU samples are added to the "cpu"
+/// profile, the samples of any other PMU event to the profile named after the event, and off-CPU
+/// time computed from scheduler tracepoints is added to the "offcpu" profile.
+/// If symbol_bundle_dir contains a symbol bundle, symbols are resolved against it first.
+///
+/// Every sample is added to the Profiler of the whole system (SYSTEM_PROFILER_KEY). The samples of
+/// the busiest processes are also added to a Profiler per process, whose stacks are rooted at the
+/// frame of their thread, and the on-CPU samples of the busiest CPUs to a Profiler per CPU. The
+/// busiest HotSpot JVMs also get a mixed-mode Profiler, whose Java frames that are not in the perf
+/// map of the JVM are filled in from the JFR recorded by async-profiler in java_jfr_paths (by
+/// PID), if any.
 pub fn build_perf_profiler_data(
     perf_data_path: &PathBuf,
     profile_start_timestamp_ms: i64,
     events_output_path: Option<&Path>,
-) -> Profiler {
+    symbol_bundle_dir: Option<&Path>,
+    java_jfr_paths: &HashMap<i32, PathBuf>,
+) -> ProfilingData {
     debug!("Start parsing raw Perf profile...");
 
-    let mut profiler = Profiler::new(profile_start_timestamp_ms);
+    let mut profiling_data = ProfilingData::default();
+    profiling_data.profilers.insert(
+        SYSTEM_PROFILER_KEY.to_string(),
+        Profiler::new(profile_start_timestamp_ms),
+    );
+
+    let symbol_bundle = symbol_bundle_dir.and_then(SymbolBundle::open);
+    // The profile may be parsed on a different system, so prefer the boot timestamp of the
+    // recorded system.
+    let bundled_system_boot_timestamp_ms = symbol_bundle
+        .as_ref()
+        .and_then(|symbol_bundle| symbol_bundle.system_boot_timestamp_ms());
 
     let perf_parse_start_time = Instant::now();
-    let perf_samples = match parse_perf_data(perf_data_path) {
-        Ok(perf_samples) => perf_samples,
+    let perf_data = match parse_perf_data(perf_data_path, symbol_bundle) {
+        Ok(perf_data) => perf_data,
         Err(e) => {
             error!("Error when parsing the raw Perf profile: {e}");
-            return profiler;
+            return profiling_data;
         }
     };
     debug!(
         "Finished parsing {} Perf samples in {:?}",
-        perf_samples.len(),
+        perf_data.samples.len(),
         perf_parse_start_time.elapsed()
     );
 
     // By default the timestamp of each sample is nanoseconds since the system booted, so we need to
     // convert it into epoch
-    let system_boot_timestamp_ms = match procfs::boot_time() {
-        Ok(boot_time) => boot_time.timestamp_millis(),
+    let system_boot_timestamp_ms = match bundled_system_boot_timestamp_ms.map_or_else(
+        || procfs::boot_time().map(|boot_time| boot_time.timestamp_millis()),
+        Ok,
+    ) {
+        Ok(boot_time) => boot_time,
         Err(e) => {
             error!("Failed to retrieve system boot timestamp: {e}");
             // In the rare case where the system boot timestamp cannot be retrieved, assume the
             // first sample's epoch timestamp matches the profile start timestamp
-            perf_samples.get(0).map_or_else(
+            perf_data.samples.first().map_or_else(
                 || 0,
                 |first_sample| {
                     profile_start_timestamp_ms - (first_sample.timestamp / 1_000_000) as i64
@@ -56,6 +116,106 @@ pub fn build_perf_profiler_data(
         }
     };
 
+    add_perf_samples(
+        &mut profiling_data,
+        profile_start_timestamp_ms,
+        &perf_data,
+        system_boot_timestamp_ms,
+        events_output_path,
+        &JavaStacks::from_jfr_files(java_jfr_paths),
+    );
+    profiling_data
+}
+
+/// Parse a raw Perf profile recorded on another system, such as a perf.data from a customer, and
+/// build the Profiler Data. The boot time of that system is unknown, so the timestamps of the
+/// samples are anchored such that the last sample is taken at recording_end_timestamp_ms, and the
+/// profile starts at the first sample. Symbols are resolved against the files on this system.
+pub fn build_imported_perf_profiler_data(
+    perf_data_path: &PathBuf,
+    recording_end_timestamp_ms: i64,
+) -> Result<ProfilingData> {
+    let perf_data = parse_perf_data(perf_data_path, None)?;
+    let first_timestamp_ms = perf_data
+        .samples
+        .iter()
+        .map(|perf_sample| (perf_sample.timestamp / 1_000_000) as i64)
+        .min()
+        .unwrap_or(0);
+    let last_timestamp_ms = perf_data
+        .samples
+        .iter()
+        .map(|perf_sample| (perf_sample.timestamp / 1_000_000) as i64)
+        .max()
+        .unwrap_or(0);
+    let system_boot_timestamp_ms = recording_end_timestamp_ms - last_timestamp_ms;
+
+    let mut profiling_data = ProfilingData::default();
+    add_perf_samples(
+        &mut profiling_data,
+        system_boot_timestamp_ms + first_timestamp_ms,
+        &perf_data,
+        system_boot_timestamp_ms,
+        None,
+        &JavaStacks::default(),
+    );
+    Ok(profiling_data)
+}
+
+/// Add the stacks of the Perf samples to the profiles of the Profilers of the system, processes,
+/// JVMs and CPUs, converting the timestamps of the samples to the epoch with the system boot
+/// timestamp. The hottest functions of every profile type are annotated in the Profiler of the
+/// system.
+fn add_perf_samples(
+    profiling_data: &mut ProfilingData,
+    profile_start_timestamp_ms: i64,
+    perf_data: &ParsedPerfData,
+    system_boot_timestamp_ms: i64,
+    events_output_path: Option<&Path>,
+    java_stacks: &JavaStacks,
+) {
+    let perf_samples = &perf_data.samples;
+    let thread_name = |tid: i32| {
+        perf_data
+            .thread_names
+            .get(&tid)
+            .map_or("[unknown]", String::as_str)
+    };
+
+    // Only the busiest processes get their own Profiler to bound the size of the report.
+    let mut num_samples_per_pid: HashMap<i32, usize> = HashMap::new();
+    for perf_sample in perf_samples {
+        *num_samples_per_pid.entry(perf_sample.pid).or_default() += 1;
+    }
+    let mut pids_by_num_samples: Vec<(i32, usize)> = num_samples_per_pid.into_iter().collect();
+    pids_by_num_samples.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let process_profiler_keys: HashMap<i32, String> = pids_by_num_samples
+        .iter()
+        .take(MAX_PROCESS_PROFILERS)
+        .map(|&(pid, _)| (pid, process_profiler_key(thread_name(pid), pid)))
+        .collect();
+    let java_profiler_keys: HashMap<i32, String> = pids_by_num_samples
+        .iter()
+        .filter(|(pid, _)| perf_data.hotspot_jvm_pids.contains(pid))
+        .take(MAX_PROCESS_PROFILERS)
+        .map(|&(pid, _)| (pid, java_profiler_key(thread_name(pid), pid)))
+        .collect();
+    // Likewise for the busiest CPUs, which bounds the size of the report on systems with many
+    // CPUs.
+    let mut num_samples_per_cpu: HashMap<u32, usize> = HashMap::new();
+    for perf_sample in perf_samples {
+        if let (Some(cpu), ThreadState::None) = (perf_sample.cpu, perf_sample.thread_state) {
+            *num_samples_per_cpu.entry(cpu).or_default() += 1;
+        }
+    }
+    let mut cpus_by_num_samples: Vec<(u32, usize)> = num_samples_per_cpu.into_iter().collect();
+    cpus_by_num_samples.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let cpu_profiler_keys: HashMap<u32, String> = cpus_by_num_samples
+        .iter()
+        .take(MAX_CPU_PROFILERS)
+        .map(|&(cpu, _)| (cpu, cpu_profiler_key(cpu)))
+        .collect();
+
     let mut stack_output_file = if let Some(events_output_path) = events_output_path {
         if let Ok(file) = File::create(events_output_path) {
             Some(file)
@@ -71,42 +231,103 @@ pub fn build_perf_profiler_data(
     };
 
     let build_perf_profiler_data_start_time = Instant::now();
-    let profile_type = "cpu";
-    for perf_sample in &perf_samples {
-        let mut frames: Vec<String> = perf_sample
+    for perf_sample in perf_samples {
+        // Functions inlined at an address are expanded into frames ordered from the innermost
+        // one, followed by the frame of the outermost function.
+        let resolved_frames: Vec<Option<&ResolvedSymbol>> = perf_sample
             .call_chain
+            .iter()
+            .flat_map(|resolved_symbol| match resolved_symbol {
+                Some(s) => s.inlined_frames.iter().chain([s]).map(Some).collect(),
+                None => vec![None],
+            })
+            .collect();
+        let mut frames: Vec<String> = resolved_frames
             .iter()
             .map(|resolved_symbol| {
-                resolved_symbol
-                    .as_ref()
-                    .map_or("[unknown]".to_string(), |s| {
-                        format!("{}{}", s.name, s.frame_type.literal_suffix())
-                    })
+                resolved_symbol.map_or("[unknown]".to_string(), |s| {
+                    format!("{}{}", s.name, s.frame_type.literal_suffix())
+                })
             })
             .collect();
         // Perf sample's call chain is from leaf to root, so reverse the frames
         frames.reverse();
+        let mut locations: Vec<Option<&SourceLocation>> = resolved_frames
+            .iter()
+            .rev()
+            .map(|resolved_symbol| resolved_symbol.and_then(|s| s.location.as_ref()))
+            .collect();
 
         let sample_timestamp_ms =
             system_boot_timestamp_ms + (perf_sample.timestamp / 1_000_000) as i64;
 
         if let Some(file) = stack_output_file.as_mut() {
+            // Also include the source locations of the frames if available.
+            let frames_with_locations: Vec<String> = frames
+                .iter()
+                .zip(&locations)
+                .map(|(frame, location)| match location {
+                    Some(location) => format!("{frame} [{location}]"),
+                    None => frame.clone(),
+                })
+                .collect();
             let _ = writeln!(
                 file,
                 "{}|{}|{}",
                 sample_timestamp_ms,
                 perf_sample.pid,
-                frames.join(";")
+                frames_with_locations.join(";")
             );
         }
 
-        profiler.insert_stack(
-            profile_type,
-            sample_timestamp_ms,
-            ThreadState::None,
-            &frames,
-            1,
-        );
+        let profile_type = if perf_sample.thread_state == ThreadState::None {
+            perf_data
+                .event_profile_types
+                .get(perf_sample.attr_index)
+                .map_or(ON_CPU_PROFILE_TYPE, String::as_str)
+        } else {
+            OFF_CPU_PROFILE_TYPE
+        };
+        let mut insert_stack =
+            |profiler_key: &str, frames: &[String], locations: &[Option<&SourceLocation>]| {
+                profiling_data
+                    .profilers
+                    .entry(profiler_key.to_string())
+                    .or_insert_with(|| Profiler::new(profile_start_timestamp_ms))
+                    .insert_stack_with_locations(
+                        profile_type,
+                        sample_timestamp_ms,
+                        perf_sample.thread_state,
+                        frames,
+                        locations,
+                        perf_sample.weight,
+                    );
+            };
+        insert_stack(SYSTEM_PROFILER_KEY, &frames, &locations);
+        if let Some(cpu_profiler_key) = perf_sample
+            .cpu
+            .filter(|_| perf_sample.thread_state == ThreadState::None)
+            .and_then(|cpu| cpu_profiler_keys.get(&cpu))
+        {
+            insert_stack(cpu_profiler_key, &frames, &locations);
+        }
+        let thread_frame = format!("{} [{}]", thread_name(perf_sample.tid), perf_sample.tid);
+        if let Some(java_profiler_key) = java_profiler_keys.get(&perf_sample.pid) {
+            let resolved_frames: Vec<Option<&ResolvedSymbol>> =
+                resolved_frames.iter().rev().copied().collect();
+            let mut java_frames = mixed_frames(
+                &frames,
+                &resolved_frames,
+                java_stacks.find(perf_sample.pid, perf_sample.tid, perf_sample.timestamp),
+            );
+            java_frames.insert(0, thread_frame.clone());
+            insert_stack(java_profiler_key, &java_frames, &[]);
+        }
+        if let Some(process_profiler_key) = process_profiler_keys.get(&perf_sample.pid) {
+            frames.insert(0, thread_frame);
+            locations.insert(0, None);
+            insert_stack(process_profiler_key, &frames, &locations);
+        }
     }
     debug!(
         "Finished building Perf ProfilerData for {} samples in {:?}",
@@ -114,11 +335,49 @@ pub fn build_perf_profiler_data(
         build_perf_profiler_data_start_time.elapsed()
     );
 
-    profiler
+    if let Some(system_profiler) = profiling_data.profilers.get_mut(SYSTEM_PROFILER_KEY) {
+        system_profiler.annotations = perf_data
+            .instruction_samples
+            .annotate(&perf_data.event_profile_types);
+    }
+}
+
+/// Whether a process mapped a jitdump file (jit-<pid>.dump) during the recording of the raw Perf
+/// profile. JIT runtimes, such as the JVMs with the perf JVMTI agent or Node.js with --perf-prof,
+/// map the file so that perf inject -j can find the JIT-compiled code described in it.
+pub fn perf_data_maps_jitdump(perf_data_path: &Path) -> Result<bool> {
+    let perf_data_file = File::open(perf_data_path)?;
+    let buf_reader = BufReader::with_capacity(1 << 20, perf_data_file);
+    let PerfFileReader {
+        mut perf_file,
+        mut record_iter,
+    } = PerfFileReader::parse_file(buf_reader)?;
+
+    let jitdump_file_name = Regex::new(r"(^|/)jit-\d+\.dump$").unwrap();
+    while let Some(record) = record_iter.next_record(&mut perf_file)? {
+        let PerfFileRecord::EventRecord { record, .. } = record else {
+            continue;
+        };
+        if record.record_type != RecordType::MMAP && record.record_type != RecordType::MMAP2 {
+            continue;
+        }
+        let path = match record.parse() {
+            Ok(EventRecord::Mmap(mmap)) => rawdata_to_string(&mmap.path),
+            Ok(EventRecord::Mmap2(mmap2)) => rawdata_to_string(&mmap2.path),
+            _ => continue,
+        };
+        if jitdump_file_name.is_match(&path) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
 /// Parse every record in the raw Perf profile and collect all symbolicated samples.
-fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
+fn parse_perf_data(
+    perf_data_path: &PathBuf,
+    symbol_bundle: Option<SymbolBundle>,
+) -> Result<ParsedPerfData> {
     let perf_data_file = File::open(perf_data_path)?;
     // Read an 1MB chunk at a time - the raw perf data typically has a size of several MB to ~500MB.
     let buf_reader = BufReader::with_capacity(1 << 20, perf_data_file);
@@ -134,7 +393,17 @@ fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
         env::consts::ARCH
     };
 
-    let mut symbol_resolver = SymbolResolver::for_arch(arch);
+    let mut symbol_resolver = SymbolResolver::for_arch(arch, symbol_bundle);
+
+    // With --call-graph dwarf, Perf copies the userspace stack of every sample instead of
+    // walking the frame pointers, which needs to be unwound here.
+    if perf_file.event_attributes().iter().any(|attr| {
+        attr.attributes()
+            .sample_format
+            .contains(SampleFormat::STACK_USER)
+    }) {
+        symbol_resolver.enable_stack_unwinding(arch);
+    }
 
     // Collect all ELF Build-IDs from the profile, which can be used to find the original
     // build version of an ELF file.
@@ -153,7 +422,48 @@ fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
         Err(e) => error!("Failed to read the Build-IDs from the Perf data: {e}"),
     };
 
+    // The off-CPU profile is recorded through the scheduler tracepoints, whose raw data layout
+    // is described by the tracing data saved in the profile.
+    let event_attr_index = |event_name: &str| {
+        perf_file
+            .event_attributes()
+            .iter()
+            .position(|attr| attr.name() == Some(event_name))
+    };
+    let sched_switch_attr_index = event_attr_index(SCHED_SWITCH_EVENT);
+    let sched_wakeup_attr_index = event_attr_index(SCHED_WAKEUP_EVENT);
+    // The first event is cpu-clock (or whatever event an imported profile was recorded with),
+    // and every other PMU event is a profile type of its own.
+    let event_profile_types: Vec<String> = perf_file
+        .event_attributes()
+        .iter()
+        .enumerate()
+        .map(|(attr_index, attr)| match (attr_index, attr.name()) {
+            (0, _) => ON_CPU_PROFILE_TYPE.to_string(),
+            (_, Some(event_name)) => event_profile_type(event_name),
+            (_, None) => format!("event{attr_index}"),
+        })
+        .collect();
+    // The samples of a PMU event sampled at a fixed period only record the period if asked to.
+    let event_periods: Vec<u64> = perf_file
+        .event_attributes()
+        .iter()
+        .map(|attr| match attr.attributes().sampling_policy {
+            SamplingPolicy::Period(period) => period.get(),
+            _ => 1,
+        })
+        .collect();
+    let sched_tracepoint_format = perf_file
+        .feature_section_data(Feature::TRACING_DATA)
+        .map_or_else(
+            SchedTracepointFormat::default,
+            SchedTracepointFormat::from_tracing_data,
+        );
+    let mut off_cpu_tracker: OffCpuTracker<PerfSample> = OffCpuTracker::new();
+
     let mut perf_samples: Vec<PerfSample> = Vec::new();
+    let mut thread_names: HashMap<i32, String> = HashMap::new();
+    let mut instruction_samples = InstructionSamples::default();
 
     let mut num_record_parsing_errors: usize = 0;
     while let Some(record) = record_iter.next_record(&mut perf_file)? {
@@ -186,6 +496,9 @@ fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
                             rawdata_to_string(&mmap2.path),
                         );
                     }
+                    EventRecord::Comm(comm) => {
+                        thread_names.insert(comm.tid, rawdata_to_string(&comm.name));
+                    }
                     EventRecord::Fork(fork) => {
                         if fork.ppid != fork.pid && (record.misc & PERF_RECORD_MISC_FORK_EXEC) == 0
                         {
@@ -193,21 +506,51 @@ fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
                         }
                     }
                     EventRecord::Sample(sample_record) => {
-                        // For every PMU event that Perf record collects, it creates an entry in the
-                        // attribute table. We only collect the cpu-clock event for now, so limit the
-                        // scope to only handling one type of PMU event (the first one in the table).
-                        if attr_index != 0 {
+                        if Some(attr_index) == sched_switch_attr_index {
+                            handle_sched_switch_event(
+                                &sample_record,
+                                &sched_tracepoint_format,
+                                &mut off_cpu_tracker,
+                                &mut symbol_resolver,
+                                &mut perf_samples,
+                            );
+                            continue;
+                        }
+                        if Some(attr_index) == sched_wakeup_attr_index {
+                            if let (Some(raw), Some(timestamp)) =
+                                (&sample_record.raw, sample_record.timestamp)
+                            {
+                                if let Some(tid) =
+                                    sched_tracepoint_format.parse_sched_wakeup(&raw.as_slice())
+                                {
+                                    off_cpu_tracker.wakeup(tid, timestamp);
+                                }
+                            }
                             continue;
                         }
-                        if let Some(perf_sample) =
+                        if let Some(mut perf_sample) =
                             handle_sample_event(&sample_record, &mut symbol_resolver)
                         {
+                            // The cpu-clock samples are counted, while the samples of the other
+                            // PMU events are weighted by the number of occurrences.
+                            perf_sample.attr_index = attr_index;
+                            if attr_index != 0 {
+                                perf_sample.weight =
+                                    sample_record.period.unwrap_or(event_periods[attr_index]);
+                            }
+                            if let Some((address, function)) = sample_record.ip.and_then(|ip| {
+                                symbol_resolver.resolve_elf_function(perf_sample.pid, ip)
+                            }) {
+                                instruction_samples.add(
+                                    attr_index,
+                                    function,
+                                    address,
+                                    perf_sample.weight,
+                                );
+                            }
                             perf_samples.push(perf_sample)
                         }
                     }
-                    EventRecord::ContextSwitch(_) => {
-                        // Not used for now, but it can be useful for off-CPU profiling in the future.
-                    }
                     _ => {}
                 };
             }
@@ -217,7 +560,19 @@ fn parse_perf_data(perf_data_path: &PathBuf) -> Result<Vec<PerfSample>> {
 
     debug!("Number of Perf profile parsing errors: {num_record_parsing_errors}");
 
-    Ok(perf_samples)
+    let hotspot_jvm_pids: HashSet<i32> = perf_samples
+        .iter()
+        .map(|perf_sample| perf_sample.pid)
+        .filter(|&pid| symbol_resolver.is_pid_hotspot_jvm(pid))
+        .collect();
+
+    Ok(ParsedPerfData {
+        samples: perf_samples,
+        thread_names,
+        event_profile_types,
+        instruction_samples,
+        hotspot_jvm_pids,
+    })
 }
 
 /// Handle a Perf sample event by symbolicating every frame in the call chain and performing
@@ -243,8 +598,16 @@ fn handle_sample_event(
     let mut frame_addresses: Vec<u64> = Vec::new();
     let mut resolved_call_chain: Vec<Option<ResolvedSymbol>> = Vec::new();
 
+    // The userspace frames unwound from the stack snapshot replace the ones in the call chain,
+    // which only has the leaf when the frame pointers are missing.
+    let unwound_user_frames = unwind_user_stack(sample_record, pid, symbol_resolver);
+
     // Refer to add_callchain_ip in
     // https://github.com/torvalds/linux/blob/master/tools/perf/util/machine.c
+    // Except for the first frame after a context sentinel, every frame is a return address
+    // pointing to the instruction after the call. Resolve the call instruction instead, so
+    // that the source line and inlined functions are the ones of the call site.
+    let mut is_context_leaf = true;
     for i in 0..call_chain.len() {
         let frame_addr = match call_chain.get(i) {
             Some(frame_addr) => frame_addr,
@@ -253,61 +616,211 @@ fn handle_sample_event(
         // The userspace sentinel indicates that the following frames are all
         // from the userspace, and the next frame is the leaf frame.
         if frame_addr == PERF_CONTEXT_USER {
+            if unwound_user_frames.is_some() {
+                break;
+            }
             leaf_frame_idx = Some(frame_addresses.len());
         }
         if frame_addr >= PERF_CONTEXT_MAX {
+            is_context_leaf = true;
             continue;
         }
-        resolved_call_chain.push(symbol_resolver.resolve(pid, frame_addr));
+        let resolve_addr = if is_context_leaf {
+            frame_addr
+        } else {
+            frame_addr.saturating_sub(1)
+        };
+        is_context_leaf = false;
+        resolved_call_chain.push(symbol_resolver.resolve(pid, resolve_addr));
         frame_addresses.push(frame_addr);
     }
 
+    let has_unwound_user_frames = unwound_user_frames.is_some();
+    for (i, frame_addr) in unwound_user_frames.into_iter().flatten().enumerate() {
+        let resolve_addr = if i == 0 {
+            frame_addr
+        } else {
+            frame_addr.saturating_sub(1)
+        };
+        resolved_call_chain.push(symbol_resolver.resolve(pid, resolve_addr));
+    }
+
     let mut perf_sample = PerfSample {
         pid,
+        tid: sample_record.tid.unwrap_or(pid),
+        cpu: sample_record.cpu,
         timestamp,
         call_chain: resolved_call_chain,
+        thread_state: ThreadState::None,
+        attr_index: 0,
+        weight: 1,
     };
 
-    // On ARM, the caller of the leaf frame might not be in the call chain, due
-    // to the fact that the function invocation instruction (bl) saves the return
-    // address to the LR register, and it relies on the invoked function's prologue
-    // to save it to stack. Therefore, Perf, which relies on the stack to trace
-    // and create the call chain, might be missing the leaf frame's caller in the
-    // call chain. We need to check if the value of the leaf frame's LR register
-    // can be used to recover its caller frame.
-    if symbol_resolver.support_leaf_caller_recovery() {
-        let (lr, fp, sp) = match &sample_record.user_regs {
-            Some(user_regs) => (user_regs.get(30), user_regs.get(29), user_regs.get(31)),
-            None => return Some(perf_sample),
-        };
-        // LR is crucial, while fp and sp are used as fallback.
-        if lr.is_none() {
-            return Some(perf_sample);
-        }
-        // When there are no userspace frames, there is nothing to recover.
-        if leaf_frame_idx.map_or(true, |idx| idx >= frame_addresses.len()) {
-            return Some(perf_sample);
+    // The unwinder already recovered the caller of the leaf frame from the CFI rules.
+    if has_unwound_user_frames {
+        return Some(perf_sample);
+    }
+
+    // When there are no userspace frames, there is nothing to recover.
+    let leaf_frame_idx = match leaf_frame_idx {
+        Some(idx) if idx < frame_addresses.len() => idx,
+        _ => return Some(perf_sample),
+    };
+    let leaf_addr = frame_addresses[leaf_frame_idx];
+    match symbol_resolver.leaf_caller_recovery_arch() {
+        // On ARM, the caller of the leaf frame might not be in the call chain, due
+        // to the fact that the function invocation instruction (bl) saves the return
+        // address to the LR register, and it relies on the invoked function's prologue
+        // to save it to stack. Therefore, Perf, which relies on the stack to trace
+        // and create the call chain, might be missing the leaf frame's caller in the
+        // call chain. We need to check if the value of the leaf frame's LR register
+        // can be used to recover its caller frame.
+        Some(UnwindArch::Aarch64) => {
+            let (lr, fp, sp) = match &sample_record.user_regs {
+                Some(user_regs) => (user_regs.get(30), user_regs.get(29), user_regs.get(31)),
+                None => return Some(perf_sample),
+            };
+            // LR is crucial, while fp and sp are used as fallback.
+            let lr = match lr {
+                Some(lr) => lr,
+                None => return Some(perf_sample),
+            };
+            // If the leaf caller was successfully recovered, insert it right after
+            // the leaf frame in the call chain.
+            if let Some(leaf_caller_addr) =
+                symbol_resolver.recover_leaf_frame_caller(pid, leaf_addr, lr, fp, sp)
+            {
+                // Match perf's check: if (leaf_frame_caller && leaf_frame_caller != ip)
+                if leaf_caller_addr != 0 && leaf_caller_addr != leaf_addr {
+                    perf_sample.call_chain.insert(
+                        leaf_frame_idx + 1,
+                        symbol_resolver.resolve(pid, leaf_caller_addr - 1),
+                    );
+                }
+            }
         }
-        let leaf_frame_idx = leaf_frame_idx.unwrap();
-        let leaf_addr = frame_addresses[leaf_frame_idx];
-        // If the leaf caller was successfully recovered, insert it right after
-        // the leaf frame in the call chain.
-        if let Some(leaf_caller_addr) =
-            symbol_resolver.recover_leaf_frame_caller(pid, leaf_addr, lr.unwrap(), fp, sp)
-        {
-            // Match perf's check: if (leaf_frame_caller && leaf_frame_caller != ip)
-            if leaf_caller_addr != 0 && leaf_caller_addr != leaf_addr {
+        // On x86_64, the return address is pushed onto the stack, but the leaf frame's caller is
+        // still missing if the leaf has not set up its frame pointer. Perf does not copy the
+        // stack in frame pointer mode, so the caller is recovered from the call instruction
+        // preceding the next return address in the call chain.
+        Some(UnwindArch::X86_64) => {
+            let return_addr = match frame_addresses.get(leaf_frame_idx + 1) {
+                Some(return_addr) => *return_addr,
+                None => return Some(perf_sample),
+            };
+            if let Some(leaf_caller_addr) =
+                symbol_resolver.recover_x86_leaf_frame_caller(pid, leaf_addr, return_addr)
+            {
+                // The recovered address is the entry of the caller rather than a return address.
                 perf_sample.call_chain.insert(
                     leaf_frame_idx + 1,
                     symbol_resolver.resolve(pid, leaf_caller_addr),
                 );
             }
         }
+        None => {}
     }
 
     Some(perf_sample)
 }
 
+/// Unwind the userspace stack snapshot of a sample, if the profile was recorded with
+/// --call-graph dwarf and the sample was taken in userspace or has a userspace stack.
+fn unwind_user_stack(
+    sample_record: &SampleRecord,
+    pid: i32,
+    symbol_resolver: &mut SymbolResolver,
+) -> Option<Vec<u64>> {
+    let arch = symbol_resolver.stack_unwinding_arch()?;
+    let (user_stack, dyn_size) = sample_record.user_stack.as_ref()?;
+    let user_regs = sample_record.user_regs.as_ref()?;
+    let regs = UnwindRegisters::from_perf_registers(arch, |register| user_regs.get(register))?;
+    let user_stack = user_stack.as_slice();
+    // Only the first dyn_size bytes of the snapshot were actually copied.
+    let user_stack = &user_stack[..(*dyn_size as usize).min(user_stack.len())];
+    if user_stack.is_empty() {
+        return None;
+    }
+    Some(symbol_resolver.unwind_user_stack(pid, regs, &StackMemory::new(regs.sp, user_stack)))
+}
+
+/// Handle a sched_switch tracepoint event: the task being switched out starts its off-CPU
+/// interval with the sampled stack, while the task being switched in ends its off-CPU interval,
+/// which is converted into off-CPU samples.
+fn handle_sched_switch_event(
+    sample_record: &SampleRecord,
+    sched_tracepoint_format: &SchedTracepointFormat,
+    off_cpu_tracker: &mut OffCpuTracker<PerfSample>,
+    symbol_resolver: &mut SymbolResolver,
+    perf_samples: &mut Vec<PerfSample>,
+) {
+    let (raw, timestamp) = match (&sample_record.raw, sample_record.timestamp) {
+        (Some(raw), Some(timestamp)) => (raw, timestamp),
+        _ => return,
+    };
+    let (prev_tid, prev_state, next_tid) =
+        match sched_tracepoint_format.parse_sched_switch(&raw.as_slice()) {
+            Some(sched_switch) => sched_switch,
+            None => return,
+        };
+
+    // TID 0 is the idle task of every CPU, whose off-CPU time is not interesting.
+    if prev_tid != 0 {
+        // The tracepoint fires in the context of the task being switched out, so the call chain
+        // is where that task blocked.
+        if let Some(perf_sample) = handle_sample_event(sample_record, symbol_resolver) {
+            off_cpu_tracker.switch_out(prev_tid, timestamp, prev_state, perf_sample);
+        }
+    }
+    if next_tid != 0 {
+        if let Some(off_cpu_interval) = off_cpu_tracker.switch_in(next_tid, timestamp) {
+            perf_samples.extend(off_cpu_interval_to_samples(off_cpu_interval));
+        }
+    }
+}
+
+/// Convert an off-CPU interval into at most two samples weighted by the time (in microseconds)
+/// spent blocked and runnable respectively.
+fn off_cpu_interval_to_samples(off_cpu_interval: OffCpuInterval<PerfSample>) -> Vec<PerfSample> {
+    let OffCpuInterval {
+        switch_out_timestamp,
+        wakeup_timestamp,
+        blocked_thread_state,
+        blocked_duration,
+        runnable_duration,
+        stack,
+    } = off_cpu_interval;
+
+    let mut samples = Vec::new();
+    let blocked_us = blocked_duration / 1_000;
+    let runnable_us = runnable_duration / 1_000;
+    if blocked_us > 0 {
+        samples.push(PerfSample {
+            pid: stack.pid,
+            tid: stack.tid,
+            cpu: stack.cpu,
+            timestamp: switch_out_timestamp,
+            call_chain: stack.call_chain.clone(),
+            thread_state: blocked_thread_state,
+            attr_index: stack.attr_index,
+            weight: blocked_us,
+        });
+    }
+    if runnable_us > 0 {
+        samples.push(PerfSample {
+            pid: stack.pid,
+            tid: stack.tid,
+            cpu: stack.cpu,
+            timestamp: wakeup_timestamp.unwrap_or(switch_out_timestamp),
+            call_chain: stack.call_chain,
+            thread_state: ThreadState::SchedRunnable,
+            attr_index: stack.attr_index,
+            weight: runnable_us,
+        });
+    }
+    samples
+}
+
 fn rawdata_to_string(raw: &RawData) -> String {
     let cow = raw.as_slice();
     let bytes: &[u8] = match &cow {
@@ -318,3 +831,265 @@ fn rawdata_to_string(raw: &RawData) -> String {
     let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
     String::from_utf8_lossy(&bytes[..end]).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling::jfr::JavaStack;
+    use crate::profiling::perf::perf_event_with_period;
+    use crate::profiling::FrameType;
+
+    fn create_perf_sample(
+        pid: i32,
+        tid: i32,
+        cpu: u32,
+        thread_state: ThreadState,
+        call_chain: &[&str],
+    ) -> PerfSample {
+        PerfSample {
+            pid,
+            tid,
+            cpu: Some(cpu),
+            timestamp: 5_000_000,
+            call_chain: call_chain
+                .iter()
+                .map(|name| {
+                    Some(ResolvedSymbol {
+                        name: name.to_string(),
+                        offset: 0,
+                        source: String::new(),
+                        frame_type: FrameType::Native,
+                        location: None,
+                        inlined_frames: Vec::new(),
+                    })
+                })
+                .collect(),
+            thread_state,
+            attr_index: 0,
+            weight: 1,
+        }
+    }
+
+    #[test]
+    fn test_add_perf_samples_per_process_and_cpu() {
+        let perf_data = ParsedPerfData {
+            samples: vec![
+                create_perf_sample(100, 101, 0, ThreadState::None, &["compute", "main"]),
+                create_perf_sample(100, 101, 0, ThreadState::None, &["compute", "main"]),
+                create_perf_sample(200, 200, 1, ThreadState::None, &["read", "main"]),
+                create_perf_sample(100, 101, 1, ThreadState::SchedSleeping, &["wait", "main"]),
+            ],
+            thread_names: HashMap::from([
+                (100, "server".to_string()),
+                (101, "worker".to_string()),
+                (200, "client".to_string()),
+            ]),
+            event_profile_types: vec![ON_CPU_PROFILE_TYPE.to_string()],
+            instruction_samples: InstructionSamples::default(),
+            hotspot_jvm_pids: HashSet::new(),
+        };
+        let mut profiling_data = ProfilingData::default();
+        add_perf_samples(
+            &mut profiling_data,
+            0,
+            &perf_data,
+            0,
+            None,
+            &JavaStacks::default(),
+        );
+
+        let mut profiler_keys: Vec<&str> = profiling_data
+            .profilers
+            .keys()
+            .map(String::as_str)
+            .collect();
+        profiler_keys.sort();
+        assert_eq!(
+            profiler_keys,
+            vec![
+                "CPU 0",
+                "CPU 1",
+                "cpu",
+                "process: client [200]",
+                "process: server [100]"
+            ]
+        );
+
+        let collapsed = |profiler_key: &str, profile_type: &str| {
+            let mut lines: Vec<String> = profiling_data.profilers[profiler_key]
+                .generate_collapsed(profile_type, &[ThreadState::None])
+                .lines()
+                .map(String::from)
+                .collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(
+            collapsed(SYSTEM_PROFILER_KEY, ON_CPU_PROFILE_TYPE),
+            vec!["main;compute 2", "main;read 1"]
+        );
+        assert_eq!(
+            collapsed("process: server [100]", ON_CPU_PROFILE_TYPE),
+            vec!["worker [101];main;compute 2"]
+        );
+        assert_eq!(collapsed("CPU 1", ON_CPU_PROFILE_TYPE), vec!["main;read 1"]);
+        // Off-CPU samples are split by process, but not by CPU.
+        assert!(profiling_data.profilers["process: server [100]"]
+            .profiles
+            .contains_key(OFF_CPU_PROFILE_TYPE));
+        assert!(!profiling_data.profilers["CPU 1"]
+            .profiles
+            .contains_key(OFF_CPU_PROFILE_TYPE));
+    }
+
+    #[test]
+    fn test_add_perf_samples_cpu_profilers_limit() {
+        // CPU N has N + 1 samples, so the busiest CPUs are the ones with the highest numbers.
+        let num_cpus = MAX_CPU_PROFILERS as u32 + 4;
+        let samples = (0..num_cpus)
+            .flat_map(|cpu| {
+                (0..=cpu).map(move |_| {
+                    create_perf_sample(100, 100, cpu, ThreadState::None, &["compute", "main"])
+                })
+            })
+            .collect();
+        let perf_data = ParsedPerfData {
+            samples,
+            thread_names: HashMap::from([(100, "server".to_string())]),
+            event_profile_types: vec![ON_CPU_PROFILE_TYPE.to_string()],
+            instruction_samples: InstructionSamples::default(),
+            hotspot_jvm_pids: HashSet::new(),
+        };
+        let mut profiling_data = ProfilingData::default();
+        add_perf_samples(
+            &mut profiling_data,
+            0,
+            &perf_data,
+            0,
+            None,
+            &JavaStacks::default(),
+        );
+
+        let cpu_profiler_keys: HashSet<String> = profiling_data
+            .profilers
+            .keys()
+            .filter(|profiler_key| profiler_key.starts_with("CPU "))
+            .cloned()
+            .collect();
+        let expected_keys: HashSet<String> = (num_cpus - MAX_CPU_PROFILERS as u32..num_cpus)
+            .map(cpu_profiler_key)
+            .collect();
+        assert_eq!(cpu_profiler_keys, expected_keys);
+    }
+
+    #[test]
+    fn test_add_perf_samples_per_event() {
+        let mut cache_miss_sample =
+            create_perf_sample(100, 100, 0, ThreadState::None, &["load", "main"]);
+        cache_miss_sample.attr_index = 1;
+        cache_miss_sample.weight = 10_000;
+        let perf_data = ParsedPerfData {
+            samples: vec![
+                create_perf_sample(100, 100, 0, ThreadState::None, &["compute", "main"]),
+                cache_miss_sample,
+            ],
+            thread_names: HashMap::from([(100, "server".to_string())]),
+            event_profile_types: vec![ON_CPU_PROFILE_TYPE.to_string(), "cache-misses".to_string()],
+            instruction_samples: InstructionSamples::default(),
+            hotspot_jvm_pids: HashSet::new(),
+        };
+        let mut profiling_data = ProfilingData::default();
+        add_perf_samples(
+            &mut profiling_data,
+            0,
+            &perf_data,
+            0,
+            None,
+            &JavaStacks::default(),
+        );
+
+        let profiler = &profiling_data.profilers[SYSTEM_PROFILER_KEY];
+        assert_eq!(
+            profiler.generate_collapsed(ON_CPU_PROFILE_TYPE, &[ThreadState::None]),
+            "main;compute 1\n"
+        );
+        assert_eq!(
+            profiler.generate_collapsed("cache-misses", &[ThreadState::None]),
+            "main;load 10000\n"
+        );
+        assert!(profiling_data.profilers["CPU 0"]
+            .profiles
+            .contains_key("cache-misses"));
+    }
+
+    #[test]
+    fn test_add_perf_samples_java_mixed_mode() {
+        let mut jvm_sample = create_perf_sample(
+            300,
+            301,
+            0,
+            ThreadState::None,
+            &["do_syscall_64", "epoll_wait", "[jit]", "start_thread"],
+        );
+        jvm_sample.call_chain[0].as_mut().unwrap().frame_type = FrameType::Kernel;
+        jvm_sample.call_chain[2] = None;
+        let perf_data = ParsedPerfData {
+            samples: vec![
+                jvm_sample,
+                create_perf_sample(200, 200, 1, ThreadState::None, &["read", "main"]),
+            ],
+            thread_names: HashMap::from([
+                (300, "java".to_string()),
+                (301, "epoll-worker".to_string()),
+                (200, "client".to_string()),
+            ]),
+            event_profile_types: vec![ON_CPU_PROFILE_TYPE.to_string()],
+            instruction_samples: InstructionSamples::default(),
+            hotspot_jvm_pids: HashSet::from([300]),
+        };
+        let mut java_stacks = JavaStacks::default();
+        java_stacks.insert(
+            300,
+            JavaStack {
+                tid: 301,
+                timestamp: 6_000_000,
+                frames: vec![
+                    "start_thread".to_string(),
+                    "io.netty.channel.epoll.EpollEventLoop.run_[j]".to_string(),
+                ],
+            },
+        );
+        let mut profiling_data = ProfilingData::default();
+        add_perf_samples(&mut profiling_data, 0, &perf_data, 0, None, &java_stacks);
+
+        assert!(!profiling_data.profilers.contains_key("java: client [200]"));
+        assert_eq!(
+            profiling_data.profilers["java: java [300]"]
+                .generate_collapsed(ON_CPU_PROFILE_TYPE, &[ThreadState::None]),
+            "epoll-worker [301];start_thread;io.netty.channel.epoll.EpollEventLoop.run_[j];epoll_wait;do_syscall_64_[k] 1\n"
+        );
+        // The other Profilers keep the frames sampled by Perf
+        assert_eq!(
+            profiling_data.profilers["process: java [300]"]
+                .generate_collapsed(ON_CPU_PROFILE_TYPE, &[ThreadState::None]),
+            "epoll-worker [301];start_thread;[unknown];epoll_wait;do_syscall_64_[k] 1\n"
+        );
+    }
+
+    #[test]
+    fn test_event_profile_type() {
+        for (event, sampled_event) in [
+            ("cache-misses", "cache-misses/period=1000/"),
+            ("branch-misses:u", "branch-misses/period=1000/u"),
+            ("cpu/event=0x3c/", "cpu/event=0x3c,period=1000/"),
+            ("cpu/event=0x3c/k", "cpu/event=0x3c,period=1000/k"),
+        ] {
+            assert_eq!(perf_event_with_period(event, 1000), sampled_event);
+            assert_eq!(event_profile_type(sampled_event), event);
+        }
+        assert_eq!(
+            perf_event_with_period("cache-misses/period=50/", 1000),
+            "cache-misses/period=50/"
+        );
+    }
+}
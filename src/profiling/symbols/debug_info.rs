@@ -0,0 +1,530 @@
+use crate::profiling::symbols::demangle_symbol;
+use crate::profiling::SourceLocation;
+use gimli::{AttributeValue, EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+type DwarfReader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// The directory where distributions install split debug info files.
+const DEBUG_FILE_DIRECTORY: &str = "/usr/lib/debug";
+
+/// A row in the line number table, indicating that all addresses between addr and the next
+/// row's addr are generated from the line of the file.
+struct LineRow {
+    addr: u64,
+    file_id: u32,
+    /// Zero for the rows marking the end of a sequence, where the addresses are not covered.
+    line: u32,
+}
+
+/// The address range of an inlined function instance.
+struct InlinedRange {
+    start: u64,
+    end: u64,
+    /// The nesting level of the inlined function within its outermost function.
+    depth: usize,
+    name: String,
+    /// The file and line where the inlined function was called.
+    call_file_id: Option<u32>,
+    call_line: u32,
+}
+
+/// The address range of a (non-inlined) function and all inlined function instances within it.
+struct FunctionRange {
+    start: u64,
+    end: u64,
+    inlined_ranges: Vec<InlinedRange>,
+}
+
+/// A frame resolved from the debug info. Inlined frames carry the name of the inlined function,
+/// while the outermost frame uses the name from the symbol table.
+pub struct SourceFrame {
+    pub inlined_function_name: Option<String>,
+    pub location: Option<SourceLocation>,
+}
+
+/// The source-level information parsed from the .debug_info and .debug_line sections of an
+/// ELF file, used to resolve an address into file:line and inlined function frames.
+#[derive(Default)]
+pub struct DebugInfo {
+    /// The paths of all source files referenced by the line number tables.
+    files: Vec<String>,
+    /// The line number tables of all compilation units, sorted by addr for fast lookup.
+    line_rows: Vec<LineRow>,
+    /// All functions with inlined function instances, sorted by start for fast lookup.
+    function_ranges: Vec<FunctionRange>,
+}
+
+impl DebugInfo {
+    /// Parse the DWARF sections of the ELF file. Returns None if the file does not contain
+    /// any debug info.
+    pub fn from_elf(elf_obj: &object::File) -> Option<Self> {
+        elf_obj.section_by_name(".debug_info")?;
+
+        let endian = if elf_obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let dwarf_sections =
+            gimli::DwarfSections::load(|section_id| -> Result<Cow<[u8]>, gimli::Error> {
+                Ok(elf_obj
+                    .section_by_name(section_id.name())
+                    .and_then(|section| section.uncompressed_data().ok())
+                    .unwrap_or(Cow::Borrowed(&[])))
+            })
+            .ok()?;
+        let dwarf = dwarf_sections.borrow(|section| EndianSlice::new(section, endian));
+
+        let mut debug_info_builder = DebugInfoBuilder::default();
+        let mut unit_headers = dwarf.units();
+        while let Ok(Some(unit_header)) = unit_headers.next() {
+            if let Ok(unit) = dwarf.unit(unit_header) {
+                debug_info_builder.add_unit(&dwarf, &unit);
+            }
+        }
+        let mut debug_info = debug_info_builder.debug_info;
+
+        // For rows at the same address, keep the end-of-sequence marker before the actual row,
+        // so that a lookup finds the actual row.
+        debug_info
+            .line_rows
+            .sort_by_key(|line_row| (line_row.addr, line_row.line != 0));
+        debug_info
+            .function_ranges
+            .sort_by_key(|function_range| function_range.start);
+        for function_range in &mut debug_info.function_ranges {
+            function_range
+                .inlined_ranges
+                .sort_by_key(|inlined_range| inlined_range.depth);
+        }
+
+        if debug_info.line_rows.is_empty() && debug_info.function_ranges.is_empty() {
+            return None;
+        }
+        Some(debug_info)
+    }
+
+    /// Resolve an address (p_vaddr) into frames ordered from the innermost inlined function
+    /// to the outermost function. The result is empty if the address is not covered.
+    pub fn resolve(&self, addr: u64) -> Vec<SourceFrame> {
+        let mut frames = Vec::new();
+        let mut location = self.line_location(addr);
+
+        if let Some(function_range) = self.function_range(addr) {
+            // The inlined ranges are sorted by depth, so walk them backwards to start from
+            // the innermost one. Each inlined function's call site is the location within
+            // the function one level up.
+            for inlined_range in function_range
+                .inlined_ranges
+                .iter()
+                .rev()
+                .filter(|inlined_range| inlined_range.start <= addr && addr < inlined_range.end)
+            {
+                frames.push(SourceFrame {
+                    inlined_function_name: Some(inlined_range.name.clone()),
+                    location: location.take(),
+                });
+                location = inlined_range.call_file_id.map(|call_file_id| {
+                    self.source_location(call_file_id, inlined_range.call_line)
+                });
+            }
+        }
+
+        if !frames.is_empty() || location.is_some() {
+            frames.push(SourceFrame {
+                inlined_function_name: None,
+                location,
+            });
+        }
+        frames
+    }
+
+    fn line_location(&self, addr: u64) -> Option<SourceLocation> {
+        let line_row = match self
+            .line_rows
+            .binary_search_by(|line_row| line_row.addr.cmp(&addr).then(std::cmp::Ordering::Less))
+        {
+            Ok(_) | Err(0) => return None,
+            Err(i) => &self.line_rows[i - 1],
+        };
+        if line_row.line == 0 {
+            return None;
+        }
+        Some(self.source_location(line_row.file_id, line_row.line))
+    }
+
+    fn function_range(&self, addr: u64) -> Option<&FunctionRange> {
+        let idx = match self
+            .function_ranges
+            .binary_search_by_key(&addr, |function_range| function_range.start)
+        {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let function_range = &self.function_ranges[idx];
+        (addr < function_range.end).then_some(function_range)
+    }
+
+    fn source_location(&self, file_id: u32, line: u32) -> SourceLocation {
+        SourceLocation {
+            file: self.files[file_id as usize].clone(),
+            line,
+        }
+    }
+}
+
+#[derive(Default)]
+struct DebugInfoBuilder {
+    debug_info: DebugInfo,
+    /// Map every file path to its index in DebugInfo.files.
+    file_ids: HashMap<String, u32>,
+}
+
+impl DebugInfoBuilder {
+    fn add_unit<'a>(
+        &mut self,
+        dwarf: &gimli::Dwarf<DwarfReader<'a>>,
+        unit: &gimli::Unit<DwarfReader<'a>>,
+    ) {
+        // Map the file indices of this unit's line program to the global file ids.
+        let mut unit_file_ids: HashMap<u64, Option<u32>> = HashMap::new();
+
+        if let Some(line_program) = unit.line_program.clone() {
+            let mut line_rows = line_program.rows();
+            // Sequences of functions discarded by the linker start at address 0, and are skipped.
+            let mut skip_sequence: Option<bool> = None;
+            while let Ok(Some((line_program_header, line_row))) = line_rows.next_row() {
+                let skip = *skip_sequence.get_or_insert(line_row.address() == 0);
+                if line_row.end_sequence() {
+                    skip_sequence = None;
+                    if !skip {
+                        self.debug_info.line_rows.push(LineRow {
+                            addr: line_row.address(),
+                            file_id: 0,
+                            line: 0,
+                        });
+                    }
+                    continue;
+                }
+                if skip {
+                    continue;
+                }
+                let file_id = *unit_file_ids
+                    .entry(line_row.file_index())
+                    .or_insert_with(|| {
+                        unit_file_path(dwarf, unit, line_program_header, line_row.file_index())
+                            .map(|file_path| self.intern_file(file_path))
+                    });
+                if let (Some(file_id), Some(line)) = (file_id, line_row.line()) {
+                    self.debug_info.line_rows.push(LineRow {
+                        addr: line_row.address(),
+                        file_id,
+                        line: line.get() as u32,
+                    });
+                }
+            }
+        }
+
+        self.add_unit_inlined_ranges(dwarf, unit, &mut unit_file_ids);
+    }
+
+    /// Walk the DIE tree of the unit to collect the address ranges of every function and the
+    /// inlined function instances within them.
+    fn add_unit_inlined_ranges<'a>(
+        &mut self,
+        dwarf: &gimli::Dwarf<DwarfReader<'a>>,
+        unit: &gimli::Unit<DwarfReader<'a>>,
+        unit_file_ids: &mut HashMap<u64, Option<u32>>,
+    ) {
+        let mut name_cache: HashMap<gimli::UnitOffset, Option<String>> = HashMap::new();
+        // The depth of the function DIE being walked, and the depths of the enclosing
+        // inlined function DIEs.
+        let mut function_depth: Option<isize> = None;
+        let mut function_ranges: Vec<FunctionRange> = Vec::new();
+        let mut inlined_depths: Vec<isize> = Vec::new();
+
+        let mut entries = unit.entries();
+        while let Ok(Some(entry)) = entries.next_dfs() {
+            let depth = entry.depth();
+            while inlined_depths.last().is_some_and(|&d| d >= depth) {
+                inlined_depths.pop();
+            }
+            if function_depth.is_some_and(|d| d >= depth) {
+                function_depth = None;
+                self.finish_function_ranges(&mut function_ranges);
+            }
+
+            match entry.tag() {
+                gimli::DW_TAG_subprogram => {
+                    // Nested functions (e.g. lambdas) are tracked as functions on their own.
+                    self.finish_function_ranges(&mut function_ranges);
+                    function_ranges = die_address_ranges(dwarf, unit, entry)
+                        .into_iter()
+                        .map(|(start, end)| FunctionRange {
+                            start,
+                            end,
+                            inlined_ranges: Vec::new(),
+                        })
+                        .collect();
+                    function_depth = (!function_ranges.is_empty()).then_some(depth);
+                    inlined_depths.clear();
+                }
+                gimli::DW_TAG_inlined_subroutine if function_depth.is_some() => {
+                    inlined_depths.push(depth);
+                    let name = die_name(unit, entry, &mut name_cache, dwarf)
+                        .unwrap_or_else(|| "[inlined]".to_string());
+                    let call_file_id = match entry.attr_value(gimli::DW_AT_call_file) {
+                        Some(AttributeValue::FileIndex(file_index))
+                        | Some(AttributeValue::Udata(file_index)) => {
+                            *unit_file_ids.entry(file_index).or_insert_with(|| {
+                                let line_program_header = unit.line_program.as_ref()?.header();
+                                unit_file_path(dwarf, unit, line_program_header, file_index)
+                                    .map(|file_path| self.intern_file(file_path))
+                            })
+                        }
+                        _ => None,
+                    };
+                    let call_line = match entry.attr_value(gimli::DW_AT_call_line) {
+                        Some(AttributeValue::Udata(line)) => line as u32,
+                        Some(attr) => attr.udata_value().unwrap_or(0) as u32,
+                        None => 0,
+                    };
+                    for (start, end) in die_address_ranges(dwarf, unit, entry) {
+                        if let Some(function_range) =
+                            function_ranges.iter_mut().find(|function_range| {
+                                function_range.start <= start && start < function_range.end
+                            })
+                        {
+                            function_range.inlined_ranges.push(InlinedRange {
+                                start,
+                                end,
+                                depth: inlined_depths.len(),
+                                name: name.clone(),
+                                call_file_id,
+                                call_line,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.finish_function_ranges(&mut function_ranges);
+    }
+
+    fn finish_function_ranges(&mut self, function_ranges: &mut Vec<FunctionRange>) {
+        self.debug_info.function_ranges.extend(
+            function_ranges
+                .drain(..)
+                .filter(|function_range| !function_range.inlined_ranges.is_empty()),
+        );
+    }
+
+    fn intern_file(&mut self, file_path: String) -> u32 {
+        if let Some(&file_id) = self.file_ids.get(&file_path) {
+            return file_id;
+        }
+        let file_id = self.debug_info.files.len() as u32;
+        self.debug_info.files.push(file_path.clone());
+        self.file_ids.insert(file_path, file_id);
+        file_id
+    }
+}
+
+/// Compute the full path of a file in the line program header, joining its directory and the
+/// compilation directory if the path is relative.
+fn unit_file_path<'a>(
+    dwarf: &gimli::Dwarf<DwarfReader<'a>>,
+    unit: &gimli::Unit<DwarfReader<'a>>,
+    line_program_header: &gimli::LineProgramHeader<DwarfReader<'a>>,
+    file_index: u64,
+) -> Option<String> {
+    let file_entry = line_program_header.file(file_index)?;
+    let attr_to_string = |attr| {
+        dwarf
+            .attr_string(unit, attr)
+            .ok()
+            .map(|s| s.to_string_lossy().into_owned())
+    };
+
+    let mut file_path = attr_to_string(file_entry.path_name())?;
+    if !file_path.starts_with('/') {
+        if let Some(directory) = file_entry
+            .directory(line_program_header)
+            .and_then(attr_to_string)
+        {
+            file_path = format!("{}/{}", directory.trim_end_matches('/'), file_path);
+        }
+    }
+    if !file_path.starts_with('/') {
+        if let Some(comp_dir) = &unit.comp_dir {
+            file_path = format!(
+                "{}/{}",
+                comp_dir.to_string_lossy().trim_end_matches('/'),
+                file_path
+            );
+        }
+    }
+    Some(file_path)
+}
+
+/// Collect all address ranges of a DIE from its low_pc/high_pc or ranges attributes.
+fn die_address_ranges<'a>(
+    dwarf: &gimli::Dwarf<DwarfReader<'a>>,
+    unit: &gimli::Unit<DwarfReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<DwarfReader<'a>>,
+) -> Vec<(u64, u64)> {
+    let mut address_ranges = Vec::new();
+    if let Ok(mut ranges) = dwarf.die_ranges(unit, entry) {
+        while let Ok(Some(range)) = ranges.next() {
+            if range.begin < range.end && range.begin != 0 {
+                address_ranges.push((range.begin, range.end));
+            }
+        }
+    }
+    address_ranges
+}
+
+/// Get the name of the function described by the DIE, following the abstract origin or
+/// specification if needed. The linkage name is preferred as it includes the namespaces.
+fn die_name<'a>(
+    unit: &gimli::Unit<DwarfReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<DwarfReader<'a>>,
+    name_cache: &mut HashMap<gimli::UnitOffset, Option<String>>,
+    dwarf: &gimli::Dwarf<DwarfReader<'a>>,
+) -> Option<String> {
+    for name_attr in [
+        gimli::DW_AT_linkage_name,
+        gimli::DW_AT_MIPS_linkage_name,
+        gimli::DW_AT_name,
+    ] {
+        if let Some(name) = entry
+            .attr_value(name_attr)
+            .and_then(|attr| dwarf.attr_string(unit, attr).ok())
+        {
+            return Some(demangle_symbol(&name.to_string_lossy()));
+        }
+    }
+
+    for origin_attr in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        // References to DIEs in other units (e.g. with LTO) are not followed.
+        if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(origin_attr) {
+            if let Some(name) = name_cache.get(&offset) {
+                return name.clone();
+            }
+            // Mark the offset first to guard against reference cycles.
+            name_cache.insert(offset, None);
+            let name = unit
+                .entry(offset)
+                .ok()
+                .and_then(|origin_entry| die_name(unit, &origin_entry, name_cache, dwarf));
+            name_cache.insert(offset, name.clone());
+            return name;
+        }
+    }
+
+    None
+}
+
+/// Find the split debug info file of an ELF file, which is usually installed by the debuginfo
+/// or dbgsym packages. The file is located through:
+/// 1. The Build-ID: /usr/lib/debug/.build-id/xx/yyyy.debug
+/// 2. The .gnu_debuglink section: <dir>/<debuglink>, <dir>/.debug/<debuglink>, or
+///    /usr/lib/debug/<dir>/<debuglink>
+///
+/// The candidate is only accepted if its Build-ID matches the ELF file's.
+pub fn find_split_debug_file(
+    elf_obj: &object::File,
+    elf_file_path: &str,
+) -> Option<(Vec<u8>, String)> {
+    let build_id = elf_obj.build_id().ok().flatten().map(|b| b.to_vec());
+
+    let mut candidates: Vec<String> = Vec::new();
+    if let Some(build_id) = &build_id {
+        if build_id.len() > 1 {
+            let build_id_hex = build_id
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            candidates.push(format!(
+                "{DEBUG_FILE_DIRECTORY}/.build-id/{}/{}.debug",
+                &build_id_hex[..2],
+                &build_id_hex[2..]
+            ));
+        }
+    }
+    if let Ok(Some((debuglink, _crc))) = elf_obj.gnu_debuglink() {
+        let debuglink = String::from_utf8_lossy(debuglink).into_owned();
+        let elf_dir = Path::new(elf_file_path)
+            .parent()
+            .map_or(String::new(), |dir| dir.to_string_lossy().into_owned());
+        candidates.push(format!("{elf_dir}/{debuglink}"));
+        candidates.push(format!("{elf_dir}/.debug/{debuglink}"));
+        candidates.push(format!("{DEBUG_FILE_DIRECTORY}{elf_dir}/{debuglink}"));
+    }
+
+    for candidate in candidates {
+        if candidate == elf_file_path {
+            continue;
+        }
+        let data = match fs::read(&candidate) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let is_matching = match object::File::parse(&*data) {
+            Ok(debug_obj) => match (&build_id, debug_obj.build_id().ok().flatten()) {
+                (Some(build_id), Some(debug_build_id)) => build_id.as_slice() == debug_build_id,
+                _ => true,
+            },
+            Err(_) => false,
+        };
+        if is_matching {
+            return Some((data, candidate));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::ObjectSymbol;
+
+    #[inline(never)]
+    fn debug_info_test_target() -> u64 {
+        std::hint::black_box(42)
+    }
+
+    #[test]
+    fn test_resolve_source_location() {
+        // Test binaries are built with debug info, so use the test binary itself.
+        std::hint::black_box(debug_info_test_target());
+        let data = fs::read("/proc/self/exe").unwrap();
+        let elf_obj = object::File::parse(&*data).unwrap();
+        let debug_info = DebugInfo::from_elf(&elf_obj).expect("test binary has no debug info");
+
+        let target_addr = elf_obj
+            .symbols()
+            .find(|sym| {
+                sym.name()
+                    .is_ok_and(|name| name.contains("debug_info_test_target"))
+            })
+            .expect("test target symbol not found")
+            .address();
+        let source_frames = debug_info.resolve(target_addr);
+        let location = source_frames
+            .last()
+            .and_then(|source_frame| source_frame.location.as_ref())
+            .expect("test target has no source location");
+        assert!(location.file.ends_with("debug_info.rs"));
+        assert!(location.line > 0);
+        // The address before the first function is not covered.
+        assert!(debug_info.resolve(0).is_empty());
+    }
+}
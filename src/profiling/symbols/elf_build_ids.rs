@@ -1,6 +1,7 @@
+use object::{Object, ReadCache};
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 
 /// An ELF Build-ID is a unique identifier embedded in an ELF file by linkers, which is to
 /// uniquely identify a specific build of a binary or shared library. Tools like Perf use
@@ -20,6 +21,11 @@ impl ElfBuildIds {
         self.build_ids.insert(elf_file_path.to_string(), build_id);
     }
 
+    /// Get the Build-ID of an ELF file path.
+    pub fn get_build_id(&self, elf_file_path: &str) -> Option<&str> {
+        self.build_ids.get(elf_file_path).map(String::as_str)
+    }
+
     /// Find the original build version of the ELF file using the ELF file's Build-ID.
     pub fn find_original_elf_file(&self, elf_file_path: &str) -> Option<PathBuf> {
         let build_id = self.build_ids.get(elf_file_path)?;
@@ -63,3 +69,26 @@ impl ElfBuildIds {
         None
     }
 }
+
+pub fn build_id_to_hex(build_id: &[u8]) -> String {
+    build_id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether the ELF file data has the Build-ID.
+pub fn has_build_id(elf_data: &[u8], build_id: &str) -> bool {
+    match object::File::parse(elf_data) {
+        Ok(elf_obj) => elf_obj
+            .build_id()
+            .ok()
+            .flatten()
+            .is_some_and(|elf_build_id| build_id_to_hex(elf_build_id) == build_id),
+        Err(_) => false,
+    }
+}
+
+/// Read the Build-ID of an ELF file, without reading the whole file.
+pub fn read_elf_file_build_id(elf_file_path: &Path) -> Option<String> {
+    let read_cache = ReadCache::new(File::open(elf_file_path).ok()?);
+    let elf_obj = object::File::parse(&read_cache).ok()?;
+    Some(build_id_to_hex(elf_obj.build_id().ok()??))
+}
@@ -1,7 +1,11 @@
+use crate::profiling::symbols::debug_info::{find_split_debug_file, DebugInfo};
 use crate::profiling::symbols::demangle_symbol;
+use crate::profiling::symbols::stack_unwinder::{StackMemory, UnwindArch, UnwindRegisters};
 use crate::profiling::symbols::{
-    prefer_second_symbol, resolve_symbol, RawSymbol, ResolvedSymbol, SymbolTableEntry,
+    find_symbol_table_entry, prefer_second_symbol, resolve_symbol, RawSymbol, ResolvedSymbol,
+    SymbolTableEntry,
 };
+use crate::profiling::FrameType;
 use anyhow::Result;
 use object::{
     read::elf::SectionHeader, Architecture, Object, ObjectKind, ObjectSection, ObjectSegment,
@@ -18,17 +22,17 @@ struct LoadSegment {
 }
 
 /// Indicates what a CFI (Call Frame Information) in .eh_frame or .debug_frame sections says
-/// about if the current value in the LR register is the correct return address, and can be
-/// used for leaf frame recovery.
+/// about how to recover the caller's value of a register, such as if the current value in the
+/// LR register is the correct return address, which is used for leaf frame recovery.
 #[derive(Clone, Copy, PartialEq, Debug)]
 enum CfiRule {
-    /// The current value in the LR register is the correct return address.
+    /// The current value in the register is the caller's value.
     SameValue,
-    /// The current value in the LR register is not the correct return address, which
-    /// is instead saved on stack with some offsets.
-    Offset,
-    /// The current value in the LR register is not the correct return address for other
-    /// reasons that we do not handle.
+    /// The caller's value is saved on stack at the offset from the CFA.
+    Offset(i64),
+    /// The register has no value in the caller, e.g. the return address of the outermost frame.
+    Undefined,
+    /// The caller's value is recovered in other ways that we do not handle.
     Other,
     /// There is no CFI rule (used for sentinel entries)
     None,
@@ -37,13 +41,33 @@ impl CfiRule {
     fn is_none(&self) -> bool {
         *self == CfiRule::None
     }
+
+    fn from_register_rule<R: gimli::ReaderOffset>(
+        register_rule: Option<&gimli::RegisterRule<R>>,
+        default_rule: CfiRule,
+    ) -> Self {
+        match register_rule {
+            Some(gimli::RegisterRule::SameValue) => CfiRule::SameValue,
+            Some(gimli::RegisterRule::Offset(offset)) => CfiRule::Offset(*offset),
+            Some(gimli::RegisterRule::Undefined) => CfiRule::Undefined,
+            Some(_) => CfiRule::Other,
+            None => default_rule,
+        }
+    }
 }
 
 /// An entry in the CFI rule table, which indicates that for any address that lies between
-/// addr and the next entry's addr, the rule applies.
+/// addr and the next entry's addr, the rules apply.
 struct CfiRuleTableEntry {
     addr: u64,
+    /// The rule of the return address (the LR register on AArch64).
     rule: CfiRule,
+    /// The rule of the frame pointer register.
+    frame_pointer_rule: CfiRule,
+    /// The CFA (Canonical Frame Address, the stack pointer at the call site in the caller) is
+    /// the value of the DWARF register plus the offset. It is None for sentinel entries, or if
+    /// the CFA is computed by a DWARF expression.
+    cfa: Option<(u16, i64)>,
 }
 
 /// Store all symbols retrieved from an ELF file to resolve a file offset.
@@ -53,8 +77,14 @@ pub struct ElfSymbols {
     symbol_table: Vec<SymbolTableEntry>,
     /// All load segments in the ELF file's program header, used to convert a file_offset into p_vaddr.
     load_segments: Vec<LoadSegment>,
-    /// All CFI rules sorted by addr for fast look up during leaf frame recovery.
+    /// All CFI rules sorted by addr for fast look up during leaf frame recovery and stack
+    /// unwinding.
     cfi_rule_table: Vec<CfiRuleTableEntry>,
+    /// The architecture of the ELF file, if the stack unwinder supports it.
+    unwind_arch: Option<UnwindArch>,
+    /// The DWARF line number tables and inlined functions, from the ELF file itself or its
+    /// split debug info file.
+    debug_info: Option<DebugInfo>,
     /// The name of the ELF file.
     elf_filename: String,
     /// True for ET_DYN (shared libraries and PIE executables).
@@ -62,11 +92,12 @@ pub struct ElfSymbols {
 }
 
 impl ElfSymbols {
-    /// Parse the contents of an ELF file and build the symbol table.
+    /// Parse the contents of an ELF file and build the symbol table. If build_cfi_rule_table is
+    /// set, also build the CFI rule table for leaf frame recovery and stack unwinding.
     pub fn from_elf_data(
         elf_data: &[u8],
         elf_filename: String,
-        support_leaf_frame_recovery: bool,
+        build_cfi_rule_table: bool,
     ) -> Result<Self> {
         let elf_obj = object::File::parse(elf_data)?;
         let is_dyn = elf_obj.kind() == ObjectKind::Dynamic;
@@ -142,6 +173,26 @@ impl ElfSymbols {
                 plt_address_range,
             );
         }
+        // Distro binaries ship without DWARF sections, which are instead kept in a separate debug
+        // info file (installed by the debuginfo or dbgsym packages) together with the full .symtab.
+        let split_debug_file = if elf_obj.section_by_name(".debug_info").is_none() {
+            find_split_debug_file(&elf_obj, &elf_filename)
+        } else {
+            None
+        };
+        let split_debug_obj: Option<object::File> = split_debug_file
+            .as_ref()
+            .and_then(|(data, _)| object::File::parse(data as &[u8]).ok());
+        if let Some(ref split_debug_obj) = split_debug_obj {
+            collect_raw_symbols(
+                split_debug_obj.symbols(),
+                &mut raw_symbols,
+                &mut symtab_index_name_map,
+                plt_address_range,
+            );
+        }
+        let debug_info = DebugInfo::from_elf(split_debug_obj.as_ref().unwrap_or(&elf_obj));
+
         let mut symbol_table = process_raw_symbols(raw_symbols);
 
         let plt_symbols = parse_plt(
@@ -160,11 +211,12 @@ impl ElfSymbols {
         symbol_table.extend(plt_symbols);
         symbol_table.sort_by_key(|symbol_table_entry| symbol_table_entry.addr);
 
-        // If leaf frame recovery is enabled, build the CFI rule table
-        let cfi_rule_table = if support_leaf_frame_recovery {
-            create_cfi_rule_table(&elf_obj)
-        } else {
-            Vec::new()
+        let unwind_arch = UnwindArch::from_object_arch(architecture);
+        let cfi_rule_table = match unwind_arch {
+            Some(unwind_arch) if build_cfi_rule_table => {
+                create_cfi_rule_table(&elf_obj, unwind_arch)
+            }
+            _ => Vec::new(),
         };
 
         Ok(ElfSymbols {
@@ -172,6 +224,8 @@ impl ElfSymbols {
             load_segments,
             elf_filename,
             cfi_rule_table,
+            unwind_arch,
+            debug_info,
             is_dyn,
         })
     }
@@ -181,11 +235,40 @@ impl ElfSymbols {
         if self.symbol_table.is_empty() {
             return None;
         }
-        resolve_symbol(
-            self.file_offset_to_p_vaddr(file_offset)?,
-            &self.symbol_table,
-            &self.elf_filename,
-        )
+        let p_vaddr = self.file_offset_to_p_vaddr(file_offset)?;
+        let mut resolved_symbol = resolve_symbol(p_vaddr, &self.symbol_table, &self.elf_filename)?;
+        if let Some(debug_info) = &self.debug_info {
+            for source_frame in debug_info.resolve(p_vaddr) {
+                match source_frame.inlined_function_name {
+                    Some(inlined_function_name) => {
+                        resolved_symbol.inlined_frames.push(ResolvedSymbol {
+                            name: inlined_function_name,
+                            offset: 0,
+                            source: self.elf_filename.clone(),
+                            frame_type: FrameType::Inlined,
+                            location: source_frame.location,
+                            inlined_frames: Vec::new(),
+                        })
+                    }
+                    None => resolved_symbol.location = source_frame.location,
+                }
+            }
+        }
+        Some(resolved_symbol)
+    }
+
+    /// Resolve a file_offset into its virtual address, along with the name and the virtual
+    /// address range of the function containing it.
+    pub fn resolve_function(&self, file_offset: u64) -> Option<(u64, &str, u64, u64)> {
+        let p_vaddr = self.file_offset_to_p_vaddr(file_offset)?;
+        let (symbol_table_entry, symbol_end) =
+            find_symbol_table_entry(p_vaddr, &self.symbol_table)?;
+        Some((
+            p_vaddr,
+            &symbol_table_entry.name,
+            symbol_table_entry.addr,
+            symbol_end,
+        ))
     }
 
     /// Unwind the leaf caller frame by checking the corresponding CFI rule and deciding if the
@@ -212,21 +295,16 @@ impl ElfSymbols {
                 leaf_file_offset
             }
         };
-
-        let matched_cfi_rule = match self
-            .cfi_rule_table
-            .binary_search_by_key(&cfi_table_lookup_key, |cfi_rule_table_entry| {
-                cfi_rule_table_entry.addr
-            }) {
-            Ok(i) => self.cfi_rule_table[i].rule,
-            Err(0) => CfiRule::None,
-            Err(i) => self.cfi_rule_table[i - 1].rule,
-        };
+        let matched_cfi_rule = self
+            .find_cfi_rule_table_entry(cfi_table_lookup_key)
+            .map_or(CfiRule::None, |cfi_rule_table_entry| {
+                cfi_rule_table_entry.rule
+            });
         match matched_cfi_rule {
             // CFI rule says the value in LR is the correct return address of the leaf
             CfiRule::SameValue => Some(lr),
             // CFI rule says the value in LR is not the correct return address of the leaf
-            CfiRule::Offset | CfiRule::Other => None,
+            CfiRule::Offset(_) | CfiRule::Undefined | CfiRule::Other => None,
             // No CFI rule coverage, so fall back to making decision using register values.
             // It follows the same logics in the aarch64_unwind.c of elfutils, which is used
             // by Perf.
@@ -248,6 +326,53 @@ impl ElfSymbols {
         }
     }
 
+    /// Unwind the frame executing the instruction at file_offset using the CFI rules: compute the
+    /// CFA from the registers, and recover the caller's registers saved on the stack.
+    pub fn unwind_frame(
+        &self,
+        file_offset: u64,
+        regs: &UnwindRegisters,
+        stack: &StackMemory,
+    ) -> Option<UnwindRegisters> {
+        let unwind_arch = self.unwind_arch?;
+        // The CFI covers the same p_vaddr as the symbols, which can differ from the file offset
+        // even for ET_DYN files, depending on the linker's segment layout.
+        let p_vaddr = self.file_offset_to_p_vaddr(file_offset)?;
+        let cfi_rule_table_entry = self.find_cfi_rule_table_entry(p_vaddr)?;
+        let (cfa_register, cfa_offset) = cfi_rule_table_entry.cfa?;
+        let cfa = regs
+            .get_dwarf_register(unwind_arch, cfa_register)?
+            .checked_add_signed(cfa_offset)?;
+        let recover_register = |rule: CfiRule, current_value: Option<u64>| match rule {
+            CfiRule::Offset(offset) => stack.read_u64(cfa.checked_add_signed(offset)?),
+            CfiRule::SameValue => current_value,
+            _ => None,
+        };
+        Some(UnwindRegisters {
+            pc: recover_register(cfi_rule_table_entry.rule, regs.lr)?,
+            sp: cfa,
+            fp: recover_register(cfi_rule_table_entry.frame_pointer_rule, regs.fp),
+            lr: None,
+        })
+    }
+
+    /// Find the CFI rule table entry covering the address.
+    fn find_cfi_rule_table_entry(&self, cfi_table_lookup_key: u64) -> Option<&CfiRuleTableEntry> {
+        let cfi_rule_table_entry = match self
+            .cfi_rule_table
+            .binary_search_by_key(&cfi_table_lookup_key, |cfi_rule_table_entry| {
+                cfi_rule_table_entry.addr
+            }) {
+            Ok(i) => &self.cfi_rule_table[i],
+            Err(0) => return None,
+            Err(i) => &self.cfi_rule_table[i - 1],
+        };
+        if cfi_rule_table_entry.rule.is_none() {
+            return None;
+        }
+        Some(cfi_rule_table_entry)
+    }
+
     /// Use the load segment of the ELF file to convert a file_offset into p_vaddr. The number
     /// of load segments is usually small (~4), so linear scan is faster.
     fn file_offset_to_p_vaddr(&self, file_offset: u64) -> Option<u64> {
@@ -548,7 +673,15 @@ fn get_elf_section_sh_entsize(elf_obj: &object::File, section_name: &str) -> u64
 
 /// Create the CFI rule table from the content of an ELF file, by parsing the .eh_frame
 /// and .debug_frame sections.
-fn create_cfi_rule_table(elf_obj: &object::File) -> Vec<CfiRuleTableEntry> {
+fn create_cfi_rule_table(
+    elf_obj: &object::File,
+    unwind_arch: UnwindArch,
+) -> Vec<CfiRuleTableEntry> {
+    // The vendor only affects the AArch64-specific CFI instructions.
+    let vendor = match unwind_arch {
+        UnwindArch::Aarch64 => gimli::Vendor::AArch64,
+        UnwindArch::X86_64 => gimli::Vendor::Default,
+    };
     let mut cfi_rule_table: Vec<CfiRuleTableEntry> = Vec::new();
 
     let mut base_addresses = gimli::BaseAddresses::default();
@@ -568,10 +701,11 @@ fn create_cfi_rule_table(elf_obj: &object::File) -> Vec<CfiRuleTableEntry> {
         if let Ok(eh_frame_section_data) = eh_frame_section.data() {
             let mut eh_frame_unwind_section =
                 gimli::EhFrame::new(eh_frame_section_data, gimli::LittleEndian);
-            eh_frame_unwind_section.set_vendor(gimli::Vendor::AArch64);
+            eh_frame_unwind_section.set_vendor(vendor);
             collect_cfi_rules(
                 &eh_frame_unwind_section,
                 &base_addresses,
+                unwind_arch,
                 &mut cfi_rule_table,
             );
         }
@@ -585,10 +719,11 @@ fn create_cfi_rule_table(elf_obj: &object::File) -> Vec<CfiRuleTableEntry> {
             let mut debug_frame_unwind_section =
                 gimli::DebugFrame::new(&debug_frame_section_data, gimli::LittleEndian);
             debug_frame_unwind_section.set_address_size(8);
-            debug_frame_unwind_section.set_vendor(gimli::Vendor::AArch64);
+            debug_frame_unwind_section.set_vendor(vendor);
             collect_cfi_rules(
                 &debug_frame_unwind_section,
                 &base_addresses,
+                unwind_arch,
                 &mut cfi_rule_table,
             );
         }
@@ -610,6 +745,7 @@ fn create_cfi_rule_table(elf_obj: &object::File) -> Vec<CfiRuleTableEntry> {
 fn collect_cfi_rules<'a, S>(
     section: &S,
     base_addresses: &gimli::BaseAddresses,
+    unwind_arch: UnwindArch,
     cfi_rule_table: &mut Vec<CfiRuleTableEntry>,
 ) where
     S: gimli::UnwindSection<gimli::EndianSlice<'a, gimli::LittleEndian>>,
@@ -619,9 +755,15 @@ fn collect_cfi_rules<'a, S>(
     // entry has CFI (Call Frame Information) that describes the rules.
     let mut fde_entries = section.entries(base_addresses);
     let mut unwind_context = gimli::UnwindContext::new();
-    // The whole frame-unwinding logic is run on ARM only, so we only care
-    // about the LR (X30) register, which stores the return address.
-    let lr_register = gimli::Register(30);
+    let return_address_register = gimli::Register(unwind_arch.dwarf_return_address_register());
+    let frame_pointer_register = gimli::Register(unwind_arch.dwarf_frame_pointer_register());
+    // When there are no explicit rules, use default CFI rules specified by the platform's ABI.
+    // On AArch64, it follows the definitions in aarch64_abi_cfi in aarch64_cfi.c of elfutils.
+    // On x86_64, the CIE always defines the rule of the return address.
+    let default_return_address_rule = match unwind_arch {
+        UnwindArch::Aarch64 => CfiRule::SameValue,
+        UnwindArch::X86_64 => CfiRule::Undefined,
+    };
 
     while let Ok(Some(fde_entry)) = fde_entries.next() {
         if let gimli::CieOrFde::Fde(partial_fde) = fde_entry {
@@ -636,17 +778,29 @@ fn collect_cfi_rules<'a, S>(
                     while let Ok(Some(cfi_row)) = cfi_rows_iter.next_row() {
                         let addr = cfi_row.start_address();
 
-                        let rule = match cfi_row.register(lr_register) {
-                            Some(gimli::RegisterRule::SameValue) => CfiRule::SameValue,
-                            Some(gimli::RegisterRule::Offset(_)) => CfiRule::Offset,
-                            Some(_) => CfiRule::Other,
-                            // When there are no explicit rules, use default CFI rules specified
-                            // by the platform's ABI. It follows the definitions in aarch64_abi_cfi
-                            // in aarch64_cfi.c of elfutils.
-                            None => CfiRule::SameValue,
+                        let rule = CfiRule::from_register_rule(
+                            cfi_row.register(return_address_register).as_ref(),
+                            default_return_address_rule,
+                        );
+                        // The frame pointer is callee-saved, so it keeps the caller's value
+                        // unless the CFI says where it is saved.
+                        let frame_pointer_rule = CfiRule::from_register_rule(
+                            cfi_row.register(frame_pointer_register).as_ref(),
+                            CfiRule::SameValue,
+                        );
+                        let cfa = match cfi_row.cfa() {
+                            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                                Some((register.0, *offset))
+                            }
+                            gimli::CfaRule::Expression(_) => None,
                         };
 
-                        cfi_rule_table.push(CfiRuleTableEntry { addr, rule });
+                        cfi_rule_table.push(CfiRuleTableEntry {
+                            addr,
+                            rule,
+                            frame_pointer_rule,
+                            cfa,
+                        });
                     }
                 }
 
@@ -656,6 +810,8 @@ fn collect_cfi_rules<'a, S>(
                 cfi_rule_table.push(CfiRuleTableEntry {
                     addr: fde_end,
                     rule: CfiRule::None,
+                    frame_pointer_rule: CfiRule::None,
+                    cfa: None,
                 })
             }
         }
@@ -1,3 +1,4 @@
+use crate::profiling::symbols::mount_namespace::find_perf_map_file;
 use crate::profiling::symbols::{resolve_symbol, ResolvedSymbol, SymbolTableEntry};
 use crate::run_command_and_wait;
 use anyhow::Result;
@@ -27,15 +28,24 @@ impl JitSymbols {
     /// Refer to the dso__load_perf_map function in
     /// https://github.com/torvalds/linux/blob/master/tools/perf/util/symbol.c
     pub fn from_perf_map(pid: i32, is_hotspot_jvm: bool) -> Result<Self> {
-        let perf_map_file_path = PathBuf::from(format!("/tmp/perf-{}.map", pid));
-        if !perf_map_file_path.exists() {
-            if is_hotspot_jvm {
+        // A process in a container writes the perf map to its own /tmp, named after its PID in
+        // the container.
+        let perf_map_file_path = match find_perf_map_file(pid) {
+            Some(perf_map_file_path) => perf_map_file_path,
+            None if is_hotspot_jvm => {
                 create_hotspot_jvm_perf_map(pid);
-            } else {
-                return Ok(Self::default());
+                match find_perf_map_file(pid) {
+                    Some(perf_map_file_path) => perf_map_file_path,
+                    None => return Ok(Self::default()),
+                }
             }
-        }
+            None => return Ok(Self::default()),
+        };
+        Self::from_perf_map_file(perf_map_file_path)
+    }
 
+    /// Parse the JIT symbols in a perf map file, such as a copy of /tmp/perf-<pid>.map.
+    pub fn from_perf_map_file(perf_map_file_path: PathBuf) -> Result<Self> {
         let perf_map_file = File::open(&perf_map_file_path)?;
         let buf_reader = BufReader::new(perf_map_file);
 
@@ -39,10 +39,18 @@ pub struct KernelSymbols {
 impl KernelSymbols {
     /// Parse the content of /proc/kallsyms to build the Kernel symbol table.
     pub fn from_kallsyms() -> Result<Self> {
+        Self::from_kallsyms_files(
+            PathBuf::from("/proc/kallsyms"),
+            PathBuf::from("/proc/modules"),
+        )
+    }
+
+    /// Build the Kernel symbol table from copies of /proc/kallsyms and /proc/modules.
+    pub fn from_kallsyms_files(kallsyms_path: PathBuf, modules_path: PathBuf) -> Result<Self> {
         Ok(KernelSymbols {
             symbol_table: process_raw_kernel_symbols(
-                collect_raw_kernel_symbols(PathBuf::from("/proc/kallsyms"))?,
-                load_proc_modules(),
+                collect_raw_kernel_symbols(kallsyms_path)?,
+                load_proc_modules(modules_path),
             ),
         })
     }
@@ -75,10 +83,10 @@ fn is_valid_type_letter(t: char) -> bool {
 
 /// Load /proc/modules so we can attribute a symbol to its module when
 /// kallsyms has duplicates at the same address from different modules.
-fn load_proc_modules() -> Vec<ProcModuleEntry> {
+fn load_proc_modules(path: PathBuf) -> Vec<ProcModuleEntry> {
     let mut proc_module_entries: Vec<ProcModuleEntry> = Vec::new();
 
-    if let Ok(proc_module_content) = fs::read_to_string("/proc/modules") {
+    if let Ok(proc_module_content) = fs::read_to_string(path) {
         for line in proc_module_content.lines() {
             // Format: name size refcount deps state base_addr
             let parts: Vec<&str> = line.split_whitespace().collect();
@@ -1,12 +1,16 @@
 #![cfg(target_os = "linux")]
 
-use crate::profiling::FrameType;
+use crate::profiling::{FrameType, SourceLocation};
 
+mod debug_info;
 mod elf_build_ids;
 mod elf_symbols;
 mod jit_symbols;
 mod kernel_symbols;
 mod mmap_resolver;
+mod mount_namespace;
+pub mod stack_unwinder;
+pub mod symbol_bundle;
 pub mod symbol_resolver;
 mod vdso_symbols;
 
@@ -21,7 +25,7 @@ pub struct SymbolTableEntry {
 }
 
 /// Information of a resolved symbol.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ResolvedSymbol {
     /// The human-readable name of the symbol.
     pub name: String,
@@ -31,15 +35,33 @@ pub struct ResolvedSymbol {
     pub source: String,
     /// The type of the frame.
     pub frame_type: FrameType,
+    /// The source file and line of the address, if debug info is available.
+    pub location: Option<SourceLocation>,
+    /// The functions inlined at the address, ordered from the innermost to the outermost.
+    pub inlined_frames: Vec<ResolvedSymbol>,
 }
 
-/// Attempt to resolve an address into a symbol in the symbol table. Entries in the symbol
-/// table is sorted by every symbol's starting address to enable binary search.
-pub fn resolve_symbol(
+/// A function in an ELF file that can be disassembled, identified by its virtual address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ElfFunction {
+    /// The human-readable name of the function.
+    pub name: String,
+    /// The ELF file that the function belongs to.
+    pub source: String,
+    /// The path that the data of the ELF file was read from.
+    pub elf_data_path: String,
+    /// The virtual address range [start, end) of the function.
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Find the entry of the symbol table that contains an address, along with the end address of
+/// the symbol. Entries in the symbol table is sorted by every symbol's starting address to enable
+/// binary search.
+pub fn find_symbol_table_entry(
     addr: u64,
-    symbol_table: &Vec<SymbolTableEntry>,
-    source: &str,
-) -> Option<ResolvedSymbol> {
+    symbol_table: &[SymbolTableEntry],
+) -> Option<(&SymbolTableEntry, u64)> {
     let idx = match symbol_table
         .binary_search_by_key(&addr, |symbol_table_entry| symbol_table_entry.addr)
     {
@@ -48,23 +70,31 @@ pub fn resolve_symbol(
         Err(i) => i - 1,
     };
     let symbol_table_entry = &symbol_table[idx];
-    let symbol_offset = addr - symbol_table_entry.addr;
-    if symbol_table_entry.size > 0 {
-        if symbol_offset >= symbol_table_entry.size {
-            return None;
-        }
+    let symbol_end = if symbol_table_entry.size > 0 {
+        symbol_table_entry.addr + symbol_table_entry.size
     } else {
         // Handle symbols with zero-size, referring to function symbols__fixup_end in
         // https://github.com/torvalds/linux/blob/master/tools/perf/util/symbol.c
         if idx + 1 < symbol_table.len() {
-            let next_addr = symbol_table[idx + 1].addr;
-            if addr >= next_addr {
-                return None;
-            }
-        } else if addr >= tail_symbol_bound(symbol_table_entry.addr) {
-            return None;
+            symbol_table[idx + 1].addr
+        } else {
+            tail_symbol_bound(symbol_table_entry.addr)
         }
+    };
+    if addr >= symbol_end {
+        return None;
     }
+    Some((symbol_table_entry, symbol_end))
+}
+
+/// Attempt to resolve an address into a symbol in the symbol table.
+pub fn resolve_symbol(
+    addr: u64,
+    symbol_table: &Vec<SymbolTableEntry>,
+    source: &str,
+) -> Option<ResolvedSymbol> {
+    let (symbol_table_entry, _) = find_symbol_table_entry(addr, symbol_table)?;
+    let symbol_offset = addr - symbol_table_entry.addr;
 
     Some(ResolvedSymbol {
         name: symbol_table_entry.name.clone(),
@@ -72,6 +102,8 @@ pub fn resolve_symbol(
         source: source.to_string(),
         // To be overriden by the caller.
         frame_type: FrameType::default(),
+        location: None,
+        inlined_frames: Vec::new(),
     })
 }
 
@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// The path of a file in the filesystem of a process, which is different from the file at the
+/// same path on the host if the process runs in a container (i.e. in its own mount namespace).
+/// Accessing it requires the same user as the process or root, and it no longer exists once the
+/// process exits.
+pub fn process_root_path(pid: i32, path: &str) -> PathBuf {
+    PathBuf::from(format!("/proc/{pid}/root{path}"))
+}
+
+/// The mount namespace of a process, such as mnt:[4026531841].
+fn mount_namespace(pid: &str) -> Option<PathBuf> {
+    fs::read_link(format!("/proc/{pid}/ns/mnt")).ok()
+}
+
+/// Whether a process runs in a different mount namespace than APerf, such as in a container.
+pub fn is_in_other_mount_namespace(pid: i32) -> bool {
+    match (mount_namespace("self"), mount_namespace(&pid.to_string())) {
+        (Some(self_mount_namespace), Some(mount_namespace)) => {
+            self_mount_namespace != mount_namespace
+        }
+        _ => false,
+    }
+}
+
+/// The PID of a process in its own PID namespace, which is the last value of the NSpid field
+/// in /proc/<pid>/status. Runtimes in containers name their perf maps after this PID.
+pub fn namespace_pid(pid: i32) -> Option<i32> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))?
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()
+}
+
+/// All running processes that are in a different mount namespace than APerf.
+fn other_mount_namespace_processes() -> Vec<(i32, PathBuf)> {
+    let self_mount_namespace = match mount_namespace("self") {
+        Some(self_mount_namespace) => self_mount_namespace,
+        None => return Vec::new(),
+    };
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut processes = Vec::new();
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_string_lossy().parse::<i32>() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        if let Some(mount_namespace) = mount_namespace(&pid.to_string()) {
+            if mount_namespace != self_mount_namespace {
+                processes.push((pid, mount_namespace));
+            }
+        }
+    }
+    processes
+}
+
+/// One process from every mount namespace other than APerf's, through whose root directory the
+/// files in every container can be accessed.
+pub fn other_mount_namespace_pids() -> Vec<i32> {
+    let mut processes = other_mount_namespace_processes();
+    processes.sort();
+    let mut mount_namespaces = Vec::new();
+    let mut pids = Vec::new();
+    for (pid, mount_namespace) in processes {
+        if !mount_namespaces.contains(&mount_namespace) {
+            mount_namespaces.push(mount_namespace);
+            pids.push(pid);
+        }
+    }
+    pids
+}
+
+/// The perf map file of a process in a container, which is written to /tmp/perf-<nspid>.map in
+/// the container's filesystem.
+fn container_perf_map_file(pid: i32) -> Option<PathBuf> {
+    let perf_map_file_path =
+        process_root_path(pid, &format!("/tmp/perf-{}.map", namespace_pid(pid)?));
+    perf_map_file_path.exists().then_some(perf_map_file_path)
+}
+
+/// Find the perf map file of a process, either /tmp/perf-<pid>.map on the host or the one in the
+/// process's container.
+pub fn find_perf_map_file(pid: i32) -> Option<PathBuf> {
+    let perf_map_file_path = PathBuf::from(format!("/tmp/perf-{pid}.map"));
+    if perf_map_file_path.exists() {
+        return Some(perf_map_file_path);
+    }
+    if is_in_other_mount_namespace(pid) {
+        return container_perf_map_file(pid);
+    }
+    None
+}
+
+/// The perf map files of all running processes in containers, mapped to their PIDs on the host.
+pub fn container_perf_map_files() -> Vec<(i32, PathBuf)> {
+    other_mount_namespace_processes()
+        .into_iter()
+        .filter_map(|(pid, _)| Some((pid, container_perf_map_file(pid)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_own_mount_namespace() {
+        let pid = std::process::id() as i32;
+        assert!(!is_in_other_mount_namespace(pid));
+        assert!(!other_mount_namespace_pids().contains(&pid));
+        assert_eq!(
+            process_root_path(pid, "/usr/lib/libc.so.6"),
+            PathBuf::from(format!("/proc/{pid}/root/usr/lib/libc.so.6"))
+        );
+        assert!(namespace_pid(pid).is_some());
+    }
+}
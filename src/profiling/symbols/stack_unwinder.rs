@@ -0,0 +1,265 @@
+use object::Architecture;
+
+/// The maximum number of userspace frames to unwind from a stack snapshot, matching the
+/// default kernel.perf_event_max_stack.
+pub const MAX_UNWIND_FRAMES: usize = 127;
+
+/// The architectures supported by the stack unwinder, which determine the register numbers
+/// used by DWARF CFI and by Perf.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnwindArch {
+    Aarch64,
+    X86_64,
+}
+
+impl UnwindArch {
+    /// Map the string values from uname -m.
+    pub fn from_uname(arch: &str) -> Option<Self> {
+        match arch {
+            "aarch64" | "arm64" => Some(UnwindArch::Aarch64),
+            "x86_64" => Some(UnwindArch::X86_64),
+            _ => None,
+        }
+    }
+
+    pub fn from_object_arch(architecture: Architecture) -> Option<Self> {
+        match architecture {
+            Architecture::Aarch64 => Some(UnwindArch::Aarch64),
+            Architecture::X86_64 => Some(UnwindArch::X86_64),
+            _ => None,
+        }
+    }
+
+    // The DWARF register numbers are defined by the psABI of each architecture:
+    // https://github.com/ARM-software/abi-aa/blob/main/aadwarf64/aadwarf64.rst
+    // https://gitlab.com/x86-psABIs/x86-64-ABI
+
+    /// The DWARF register (or column, on x86_64) holding the return address.
+    pub fn dwarf_return_address_register(&self) -> u16 {
+        match self {
+            UnwindArch::Aarch64 => 30,
+            UnwindArch::X86_64 => 16,
+        }
+    }
+
+    pub fn dwarf_frame_pointer_register(&self) -> u16 {
+        match self {
+            UnwindArch::Aarch64 => 29,
+            UnwindArch::X86_64 => 6,
+        }
+    }
+
+    pub fn dwarf_stack_pointer_register(&self) -> u16 {
+        match self {
+            UnwindArch::Aarch64 => 31,
+            UnwindArch::X86_64 => 7,
+        }
+    }
+
+    // The Perf register numbers are defined in arch/<arch>/include/uapi/asm/perf_regs.h.
+
+    fn perf_pc_register(&self) -> u64 {
+        match self {
+            UnwindArch::Aarch64 => 32,
+            UnwindArch::X86_64 => 8,
+        }
+    }
+
+    fn perf_stack_pointer_register(&self) -> u64 {
+        match self {
+            UnwindArch::Aarch64 => 31,
+            UnwindArch::X86_64 => 7,
+        }
+    }
+
+    fn perf_frame_pointer_register(&self) -> u64 {
+        match self {
+            UnwindArch::Aarch64 => 29,
+            UnwindArch::X86_64 => 6,
+        }
+    }
+
+    fn perf_link_register(&self) -> Option<u64> {
+        match self {
+            UnwindArch::Aarch64 => Some(30),
+            UnwindArch::X86_64 => None,
+        }
+    }
+}
+
+/// The registers needed to unwind a frame. Only the registers that CFA rules are commonly
+/// based on, or that hold the return address, are tracked.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UnwindRegisters {
+    pub pc: u64,
+    pub sp: u64,
+    pub fp: Option<u64>,
+    /// The link register, which is only valid in the leaf frame on AArch64.
+    pub lr: Option<u64>,
+}
+
+impl UnwindRegisters {
+    /// Build the registers from the user registers sampled by Perf, where get_perf_register
+    /// returns the value of a Perf register number.
+    pub fn from_perf_registers(
+        arch: UnwindArch,
+        get_perf_register: impl Fn(u64) -> Option<u64>,
+    ) -> Option<Self> {
+        Some(UnwindRegisters {
+            pc: get_perf_register(arch.perf_pc_register())?,
+            sp: get_perf_register(arch.perf_stack_pointer_register())?,
+            fp: get_perf_register(arch.perf_frame_pointer_register()),
+            lr: arch.perf_link_register().and_then(get_perf_register),
+        })
+    }
+
+    /// Get the value of a DWARF register.
+    pub fn get_dwarf_register(&self, arch: UnwindArch, register: u16) -> Option<u64> {
+        if register == arch.dwarf_stack_pointer_register() {
+            Some(self.sp)
+        } else if register == arch.dwarf_frame_pointer_register() {
+            self.fp
+        } else if register == arch.dwarf_return_address_register() {
+            self.lr
+        } else {
+            None
+        }
+    }
+}
+
+/// A snapshot of the userspace stack copied by Perf, starting at the sampled stack pointer.
+pub struct StackMemory<'a> {
+    base: u64,
+    data: &'a [u8],
+}
+
+impl<'a> StackMemory<'a> {
+    pub fn new(base: u64, data: &'a [u8]) -> Self {
+        StackMemory { base, data }
+    }
+
+    /// Read a word at the address, if it is within the snapshot.
+    pub fn read_u64(&self, addr: u64) -> Option<u64> {
+        let start = usize::try_from(addr.checked_sub(self.base)?).ok()?;
+        let bytes = self.data.get(start..start.checked_add(8)?)?;
+        Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// Unwind a frame by following the frame record that the frame pointer points to, which is used
+/// when there is no CFI covering the instruction (e.g. JIT-compiled code). On both x86_64 and
+/// AArch64, the frame record stores the caller's frame pointer followed by the return address.
+pub fn unwind_frame_by_frame_pointer(
+    regs: &UnwindRegisters,
+    stack: &StackMemory,
+) -> Option<UnwindRegisters> {
+    let fp = regs.fp?;
+    if fp == 0 {
+        return None;
+    }
+    Some(UnwindRegisters {
+        pc: stack.read_u64(fp.checked_add(8)?)?,
+        sp: fp.checked_add(16)?,
+        fp: Some(stack.read_u64(fp)?),
+        lr: None,
+    })
+}
+
+/// The length of an x86_64 direct near call instruction (E8 followed by a rel32 displacement).
+pub const X86_DIRECT_CALL_LENGTH: usize = 5;
+
+/// Decode the displacement of an x86_64 direct near call, given the instruction bytes that
+/// precede a return address. The call target is the return address plus the displacement.
+pub fn decode_x86_direct_call(instruction: &[u8]) -> Option<i32> {
+    match instruction {
+        [0xe8, displacement @ ..] => Some(i32::from_le_bytes(displacement.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_x86_direct_call() {
+        assert_eq!(
+            decode_x86_direct_call(&[0xe8, 0x10, 0x00, 0x00, 0x00]),
+            Some(0x10)
+        );
+        assert_eq!(
+            decode_x86_direct_call(&[0xe8, 0xf0, 0xff, 0xff, 0xff]),
+            Some(-0x10)
+        );
+        // An indirect call (call *%rax) preceded by other bytes.
+        assert_eq!(
+            decode_x86_direct_call(&[0x48, 0x89, 0xc7, 0xff, 0xd0]),
+            None
+        );
+        assert_eq!(decode_x86_direct_call(&[0xe8, 0x10]), None);
+    }
+
+    fn stack_bytes(words: &[u64]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_stack_memory_bounds() {
+        let data = stack_bytes(&[1, 2, 3]);
+        let stack = StackMemory::new(0x1000, &data);
+        assert_eq!(stack.read_u64(0x1000), Some(1));
+        assert_eq!(stack.read_u64(0x1010), Some(3));
+        assert_eq!(stack.read_u64(0x1011), None);
+        assert_eq!(stack.read_u64(0xff8), None);
+        assert_eq!(stack.read_u64(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_unwind_by_frame_pointer() {
+        // Frame records at 0x1008 -> 0x1020 -> 0 (outermost)
+        let data = stack_bytes(&[0xdead, 0x1020, 0x4000, 0xbeef, 0, 0x5000]);
+        let stack = StackMemory::new(0x1000, &data);
+        let regs = UnwindRegisters {
+            pc: 0x3000,
+            sp: 0x1000,
+            fp: Some(0x1008),
+            lr: None,
+        };
+        let caller_regs = unwind_frame_by_frame_pointer(&regs, &stack).unwrap();
+        assert_eq!(caller_regs.pc, 0x4000);
+        assert_eq!(caller_regs.sp, 0x1018);
+        assert_eq!(caller_regs.fp, Some(0x1020));
+        let caller_regs = unwind_frame_by_frame_pointer(&caller_regs, &stack).unwrap();
+        assert_eq!(caller_regs.pc, 0x5000);
+        assert_eq!(caller_regs.fp, Some(0));
+        assert!(unwind_frame_by_frame_pointer(&caller_regs, &stack).is_none());
+    }
+
+    #[test]
+    fn test_registers_from_perf() {
+        let x86_regs = UnwindRegisters::from_perf_registers(UnwindArch::X86_64, |register| {
+            Some(register * 0x10)
+        })
+        .unwrap();
+        assert_eq!(x86_regs.pc, 0x80);
+        assert_eq!(x86_regs.sp, 0x70);
+        assert_eq!(x86_regs.fp, Some(0x60));
+        assert_eq!(x86_regs.lr, None);
+        assert_eq!(
+            x86_regs.get_dwarf_register(UnwindArch::X86_64, 7),
+            Some(0x70)
+        );
+        assert_eq!(x86_regs.get_dwarf_register(UnwindArch::X86_64, 3), None);
+
+        let arm_regs = UnwindRegisters::from_perf_registers(UnwindArch::Aarch64, |register| {
+            Some(register * 0x10)
+        })
+        .unwrap();
+        assert_eq!(arm_regs.pc, 0x200);
+        assert_eq!(arm_regs.lr, Some(0x1e0));
+        assert_eq!(
+            arm_regs.get_dwarf_register(UnwindArch::Aarch64, 30),
+            Some(0x1e0)
+        );
+    }
+}
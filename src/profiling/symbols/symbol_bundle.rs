@@ -0,0 +1,409 @@
+use crate::profiling::symbols::elf_build_ids::{
+    build_id_to_hex, has_build_id, read_elf_file_build_id, ElfBuildIds,
+};
+use crate::profiling::symbols::mount_namespace::{
+    container_perf_map_files, other_mount_namespace_pids, process_root_path,
+};
+use crate::profiling::symbols::vdso_symbols::read_vdso_elf_data;
+use crate::profiling::symbols::VDSO_ELF_FILE_PATH;
+use anyhow::Result;
+use linux_perf_data::PerfFileReader;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The name of the directory within the run data directory that stores the symbol bundle.
+pub const SYMBOL_BUNDLE_DIR_NAME: &str = "symbol_bundle";
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const ELF_FILES_DIR_NAME: &str = "elf";
+const JIT_MAPS_DIR_NAME: &str = "jit";
+const KALLSYMS_FILE_NAME: &str = "kallsyms";
+const MODULES_FILE_NAME: &str = "modules";
+
+/// An ELF file copied into the symbol bundle, stored as elf/<build_id>.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundledElfFile {
+    /// The path of the ELF file on the recorded system.
+    pub path: String,
+    pub build_id: String,
+    pub size: u64,
+}
+
+/// Describes the content of a symbol bundle.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SymbolBundleManifest {
+    /// The boot timestamp of the recorded system, which is needed to convert the sample
+    /// timestamps into epoch when the profile is parsed on another system.
+    pub system_boot_timestamp_ms: Option<i64>,
+    pub elf_files: Vec<BundledElfFile>,
+    /// The sampled ELF files that were not bundled, since they could not be found, no longer
+    /// matched the Build-ID, or did not fit into the size budget.
+    pub missing_elf_files: Vec<String>,
+    /// The PIDs whose /tmp/perf-<pid>.map files were bundled.
+    pub jit_map_pids: Vec<i32>,
+}
+
+/// A symbol bundle is a copy of everything needed to symbolize a Perf profile, captured at the
+/// end of recording: the sampled ELF files (identified by their Build-IDs), the Kernel symbols,
+/// and the JIT symbol maps. It allows the profile to be symbolized after the recorded system is
+/// gone, or on a different system where the ELF files are missing or are of different builds.
+pub struct SymbolBundle {
+    dir: PathBuf,
+    manifest: SymbolBundleManifest,
+    /// A map from an ELF file's path on the recorded system to its Build-ID.
+    elf_build_ids: HashMap<String, String>,
+}
+
+impl SymbolBundle {
+    /// Open the symbol bundle in the directory, if one exists.
+    pub fn open(dir: &Path) -> Option<Self> {
+        let manifest: SymbolBundleManifest = match fs::read_to_string(dir.join(MANIFEST_FILE_NAME))
+        {
+            Ok(json) => serde_json::from_str(&json).ok()?,
+            Err(_) => return None,
+        };
+        let elf_build_ids = manifest
+            .elf_files
+            .iter()
+            .map(|elf_file| (elf_file.path.clone(), elf_file.build_id.clone()))
+            .collect();
+        Some(SymbolBundle {
+            dir: dir.to_path_buf(),
+            manifest,
+            elf_build_ids,
+        })
+    }
+
+    pub fn system_boot_timestamp_ms(&self) -> Option<i64> {
+        self.manifest.system_boot_timestamp_ms
+    }
+
+    /// Find the bundled copy of the ELF file at the path on the recorded system.
+    pub fn find_elf_file(&self, elf_file_path: &str) -> Option<PathBuf> {
+        let build_id = self.elf_build_ids.get(elf_file_path)?;
+        Some(self.dir.join(ELF_FILES_DIR_NAME).join(build_id))
+    }
+
+    /// Find the bundled copy of the /tmp/perf-<pid>.map file of a PID.
+    pub fn find_jit_map(&self, pid: i32) -> Option<PathBuf> {
+        if !self.manifest.jit_map_pids.contains(&pid) {
+            return None;
+        }
+        Some(
+            self.dir
+                .join(JIT_MAPS_DIR_NAME)
+                .join(jit_map_file_name(pid)),
+        )
+    }
+
+    /// The bundled copies of /proc/kallsyms and /proc/modules.
+    pub fn kernel_symbol_files(&self) -> Option<(PathBuf, PathBuf)> {
+        let kallsyms_path = self.dir.join(KALLSYMS_FILE_NAME);
+        if !kallsyms_path.exists() {
+            return None;
+        }
+        Some((kallsyms_path, self.dir.join(MODULES_FILE_NAME)))
+    }
+}
+
+fn jit_map_file_name(pid: i32) -> String {
+    format!("perf-{pid}.map")
+}
+
+/// Create a symbol bundle in bundle_dir for the Perf profiles. The Kernel symbols are always
+/// bundled, while the JIT maps written since recording_start and the sampled ELF files are
+/// bundled from the smallest to the largest until size_budget_bytes is used up. JIT maps
+/// are bundled first, since they cannot be recovered once the processes exit.
+pub fn create_symbol_bundle(
+    perf_data_paths: &[PathBuf],
+    bundle_dir: &Path,
+    size_budget_bytes: u64,
+    recording_start: SystemTime,
+) -> Result<SymbolBundleManifest> {
+    fs::create_dir_all(bundle_dir.join(ELF_FILES_DIR_NAME))?;
+    fs::create_dir_all(bundle_dir.join(JIT_MAPS_DIR_NAME))?;
+
+    let mut manifest = SymbolBundleManifest {
+        system_boot_timestamp_ms: procfs::boot_time()
+            .ok()
+            .map(|boot_time| boot_time.timestamp_millis()),
+        ..Default::default()
+    };
+
+    // Files under /proc report a size of 0, so they need to be read instead of copied.
+    for (proc_file_path, file_name) in [
+        ("/proc/kallsyms", KALLSYMS_FILE_NAME),
+        ("/proc/modules", MODULES_FILE_NAME),
+    ] {
+        if let Err(e) =
+            fs::read(proc_file_path).and_then(|data| fs::write(bundle_dir.join(file_name), data))
+        {
+            warn!("Failed to bundle {proc_file_path}: {e}");
+        }
+    }
+
+    let mut remaining_budget_bytes = size_budget_bytes;
+
+    let mut jit_maps = collect_recent_jit_maps(recording_start);
+    jit_maps.sort_by_key(|(_, _, size)| *size);
+    for (pid, jit_map_path, size) in jit_maps {
+        if size > remaining_budget_bytes {
+            debug!(
+                "Skipped bundling {} due to size budget",
+                jit_map_path.display()
+            );
+            continue;
+        }
+        match fs::copy(
+            &jit_map_path,
+            bundle_dir
+                .join(JIT_MAPS_DIR_NAME)
+                .join(jit_map_file_name(pid)),
+        ) {
+            Ok(_) => {
+                remaining_budget_bytes -= size;
+                manifest.jit_map_pids.push(pid);
+            }
+            Err(e) => debug!("Failed to bundle {}: {e}", jit_map_path.display()),
+        }
+    }
+
+    // The VDSO is not a file on disk, but is part of the Kernel that the profile was recorded
+    // on, so it is read from APerf's memory.
+    let vdso_data = read_vdso_elf_data();
+
+    let sampled_elf_build_ids = collect_sampled_elf_build_ids(perf_data_paths);
+    let mut elf_build_ids = ElfBuildIds::default();
+    for (elf_file_path, build_id) in &sampled_elf_build_ids {
+        elf_build_ids.add_build_id(elf_file_path, build_id.clone());
+    }
+    // The sampled processes in containers map the files in their own filesystems.
+    let container_pids = other_mount_namespace_pids();
+    let mut elf_file_candidates: Vec<(&String, &String, Option<PathBuf>, u64)> = Vec::new();
+    for (elf_file_path, build_id) in &sampled_elf_build_ids {
+        if elf_file_path == VDSO_ELF_FILE_PATH {
+            if let Some(vdso_data) = &vdso_data {
+                elf_file_candidates.push((elf_file_path, build_id, None, vdso_data.len() as u64));
+                continue;
+            }
+        }
+        match find_elf_file(&elf_build_ids, elf_file_path, build_id, &container_pids) {
+            Some((source_path, size)) => {
+                elf_file_candidates.push((elf_file_path, build_id, Some(source_path), size))
+            }
+            None => manifest.missing_elf_files.push(elf_file_path.clone()),
+        }
+    }
+    elf_file_candidates.sort_by_key(|(_, _, _, size)| *size);
+
+    for (elf_file_path, build_id, source_path, size) in elf_file_candidates {
+        if size > remaining_budget_bytes {
+            manifest.missing_elf_files.push(elf_file_path.clone());
+            continue;
+        }
+        let elf_data = match source_path {
+            Some(source_path) => match fs::read(&source_path) {
+                Ok(elf_data) => elf_data,
+                Err(_) => {
+                    manifest.missing_elf_files.push(elf_file_path.clone());
+                    continue;
+                }
+            },
+            None => vdso_data.clone().unwrap_or_default(),
+        };
+        // The file at the original path may have been replaced by a different build since it
+        // was sampled, in which case it cannot be used to symbolize the profile.
+        if !has_build_id(&elf_data, build_id) {
+            debug!("Skipped bundling {elf_file_path} due to mismatched Build-ID");
+            manifest.missing_elf_files.push(elf_file_path.clone());
+            continue;
+        }
+        let bundled_elf_file_path = bundle_dir.join(ELF_FILES_DIR_NAME).join(build_id);
+        if !bundled_elf_file_path.exists() {
+            if let Err(e) = fs::write(&bundled_elf_file_path, &elf_data) {
+                warn!("Failed to bundle {elf_file_path}: {e}");
+                manifest.missing_elf_files.push(elf_file_path.clone());
+                continue;
+            }
+            remaining_budget_bytes -= size;
+        }
+        manifest.elf_files.push(BundledElfFile {
+            path: elf_file_path.clone(),
+            build_id: build_id.clone(),
+            size,
+        });
+    }
+
+    fs::write(
+        bundle_dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_string(&manifest)?,
+    )?;
+
+    Ok(manifest)
+}
+
+/// Collect the Build-IDs of the ELF files that were sampled in the Perf profiles, which Perf
+/// writes into the header of the profile.
+fn collect_sampled_elf_build_ids(perf_data_paths: &[PathBuf]) -> BTreeMap<String, String> {
+    let mut elf_build_ids: BTreeMap<String, String> = BTreeMap::new();
+    for perf_data_path in perf_data_paths {
+        let perf_file = match File::open(perf_data_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(PerfFileReader::parse_file(BufReader::new(file))?))
+        {
+            Ok(PerfFileReader { perf_file, .. }) => perf_file,
+            Err(e) => {
+                debug!("Failed to read {}: {e}", perf_data_path.display());
+                continue;
+            }
+        };
+        if let Ok(build_ids) = perf_file.build_ids() {
+            for dso_info in build_ids.values() {
+                elf_build_ids.insert(
+                    String::from_utf8_lossy(&dso_info.path).to_string(),
+                    build_id_to_hex(&dso_info.build_id),
+                );
+            }
+        }
+    }
+    elf_build_ids
+}
+
+/// Find the file of a sampled ELF file path that matches its Build-ID: the copy in Perf's
+/// Build-ID cache, the file at the same path on the host, or the file at the same path in one of
+/// the containers. Returns the path of the file and its size.
+fn find_elf_file(
+    elf_build_ids: &ElfBuildIds,
+    elf_file_path: &str,
+    build_id: &str,
+    container_pids: &[i32],
+) -> Option<(PathBuf, u64)> {
+    let candidates = elf_build_ids
+        .find_original_elf_file(elf_file_path)
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(elf_file_path)))
+        .chain(
+            container_pids
+                .iter()
+                .map(|pid| process_root_path(*pid, elf_file_path)),
+        );
+    for candidate in candidates {
+        match fs::metadata(&candidate) {
+            Ok(metadata) if metadata.is_file() => {
+                if read_elf_file_build_id(&candidate).as_deref() == Some(build_id) {
+                    return Some((candidate, metadata.len()));
+                }
+            }
+            _ => continue,
+        }
+    }
+    None
+}
+
+/// Collect the /tmp/perf-<pid>.map files on the host and in the containers that were written
+/// since the recording started. The perf maps in containers are mapped to the PIDs on the host.
+fn collect_recent_jit_maps(recording_start: SystemTime) -> Vec<(i32, PathBuf, u64)> {
+    let mut perf_map_files: Vec<(i32, PathBuf)> = Vec::new();
+    if let Ok(entries) = fs::read_dir("/tmp") {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(pid) = file_name
+                .strip_prefix("perf-")
+                .and_then(|name| name.strip_suffix(".map"))
+                .and_then(|pid| pid.parse::<i32>().ok())
+            {
+                perf_map_files.push((pid, entry.path()));
+            }
+        }
+    }
+    perf_map_files.extend(container_perf_map_files());
+
+    let mut jit_maps: Vec<(i32, PathBuf, u64)> = Vec::new();
+    for (pid, perf_map_file_path) in perf_map_files {
+        if jit_maps
+            .iter()
+            .any(|(jit_map_pid, _, _)| *jit_map_pid == pid)
+        {
+            continue;
+        }
+        if let Ok(metadata) = fs::metadata(&perf_map_file_path) {
+            if metadata
+                .modified()
+                .is_ok_and(|modified| modified >= recording_start)
+            {
+                jit_maps.push((pid, perf_map_file_path, metadata.len()));
+            }
+        }
+    }
+    jit_maps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::Object;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let exe_data = fs::read("/proc/self/exe").unwrap();
+        let exe_build_id = object::File::parse(&*exe_data)
+            .unwrap()
+            .build_id()
+            .unwrap()
+            .map(build_id_to_hex);
+        let Some(exe_build_id) = exe_build_id else {
+            return;
+        };
+        assert!(has_build_id(&exe_data, &exe_build_id));
+        assert!(!has_build_id(&exe_data, "00"));
+        assert_eq!(
+            read_elf_file_build_id(Path::new("/proc/self/exe")),
+            Some(exe_build_id.clone())
+        );
+
+        let bundle_dir = TempDir::new().unwrap();
+        let elf_dir = bundle_dir.path().join(ELF_FILES_DIR_NAME);
+        fs::create_dir_all(&elf_dir).unwrap();
+        fs::write(elf_dir.join(&exe_build_id), &exe_data).unwrap();
+        let manifest = SymbolBundleManifest {
+            system_boot_timestamp_ms: Some(1000),
+            elf_files: vec![BundledElfFile {
+                path: "/usr/bin/app".to_string(),
+                build_id: exe_build_id.clone(),
+                size: exe_data.len() as u64,
+            }],
+            missing_elf_files: vec!["/usr/lib/libgone.so".to_string()],
+            jit_map_pids: vec![42],
+        };
+        fs::write(
+            bundle_dir.path().join(MANIFEST_FILE_NAME),
+            serde_json::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+
+        let symbol_bundle = SymbolBundle::open(bundle_dir.path()).unwrap();
+        assert_eq!(symbol_bundle.system_boot_timestamp_ms(), Some(1000));
+        assert_eq!(
+            symbol_bundle.find_elf_file("/usr/bin/app"),
+            Some(elf_dir.join(&exe_build_id))
+        );
+        assert_eq!(symbol_bundle.find_elf_file("/usr/lib/libgone.so"), None);
+        assert_eq!(
+            symbol_bundle.find_jit_map(42),
+            Some(
+                bundle_dir
+                    .path()
+                    .join(JIT_MAPS_DIR_NAME)
+                    .join("perf-42.map")
+            )
+        );
+        assert_eq!(symbol_bundle.find_jit_map(43), None);
+        assert!(symbol_bundle.kernel_symbol_files().is_none());
+    }
+}
@@ -1,14 +1,22 @@
-use crate::profiling::symbols::elf_build_ids::ElfBuildIds;
+use crate::profiling::symbols::elf_build_ids::{has_build_id, ElfBuildIds};
 use crate::profiling::symbols::elf_symbols::ElfSymbols;
 use crate::profiling::symbols::jit_symbols::JitSymbols;
 use crate::profiling::symbols::kernel_symbols::KernelSymbols;
 use crate::profiling::symbols::mmap_resolver::MmapResolver;
+use crate::profiling::symbols::mount_namespace::{is_in_other_mount_namespace, process_root_path};
+use crate::profiling::symbols::stack_unwinder::{
+    decode_x86_direct_call, unwind_frame_by_frame_pointer, StackMemory, UnwindArch,
+    UnwindRegisters, MAX_UNWIND_FRAMES, X86_DIRECT_CALL_LENGTH,
+};
+use crate::profiling::symbols::symbol_bundle::SymbolBundle;
 use crate::profiling::symbols::vdso_symbols::read_vdso_elf_data;
-use crate::profiling::symbols::{ResolvedSymbol, VDSO_ELF_FILE_PATH};
+use crate::profiling::symbols::{ElfFunction, ResolvedSymbol, VDSO_ELF_FILE_PATH};
 use crate::profiling::FrameType;
 use log::{debug, error, warn};
 use std::collections::HashMap;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
 
 #[derive(Default)]
 pub struct SymbolResolver {
@@ -24,13 +32,24 @@ pub struct SymbolResolver {
     kernel_symbols: KernelSymbols,
     /// The start of kernel space, used to distinguish Kernel instructions from userspace instructions.
     kernel_space_start_address: u64,
-    /// Whether to create the frame unwinder to support leaf frame recovery.
-    support_leaf_caller_recovery: bool,
+    /// The architecture to recover the missing caller of leaf frames for, if supported.
+    leaf_caller_recovery_arch: Option<UnwindArch>,
+    /// The architecture to unwind userspace stack snapshots for, if the profile has them.
+    stack_unwinding_arch: Option<UnwindArch>,
+    /// The symbol files captured at the end of recording, which take precedence over the files
+    /// on the current system.
+    symbol_bundle: Option<SymbolBundle>,
+    /// The paths that the data of ELF files were read from, mapped to the ELF file paths.
+    elf_data_paths: HashMap<String, String>,
+    /// The displacements of the x86_64 direct call instructions read from ELF files, mapped to
+    /// the ELF file paths and the file offsets of the instructions.
+    x86_direct_calls: HashMap<(String, u64), Option<i32>>,
 }
 
 impl SymbolResolver {
-    /// Initialize the Symbol Resolver for a specific architecture.
-    pub fn for_arch(arch: &str) -> Self {
+    /// Initialize the Symbol Resolver for a specific architecture. If a symbol bundle is
+    /// provided, symbols are resolved against it before falling back to the current system.
+    pub fn for_arch(arch: &str, symbol_bundle: Option<SymbolBundle>) -> Self {
         let mut symbol_resolver = Self::default();
 
         // Expect string values from uname -m.
@@ -38,32 +57,53 @@ impl SymbolResolver {
         if arch == "aarch64" || arch == "arm64" {
             // https://www.kernel.org/doc/html/next/arm64/memory.html
             symbol_resolver.kernel_space_start_address = 0xffff_0000_0000_0000;
-            symbol_resolver.support_leaf_caller_recovery = true;
+            symbol_resolver.leaf_caller_recovery_arch = Some(UnwindArch::Aarch64);
         } else if arch == "x86_64" {
             // https://www.kernel.org/doc/Documentation/x86/x86_64/mm.txt
             symbol_resolver.kernel_space_start_address = 0xffff_8000_0000_0000;
-            symbol_resolver.support_leaf_caller_recovery = false;
+            symbol_resolver.leaf_caller_recovery_arch = Some(UnwindArch::X86_64);
         } else {
             warn!("Unrecognized arch value when creating Symbol Resolver: {arch}");
             symbol_resolver.kernel_space_start_address = 0xffff_0000_0000_0000;
-            symbol_resolver.support_leaf_caller_recovery = false;
+            symbol_resolver.leaf_caller_recovery_arch = None;
         }
 
-        // Parse /proc/kallsyms and create the Kernel Symbol table.
-        match KernelSymbols::from_kallsyms() {
+        // Parse /proc/kallsyms, or its bundled copy, and create the Kernel Symbol table.
+        let kernel_symbols = match symbol_bundle
+            .as_ref()
+            .and_then(|symbol_bundle| symbol_bundle.kernel_symbol_files())
+        {
+            Some((kallsyms_path, modules_path)) => {
+                KernelSymbols::from_kallsyms_files(kallsyms_path, modules_path)
+            }
+            None => KernelSymbols::from_kallsyms(),
+        };
+        match kernel_symbols {
             Ok(kernel_symbols) => symbol_resolver.kernel_symbols = kernel_symbols,
-            Err(e) => error!(
-                "Failed to parse Kernel symbols from /proc/kallsyms: {:?}",
-                e
-            ),
+            Err(e) => error!("Failed to parse Kernel symbols from kallsyms: {:?}", e),
         }
+        symbol_resolver.symbol_bundle = symbol_bundle;
 
         symbol_resolver
     }
 
-    /// Whether the resolver supports leaf frame recovery.
-    pub fn support_leaf_caller_recovery(&self) -> bool {
-        self.support_leaf_caller_recovery
+    /// The architecture to recover the missing caller of leaf frames for, if supported.
+    pub fn leaf_caller_recovery_arch(&self) -> Option<UnwindArch> {
+        self.leaf_caller_recovery_arch
+    }
+
+    /// Enable unwinding userspace stack snapshots, which requires building the CFI rule tables
+    /// of ELF files. It needs to be enabled before any symbol is resolved.
+    pub fn enable_stack_unwinding(&mut self, arch: &str) {
+        self.stack_unwinding_arch = UnwindArch::from_uname(&arch.to_lowercase());
+        if self.stack_unwinding_arch.is_none() {
+            warn!("Unwinding stack snapshots is not supported on {arch}");
+        }
+    }
+
+    /// The architecture to unwind stack snapshots for, if stack unwinding is enabled.
+    pub fn stack_unwinding_arch(&self) -> Option<UnwindArch> {
+        self.stack_unwinding_arch
     }
 
     /// Store an MMAP event to be used for future symbol resolutions.
@@ -84,6 +124,11 @@ impl SymbolResolver {
         }
     }
 
+    /// Whether the process has mapped the libjvm.so of HotSpot.
+    pub fn is_pid_hotspot_jvm(&self, pid: i32) -> bool {
+        self.mmap_resolver.is_pid_hotspot_jvm(pid)
+    }
+
     /// Update the MMAP table when a process is forked - its parent's MMAP tables
     /// should be inherited, until the process is exec'd and receives its own MMAP events.
     pub fn handle_forked_process_mmap(&mut self, ppid: i32, pid: i32) {
@@ -105,6 +150,32 @@ impl SymbolResolver {
         }
     }
 
+    /// Resolve a userspace instruction address of a PID into its virtual address in the ELF file
+    /// and the function containing it, so that the function can be disassembled. Only the ELF
+    /// files read from a file, which excludes the VDSO, are resolved.
+    pub fn resolve_elf_function(&mut self, pid: i32, addr: u64) -> Option<(u64, ElfFunction)> {
+        if addr >= self.kernel_space_start_address {
+            return None;
+        }
+        let (file_offset, elf_file_path) = self.mmap_resolver.resolve_addr(pid, addr)?;
+        self.lazy_load_elf_file(pid, &elf_file_path);
+        let elf_data_path = self.elf_data_paths.get(&elf_file_path)?;
+        let (p_vaddr, name, start, end) = self
+            .elf_symbol_tables
+            .get(&elf_file_path)?
+            .resolve_function(file_offset)?;
+        Some((
+            p_vaddr,
+            ElfFunction {
+                name: name.to_string(),
+                source: elf_file_path,
+                elf_data_path: elf_data_path.clone(),
+                start,
+                end,
+            },
+        ))
+    }
+
     /// Resolve a kernel instruction address through the following steps:
     /// 1. Attempt to resolve the address directly using the Kernel symbol table (built from /proc/kallsyms).
     /// 2. If the resolution failed, try to translate the address into file offset and ELF file
@@ -141,7 +212,7 @@ impl SymbolResolver {
     }
 
     /// Resolve an instruction address from the JIT symbol table of the corresponding PID. If the
-    /// symbol table does not exist, build it from the /tmp/perf-<pid>.map file.
+    /// symbol table does not exist, build it from the bundled or the /tmp/perf-<pid>.map file.
     fn resolve_by_jit_symbols(&mut self, pid: i32, addr: u64) -> Option<ResolvedSymbol> {
         // Still insert a dummy symbol table in case it could not be built, so that
         // the resolver does not attempt to keep rebuilding the symbol table.
@@ -149,8 +220,17 @@ impl SymbolResolver {
             .jit_symbol_tables
             .entry(pid)
             .or_insert_with(|| {
-                JitSymbols::from_perf_map(pid, self.mmap_resolver.is_pid_hotspot_jvm(pid))
-                    .unwrap_or_default()
+                match self
+                    .symbol_bundle
+                    .as_ref()
+                    .and_then(|symbol_bundle| symbol_bundle.find_jit_map(pid))
+                {
+                    Some(perf_map_file_path) => JitSymbols::from_perf_map_file(perf_map_file_path),
+                    None => {
+                        JitSymbols::from_perf_map(pid, self.mmap_resolver.is_pid_hotspot_jvm(pid))
+                    }
+                }
+                .unwrap_or_default()
             })
             .resolve(addr)?;
         resolved_symbol.frame_type = FrameType::Jit;
@@ -179,65 +259,108 @@ impl SymbolResolver {
     }
 
     /// Attempt to load the data of an ELF file and use it to build the ELF symbol table. If leaf
-    /// frame recovery is enabled, also build the Frame Unwinder from the same ELF file data.
+    /// frame recovery on AArch64 or stack unwinding is enabled, also build the CFI rule table
+    /// from the same ELF file data.
     fn lazy_load_elf_file(&mut self, pid: i32, elf_file_path: &str) {
         if self.elf_symbol_tables.contains_key(elf_file_path) {
             return;
         }
 
-        let load_elf_file_data = || -> Option<(Vec<u8>, String)> {
-            // First try to find the original build of the ELF file using the Build-ID - they ensure
+        // Returns the ELF file data, the source to report symbols under, and the path that the
+        // data was read from.
+        let load_elf_file_data = || -> Option<(Vec<u8>, String, Option<String>)> {
+            // The bundled copy was verified against the Build-ID when the profile was recorded,
+            // and is reported under its original path.
+            if let Some(bundled_elf_file_path) = self
+                .symbol_bundle
+                .as_ref()
+                .and_then(|symbol_bundle| symbol_bundle.find_elf_file(elf_file_path))
+            {
+                if let Ok(data) = fs::read(&bundled_elf_file_path) {
+                    let bundled_elf_file_path =
+                        bundled_elf_file_path.to_string_lossy().into_owned();
+                    return Some((data, elf_file_path.to_string(), Some(bundled_elf_file_path)));
+                }
+            }
+            // Then try to find the original build of the ELF file using the Build-ID - they ensure
             // that the instruction address, MMAP entries, and symbol tables all refer to the same ELF file.
             if let Some(original_elf_file_path) =
                 self.elf_build_ids.find_original_elf_file(elf_file_path)
             {
                 if let Ok(data) = fs::read(&original_elf_file_path) {
-                    return Some((data, original_elf_file_path.to_string_lossy().into_owned()));
+                    let original_elf_file_path =
+                        original_elf_file_path.to_string_lossy().into_owned();
+                    return Some((
+                        data,
+                        original_elf_file_path.clone(),
+                        Some(original_elf_file_path),
+                    ));
                 }
             }
             // If dealing with VDSO symbols, load them from APerf's memory - all processes
             // running on the same Kernel share the same VDSO symbol table.
             if elf_file_path == VDSO_ELF_FILE_PATH {
                 return read_vdso_elf_data()
-                    .map(|vdso_data| (vdso_data, VDSO_ELF_FILE_PATH.to_string()));
+                    .map(|vdso_data| (vdso_data, VDSO_ELF_FILE_PATH.to_string(), None));
             }
-            // If failed to find the original ELF file using the Build-ID, attempt to read
-            // the ELF file path directly.
-            if let Ok(data) = fs::read(elf_file_path) {
-                return Some((data, elf_file_path.to_string()));
-            }
-            // Fall back to the process's FS mount exposed by the kernel /proc/<pid>/root/<path>
+            // If failed to find the original ELF file using the Build-ID, attempt to read the ELF
+            // file path directly, or through the process's FS mount exposed by the kernel
+            // /proc/<pid>/root/<path>. The paths of a process in a container (i.e. in another
+            // mount namespace) refer to the container's filesystem, so the latter is tried first,
+            // and any file that does not match the Build-ID recorded by Perf is skipped.
             // Caveats: the process needs to be owned by the same user who ran APerf, or sudo is
             // required. Also, the path will not exist anymore if the process has exited.
-            if pid > 0 {
-                let process_fs_path = format!("/proc/{}/root{}", pid, elf_file_path);
-                if let Ok(data) = fs::read(&process_fs_path) {
-                    return Some((data, process_fs_path));
+            let mut elf_file_candidates: Vec<PathBuf> = Vec::new();
+            if pid > 0 && is_in_other_mount_namespace(pid) {
+                elf_file_candidates.push(process_root_path(pid, elf_file_path));
+                elf_file_candidates.push(PathBuf::from(elf_file_path));
+            } else {
+                elf_file_candidates.push(PathBuf::from(elf_file_path));
+                if pid > 0 {
+                    elf_file_candidates.push(process_root_path(pid, elf_file_path));
                 }
-                // In case the process has exited, retrieve the list of PIDs that have MMAP-ed
-                // this file path and access through their file system, in case one of them is
-                // still running.
+            }
+            // In case the process has exited, retrieve the list of PIDs that have MMAP-ed this
+            // file path and access through their file system, in case one of them is still running.
+            if pid > 0 {
                 for sibling_pid in self.mmap_resolver.get_file_path_pids(elf_file_path) {
                     if sibling_pid != pid {
-                        let sibling_process_fs_path =
-                            format!("/proc/{}/root{}", sibling_pid, elf_file_path);
-                        if let Ok(data) = fs::read(&sibling_process_fs_path) {
-                            return Some((data, sibling_process_fs_path));
-                        }
+                        elf_file_candidates.push(process_root_path(sibling_pid, elf_file_path));
                     }
                 }
             }
+            let build_id = self.elf_build_ids.get_build_id(elf_file_path);
+            for elf_file_candidate in elf_file_candidates {
+                let data = match fs::read(&elf_file_candidate) {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                };
+                if build_id.is_some_and(|build_id| !has_build_id(&data, build_id)) {
+                    debug!(
+                        "Skipped {} due to mismatched Build-ID",
+                        elf_file_candidate.display()
+                    );
+                    continue;
+                }
+                let elf_data_path = elf_file_candidate.to_string_lossy().into_owned();
+                return Some((data, elf_file_path.to_string(), Some(elf_data_path)));
+            }
             // Could not find or open the ELF file.
             None
         };
 
-        if let Some((elf_data, elf_data_source)) = load_elf_file_data() {
+        if let Some((elf_data, elf_data_source, elf_data_path)) = load_elf_file_data() {
+            if let Some(elf_data_path) = elf_data_path {
+                self.elf_data_paths
+                    .insert(elf_file_path.to_string(), elf_data_path);
+            }
             self.elf_symbol_tables.insert(
                 elf_file_path.to_string(),
                 ElfSymbols::from_elf_data(
                     &elf_data,
                     elf_data_source,
-                    self.support_leaf_caller_recovery,
+                    self.leaf_caller_recovery_arch == Some(UnwindArch::Aarch64)
+                        || self.stack_unwinding_arch.is_some(),
                 )
                 .unwrap_or_else(|error| {
                     debug!(
@@ -278,4 +401,281 @@ impl SymbolResolver {
                 elf_symbol_table.recover_leaf_frame_caller(leaf_file_offset, lr, fp, sp)
             })
     }
+
+    /// On x86_64, the call instruction pushes the return address onto the stack, and the leaf
+    /// function saves the caller's frame pointer and sets up its own in the prologue. A sample
+    /// taken before that (or in a function that never sets up a frame pointer) is missing the
+    /// leaf's caller in the call chain recorded by the frame pointer walk, whose first return
+    /// address then points into the caller's caller.
+    ///
+    /// Without a stack snapshot to unwind, the missing caller is recovered from the instruction
+    /// preceding that return address: if it is a direct call to a function other than the leaf's,
+    /// the call target is the missing caller. Returns the entry address of the leaf's caller.
+    pub fn recover_x86_leaf_frame_caller(
+        &mut self,
+        pid: i32,
+        leaf_addr: u64,
+        return_addr: u64,
+    ) -> Option<u64> {
+        let (file_offset, elf_file_path) = self.mmap_resolver.resolve_addr(pid, return_addr)?;
+        let call_file_offset = file_offset.checked_sub(X86_DIRECT_CALL_LENGTH as u64)?;
+        let displacement = self.read_x86_direct_call(pid, &elf_file_path, call_file_offset)?;
+        let callee_addr = return_addr.checked_add_signed(displacement as i64)?;
+
+        // The call target must be the entry of a function, which rules out bytes that only look
+        // like a direct call.
+        let callee = self.resolve(pid, callee_addr)?;
+        if callee.offset != 0 {
+            return None;
+        }
+        // If the target is the leaf's function, the leaf has set up its frame pointer and the
+        // call chain is complete. Calls to shared libraries go through their PLT entries.
+        let leaf = self.resolve(pid, leaf_addr)?;
+        if callee.name.trim_end_matches("@plt") == leaf.name.trim_end_matches("@plt") {
+            return None;
+        }
+        Some(callee_addr)
+    }
+
+    /// Read the displacement of the x86_64 direct call instruction at the file offset of an ELF
+    /// file, if the instruction is one.
+    fn read_x86_direct_call(
+        &mut self,
+        pid: i32,
+        elf_file_path: &str,
+        file_offset: u64,
+    ) -> Option<i32> {
+        let key = (elf_file_path.to_string(), file_offset);
+        if let Some(displacement) = self.x86_direct_calls.get(&key) {
+            return *displacement;
+        }
+        self.lazy_load_elf_file(pid, elf_file_path);
+        let displacement = self
+            .elf_data_paths
+            .get(elf_file_path)
+            .and_then(|elf_data_path| {
+                let mut instruction = [0u8; X86_DIRECT_CALL_LENGTH];
+                let mut elf_file = fs::File::open(elf_data_path).ok()?;
+                elf_file.seek(SeekFrom::Start(file_offset)).ok()?;
+                elf_file.read_exact(&mut instruction).ok()?;
+                decode_x86_direct_call(&instruction)
+            });
+        self.x86_direct_calls.insert(key, displacement);
+        displacement
+    }
+
+    /// Unwind a userspace stack snapshot into the addresses of its frames, ordered from the leaf
+    /// to the root. Every frame is unwound using the CFI rules of the ELF file it executes, and
+    /// falls back to the frame pointer when no CFI rule covers the instruction.
+    pub fn unwind_user_stack(
+        &mut self,
+        pid: i32,
+        regs: UnwindRegisters,
+        stack: &StackMemory,
+    ) -> Vec<u64> {
+        let mut frame_addresses = vec![regs.pc];
+        let mut regs = regs;
+        while frame_addresses.len() < MAX_UNWIND_FRAMES {
+            // Except for the leaf, pc is the return address pointing to the instruction after
+            // the call, which may be covered by the CFI rule of the next function.
+            let lookup_addr = if frame_addresses.len() == 1 {
+                regs.pc
+            } else {
+                regs.pc.saturating_sub(1)
+            };
+            let caller_regs = match self
+                .unwind_frame_by_cfi(pid, lookup_addr, &regs, stack)
+                .or_else(|| unwind_frame_by_frame_pointer(&regs, stack))
+            {
+                Some(caller_regs) => caller_regs,
+                None => break,
+            };
+            // Stop at the outermost frame, or if the stack pointer does not move toward the
+            // bottom of the stack, which indicates a bad unwind.
+            if caller_regs.pc == 0 || caller_regs.sp <= regs.sp {
+                break;
+            }
+            frame_addresses.push(caller_regs.pc);
+            regs = caller_regs;
+        }
+        frame_addresses
+    }
+
+    fn unwind_frame_by_cfi(
+        &mut self,
+        pid: i32,
+        addr: u64,
+        regs: &UnwindRegisters,
+        stack: &StackMemory,
+    ) -> Option<UnwindRegisters> {
+        let (file_offset, elf_file_path) = self.mmap_resolver.resolve_addr(pid, addr)?;
+        self.lazy_load_elf_file(pid, &elf_file_path);
+        self.elf_symbol_tables
+            .get(&elf_file_path)?
+            .unwind_frame(file_offset, regs, stack)
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use std::arch::asm;
+
+    /// Capture the registers and a copy of the current thread's stack above the stack pointer.
+    #[inline(never)]
+    fn capture_user_stack() -> (UnwindRegisters, Vec<u8>) {
+        let (pc, sp, fp): (u64, u64, u64);
+        unsafe {
+            asm!(
+                "lea {pc}, [rip]",
+                "mov {sp}, rsp",
+                "mov {fp}, rbp",
+                pc = out(reg) pc,
+                sp = out(reg) sp,
+                fp = out(reg) fp,
+            );
+        }
+        // Only copy within the stack mapping, and at most as much as Perf does by default.
+        let stack_end = fs::read_to_string("/proc/self/maps")
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+                Some((
+                    u64::from_str_radix(start, 16).ok()?,
+                    u64::from_str_radix(end, 16).ok()?,
+                ))
+            })
+            .find(|(start, end)| *start <= sp && sp < *end)
+            .unwrap()
+            .1;
+        let stack_size = (stack_end - sp).min(8192) as usize;
+        let stack = unsafe { std::slice::from_raw_parts(sp as *const u8, stack_size) }.to_vec();
+        let regs = UnwindRegisters {
+            pc,
+            sp,
+            fp: Some(fp),
+            lr: None,
+        };
+        (regs, stack)
+    }
+
+    /// Add the executable mappings of the current process, as Perf would record them.
+    fn add_self_mmaps(symbol_resolver: &mut SymbolResolver, pid: i32) {
+        for line in fs::read_to_string("/proc/self/maps").unwrap().lines() {
+            // Format: start-end perms offset dev inode path
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 6 || !parts[1].contains('x') {
+                continue;
+            }
+            let (start, end) = parts[0].split_once('-').unwrap();
+            let start = u64::from_str_radix(start, 16).unwrap();
+            let end = u64::from_str_radix(end, 16).unwrap();
+            let offset = u64::from_str_radix(parts[2], 16).unwrap();
+            symbol_resolver.add_mmap(pid, start, end - start, offset, parts[5].to_string());
+        }
+    }
+
+    #[inline(never)]
+    fn unwind_test_caller() -> (UnwindRegisters, Vec<u8>) {
+        std::hint::black_box(capture_user_stack())
+    }
+
+    #[test]
+    fn test_unwind_user_stack() {
+        let pid = std::process::id() as i32;
+        let mut symbol_resolver = SymbolResolver::for_arch("x86_64", None);
+        symbol_resolver.enable_stack_unwinding("x86_64");
+        add_self_mmaps(&mut symbol_resolver, pid);
+
+        let (regs, stack) = unwind_test_caller();
+        let frame_addresses =
+            symbol_resolver.unwind_user_stack(pid, regs, &StackMemory::new(regs.sp, &stack));
+        let frame_names: Vec<String> = frame_addresses
+            .iter()
+            .enumerate()
+            .map(|(i, &addr)| {
+                let addr = if i == 0 { addr } else { addr - 1 };
+                symbol_resolver
+                    .resolve(pid, addr)
+                    .map_or(String::new(), |resolved_symbol| resolved_symbol.name)
+            })
+            .collect();
+
+        assert!(frame_names[0].contains("capture_user_stack"));
+        assert!(frame_names[1].contains("unwind_test_caller"));
+        assert!(frame_names[2].contains("test_unwind_user_stack"));
+    }
+
+    #[test]
+    fn test_resolve_elf_function() {
+        let pid = std::process::id() as i32;
+        let mut symbol_resolver = SymbolResolver::for_arch("x86_64", None);
+        add_self_mmaps(&mut symbol_resolver, pid);
+
+        let frameless_leaf_addr = frameless_leaf as *const () as u64;
+        let (address, function) = symbol_resolver
+            .resolve_elf_function(pid, frameless_leaf_addr)
+            .unwrap();
+        assert!(function.name.contains("frameless_leaf"));
+        assert_eq!(address, function.start);
+        assert!(function.end > function.start);
+        // Any instruction in the function resolves to the same function.
+        let (next_address, next_function) = symbol_resolver
+            .resolve_elf_function(pid, frameless_leaf_addr + 1)
+            .unwrap();
+        assert_eq!(next_address, address + 1);
+        assert_eq!(next_function, function);
+    }
+
+    #[inline(never)]
+    fn direct_call_target() -> u64 {
+        std::hint::black_box(1)
+    }
+
+    #[inline(never)]
+    fn frameless_leaf() -> u64 {
+        std::hint::black_box(2)
+    }
+
+    /// Get the return address of a direct call to direct_call_target, which is never executed.
+    fn direct_call_return_address() -> u64 {
+        let return_addr: u64;
+        unsafe {
+            asm!(
+                "jmp 2f",
+                "call {target}",
+                "2:",
+                "lea {return_addr}, [rip + 2b]",
+                target = sym direct_call_target,
+                return_addr = out(reg) return_addr,
+            );
+        }
+        return_addr
+    }
+
+    #[test]
+    fn test_recover_x86_leaf_frame_caller() {
+        let pid = std::process::id() as i32;
+        let mut symbol_resolver = SymbolResolver::for_arch("x86_64", None);
+        add_self_mmaps(&mut symbol_resolver, pid);
+
+        let return_addr = direct_call_return_address();
+        let frameless_leaf_addr = frameless_leaf as *const () as u64;
+        let direct_call_target_addr = direct_call_target as *const () as u64;
+        // The leaf was called by direct_call_target, which is missing from the call chain.
+        assert_eq!(
+            symbol_resolver.recover_x86_leaf_frame_caller(pid, frameless_leaf_addr, return_addr),
+            Some(direct_call_target_addr)
+        );
+        // The leaf itself was the call target, so the call chain is complete.
+        assert_eq!(
+            symbol_resolver.recover_x86_leaf_frame_caller(
+                pid,
+                direct_call_target_addr + 1,
+                return_addr
+            ),
+            None
+        );
+    }
 }
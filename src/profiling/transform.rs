@@ -0,0 +1,354 @@
+//! Report-time transforms of the call stacks of profiles, which rebuild the call tree of a
+//! profile from its stacks to hide the frames that are not of interest.
+
+use crate::data::common::data_formats::Profiler;
+use crate::profiling::{FrameType, Profile};
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The possible values of the frame view of the profile transforms.
+pub const PROFILE_FRAME_VIEWS: [&str; 3] = ["all", "user", "kernel"];
+
+/// The frame types that are identified by a suffix in the frame names.
+const SUFFIXED_FRAME_TYPES: [FrameType; 6] = [
+    FrameType::Jit,
+    FrameType::Inlined,
+    FrameType::Kernel,
+    FrameType::Interpreted,
+    FrameType::C1,
+    FrameType::Vdso,
+];
+
+/// The frames of the stacks to keep by where they run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameView {
+    #[default]
+    All,
+    /// Only the frames that run in userspace, attributing the kernel time to the innermost
+    /// userspace frames.
+    User,
+    /// Only the kernel frames, dropping the stacks that never entered the kernel.
+    Kernel,
+}
+
+impl std::str::FromStr for FrameView {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "all" => Ok(FrameView::All),
+            "user" => Ok(FrameView::User),
+            "kernel" => Ok(FrameView::Kernel),
+            _ => bail!(
+                "Invalid frame view '{s}', expected one of {}",
+                PROFILE_FRAME_VIEWS.join(", ")
+            ),
+        }
+    }
+}
+
+/// The transforms applied to every stack of a profile, in the order of the fields.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileTransforms {
+    /// Keep only the user or the kernel frames.
+    pub view: FrameView,
+    /// Drop the frames matching any of the regexes, attaching their callees to their callers.
+    pub drop_frames: Vec<Regex>,
+    /// Fold the consecutive frames of the same module, i.e. Java package, C++ namespace or Rust
+    /// crate, or the kernel, into a single frame named after the module.
+    pub fold_modules: bool,
+    /// Collapse the consecutive frames of the same function into a single frame.
+    pub collapse_recursion: bool,
+}
+
+impl ProfileTransforms {
+    pub fn new(
+        view: &str,
+        drop_frames: &[String],
+        fold_modules: bool,
+        collapse_recursion: bool,
+    ) -> Result<Self> {
+        let drop_frames = drop_frames
+            .iter()
+            .map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Ok(regex),
+                Err(e) => bail!("Invalid regex '{pattern}' of the frames to drop: {e}"),
+            })
+            .collect::<Result<Vec<Regex>>>()?;
+        Ok(ProfileTransforms {
+            view: view.parse()?,
+            drop_frames,
+            fold_modules,
+            collapse_recursion,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view == FrameView::All
+            && self.drop_frames.is_empty()
+            && !self.fold_modules
+            && !self.collapse_recursion
+    }
+
+    /// Transform a stack, from the root to the leaf. An empty stack means that the samples of
+    /// the stack are dropped.
+    pub fn transform_frames(&self, frames: &[&str]) -> Vec<String> {
+        let mut transformed: Vec<String> = Vec::with_capacity(frames.len());
+        let mut last_module: Option<String> = None;
+        for &frame in frames {
+            let (name, frame_type) = split_frame_type(frame);
+            let is_kernel = matches!(frame_type, FrameType::Kernel);
+            match self.view {
+                FrameView::User if is_kernel => continue,
+                FrameView::Kernel if !is_kernel => continue,
+                _ => {}
+            }
+            if self.drop_frames.iter().any(|regex| regex.is_match(frame)) {
+                continue;
+            }
+            if self.fold_modules {
+                if let Some(module) = frame_module(name, frame_type) {
+                    if last_module.as_ref() != Some(&module) {
+                        transformed.push(format!("{}{}", module, frame_type.literal_suffix()));
+                        last_module = Some(module);
+                    }
+                    continue;
+                }
+                last_module = None;
+            }
+            if self.collapse_recursion && transformed.last().is_some_and(|last| last == frame) {
+                continue;
+            }
+            transformed.push(frame.to_string());
+        }
+        transformed
+    }
+}
+
+/// Split a frame into its name and the type of its suffix.
+fn split_frame_type(frame: &str) -> (&str, FrameType) {
+    SUFFIXED_FRAME_TYPES
+        .iter()
+        .find_map(|frame_type| {
+            frame
+                .strip_suffix(frame_type.literal_suffix())
+                .map(|name| (name, *frame_type))
+        })
+        .unwrap_or((frame, FrameType::Native))
+}
+
+/// The module of a frame: the package of a Java method, the outermost namespace of a C++ or
+/// Rust function, or the kernel. The frames of other functions, such as C functions, are not
+/// part of a module.
+fn frame_module(name: &str, frame_type: FrameType) -> Option<String> {
+    if matches!(frame_type, FrameType::Kernel) {
+        return Some("[kernel]".to_string());
+    }
+    if frame_type.is_java() {
+        // The methods are named "<package>.<class>.<method>" in JFR stacks, and
+        // "<package>.<class>::<method>" in perf maps.
+        let class = match name.split_once("::") {
+            Some((class, _)) => class,
+            None => name.rsplit_once('.')?.0,
+        };
+        let (package, _) = class.rsplit_once('.')?;
+        return Some(format!("{package}.*"));
+    }
+    // Qualified C++ and Rust functions, including the trait implementations of Rust such as
+    // "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop".
+    let (namespace, _) = name.trim_start_matches('<').split_once("::")?;
+    if namespace.is_empty() || namespace.contains([' ', '(', '<']) {
+        return None;
+    }
+    Some(format!("{namespace}::*"))
+}
+
+impl Profile {
+    /// The frames of the call path of a node, from the root to the node.
+    fn node_frames(&self, node_id: usize) -> Vec<&str> {
+        let mut frames = Vec::new();
+        let mut cur = node_id;
+        while let Some(parent) = self.context_tree[cur].parent {
+            frames.push(self.frame_map.name(self.context_tree[cur].frame_id));
+            cur = parent;
+        }
+        frames.reverse();
+        frames
+    }
+
+    /// Build the profile of the transformed stacks, keeping the time blocks of the samples and
+    /// the time range of the profile.
+    pub fn transformed(&self, transforms: &ProfileTransforms) -> Result<Profile> {
+        let mut profile = Profile::new();
+        let mut transformed_frames: HashMap<usize, Vec<String>> = HashMap::new();
+        for (block_idx, block) in self.blocks.iter().enumerate() {
+            for (&thread_state_id, node_counts) in block {
+                for (&node_id, &count) in node_counts {
+                    let frames = transformed_frames
+                        .entry(node_id)
+                        .or_insert_with(|| transforms.transform_frames(&self.node_frames(node_id)));
+                    if !frames.is_empty() {
+                        profile.insert_stack_in_block(block_idx, thread_state_id, frames, count);
+                    }
+                }
+            }
+        }
+        profile.frame_map.copy_locations(&self.frame_map);
+        // The sample stats of the inserted stacks are of all blocks.
+        if self.time_range != (0, 0) {
+            profile.set_time_range(self.time_range.0, self.time_range.1)?;
+        }
+        Ok(profile)
+    }
+}
+
+impl Profiler {
+    /// Apply the transforms to the stacks of every profile.
+    pub fn apply_transforms(&mut self, transforms: &ProfileTransforms) -> Result<()> {
+        for profile in self.profiles.values_mut() {
+            *profile = profile.transformed(transforms)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling::ThreadState;
+
+    fn transforms(
+        view: &str,
+        drop_frames: &[&str],
+        fold: bool,
+        collapse: bool,
+    ) -> ProfileTransforms {
+        let drop_frames: Vec<String> = drop_frames.iter().map(|s| s.to_string()).collect();
+        ProfileTransforms::new(view, &drop_frames, fold, collapse).unwrap()
+    }
+
+    #[test]
+    fn test_transform_frames() {
+        let frames = [
+            "_start",
+            "__libc_start_main",
+            "std::rt::lang_start",
+            "std::rt::lang_start_internal",
+            "app::main",
+            "app::parse",
+            "app::parse",
+            "read",
+            "entry_SYSCALL_64_[k]",
+            "do_syscall_64_[k]",
+        ];
+
+        assert_eq!(
+            transforms(
+                "all",
+                &["^_start$", "__libc_start_main", "std::rt"],
+                false,
+                false
+            )
+            .transform_frames(&frames),
+            vec![
+                "app::main",
+                "app::parse",
+                "app::parse",
+                "read",
+                "entry_SYSCALL_64_[k]",
+                "do_syscall_64_[k]"
+            ]
+        );
+        assert_eq!(
+            transforms("user", &[], false, true).transform_frames(&frames),
+            frames[..6]
+                .iter()
+                .chain(&frames[7..8])
+                .copied()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            transforms("kernel", &[], false, false).transform_frames(&frames),
+            vec!["entry_SYSCALL_64_[k]", "do_syscall_64_[k]"]
+        );
+        assert_eq!(
+            transforms("all", &[], true, false).transform_frames(&frames),
+            vec![
+                "_start",
+                "__libc_start_main",
+                "std::*",
+                "app::*",
+                "read",
+                "[kernel]_[k]"
+            ]
+        );
+        // The stacks without kernel frames are dropped from the kernel view
+        assert!(transforms("kernel", &[], false, false)
+            .transform_frames(&frames[..8])
+            .is_empty());
+        assert!(ProfileTransforms::new("fast", &[], false, false).is_err());
+        assert!(ProfileTransforms::new("all", &["(".to_string()], false, false).is_err());
+    }
+
+    #[test]
+    fn test_frame_module() {
+        for (frame, module) in [
+            ("java.util.HashMap.get_[j]", Some("java.util.*")),
+            (
+                "io.netty.channel.epoll.Native::epollWait_[j]",
+                Some("io.netty.channel.epoll.*"),
+            ),
+            ("Main.main_[0]", None),
+            (
+                "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop",
+                Some("alloc::*"),
+            ),
+            ("tokio::runtime::park", Some("tokio::*")),
+            ("schedule_[k]", Some("[kernel]")),
+            ("malloc", None),
+        ] {
+            let (name, frame_type) = split_frame_type(frame);
+            assert_eq!(frame_module(name, frame_type).as_deref(), module, "{frame}");
+        }
+    }
+
+    #[test]
+    fn test_profile_transformed() {
+        let mut profile = Profile::new();
+        let stack = |frames: &[&str]| frames.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        profile.insert_stack(
+            0,
+            0,
+            20,
+            ThreadState::None,
+            &stack(&["main", "a", "a", "b"]),
+            3,
+        );
+        profile.insert_stack(30, 0, 20, ThreadState::None, &stack(&["main", "a", "b"]), 2);
+        profile.insert_stack(30, 0, 20, ThreadState::None, &stack(&["main", "c_[k]"]), 4);
+
+        let transformed = profile
+            .transformed(&transforms("user", &["^main$"], false, true))
+            .unwrap();
+        assert_eq!(
+            transformed.generate_collapsed(&[ThreadState::None]),
+            "a;b 5\n"
+        );
+        // The samples stay in their time blocks
+        assert_eq!(transformed.blocks.len(), 2);
+
+        let mut profile = profile;
+        profile.set_time_range(1, 2).unwrap();
+        let transformed = profile
+            .transformed(&transforms("all", &[], false, true))
+            .unwrap();
+        let mut collapsed: Vec<String> = transformed
+            .generate_collapsed(&[ThreadState::None])
+            .lines()
+            .map(String::from)
+            .collect();
+        collapsed.sort();
+        assert_eq!(collapsed, vec!["main;a;b 2", "main;c_[k] 4"]);
+    }
+}
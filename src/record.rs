@@ -3,10 +3,12 @@
 use crate::aperf_stats_flush;
 use crate::aperf_stats_initialize;
 use crate::data;
-use crate::data::java_profile::JavaProfile;
+use crate::data::ext_profile::parse_ext_profilers;
+use crate::data::java_profile::{parse_asprof_options, JavaProfile};
 use crate::data_collection::DataCollectionEngine;
 use crate::data_collection::InitParams;
 use crate::no_tar_gz_file_name;
+use crate::redact::{RedactOptions, Redactor};
 use crate::{get_data_name_from_type, UNGROUPED_PMU_MODE};
 use anyhow::bail;
 use anyhow::Result;
@@ -46,6 +48,17 @@ pub struct Record {
     )]
     pub period: u64,
 
+    /// Interval (in seconds) at which the run metadata is checkpointed and the collected data
+    /// is synced to disk, so that a run interrupted by a crash or a reboot can be finalized by
+    /// aperf recover. Set to 0 to only checkpoint once, after the first collection.
+    #[clap(
+        help_heading = "Basic Options",
+        long,
+        value_parser,
+        default_value_t = 10
+    )]
+    pub checkpoint_interval: u64,
+
     /// The list of performance data to skip collection. Cannot be used with --collect_only.
     #[clap(
         help_heading = "Data Selection",
@@ -82,6 +95,53 @@ pub struct Record {
     )]
     pub perf_frequency: u32,
 
+    /// How Perf collects the call stacks of the profile: "fp" walks the frame pointers, while
+    /// "dwarf" copies the userspace stack of every sample and unwinds it with the DWARF call
+    /// frame information when processing the profile. Use "dwarf" for binaries and libraries
+    /// built without frame pointers, at the cost of a much larger profile.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser = PossibleValuesParser::new(["fp", "dwarf"]),
+        default_value = "fp"
+    )]
+    pub call_graph: String,
+
+    /// Also record the time threads spend off-CPU (blocked on locks, I/O, sleeps or waiting
+    /// for a CPU) with stacks, using scheduler tracepoints. Requires --profile.
+    #[clap(help_heading = "Profiling", long, value_parser, requires = "profile")]
+    pub profile_offcpu: bool,
+
+    /// Also sample the given PMU events, such as cache-misses, LLC-load-misses, branch-misses or
+    /// stalled-cycles-backend, with stacks. Each event is a profile type of the Perf profile,
+    /// weighted by the number of occurrences. Requires --profile.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser,
+        value_names = &["Event>,<Event"],
+        num_args = 1..,
+        value_delimiter = ',',
+        requires = "profile"
+    )]
+    pub profile_events: Vec<String>,
+
+    /// The number of occurrences of a PMU event of --profile-events between two samples.
+    /// Requires --profile.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = 100000,
+        requires = "profile"
+    )]
+    pub profile_event_period: u64,
+
+    /// Size budget (in MB) of the symbol files copied into the run archive at the end of
+    /// profiling, so that the profile can be symbolized on another system. Disabled by default.
+    #[clap(help_heading = "Profiling", long, value_parser, default_value_t = 0)]
+    pub symbol_bundle_size: u64,
+
     /// Profile JVMs using async-profiler. Specify args using comma separated values. Profiles all JVMs if no args are provided.
     #[clap(
         help_heading = "Profiling",
@@ -92,6 +152,49 @@ pub struct Record {
     )]
     pub profile_java: Option<String>,
 
+    /// The async-profiler events of Java profiling, as comma separated events with optional
+    /// intervals: one execution event (cpu, itimer, ctimer, cycles, ...) with its sampling
+    /// interval, alloc[=BYTES], wall[=INTERVAL], lock[=THRESHOLD], nativemem[=BYTES] and live.
+    /// The events that are not listed are not profiled, e.g. "cpu,alloc=512k" disables
+    /// wall-clock profiling. Defaults to "cpu,alloc=2m,wall=100ms". Prefix the events with
+    /// "<PID/Name>:" to only apply them to the given JVMs. Requires --profile-java.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser,
+        value_names = &["[PID/Name:]Event[=Interval],..."],
+        requires = "profile_java"
+    )]
+    pub java_profile_events: Vec<String>,
+
+    /// Additional asprof arguments, split like a shell command line, such as thread filters (e.g.
+    /// "--filter 120-130") and include/exclude patterns (e.g. "-I 'com/example/*'"). Prefix the
+    /// arguments with "<PID/Name>:" to only apply them to the given JVMs. Requires --profile-java.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser,
+        value_names = &["[PID/Name:]Args"],
+        allow_hyphen_values = true,
+        requires = "profile_java"
+    )]
+    pub asprof_args: Vec<String>,
+
+    /// Run an external profiler along with the collection and ingest its profile. The profiler
+    /// is either a shell command, which writes the profile to its standard output or to
+    /// "{output}", or a local HTTP endpoint (http://localhost...) responding with the profile, where
+    /// "{duration}" is replaced with the collection duration in seconds. The profile format is
+    /// folded (default), pprof or speedscope, and its samples are of the given profile type
+    /// (cpu by default). Can be repeated, e.g.
+    /// --profile-ext 'py=py-spy record -p 1234 -f raw -o {output} -d {duration}'.
+    #[clap(
+        help_heading = "Profiling",
+        long,
+        value_parser,
+        value_names = &["Name=Command|URL[,format=FORMAT][,type=TYPE]"]
+    )]
+    pub profile_ext: Vec<String>,
+
     /// Save all profile events in the output file.
     #[clap(help_heading = "Profiling", long, value_parser, hide = true)]
     pub save_profile_events: bool,
@@ -109,6 +212,14 @@ pub struct Record {
     #[clap(help_heading = "PMU Options", long, value_parser, verbatim_doc_comment)]
     pub ungroup_pmu_events: bool,
 
+    /// Replace the sensitive data of the run, such as host names, EC2 instance and AMI IDs and
+    /// IP addresses, with pseudonyms at the end of the collection, as aperf redact does.
+    #[clap(help_heading = "Redaction", long, value_parser)]
+    pub redact: bool,
+
+    #[clap(flatten)]
+    pub redact_options: RedactOptions,
+
     #[cfg(feature = "hotline")]
     /// SPE sampling frequency, defaulted to 1kHz on Grv4.
     #[clap(
@@ -145,6 +256,17 @@ pub fn record(record: &Record, tmp_dir: &Path, runlog: &Path) -> Result<()> {
                 Please increase the overall recording period or decrease the interval.", interval = record.interval, period =record.period);
         bail!("Cannot start recording with the given parameters.");
     }
+    let redactor = if record.redact {
+        Some(Redactor::new(&record.redact_options)?)
+    } else {
+        if !record.redact_options.redact_rule.is_empty()
+            || record.redact_options.redact_key.is_some()
+        {
+            error!("The --redact-rule and --redact-key options require --redact.");
+            bail!("Cannot start recording with the given parameters.");
+        }
+        None
+    };
 
     // Parse and validate the provided run name or path. If it is not provided or invalid,
     // use the default name and path.
@@ -180,6 +302,7 @@ pub fn record(record: &Record, tmp_dir: &Path, runlog: &Path) -> Result<()> {
     let mut init_params = InitParams::new(run_name, run_data_dir.clone());
     init_params.period = record.period;
     init_params.interval = record.interval;
+    init_params.checkpoint_interval = record.checkpoint_interval;
     init_params.tmp_dir = tmp_dir.to_path_buf();
     init_params.runlog = runlog.to_path_buf();
     init_params.page_size = match procfs::page_size() {
@@ -210,12 +333,23 @@ pub fn record(record: &Record, tmp_dir: &Path, runlog: &Path) -> Result<()> {
                 String::from(get_data_name_from_type::<JavaProfile>()),
                 j.clone(),
             );
+            let (asprof_options, jvm_asprof_options) =
+                parse_asprof_options(&record.java_profile_events, &record.asprof_args)?;
+            init_params.asprof_options = asprof_options;
+            init_params.jvm_asprof_options = jvm_asprof_options;
         }
         None => {}
     }
     if record.profile {
+        init_params.perf_profile = true;
         init_params.perf_frequency = record.perf_frequency;
+        init_params.profile_offcpu = record.profile_offcpu;
+        init_params.perf_events = record.profile_events.clone();
+        init_params.perf_event_period = record.profile_event_period;
+        init_params.perf_call_graph = record.call_graph.clone();
+        init_params.symbol_bundle_size_mb = record.symbol_bundle_size;
     }
+    init_params.ext_profilers = parse_ext_profilers(&record.profile_ext)?;
     init_params.save_profile_events = record.save_profile_events;
 
     if let Err(e) = fs::create_dir(&run_data_dir) {
@@ -235,6 +369,7 @@ pub fn record(record: &Record, tmp_dir: &Path, runlog: &Path) -> Result<()> {
             .profile_java
             .as_ref()
             .map_or(false, |j| !j.is_empty()),
+        !record.profile_ext.is_empty(),
     );
 
     info!("Starting Data collection...");
@@ -253,6 +388,11 @@ pub fn record(record: &Record, tmp_dir: &Path, runlog: &Path) -> Result<()> {
         error!("Failed to write APerf stats: {e}");
     }
 
+    if let Some(redactor) = &redactor {
+        info!("Redacting run data...");
+        redactor.redact_run_dir(&run_data_dir)?;
+    }
+
     info!("Creating run data archive...");
     create_run_data_archive(&run_data_dir)?;
 
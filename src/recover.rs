@@ -0,0 +1,294 @@
+#![cfg(target_os = "linux")]
+
+use crate::aperf_runlog_file_path;
+use crate::data::common::raw_data_file::truncate_torn_record;
+use crate::data::TimeEnum;
+use crate::data_collection::InitParams;
+use crate::inspect::{decode_raw_data_file, raw_data_file_paths, RawDataFileInfo};
+use crate::record::create_run_data_archive;
+use crate::PDError;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Args, Debug)]
+pub struct Recover {
+    /// The directory of the run whose collection was interrupted, e.g. by a crash or a reboot.
+    #[clap(value_parser)]
+    pub run_dir: String,
+}
+
+/// Returns the collection interval of the raw data with the most records, which are collected
+/// once every interval.
+fn estimate_interval(infos: &[RawDataFileInfo]) -> u64 {
+    infos
+        .iter()
+        .filter(|info| info.num_records > 1)
+        .max_by_key(|info| info.num_records)
+        .and_then(|info| match (info.first_time, info.last_time) {
+            (Some(first_time), Some(last_time)) => {
+                let span = (last_time - first_time).num_milliseconds() as f64 / 1000.0;
+                Some((span / (info.num_records - 1) as f64).round() as u64)
+            }
+            _ => None,
+        })
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Builds the metadata of a run whose collection was interrupted before its first checkpoint,
+/// from the collection times of the raw data records.
+fn metadata_from_raw_data(run_dir: &Path, infos: &[RawDataFileInfo]) -> InitParams {
+    let run_name = run_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut init_params = InitParams::new(run_name, run_dir.to_path_buf());
+    init_params.pid = None;
+    init_params.collector_commit_sha = String::new();
+    if let Some(header) = infos.iter().find_map(|info| info.header.as_ref()) {
+        init_params.collector_version = header.collector_version.clone();
+    }
+    let first_time = infos.iter().filter_map(|info| info.first_time).min();
+    let last_time = infos.iter().filter_map(|info| info.last_time).max();
+    if let (Some(first_time), Some(last_time)) = (first_time, last_time) {
+        init_params.period = (last_time - first_time).num_seconds().max(0) as u64;
+    }
+    init_params.interval = estimate_interval(infos);
+    init_params.collection_start = first_time.map(TimeEnum::DateTime);
+    init_params.collection_end = last_time.map(TimeEnum::DateTime);
+    init_params
+}
+
+/// Whether the process with the given PID is running the given command, i.e. it has not exited
+/// and its PID was not reused by another program.
+fn is_process_running(pid: u32, comm: &str) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/comm")).is_ok_and(|pid_comm| pid_comm.trim() == comm)
+}
+
+/// Whether the APerf process of a checkpointed collection is still running, in which case the
+/// collection is still writing the data files.
+fn is_collection_running(init_params: &InitParams) -> bool {
+    let Some(pid) = init_params.pid.filter(|pid| *pid != std::process::id()) else {
+        return false;
+    };
+    fs::read_to_string("/proc/self/comm").is_ok_and(|comm| is_process_running(pid, comm.trim()))
+}
+
+pub fn recover(recover: &Recover) -> Result<()> {
+    let run_dir: PathBuf = PathBuf::from(&recover.run_dir).components().collect();
+    if !run_dir.exists() {
+        return Err(PDError::RunNotFound(run_dir).into());
+    }
+    if !run_dir.is_dir() {
+        return Err(PDError::InvalidDirectory(run_dir).into());
+    }
+
+    let archive_path = PathBuf::from(format!("{}.tar.gz", run_dir.display()));
+    let checkpoint = match InitParams::from_json(&run_dir) {
+        Ok(init_params) if !init_params.checkpoint => {
+            if archive_path.exists() {
+                info!(
+                    "The collection of {} was finished, nothing to recover.",
+                    run_dir.display()
+                );
+                return Ok(());
+            }
+            info!("The collection of {} was finished.", run_dir.display());
+            create_run_data_archive(&run_dir)?;
+            return Ok(());
+        }
+        Ok(init_params) => {
+            if is_collection_running(&init_params) {
+                return Err(PDError::CollectionRunning(run_dir, init_params.pid.unwrap()).into());
+            }
+            Some(init_params)
+        }
+        Err(_) => None,
+    };
+
+    // Remove the records that were being written when the collection was interrupted, so that
+    // the files end with complete records.
+    let mut infos = Vec::new();
+    for path in raw_data_file_paths(&run_dir)? {
+        let removed_bytes = truncate_torn_record(&path)?;
+        if removed_bytes > 0 {
+            warn!(
+                "Removed the torn record ({removed_bytes} bytes) at the end of {}",
+                path.display()
+            );
+        }
+        infos.push(decode_raw_data_file(&path, |_| Ok(()))?);
+    }
+    let last_time: Option<DateTime<Utc>> = infos.iter().filter_map(|info| info.last_time).max();
+
+    let mut init_params = match checkpoint {
+        Some(mut init_params) => {
+            // The records collected after the last checkpoint extend the collection.
+            if let Some(last_time) = last_time {
+                let checkpoint_end = match init_params.collection_end {
+                    Some(TimeEnum::DateTime(collection_end)) => Some(collection_end),
+                    _ => None,
+                };
+                if checkpoint_end.is_none_or(|checkpoint_end| last_time > checkpoint_end) {
+                    init_params.collection_end = Some(TimeEnum::DateTime(last_time));
+                }
+            }
+            init_params
+        }
+        None => {
+            if infos.iter().all(|info| info.num_records == 0) {
+                return Err(PDError::InvalidRunData.into());
+            }
+            warn!(
+                "The run metadata is missing, rebuilding it from the collected data. The settings of the collection are estimated."
+            );
+            metadata_from_raw_data(&run_dir, &infos)
+        }
+    };
+    // The directory might have been moved or renamed since the collection.
+    init_params.run_data_dir = run_dir.clone();
+    init_params.checkpoint = false;
+    init_params.recovered = true;
+    init_params.save_to_json()?;
+
+    let runlog = aperf_runlog_file_path(&run_dir);
+    if !runlog.exists() && init_params.runlog.is_file() {
+        fs::copy(&init_params.runlog, &runlog)?;
+    }
+
+    warn!(
+        "The data processed at the end of the collection, such as the profiles, might be missing or incomplete."
+    );
+    info!("Recovered {}", run_dir.display());
+    create_run_data_archive(&run_dir)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::common::raw_data_file::{
+        write_raw_data_file_header, write_raw_data_record, RawDataFileHeader,
+    };
+    use crate::data::cpu_utilization::CpuUtilizationRaw;
+    use crate::data::Data;
+    use crate::data_file_path;
+    use std::io::Write;
+
+    fn write_cpu_utilization(run_dir: &PathBuf, num_records: usize) {
+        let mut file = fs::File::create(data_file_path("cpu_utilization", run_dir)).unwrap();
+        write_raw_data_file_header(&mut file, &RawDataFileHeader::new("cpu_utilization", 1))
+            .unwrap();
+        for i in 0..num_records {
+            let record = Data::CpuUtilizationRaw(CpuUtilizationRaw {
+                time: TimeEnum::DateTime(
+                    format!("2026-01-30T08:25:{:02}Z", 2 * i)
+                        .parse::<DateTime<Utc>>()
+                        .unwrap(),
+                ),
+                data: format!("cpu {i}"),
+            });
+            write_raw_data_record(&mut file, &record.serialize_record().unwrap()).unwrap();
+        }
+        // A torn record
+        file.write_all(&[100, 0, 0, 0, 0, 0, 0, 0, 1, 2]).unwrap();
+    }
+
+    #[test]
+    fn test_recover_without_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let run_dir = temp_dir.path().join("interrupted_run");
+        fs::create_dir(&run_dir).unwrap();
+        write_cpu_utilization(&run_dir, 4);
+
+        recover(&Recover {
+            run_dir: run_dir.display().to_string(),
+        })
+        .unwrap();
+
+        let init_params = InitParams::from_json(&run_dir).unwrap();
+        assert!(init_params.recovered);
+        assert!(!init_params.checkpoint);
+        assert_eq!(init_params.run_name, "interrupted_run");
+        assert_eq!(init_params.interval, 2);
+        assert_eq!(init_params.period, 6);
+        assert_eq!(
+            init_params.collection_end,
+            Some(TimeEnum::DateTime(
+                "2026-01-30T08:25:06Z".parse::<DateTime<Utc>>().unwrap()
+            ))
+        );
+        let info =
+            decode_raw_data_file(&data_file_path("cpu_utilization", &run_dir), |_| Ok(())).unwrap();
+        assert_eq!(info.num_records, 4);
+        assert_eq!(info.status, crate::inspect::RawDataFileStatus::Complete);
+        assert!(run_dir.with_extension("tar.gz").exists());
+    }
+
+    #[test]
+    fn test_recover_from_checkpoint() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let run_dir = temp_dir.path().join("interrupted_run");
+        fs::create_dir(&run_dir).unwrap();
+        write_cpu_utilization(&run_dir, 4);
+        let mut checkpoint = InitParams::new("interrupted_run".to_string(), run_dir.clone());
+        checkpoint.period = 60;
+        checkpoint.interval = 2;
+        checkpoint.checkpoint = true;
+        checkpoint.collection_start = Some(TimeEnum::DateTime(
+            "2026-01-30T08:25:00Z".parse::<DateTime<Utc>>().unwrap(),
+        ));
+        checkpoint.collection_end = Some(TimeEnum::DateTime(
+            "2026-01-30T08:25:02Z".parse::<DateTime<Utc>>().unwrap(),
+        ));
+        checkpoint.save_to_json().unwrap();
+
+        recover(&Recover {
+            run_dir: run_dir.display().to_string(),
+        })
+        .unwrap();
+
+        let init_params = InitParams::from_json(&run_dir).unwrap();
+        assert!(init_params.recovered);
+        assert!(!init_params.checkpoint);
+        assert_eq!(init_params.period, 60);
+        assert_eq!(init_params.pid, checkpoint.pid);
+        assert_eq!(
+            init_params.collection_end,
+            Some(TimeEnum::DateTime(
+                "2026-01-30T08:25:06Z".parse::<DateTime<Utc>>().unwrap()
+            ))
+        );
+
+        // A finished run is left as it is.
+        recover(&Recover {
+            run_dir: run_dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(run_dir.join("metadata.json")).unwrap(),
+            serde_json::to_string(&init_params).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_is_process_running() {
+        let pid = std::process::id();
+        let comm = fs::read_to_string("/proc/self/comm").unwrap();
+        assert!(is_process_running(pid, comm.trim()));
+        // The PID was reused by another program
+        assert!(!is_process_running(pid, "not-aperf"));
+        // The PIDs are below pid_max, so no process has it
+        let pid_max: u32 = fs::read_to_string("/proc/sys/kernel/pid_max")
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(!is_process_running(pid_max, comm.trim()));
+    }
+}
@@ -0,0 +1,993 @@
+use crate::data::common::raw_data_file::{
+    write_raw_data_file_header, write_raw_data_record, RawDataFile,
+};
+use crate::data::Data;
+use crate::inspect::decode_raw_data_file;
+use crate::report::{
+    create_archive, extract_archive, generate_report_files, get_report_run_archive_paths, RunsInfo,
+};
+use crate::{find_file, no_tar_gz_file_name, PDError, APERF_FILE_FORMAT};
+use anyhow::{bail, Result};
+use clap::Args;
+use hmac::{Hmac, KeyInit, Mac};
+use log::{debug, info, warn};
+use regex::{Captures, Regex};
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The built-in rules, applied after the rules given by --redact-rule.
+pub const BUILTIN_REDACT_RULES: [(&str, &str); 6] = [
+    (
+        "hostname",
+        r"\bip-\d{1,3}-\d{1,3}-\d{1,3}-\d{1,3}(?:\.[a-z0-9-]+)*\b",
+    ),
+    ("instance_id", r"\bi-[0-9a-f]{8}(?:[0-9a-f]{9})?\b"),
+    ("ami_id", r"\bami-[0-9a-f]{8}(?:[0-9a-f]{9})?\b"),
+    (
+        "uuid",
+        r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b",
+    ),
+    ("mac", r"(?i)\b(?:[0-9a-f]{2}:){5}[0-9a-f]{2}\b"),
+    (
+        "ipv4",
+        r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b",
+    ),
+];
+
+/// The shortest host name that is replaced throughout the data of a run. Shorter names, such as
+/// "vm", are also parts of other names, like the vm.* sysctls.
+const MIN_HOST_NAME_LEN: usize = 4;
+
+/// The number of random bytes of the key generated when --redact-key is not given.
+const GENERATED_REDACT_KEY_LEN: usize = 16;
+
+#[derive(Clone, Args, Debug, Default)]
+pub struct RedactOptions {
+    /// A rule that replaces the matches of a regex with pseudonyms named after the rule, in the
+    /// format of NAME=REGEX. If the regex has a capture group, only the first group of the
+    /// matches is replaced. Can be specified multiple times.
+    /// Example: --redact-rule 'comm=\((my-service[^)]*)\)'
+    #[clap(
+        help_heading = "Redaction",
+        verbatim_doc_comment,
+        long,
+        value_parser,
+        value_name = "NAME=REGEX"
+    )]
+    pub redact_rule: Vec<String>,
+
+    /// The secret key of the pseudonyms. The same value gets the same pseudonym in all runs
+    /// redacted with the same key, so that the runs can still be compared, and the pseudonyms
+    /// cannot be reversed without the key. Defaults to a random key, which is printed to the
+    /// console but not to the runlog.
+    #[clap(help_heading = "Redaction", long, value_parser)]
+    pub redact_key: Option<String>,
+}
+
+#[derive(Clone, Args, Debug)]
+pub struct Redact {
+    /// The path to the directory or archive of a recorded run or a report.
+    #[clap(value_parser)]
+    pub path: String,
+
+    /// The directory and archive name of the redacted run or report. Defaults to
+    /// <NAME>-redacted.
+    #[clap(help_heading = "Basic Options", short, long, value_parser)]
+    pub output: Option<String>,
+
+    #[clap(flatten)]
+    pub options: RedactOptions,
+}
+
+struct RedactRule {
+    name: String,
+    regex: Regex,
+}
+
+/// Replaces the sensitive values in the data of runs with pseudonyms.
+pub struct Redactor {
+    rules: Vec<RedactRule>,
+    key: String,
+}
+
+/// Generates a random key for the pseudonyms, as hex digits.
+fn generate_redact_key() -> Result<String> {
+    let mut key = [0u8; GENERATED_REDACT_KEY_LEN];
+    if let Err(e) = getrandom::fill(&mut key) {
+        bail!("Failed to generate a redaction key: {e}");
+    }
+    Ok(key.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+impl Redactor {
+    pub fn new(options: &RedactOptions) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in &options.redact_rule {
+            let (name, regex) = match rule.split_once('=') {
+                Some((name, regex)) => (name, regex),
+                None => bail!("Invalid redaction rule {rule}, expected NAME=REGEX"),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("Invalid redaction rule name {name}, expected letters, digits or _");
+            }
+            rules.push(RedactRule {
+                name: name.to_string(),
+                regex: Regex::new(regex)?,
+            });
+        }
+        for (name, regex) in BUILTIN_REDACT_RULES {
+            rules.push(RedactRule {
+                name: name.to_string(),
+                regex: Regex::new(regex).unwrap(),
+            });
+        }
+        let key = match &options.redact_key {
+            Some(key) => key.clone(),
+            None => {
+                let key = generate_redact_key()?;
+                // Only printed to the console, since the runlog is part of the redacted run and
+                // the key would make its pseudonyms reversible.
+                eprintln!("Redacting with the generated key {key}, pass it to --redact-key to get the same pseudonyms in other runs");
+                key
+            }
+        };
+        Ok(Redactor { rules, key })
+    }
+
+    /// Returns a redactor that also replaces the given values, e.g. the host names of a run,
+    /// before applying the rules.
+    fn with_values(&self, name: &str, values: &[String]) -> Self {
+        let mut rules: Vec<RedactRule> = values
+            .iter()
+            .filter(|value| !value.is_empty())
+            .map(|value| RedactRule {
+                name: name.to_string(),
+                regex: Regex::new(&format!(r"\b{}\b", regex::escape(value))).unwrap(),
+            })
+            .collect();
+        rules.extend(self.rules.iter().map(|rule| RedactRule {
+            name: rule.name.clone(),
+            regex: rule.regex.clone(),
+        }));
+        Redactor {
+            rules,
+            key: self.key.clone(),
+        }
+    }
+
+    /// The pseudonym of a value is the HMAC-SHA256 of the value with the key, truncated to 48
+    /// bits, which cannot be reversed without the key even for guessable values.
+    fn pseudonym(&self, rule_name: &str, value: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        let hash = mac.finalize().into_bytes();
+        let hex: String = hash[..6].iter().map(|byte| format!("{byte:02x}")).collect();
+        format!("{rule_name}-{hex}")
+    }
+
+    /// Replaces the matches of all rules in a string with their pseudonyms.
+    pub fn redact_str<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(s);
+        for rule in &self.rules {
+            if !rule.regex.is_match(&redacted) {
+                continue;
+            }
+            let replaced = rule
+                .regex
+                .replace_all(&redacted, |caps: &Captures| {
+                    let whole = caps.get(0).unwrap();
+                    match caps.get(1) {
+                        Some(group) => format!(
+                            "{}{}{}",
+                            &whole.as_str()[..group.start() - whole.start()],
+                            self.pseudonym(&rule.name, group.as_str()),
+                            &whole.as_str()[group.end() - whole.start()..]
+                        ),
+                        None => self.pseudonym(&rule.name, whole.as_str()),
+                    }
+                })
+                .into_owned();
+            redacted = Cow::Owned(replaced);
+        }
+        redacted
+    }
+
+    /// Redacts all strings of a JSON value, including the keys of the objects, for the JSON files
+    /// whose schema is not known.
+    fn redact_value(&self, value: Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact_str(&s).into_owned()),
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.redact_value(value))
+                    .collect(),
+            ),
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (self.redact_str(&key).into_owned(), self.redact_value(value))
+                    })
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Redacts all files of a run directory in place. The files that cannot be redacted, such
+    /// as the binaries of the symbol bundle and the JFRs, are removed.
+    pub fn redact_run_dir(&self, run_dir: &Path) -> Result<()> {
+        let redactor = self.with_values("hostname", &host_names(run_dir));
+        redactor.redact_dir(run_dir)
+    }
+
+    fn redact_dir(&self, dir: &Path) -> Result<()> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.redact_dir(&path)?;
+            } else {
+                self.redact_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn redact_file(&self, path: &Path) -> Result<()> {
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // The APerf stats only have the names of the data and of APerf's own processes.
+        if file_name.starts_with("aperf_stats") || file_name.starts_with("aperf_run_stats") {
+            return Ok(());
+        }
+        if path
+            .extension()
+            .is_some_and(|extension| extension == APERF_FILE_FORMAT)
+        {
+            return self.redact_raw_data_file(path, &file_name);
+        }
+
+        let bytes = fs::read(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            if let Ok(value) = serde_json::from_slice::<Value>(&bytes) {
+                fs::write(path, serde_json::to_string(&self.redact_value(value))?)?;
+                return Ok(());
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => {
+                if let Cow::Owned(redacted) = self.redact_str(&text) {
+                    fs::write(path, redacted)?;
+                }
+            }
+            Err(_) => {
+                warn!("Removing {file_name}, which is binary and cannot be redacted");
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the records of a raw data file with the strings redacted, in the same format as
+    /// the file. The records that cannot be decoded are dropped.
+    fn redact_raw_data_file(&self, path: &Path, file_name: &str) -> Result<()> {
+        let mut raw_data_file = match RawDataFile::open(path) {
+            Ok(raw_data_file) => raw_data_file,
+            Err(e) => {
+                warn!("Removing {file_name}, which cannot be decoded: {e}");
+                fs::remove_file(path)?;
+                return Ok(());
+            }
+        };
+        let decoded = raw_data_file.decode_records(|header, record| {
+            Data::deserialize_record(&header.data_name, header.schema_version, record)
+        });
+        if decoded.records.is_empty() && !decoded.errors.is_empty() {
+            warn!(
+                "Removing {file_name}, whose records cannot be decoded: {}",
+                decoded.errors[0]
+            );
+            fs::remove_file(path)?;
+            return Ok(());
+        }
+        if let Some(e) = decoded.errors.first() {
+            warn!(
+                "Dropping {} records of {file_name} that cannot be decoded, starting with the {e}",
+                decoded.errors.len()
+            );
+        }
+
+        let mut records = Vec::with_capacity(decoded.records.len());
+        for record in decoded.records {
+            let value = serde_json::to_value(Redacted {
+                redactor: self,
+                value: &record,
+            })?;
+            records.push(serde_json::from_value::<Data>(value)?);
+        }
+
+        let mut file = File::create(path)?;
+        if let Some(header) = &raw_data_file.header {
+            write_raw_data_file_header(&mut file, header)?;
+        }
+        let mut writer = BufWriter::new(file);
+        for record in &records {
+            match raw_data_file.header {
+                Some(_) => write_raw_data_record(&mut writer, &record.serialize_record()?)?,
+                None => bincode::serialize_into(&mut writer, record)?,
+            }
+        }
+        writer.flush()?;
+        debug!("Redacted {} records of {file_name}", records.len());
+        Ok(())
+    }
+}
+
+/// Serializes a value with its strings and map keys redacted, but not the names of its struct
+/// fields and enum variants, so that the redacted value still matches its type.
+struct Redacted<'a, T: ?Sized> {
+    redactor: &'a Redactor,
+    value: &'a T,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Redacted<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(RedactingSerializer {
+            redactor: self.redactor,
+            inner: serializer,
+        })
+    }
+}
+
+/// A serializer that redacts the strings passed to the inner serializer. The names of the
+/// fields and variants are passed through, and so are the values serialized through their
+/// Display (collect_str), such as the times, whose format is part of their type.
+struct RedactingSerializer<'a, S> {
+    redactor: &'a Redactor,
+    inner: S,
+}
+
+/// The serializer of the elements, entries or fields of a compound value, which redacts them.
+struct RedactingCompound<'a, C> {
+    redactor: &'a Redactor,
+    inner: C,
+}
+
+impl<'a, C> RedactingCompound<'a, C> {
+    fn redacted<'v, T: ?Sized>(&self, value: &'v T) -> Redacted<'v, T>
+    where
+        'a: 'v,
+    {
+        Redacted {
+            redactor: self.redactor,
+            value,
+        }
+    }
+}
+
+impl<'a, S: Serializer> Serializer for RedactingSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = RedactingCompound<'a, S::SerializeSeq>;
+    type SerializeTuple = RedactingCompound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = RedactingCompound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = RedactingCompound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = RedactingCompound<'a, S::SerializeMap>;
+    type SerializeStruct = RedactingCompound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = RedactingCompound<'a, S::SerializeStructVariant>;
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(&self.redactor.redact_str(v))
+    }
+
+    fn collect_str<T: std::fmt::Display + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(&Redacted {
+            redactor: self.redactor,
+            value,
+        })
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_struct(
+            name,
+            &Redacted {
+                redactor: self.redactor,
+                value,
+            },
+        )
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_variant(
+            name,
+            variant_index,
+            variant,
+            &Redacted {
+                redactor: self.redactor,
+                value,
+            },
+        )
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self.inner.serialize_seq(len)?,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self.inner.serialize_tuple(len)?,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self.inner.serialize_tuple_struct(name, len)?,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self
+                .inner
+                .serialize_tuple_variant(name, variant_index, variant, len)?,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self.inner.serialize_map(len)?,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self.inner.serialize_struct(name, len)?,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(RedactingCompound {
+            redactor: self.redactor,
+            inner: self
+                .inner
+                .serialize_struct_variant(name, variant_index, variant, len)?,
+        })
+    }
+}
+
+impl<C: SerializeSeq> SerializeSeq for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleVariant> SerializeTupleVariant for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeMap> SerializeMap for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        let key = self.redacted(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStructVariant> SerializeStructVariant for RedactingCompound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = self.redacted(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+/// Returns the host names of a run from its systeminfo data, which are replaced in all data of
+/// the run, even where no rule matches them.
+fn host_names(run_dir: &Path) -> Vec<String> {
+    let file_name = match find_file(
+        &run_dir.to_path_buf(),
+        &format!("^systeminfo(_.*)?\\.{}$", APERF_FILE_FORMAT),
+        None,
+    ) {
+        Ok(file_name) => file_name,
+        Err(_) => return Vec::new(),
+    };
+    let mut host_names = Vec::new();
+    let _ = decode_raw_data_file(&run_dir.join(file_name), |record| {
+        let system_info = &record["SystemInfo"];
+        for host_name in [
+            &system_info["host_name"],
+            &system_info["instance_metadata"]["local_hostname"],
+        ] {
+            // The instance metadata is N/A outside of EC2.
+            if let Some(host_name) = host_name.as_str().filter(|name| *name != "N/A") {
+                // The short host name, without the domain
+                for name in [
+                    Some(host_name),
+                    host_name.split_once('.').map(|(name, _)| name),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    if name.len() < MIN_HOST_NAME_LEN {
+                        warn!("The host name {name} is too short to be told apart from other words, use --redact-rule to redact it");
+                    } else {
+                        host_names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        Ok(())
+    });
+    // Replace the longer names first, so that the short names do not break them apart.
+    host_names.sort_by_key(|host_name| std::cmp::Reverse(host_name.len()));
+    host_names.dedup();
+    host_names
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &dst.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), dst.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn redact(redact: &Redact, tmp_dir: &Path) -> Result<()> {
+    let redactor = Redactor::new(&redact.options)?;
+
+    let input_path = PathBuf::from(&redact.path);
+    if !input_path.exists() {
+        return Err(PDError::RunNotFound(input_path).into());
+    }
+    let input_dir = if input_path.is_dir() {
+        input_path.clone()
+    } else {
+        extract_archive(&input_path, &tmp_dir.to_path_buf())?
+    };
+    let output_dir = match &redact.output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(format!(
+            "{}-redacted",
+            redactor.redact_str(&no_tar_gz_file_name(&input_path).unwrap_or_default())
+        )),
+    };
+    let output_archive = PathBuf::from(format!("{}.tar.gz", output_dir.display()));
+    if output_dir.exists() || output_archive.exists() {
+        return Err(PDError::RedactedOutputExists(output_dir).into());
+    }
+
+    match get_report_run_archive_paths(&input_dir) {
+        // The processed data of a report cannot be told apart from the code of the report, so
+        // the report is generated again from its redacted runs.
+        Some(run_archive_paths) => {
+            let mut run_dirs = Vec::new();
+            for run_archive_path in run_archive_paths {
+                let run_dir = extract_archive(&run_archive_path, &tmp_dir.to_path_buf())?;
+                redactor.redact_run_dir(&run_dir)?;
+                let run_name = no_tar_gz_file_name(&run_dir).unwrap_or_default();
+                let redacted_run_dir =
+                    run_dir.with_file_name(redactor.redact_str(&run_name).as_ref());
+                if redacted_run_dir != run_dir {
+                    fs::rename(&run_dir, &redacted_run_dir)?;
+                }
+                run_dirs.push(redacted_run_dir.display().to_string());
+            }
+            if run_dirs.is_empty() {
+                return Err(PDError::InvalidRunData.into());
+            }
+            let mut runs_info = RunsInfo::new(output_dir, tmp_dir.to_path_buf());
+            runs_info.add_runs(&run_dirs)?;
+            runs_info.process_per_run_time_range(&Vec::new())?;
+            generate_report_files(runs_info);
+        }
+        None => {
+            copy_dir_all(&input_dir, &output_dir)?;
+            redactor.redact_run_dir(&output_dir)?;
+            let output_parent = output_dir
+                .parent()
+                .map(|parent| parent.to_path_buf())
+                .unwrap_or_default();
+            create_archive(&output_dir, &output_parent)?;
+            info!(
+                "Redacted run in {}/ and archive available at {}",
+                output_dir.display(),
+                output_archive.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::common::raw_data_file::RawDataFileHeader;
+    use crate::data::sysctl::SysctlData;
+    use crate::data::systeminfo::SystemInfo;
+    use crate::data::TimeEnum;
+    use std::collections::BTreeMap;
+
+    fn redactor(rules: &[&str]) -> Redactor {
+        Redactor::new(&RedactOptions {
+            redact_rule: rules.iter().map(|rule| rule.to_string()).collect(),
+            redact_key: Some("test".to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_redact_str() {
+        let redactor = redactor(&[r"comm=\((my-service[^)]*)\)"]);
+        let redacted = redactor.redact_str("10.0.1.12 i-0123456789abcdef0 ami-01234567 v1.2.3");
+        let pseudonyms: Vec<&str> = redacted.split(' ').collect();
+        assert!(pseudonyms[0].starts_with("ipv4-"));
+        assert!(pseudonyms[1].starts_with("instance_id-"));
+        assert!(pseudonyms[2].starts_with("ami_id-"));
+        assert_eq!(pseudonyms[3], "v1.2.3");
+
+        // The same values get the same pseudonyms, and different values different ones.
+        assert_eq!(redactor.redact_str("10.0.1.12"), pseudonyms[0]);
+        assert_ne!(redactor.redact_str("10.0.1.13"), pseudonyms[0]);
+        // Only the capture group is replaced.
+        let redacted = redactor.redact_str("42 (my-service-1) S 1");
+        assert!(redacted.starts_with("42 (comm-"));
+        assert!(redacted.ends_with(") S 1"));
+        assert_eq!(redactor.redact_str("43 (java) S 1"), "43 (java) S 1");
+
+        // The pseudonyms depend on the key.
+        let other_key_redactor = Redactor::new(&RedactOptions::default()).unwrap();
+        assert_ne!(other_key_redactor.redact_str("10.0.1.12"), pseudonyms[0]);
+
+        assert!(Redactor::new(&RedactOptions {
+            redact_rule: vec!["no_regex".to_string()],
+            redact_key: None,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_redact_run_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let run_dir = temp_dir.path().join("run");
+        fs::create_dir(&run_dir).unwrap();
+
+        let mut system_info = SystemInfo::new();
+        system_info.host_name = "build-host.example.com".to_string();
+        system_info.instance_metadata.local_hostname = "N/A".to_string();
+        let mut sysctl_data = SysctlData {
+            time: TimeEnum::DateTime(chrono::Utc::now()),
+            sysctl_data: BTreeMap::new(),
+        };
+        sysctl_data
+            .sysctl_data
+            .insert("kernel.hostname".to_string(), "build-host".to_string());
+        sysctl_data
+            .sysctl_data
+            .insert("net.ipv4.ip_forward".to_string(), "0".to_string());
+        sysctl_data
+            .sysctl_data
+            .insert("vm.swappiness".to_string(), "60".to_string());
+        for (data_name, data) in [
+            ("systeminfo", Data::SystemInfo(system_info)),
+            ("sysctl", Data::SysctlData(sysctl_data)),
+        ] {
+            let mut file = File::create(run_dir.join(format!("{data_name}.bin"))).unwrap();
+            write_raw_data_file_header(&mut file, &RawDataFileHeader::new(data_name, 1)).unwrap();
+            write_raw_data_record(&mut file, &data.serialize_record().unwrap()).unwrap();
+        }
+        fs::write(
+            run_dir.join("perf_profiler_data.json"),
+            r#"{"frames":["main","connect 10.1.2.3"]}"#,
+        )
+        .unwrap();
+        fs::write(run_dir.join("aperf_runlog"), "Collecting on build-host\n").unwrap();
+        fs::write(run_dir.join("java-profile-42.jfr"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let redactor = redactor(&[]);
+        redactor.redact_run_dir(&run_dir).unwrap();
+
+        let mut records = Vec::new();
+        decode_raw_data_file(&run_dir.join("sysctl.bin"), |record| {
+            records.push(record.clone());
+            Ok(())
+        })
+        .unwrap();
+        let sysctl_data = &records[0]["SysctlData"]["sysctl_data"];
+        let host_name = sysctl_data["kernel.hostname"].as_str().unwrap();
+        assert!(host_name.starts_with("hostname-"));
+        assert_eq!(sysctl_data["net.ipv4.ip_forward"], "0");
+        assert_eq!(sysctl_data["vm.swappiness"], "60");
+        let runlog = fs::read_to_string(run_dir.join("aperf_runlog")).unwrap();
+        assert_eq!(runlog, format!("Collecting on {host_name}\n"));
+        let profile = fs::read_to_string(run_dir.join("perf_profiler_data.json")).unwrap();
+        assert!(profile.contains("\"main\""));
+        assert!(!profile.contains("10.1.2.3"));
+        assert!(!run_dir.join("java-profile-42.jfr").exists());
+        // The host names found in systeminfo are replaced in the systeminfo itself.
+        assert!(host_names(&run_dir)
+            .iter()
+            .all(|name| name.starts_with("hostname-")));
+    }
+
+    #[test]
+    fn test_redact_field_name() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let run_dir = temp_dir.path().join("run");
+        fs::create_dir(&run_dir).unwrap();
+        let mut file = File::create(run_dir.join("sysctl.bin")).unwrap();
+        write_raw_data_file_header(&mut file, &RawDataFileHeader::new("sysctl", 1)).unwrap();
+        let mut sysctl_data = SysctlData {
+            time: TimeEnum::DateTime(chrono::Utc::now()),
+            sysctl_data: BTreeMap::new(),
+        };
+        sysctl_data
+            .sysctl_data
+            .insert("sysctl_data.time".to_string(), "sysctl_data".to_string());
+        let record = Data::SysctlData(sysctl_data);
+        write_raw_data_record(&mut file, &record.serialize_record().unwrap()).unwrap();
+
+        // The rules that match the names of the fields and variants only redact the strings
+        // and the map keys, so the records are kept.
+        let redactor = redactor(&["field=sysctl_data", "variant=DateTime", "time=time"]);
+        redactor.redact_run_dir(&run_dir).unwrap();
+        let mut records = Vec::new();
+        decode_raw_data_file(&run_dir.join("sysctl.bin"), |record| {
+            records.push(record.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(records.len(), 1);
+        let sysctl_data = records[0]["SysctlData"]["sysctl_data"].as_object().unwrap();
+        let (key, value) = sysctl_data.iter().next().unwrap();
+        assert!(key.starts_with("field-") && key.contains(".time-"));
+        assert!(value.as_str().unwrap().starts_with("field-"));
+        assert!(records[0]["SysctlData"]["time"]["DateTime"].is_string());
+    }
+}
@@ -3,10 +3,11 @@ use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
 use crate::data::{TimeEnum, JS_DIR};
 use crate::data_collection::InitParams;
 use crate::data_processing::{DataProcessingEngine, ReportParams};
+use crate::profiling::transform::{ProfileTransforms, PROFILE_FRAME_VIEWS};
 use crate::{data, no_tar_gz_file_name, PDError};
 use anyhow::{Context, Result};
 use chrono::Utc;
-use clap::Args;
+use clap::{builder::PossibleValuesParser, Args};
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
@@ -14,7 +15,7 @@ use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
 #[derive(Serialize, Deserialize)]
@@ -41,33 +42,33 @@ impl VersionInfo {
 
 /// Stores the information of all runs to be included in the report
 #[derive(Default)]
-struct RunsInfo {
+pub(crate) struct RunsInfo {
     /// The path to the report directory.
-    report_dir: PathBuf,
+    pub(crate) report_dir: PathBuf,
     /// The path to the temp working directory.
-    tmp_dir: PathBuf,
+    pub(crate) tmp_dir: PathBuf,
     /// The list of run names (the run dir/archive file name minus the file format).
-    run_names: Vec<String>,
+    pub(crate) run_names: Vec<String>,
     /// The index used to deduplicate each unique run name.
-    run_name_dedup_indices: HashMap<String, u8>,
+    pub(crate) run_name_dedup_indices: HashMap<String, u8>,
     /// The map from run names to paths to the run archives (run data tar files).
-    run_archive_paths: HashMap<String, PathBuf>,
+    pub(crate) run_archive_paths: HashMap<String, PathBuf>,
     /// The specified start time of every run's time range.
-    per_run_from_time: HashMap<String, i64>,
+    pub(crate) per_run_from_time: HashMap<String, i64>,
     /// The specified end time of every run's time range.
-    per_run_to_time: HashMap<String, i64>,
+    pub(crate) per_run_to_time: HashMap<String, i64>,
     /// Wall-clock start of `collect_data_serial`, derived from the run's
     /// metadata (InitParams). Missing entry => archive was recorded by an older
     /// aperf that did not stamp collection start/end times.
-    per_run_start_time: HashMap<String, TimeEnum>,
+    pub(crate) per_run_start_time: HashMap<String, TimeEnum>,
     /// Wall-clock end of `collect_data_serial`.
-    per_run_end_time: HashMap<String, TimeEnum>,
+    pub(crate) per_run_end_time: HashMap<String, TimeEnum>,
     /// Per-run report params initialized from the run's InitParams.
-    per_run_report_params: HashMap<String, ReportParams>,
+    pub(crate) per_run_report_params: HashMap<String, ReportParams>,
 }
 
 impl RunsInfo {
-    fn new(report_dir: PathBuf, tmp_dir: PathBuf) -> Self {
+    pub(crate) fn new(report_dir: PathBuf, tmp_dir: PathBuf) -> Self {
         RunsInfo {
             report_dir,
             tmp_dir,
@@ -75,6 +76,65 @@ impl RunsInfo {
         }
     }
 
+    /// Add the runs of the input paths, which can be run data directories or archives, or
+    /// report directories or archives, in which case all runs of the reports are added.
+    pub(crate) fn add_runs(&mut self, runs: &[String]) -> Result<()> {
+        let mut seen_run_paths: HashSet<PathBuf> = HashSet::new();
+
+        for run in runs {
+            let run_path = PathBuf::from(run);
+
+            if !run_path.exists() {
+                return Err(PDError::RunNotFound(run_path).into());
+            }
+
+            if let Ok(canonical_path) = fs::canonicalize(&run_path) {
+                if !seen_run_paths.insert(canonical_path) {
+                    return Err(PDError::DuplicateRunPath(run_path).into());
+                }
+            }
+
+            let is_run_path_dir = run_path.is_dir();
+            // Extract the data if the input run path is an archive
+            let extracted_dir_path = if is_run_path_dir {
+                run_path.clone()
+            } else {
+                extract_archive(&run_path, &self.tmp_dir)?
+            };
+
+            // If handling a report, get all run data archives in it and extract them
+            if let Some(report_run_archive_paths) =
+                get_report_run_archive_paths(&extracted_dir_path)
+            {
+                for report_run_archive_path in report_run_archive_paths {
+                    self.add_run(
+                        no_tar_gz_file_name(&report_run_archive_path).unwrap(),
+                        report_run_archive_path.clone(),
+                        extract_archive(&report_run_archive_path, &self.tmp_dir)?,
+                    )?
+                }
+            }
+            // If handling a data directory, create an archive to be copied into the report at the end
+            else if is_run_path_dir {
+                self.add_run(
+                    no_tar_gz_file_name(&run_path).unwrap(),
+                    create_archive(&run_path, &self.tmp_dir)?,
+                    run_path.clone(),
+                )?
+            }
+            // If handling a data archive, use the archive directly
+            else {
+                self.add_run(
+                    no_tar_gz_file_name(&run_path).unwrap(),
+                    run_path.clone(),
+                    extracted_dir_path,
+                )?
+            }
+        }
+
+        Ok(())
+    }
+
     fn add_run(
         &mut self,
         run_name: String,
@@ -95,6 +155,12 @@ impl RunsInfo {
         // Reads metadata (serialized InitParams) from a run's data directory.
         match InitParams::from_json(&run_dir_path) {
             Ok(meta_data) => {
+                if meta_data.checkpoint {
+                    warn!(
+                        "The collection of run {} was interrupted, use aperf recover to finalize the run.",
+                        deduped_run_name
+                    );
+                }
                 report_params.pmu_counter_mode = meta_data.pmu_counter_mode;
                 if let Some(aperf_pid) = meta_data.pid {
                     report_params.aperf_process_pids.push(aperf_pid);
@@ -150,7 +216,7 @@ impl RunsInfo {
         }
     }
 
-    fn process_per_run_time_range(
+    pub(crate) fn process_per_run_time_range(
         &mut self,
         run_time_ranges: &Vec<(String, Option<i64>, Option<i64>)>,
     ) -> Result<()> {
@@ -248,9 +314,41 @@ pub struct Report {
         num_args = 1
     )]
     pub time_range: Vec<(String, Option<i64>, Option<i64>)>,
+
+    /// Drop the frames matching the regex from the stacks of the profiles, such as
+    /// "__libc_start_main" or "std::rt", attaching their callees to their callers. Can be
+    /// specified multiple times.
+    #[clap(
+        help_heading = "Profile Options",
+        long,
+        value_parser,
+        value_name = "REGEX",
+        num_args = 1
+    )]
+    pub profile_drop_frames: Vec<String>,
+
+    /// Collapse the recursive calls of a function in the stacks of the profiles into a single
+    /// frame.
+    #[clap(help_heading = "Profile Options", long, value_parser)]
+    pub profile_collapse_recursion: bool,
+
+    /// Fold the consecutive frames of the same module in the stacks of the profiles, i.e. a Java
+    /// package, a C++ namespace, a Rust crate or the kernel, into a single frame named after the
+    /// module.
+    #[clap(help_heading = "Profile Options", long, value_parser)]
+    pub profile_fold_modules: bool,
+
+    /// Only show the userspace frames (user) or the kernel frames (kernel) of the profiles.
+    #[clap(
+        help_heading = "Profile Options",
+        long,
+        value_parser = PossibleValuesParser::new(PROFILE_FRAME_VIEWS),
+        default_value = "all"
+    )]
+    pub profile_view: String,
 }
 
-pub fn report(report: &Report, tmp_dir: &PathBuf) -> Result<()> {
+pub fn report(report: &Report, tmp_dir: &Path) -> Result<()> {
     let report_path_str = if let Some(report_name_arg) = &report.name {
         report_name_arg.clone()
     } else {
@@ -266,59 +364,18 @@ pub fn report(report: &Report, tmp_dir: &PathBuf) -> Result<()> {
         return Err(PDError::ReportExists(report_path_str).into());
     }
 
-    let mut runs_info = RunsInfo::new(report_dir_path, tmp_dir.clone());
-    let mut seen_run_paths: HashSet<PathBuf> = HashSet::new();
-
-    for run in &report.run {
-        let run_path = PathBuf::from(run);
-
-        if !run_path.exists() {
-            return Err(PDError::RunNotFound(run_path).into());
-        }
-
-        if let Ok(canonical_path) = fs::canonicalize(&run_path) {
-            if !seen_run_paths.insert(canonical_path) {
-                return Err(PDError::DuplicateRunPath(run_path).into());
-            }
-        }
-
-        let is_run_path_dir = run_path.is_dir();
-        // Extract the data if the input run path is an archive
-        let extracted_dir_path = if is_run_path_dir {
-            run_path.clone()
-        } else {
-            extract_archive(&run_path, tmp_dir)?
-        };
-
-        // If handling a report, get all run data archives in it and extract them
-        if let Some(report_run_archive_paths) = get_report_run_archive_paths(&extracted_dir_path) {
-            for report_run_archive_path in report_run_archive_paths {
-                runs_info.add_run(
-                    no_tar_gz_file_name(&report_run_archive_path).unwrap(),
-                    report_run_archive_path.clone(),
-                    extract_archive(&report_run_archive_path, tmp_dir)?,
-                )?
-            }
-        }
-        // If handling a data directory, create an archive to be copied into the report at the end
-        else if is_run_path_dir {
-            runs_info.add_run(
-                no_tar_gz_file_name(&run_path).unwrap(),
-                create_archive(&run_path, tmp_dir)?,
-                run_path.clone(),
-            )?
-        }
-        // If handling a data archive, use the archive directly
-        else {
-            runs_info.add_run(
-                no_tar_gz_file_name(&run_path).unwrap(),
-                run_path.clone(),
-                extracted_dir_path,
-            )?
-        }
-    }
-
+    let mut runs_info = RunsInfo::new(report_dir_path, tmp_dir.to_path_buf());
+    runs_info.add_runs(&report.run)?;
     runs_info.process_per_run_time_range(&report.time_range)?;
+    let profile_transforms = ProfileTransforms::new(
+        &report.profile_view,
+        &report.profile_drop_frames,
+        report.profile_fold_modules,
+        report.profile_collapse_recursion,
+    )?;
+    for report_params in runs_info.per_run_report_params.values_mut() {
+        report_params.profile_transforms = profile_transforms.clone();
+    }
 
     generate_report_files(runs_info);
 
@@ -327,7 +384,7 @@ pub fn report(report: &Report, tmp_dir: &PathBuf) -> Result<()> {
 
 /// Used to parse the --time-range option, in the format of run_name=from_time:to_time,
 /// into a tuple (run_name, from_time, to_time)
-fn parse_time_range(s: &str) -> Result<(String, Option<i64>, Option<i64>), String> {
+pub(crate) fn parse_time_range(s: &str) -> Result<(String, Option<i64>, Option<i64>), String> {
     // If there's no '=', treat the whole string as FROM:TO (applies to all runs)
     let (run_name, range) = s.split_once('=').unwrap_or(("", s));
     let (from_str, to_str) = range
@@ -453,7 +510,7 @@ pub fn get_report_run_archive_paths(report_path: &PathBuf) -> Option<Vec<PathBuf
 }
 
 /// Processes all the raw data, executes analytical rules, and produces all required report files
-fn generate_report_files(runs_info: RunsInfo) {
+pub(crate) fn generate_report_files(runs_info: RunsInfo) {
     {
         let mut base_run_name = BASE_RUN_NAME.lock().unwrap();
         *base_run_name = runs_info.run_names.get(0).unwrap().to_string();
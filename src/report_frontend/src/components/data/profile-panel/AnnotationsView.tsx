@@ -0,0 +1,79 @@
+import React from "react";
+import { Box, ExpandableSection, SpaceBetween, Table } from "@cloudscape-design/components";
+import { AnnotatedFunction, AnnotatedInstruction } from "../../../definitions/types";
+import { useReportState } from "../../ReportStateProvider";
+import { getThemeColors } from "./colors";
+
+interface Props {
+  readonly annotations: AnnotatedFunction[];
+}
+
+/**
+ * Disassembly of the hottest functions of a profile, like `perf annotate`. Every function is an
+ * expandable section listing its instructions, with the share of the function's samples taken at
+ * each instruction. The hottest function is expanded by default.
+ */
+export function AnnotationsView({ annotations }: Props) {
+  return (
+    <SpaceBetween size="s">
+      {annotations.map((annotatedFunction, idx) => (
+        <ExpandableSection
+          key={`${annotatedFunction.source}:${annotatedFunction.name}`}
+          defaultExpanded={idx === 0}
+          headerText={annotatedFunction.name}
+          headerDescription={`${annotatedFunction.source} — ${annotatedFunction.samples} samples`}
+        >
+          <AnnotatedInstructionsTable annotatedFunction={annotatedFunction} />
+        </ExpandableSection>
+      ))}
+    </SpaceBetween>
+  );
+}
+
+function AnnotatedInstructionsTable({ annotatedFunction }: { readonly annotatedFunction: AnnotatedFunction }) {
+  const { darkMode } = useReportState();
+  const theme = getThemeColors(darkMode);
+  const total = Math.max(annotatedFunction.samples, 1);
+
+  // Instructions taking at least this share of the function's samples are highlighted.
+  const HOT_INSTRUCTION_PERCENT = 5;
+
+  return (
+    <Table<AnnotatedInstruction>
+      variant="embedded"
+      items={annotatedFunction.instructions}
+      wrapLines={false}
+      columnDefinitions={[
+        {
+          id: "percent",
+          header: "Samples %",
+          cell: (item) => {
+            const percent = (item.samples / total) * 100;
+            return item.samples > 0 ? (
+              <Box
+                fontWeight={percent >= HOT_INSTRUCTION_PERCENT ? "bold" : "normal"}
+                color={percent >= HOT_INSTRUCTION_PERCENT ? "text-status-error" : "inherit"}
+              >
+                {percent.toFixed(2)}
+              </Box>
+            ) : (
+              ""
+            );
+          },
+        },
+        {
+          id: "address",
+          header: "Address",
+          cell: (item) => (
+            <span style={{ fontFamily: "monospace", color: theme.textSubtle }}>{item.address.toString(16)}</span>
+          ),
+        },
+        {
+          id: "instruction",
+          header: "Instruction",
+          cell: (item) => <span style={{ fontFamily: "monospace", whiteSpace: "pre" }}>{item.instruction}</span>,
+        },
+      ]}
+    />
+  );
+}
@@ -1,6 +1,6 @@
 import React from "react";
 import { useReportState } from "../../ReportStateProvider";
-import { FlamegraphNode, getNodeStackPath } from "./utils";
+import { FlamegraphNode, getNodeStackPath, stackMapTotal } from "./utils";
 import { defaultFlamegraphColor, diffFlamegraphColor, getThemeColors } from "./colors";
 
 const FLAMEGRAPH_ROW_HEIGHT = 18;
@@ -53,24 +53,28 @@ export function FlamegraphCanvas({
 
   const flamegraphHeight = maxDepth * FLAMEGRAPH_ROW_HEIGHT;
 
-  // Precompute normalized deltas per node (difffolded.pl: delta = cur - base, normalize by max)
+  // Precompute deltas per node (difffolded.pl -n: each side is normalized by its total samples,
+  // so that runs or ranges of different lengths are comparable). `share` is the change of the
+  // node's % samples, and `color` is the delta scaled by the largest one for coloring.
   const nodeDeltaMap = React.useMemo(() => {
     if (!baselineByStack || !currentByStack) return null;
-    const deltas = new Map<FlamegraphNode, number>();
+    const baseTotal = stackMapTotal(baselineByStack);
+    const curTotal = stackMapTotal(currentByStack);
+    if (baseTotal === 0 || curTotal === 0) return null;
+    const deltas = new Map<FlamegraphNode, { share: number; color: number }>();
     let maxAbs = 0;
     for (const node of flatNodes) {
       if (node.depth === 0) continue;
       const path = getNodeStackPath(node);
-      const cur = currentByStack.get(path)?.total || 0;
-      const base = baselineByStack.get(path)?.total || 0;
+      const cur = (currentByStack.get(path)?.total || 0) / curTotal;
+      const base = (baselineByStack.get(path)?.total || 0) / baseTotal;
       const d = cur - base;
-      deltas.set(node, d);
+      deltas.set(node, { share: d, color: 0 });
       if (Math.abs(d) > maxAbs) maxAbs = Math.abs(d);
     }
     if (maxAbs === 0) return null;
-    const normalized = new Map<FlamegraphNode, number>();
-    for (const [node, d] of deltas) normalized.set(node, d / maxAbs);
-    return normalized;
+    for (const delta of deltas.values()) delta.color = delta.share / maxAbs;
+    return deltas;
   }, [flatNodes, baselineByStack, currentByStack]);
 
   // --- Draw flamegraph ---
@@ -117,7 +121,7 @@ export function FlamegraphCanvas({
         // diffFlamegraphColor returns "transparent" for ~zero deltas; canvases
         // can't paint transparent fills usefully, so fall back to the
         // frame-type color so unchanged frames stay visible at their base color.
-        const diff = diffFlamegraphColor(nodeDeltaMap.get(node) || 0);
+        const diff = diffFlamegraphColor(nodeDeltaMap.get(node)?.color || 0);
         fill = diff === "transparent" ? defaultFlamegraphColor(node.name) : diff;
       } else {
         fill = defaultFlamegraphColor(node.name);
@@ -218,16 +222,20 @@ export function FlamegraphCanvas({
       const node = findNodeAt(mx, my, rect.width);
       if (node) {
         const pct = ((node.totalSamples / rootTotal) * 100).toFixed(2);
+        const shareDelta = nodeDeltaMap?.get(node)?.share;
+        const diffText =
+          shareDelta === undefined ? "" : `\n${shareDelta >= 0 ? "+" : ""}${(shareDelta * 100).toFixed(2)}% vs baseline`;
+        const locationText = node.location ? `\n${node.location}` : "";
         setTooltip({
           x: mx,
           y: my + heatmapGridHeight + axisHeight + 8,
-          text: `${node.name}\n${node.totalSamples} samples (${pct}%) | self: ${node.selfSamples}\n(click to zoom)`,
+          text: `${node.name}${locationText}\n${node.totalSamples} samples (${pct}%) | self: ${node.selfSamples}${diffText}\n(click to zoom)`,
         });
       } else {
         setTooltip(null);
       }
     },
-    [flamegraphRoot, findNodeAt, heatmapGridHeight, axisHeight, setTooltip],
+    [flamegraphRoot, findNodeAt, nodeDeltaMap, heatmapGridHeight, axisHeight, setTooltip],
   );
 
   const onClick = React.useCallback(
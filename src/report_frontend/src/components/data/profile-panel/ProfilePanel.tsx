@@ -19,6 +19,7 @@ import { useRegex, useContainerWidth, useHeatmapLayout, useHeatmapSelection } fr
 import { FlamegraphCanvas } from "./FlamegraphCanvas";
 import { HeatmapCanvas, HeatmapInfoBar } from "./Heatmap";
 import { TopFunctionsTable } from "./TopFunctionsTable";
+import { AnnotationsView } from "./AnnotationsView";
 import { buildKeyValueTable } from "../KeyValueTable";
 import { FrameType, FRAME_TYPE_COLORS, FRAME_TYPE_LABELS, getFrameType, getThemeColors } from "./colors";
 import {
@@ -52,9 +53,10 @@ export default function ProfilePanel({ dataType, profilerName, selectedProfile }
 
 /**
  * Reads viewMode from the provider and either renders the per-run heatmap+flamegraph
- * grid (for "flamegraph" / "top_functions") or a single full-width metadata table
+ * grid (for "flamegraph" / "top_functions"), the per-run annotated disassembly of the
+ * hottest functions (for "annotations"), or a single full-width metadata table
  * (for "metadata"). Sits inside the provider so the toolbar's segmented control
- * controls both branches.
+ * controls all branches.
  */
 function ProfilePanelBody({ dataType, profilerName, selectedProfile }: ProfilePanelProps) {
   const { viewMode } = useProfilePanelState();
@@ -72,6 +74,37 @@ function ProfilePanelBody({ dataType, profilerName, selectedProfile }: ProfilePa
       </div>
       {viewMode === "metadata" ? (
         <MetadataTable dataType={dataType} profilerName={profilerName} />
+      ) : viewMode === "annotations" ? (
+        <div style={{ display: "flex", width: "100%" }}>
+          {RUNS.map((runName) => {
+            const runData = PROCESSED_DATA[dataType]?.runs[runName] as ProfilingData | undefined;
+            const annotations = (runData?.profilers?.[profilerName]?.annotations || []).filter(
+              (annotatedFunction) => annotatedFunction.profile_type === selectedProfile,
+            );
+            return (
+              <div
+                key={runName}
+                style={{
+                  flex: `0 0 ${widthPercent}%`,
+                  minWidth: 0,
+                  overflow: "hidden",
+                  paddingTop: "10px",
+                  paddingRight: "30px",
+                  boxSizing: "border-box",
+                }}
+              >
+                <SpaceBetween size="xs">
+                  <RunHeader runName={runName} />
+                  {annotations.length > 0 ? (
+                    <AnnotationsView annotations={annotations} />
+                  ) : (
+                    <EmptyProfileState message="No annotated functions available for this profile." />
+                  )}
+                </SpaceBetween>
+              </div>
+            );
+          })}
+        </div>
       ) : (
         <div style={{ display: "flex", width: "100%" }}>
           {RUNS.map((runName, runIdx) => {
@@ -469,11 +502,12 @@ function ProfilePanelToolbar() {
           options={[
             { id: "flamegraph", text: "Flamegraph" },
             { id: "top_functions", text: "Top Functions" },
+            { id: "annotations", text: "Annotations" },
             { id: "metadata", text: "Metadata" },
           ]}
         />
       </SpaceBetween>
-      {viewMode !== "metadata" && <FrameTypeLegend />}
+      {(viewMode === "flamegraph" || viewMode === "top_functions") && <FrameTypeLegend />}
     </SpaceBetween>
   );
 }
@@ -1,6 +1,6 @@
 import React from "react";
 
-export type ViewMode = "flamegraph" | "top_functions" | "metadata";
+export type ViewMode = "flamegraph" | "top_functions" | "annotations" | "metadata";
 
 export interface ProfilePanelState {
   searchRegex: string;
@@ -1,5 +1,5 @@
 import React from "react";
-import { Profile } from "../../../definitions/types";
+import { Profile, SourceLocation } from "../../../definitions/types";
 import { getFrameType } from "./colors";
 
 // --- Block/sample aggregation ---
@@ -157,6 +157,13 @@ export function aggregateByStack(
   return byStack;
 }
 
+/** Total samples of a per-stack aggregation, i.e. the sum of self samples of all stacks */
+export function stackMapTotal(byStack: Map<string, { self: number; total: number }>): number {
+  let sum = 0;
+  for (const stats of byStack.values()) sum += stats.self;
+  return sum;
+}
+
 /** Get the full stack path for a FlamegraphNode by walking up parents */
 export function getNodeStackPath(node: FlamegraphNode): string {
   const parts: string[] = [];
@@ -171,6 +178,8 @@ export function getNodeStackPath(node: FlamegraphNode): string {
 export interface FlamegraphNode {
   frameId: number;
   name: string;
+  /** The "file:line" source location of the frame, if known. */
+  location?: string;
   selfSamples: number;
   totalSamples: number;
   children: FlamegraphNode[];
@@ -180,6 +189,11 @@ export interface FlamegraphNode {
   w: number;
 }
 
+/** Format the source location of a frame as "file:line". */
+function formatLocation(location: SourceLocation | undefined): string | undefined {
+  return location && `${location.file}:${location.line}`;
+}
+
 /** Build a flamegraph tree. If reverse, build inverted tree (leaf → root callers). */
 export function buildFlamegraph(
   analytics: Profile,
@@ -240,6 +254,7 @@ function buildForwardFlamegraph(
     const result: FlamegraphNode = {
       frameId: node.frame_id,
       name,
+      location: formatLocation(frames[node.frame_id]?.location),
       selfSamples: self_,
       totalSamples: total,
       children: [],
@@ -271,14 +286,14 @@ function buildReverseFlamegraph(
   const tree = analytics.context_tree;
   const frames = analytics.frame_map.frame_id_to_frame;
 
-  const stacks: { path: string[]; weight: number }[] = [];
+  const stacks: { path: number[]; weight: number }[] = [];
   for (let nid = 0; nid < tree.length; nid++) {
     const w = nodeSelf.get(nid) || 0;
     if (w === 0 || !stackMatched[nid]) continue;
-    const path: string[] = [];
+    const path: number[] = [];
     let cur: number | null = nid;
     while (cur !== null && cur !== 0) {
-      path.push(frames[tree[cur].frame_id]?.name || "[unknown]");
+      path.push(tree[cur].frame_id);
       cur = tree[cur].parent;
     }
     stacks.push({ path, weight: w });
@@ -287,6 +302,7 @@ function buildReverseFlamegraph(
 
   interface MutNode {
     name: string;
+    location?: string;
     self: number;
     total: number;
     children: Map<string, MutNode>;
@@ -295,10 +311,12 @@ function buildReverseFlamegraph(
   for (const { path, weight } of stacks) {
     root.total += weight;
     let cur = root;
-    for (const frame of path) {
+    for (const frameId of path) {
+      const frame = frames[frameId]?.name || "[unknown]";
       let child = cur.children.get(frame);
       if (!child) {
-        child = { name: frame, self: 0, total: 0, children: new Map() };
+        const location = formatLocation(frames[frameId]?.location);
+        child = { name: frame, location, self: 0, total: 0, children: new Map() };
         cur.children.set(frame, child);
       }
       child.total += weight;
@@ -312,6 +330,7 @@ function buildReverseFlamegraph(
     const result: FlamegraphNode = {
       frameId: nextFrameId++,
       name: n.name,
+      location: n.location,
       selfSamples: n.self,
       totalSamples: n.total,
       children: [],
@@ -16,10 +16,12 @@ declare let processed_memalloc_data;
 declare let processed_netstat_data;
 declare let processed_ena_stat_data;
 declare let processed_efa_stat_data;
+declare let processed_jvm_stat_data;
 declare let processed_numastat_data;
 declare let processed_perf_profile_data;
 declare let processed_aperf_stats_data;
 declare let processed_java_profile_data;
+declare let processed_ext_profile_data;
 declare let processed_aperf_runlog_data;
 declare let processed_hotline_data;
 declare let systeminfo_findings;
@@ -36,10 +38,12 @@ declare let memalloc_findings;
 declare let netstat_findings;
 declare let ena_stat_findings;
 declare let efa_stat_findings;
+declare let jvm_stat_findings;
 declare let numastat_findings;
 declare let perf_profile_findings;
 declare let aperf_stats_findings;
 declare let java_profile_findings;
+declare let ext_profile_findings;
 declare let aperf_runlog_findings;
 declare let hotline_findings;
 
@@ -56,11 +60,13 @@ export const PROCESSED_DATA: { [key in DataType]: ReportData } = {
   netstat: processed_netstat_data,
   ena_stat: processed_ena_stat_data,
   efa_stat: processed_efa_stat_data,
+  jvm_stat: processed_jvm_stat_data,
   numastat: processed_numastat_data,
   kernel_config: processed_kernel_config_data,
   sysctl: processed_sysctl_data,
   perf_profile: processed_perf_profile_data,
   java_profile: processed_java_profile_data,
+  ext_profile: processed_ext_profile_data,
   hotline: processed_hotline_data,
   aperf_runlog: processed_aperf_runlog_data,
   aperf_stats: processed_aperf_stats_data,
@@ -79,11 +85,13 @@ export const PER_DATA_ANALYTICAL_FINDINGS: { [key in DataType]: DataFindings } =
   netstat: netstat_findings,
   ena_stat: ena_stat_findings,
   efa_stat: efa_stat_findings,
+  jvm_stat: jvm_stat_findings,
   numastat: numastat_findings,
   kernel_config: kernel_config_findings,
   sysctl: sysctl_findings,
   perf_profile: perf_profile_findings,
   java_profile: java_profile_findings,
+  ext_profile: ext_profile_findings,
   hotline: hotline_findings,
   aperf_runlog: aperf_runlog_findings,
   aperf_stats: aperf_stats_findings,
@@ -122,6 +130,7 @@ export const NAVIGATION_SECTIONS: NavigationSection[] = [
       "interrupts",
       "diskstats",
       "processes",
+      "jvm_stat",
       {
         sectionName: "Memory Data",
         items: ["meminfo", "memalloc", "vmstat", "numastat"],
@@ -141,6 +150,7 @@ export const NAVIGATION_SECTIONS: NavigationSection[] = [
     items: [
       "perf_profile",
       "java_profile",
+      "ext_profile",
       "hotline",
     ],
   },
@@ -4616,6 +4616,158 @@ export const DATA_DESCRIPTIONS: { [key in DataType]: DataDescription } = {
       },
     },
   },
+  jvm_stat: {
+    readableName: "JVM Stats",
+    summary:
+      "JVM stats metrics measure the runtime activities of every JVM on the system, such as garbage collection, heap and metaspace usage, safepoints, class loading, JIT compilation and threads. The data is read from the hsperfdata performance counters that HotSpot JVMs publish in /tmp/hsperfdata_<user>/<pid> (the same counters used by jstat), so no agent or JDK tools are needed. Every series is a JVM named after its main class or JAR file and its PID, and the aggregate is the JVM with the highest average. JVMs started with -XX:-UsePerfData are not included.",
+    defaultUnit: "Count",
+    defaultHelpfulLinks: ["https://docs.oracle.com/en/java/javase/21/docs/specs/man/jstat.html"],
+    fieldDescriptions: {
+      gc_time_percent: {
+        readableName: "GC Time Share",
+        description:
+          "The percentage of time that the JVM spent in garbage collection pauses. Application threads cannot make progress during the pauses, so a high GC time share directly reduces the throughput and increases the latency.",
+        unit: "GC Time (%)",
+        desired: "lower",
+      },
+      gc_count: {
+        readableName: "GC Count",
+        description: "The number of garbage collections per second, by all collectors of the JVM.",
+        unit: "Count",
+        desired: "lower",
+      },
+      gc_time: {
+        readableName: "GC Time",
+        description: "The time spent in garbage collection pauses per second, by all collectors of the JVM.",
+        unit: "milliseconds",
+        desired: "lower",
+      },
+      full_gc_count: {
+        readableName: "Full GC Count",
+        description:
+          "The number of collections per second by the old generation (full) collector of the JVM, such as PSParallelCompact or G1 full collections. Full collections stop the application for much longer than young collections.",
+        unit: "Count",
+        desired: "lower",
+      },
+      full_gc_time: {
+        readableName: "Full GC Time",
+        description: "The time spent in the pauses of the old generation (full) collector per second.",
+        unit: "milliseconds",
+        desired: "lower",
+      },
+      heap_used: {
+        readableName: "Heap Used",
+        description: "The memory used by the objects in the young and old generations of the heap.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      heap_committed: {
+        readableName: "Heap Committed",
+        description: "The memory of the heap committed by the JVM, which is reserved from the system.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      young_gen_used: {
+        readableName: "Young Generation Used",
+        description: "The memory used by the objects in the young generation (eden and survivor spaces).",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      old_gen_used: {
+        readableName: "Old Generation Used",
+        description: "The memory used by the objects in the old generation, which survived multiple collections.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      old_gen_used_percent: {
+        readableName: "Old Generation Used Percentage",
+        description:
+          "The memory used in the old generation as a percentage of its maximum capacity. When it stays close to 100%, the JVM runs frequent full collections that reclaim little memory, and eventually throws OutOfMemoryError.",
+        unit: "Used (%)",
+        desired: "lower",
+        optimization: [MEMORY_USAGE_INVESTIGATION],
+      },
+      old_gen_max_capacity: {
+        readableName: "Old Generation Max Capacity",
+        description: "The maximum memory that the old generation can grow to.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      metaspace_used: {
+        readableName: "Metaspace Used",
+        description: "The native memory used by the class metadata. Steady growth indicates a class loader leak.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      metaspace_committed: {
+        readableName: "Metaspace Committed",
+        description: "The native memory committed for the class metadata.",
+        unit: "Bytes",
+        desired: "depends",
+      },
+      safepoint_count: {
+        readableName: "Safepoint Count",
+        description:
+          "The number of safepoints per second, where all application threads are stopped for VM operations such as garbage collections, deoptimizations and biased lock revocations.",
+        unit: "Count",
+        desired: "lower",
+      },
+      safepoint_time: {
+        readableName: "Safepoint Time",
+        description: "The time that the application threads were stopped at safepoints per second.",
+        unit: "milliseconds",
+        desired: "lower",
+      },
+      safepoint_sync_time: {
+        readableName: "Safepoint Sync Time",
+        description:
+          "The time spent per second to bring all application threads to safepoints. A high sync time indicates long-running loops without safepoint polls.",
+        unit: "milliseconds",
+        desired: "lower",
+      },
+      loaded_classes: {
+        readableName: "Loaded Classes",
+        description: "The number of classes currently loaded by the JVM.",
+        desired: "depends",
+      },
+      class_loads: {
+        readableName: "Class Loads",
+        description: "The number of classes loaded per second.",
+        unit: "Count",
+        desired: "depends",
+      },
+      jit_compiles: {
+        readableName: "JIT Compilations",
+        description: "The number of methods compiled by the JIT compilers per second.",
+        unit: "Count",
+        desired: "depends",
+      },
+      jit_compile_time: {
+        readableName: "JIT Compilation Time",
+        description:
+          "The time spent by the JIT compiler threads per second. It is usually high during the warm-up of the application.",
+        unit: "milliseconds",
+        desired: "depends",
+      },
+      live_threads: {
+        readableName: "Live Threads",
+        description: "The number of live Java threads, including daemon threads.",
+        desired: "depends",
+      },
+      daemon_threads: {
+        readableName: "Daemon Threads",
+        description: "The number of live Java daemon threads.",
+        desired: "depends",
+      },
+      thread_starts: {
+        readableName: "Thread Starts",
+        description:
+          "The number of Java threads started per second. A high rate indicates that threads are created for short tasks instead of using a thread pool.",
+        unit: "Count",
+        desired: "lower",
+      },
+    },
+  },
   numastat: {
     readableName: "NUMA Stats",
     summary:
@@ -4682,9 +4834,38 @@ export const DATA_DESCRIPTIONS: { [key in DataType]: DataDescription } = {
   },
   perf_profile: {
     readableName: "Perf Profiling",
-    summary: "Perf profiling is system-wide CPU profiling performed through Linux's Perf tool.",
+    summary:
+      "Perf profiling is system-wide CPU profiling performed through Linux's Perf tool. Besides the profile of the whole system (cpu), the busiest processes have their own profiles, whose stacks are rooted at the frames of their threads, and the busiest CPUs have a profile of the samples taken on them. The busiest JVMs have mixed-mode profiles (java: <name> [<pid>]), which show the native and kernel frames under the Java methods that called them, with the Java frames taken from the perf map of the JVM or, when it is also profiled with --profile-java, from the async-profiler samples. The PMU events sampled with --profile-events, such as cache-misses, have their own profile types.",
     defaultHelpfulLinks: ["https://perfwiki.github.io/main/"],
-    fieldDescriptions: {},
+    fieldDescriptions: {
+      cpu: {
+        readableName: "CPU Profiling",
+        description: "",
+      },
+      offcpu: {
+        readableName: "Off-CPU Profiling",
+        description:
+          "Time (in microseconds) that threads spent off-CPU, attributed to the stack at which they were switched out. The thread states separate time spent runnable (waiting for a CPU), sleeping (e.g. futex or epoll waits), and in uninterruptible sleep (typically blocking I/O).",
+      },
+      "cache-misses": {
+        readableName: "Cache Miss Sampling",
+        description:
+          "Number of cache misses (typically of the last level cache) attributed to the stacks of the sampled occurrences.",
+      },
+      "LLC-load-misses": {
+        readableName: "LLC Load Miss Sampling",
+        description: "Number of last level cache load misses attributed to the stacks of the sampled occurrences.",
+      },
+      "branch-misses": {
+        readableName: "Branch Miss Sampling",
+        description: "Number of mispredicted branches attributed to the stacks of the sampled occurrences.",
+      },
+      "stalled-cycles-backend": {
+        readableName: "Backend Stall Sampling",
+        description:
+          "Number of cycles stalled in the backend (e.g. waiting for memory) attributed to the stacks of the sampled occurrences.",
+      },
+    },
   },
   // TODO: Move content of profiling analytical findings to help panel
   java_profile: {
@@ -4704,12 +4885,37 @@ export const DATA_DESCRIPTIONS: { [key in DataType]: DataDescription } = {
         readableName: "CPU Utilization Profiling",
         description: "",
       },
+      lock: {
+        readableName: "Lock Contention Profiling",
+        description:
+          "Time (in nanoseconds) that threads waited for contended Java monitors and native mutexes, attributed to the stacks where they waited and the classes of the locks.",
+      },
+      nativemem: {
+        readableName: "Native Memory Profiling",
+        description: "Bytes allocated through malloc, attributed to the allocating stacks.",
+      },
+      live: {
+        readableName: "Live Object Profiling",
+        description:
+          "Bytes of the sampled heap allocations that were still alive at the end of profiling, attributed to the allocating stacks and the classes of the objects. It helps to find memory leaks.",
+      },
       legacy: {
         readableName: "Flamegraphs (legacy)",
         description: "",
       },
     },
   },
+  ext_profile: {
+    readableName: "External Profiling",
+    summary:
+      "External profiling shows the profiles of the external profilers run along with the recording through the --profile-ext option, such as py-spy, Go pprof endpoints or Node.js profilers. Each profiler is ingested from folded stacks, pprof or speedscope, and its samples cover the whole recording period.",
+    fieldDescriptions: {
+      cpu: {
+        readableName: "CPU Profiling",
+        description: "",
+      },
+    },
+  },
   hotline: {
     readableName: "Hotline",
     summary:
@@ -4846,6 +5052,11 @@ export const DATA_DESCRIPTIONS: { [key in DataType]: DataDescription } = {
         description: "The total time in us for APerf to collect the EFA stats data during one interval.",
         desired: "lower",
       },
+      jvm_stat: {
+        readableName: "JVM stats collection time",
+        description: "The total time in us for APerf to collect the JVM stats data during one interval.",
+        desired: "lower",
+      },
       numastat: {
         readableName: "NUMA stats collection time",
         description: "The total time in us for APerf to collect the NUMA stats data during one interval.",
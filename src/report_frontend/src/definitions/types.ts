@@ -11,11 +11,13 @@ export const ALL_DATA_TYPES = [
   "netstat",
   "ena_stat",
   "efa_stat",
+  "jvm_stat",
   "numastat",
   "kernel_config",
   "sysctl",
   "perf_profile",
   "java_profile",
+  "ext_profile",
   "hotline",
   "aperf_runlog",
   "aperf_stats",
@@ -73,6 +75,21 @@ export interface Profiler {
   readonly block_width_ms: number;
   readonly metadata: KeyValueData;
   readonly profiles: { [key in string]: Profile };
+  readonly annotations?: AnnotatedFunction[];
+}
+
+export interface AnnotatedFunction {
+  readonly profile_type: string;
+  readonly name: string;
+  readonly source: string;
+  readonly samples: number;
+  readonly instructions: AnnotatedInstruction[];
+}
+
+export interface AnnotatedInstruction {
+  readonly address: number;
+  readonly instruction: string;
+  readonly samples: number;
 }
 
 export interface Profile {
@@ -101,7 +118,12 @@ export interface CCTreeNode {
 }
 
 export interface FrameMap {
-  readonly frame_id_to_frame: { name: string; node_ids: number[] }[];
+  readonly frame_id_to_frame: { name: string; node_ids: number[]; location?: SourceLocation }[];
+}
+
+export interface SourceLocation {
+  readonly file: string;
+  readonly line: number;
 }
 
 export interface GraphInfo {
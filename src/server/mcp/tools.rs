@@ -5,13 +5,17 @@ use rmcp::{
     model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
     schemars, tool, tool_handler, tool_router, ErrorData as McpError, ServerHandler,
 };
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::data::common::data_formats::{AperfData, DataFormat, ProcessedData};
+use crate::data::common::data_formats::{AperfData, DataFormat, ProcessedData, Profiler};
 use crate::data::common::processed_data_accessor::ProcessedDataAccessor;
+use crate::data::perf_profile::SYSTEM_PROFILER_KEY;
+use crate::profiling::export::write_flamegraph_svg;
+use crate::profiling::transform::ProfileTransforms;
 
 use super::report::{self, LoadedReport};
 
@@ -133,6 +137,26 @@ pub struct GetFlamegraphRequest {
         description = "Regex filter for function names (case-insensitive). Only functions matching this pattern are returned. Example: 'compact|migrate' to find compaction-related functions."
     )]
     pub filter: Option<String>,
+    #[schemars(
+        description = "Profiler of the Perf profile to query instead of the whole system: a busy process (e.g. 'process: nginx [1234]') or a CPU (e.g. 'CPU 3'). Process flamegraphs are rooted at the thread frames. If omitted, the flamegraph of the whole system is used."
+    )]
+    pub profiler: Option<String>,
+    #[schemars(
+        description = "Regexes of the frames to drop from the stacks before building the flamegraph, attaching their callees to their callers (e.g. ['__libc_start_main', 'std::rt']). Useful to skip boilerplate frames."
+    )]
+    pub drop_frames: Option<Vec<String>>,
+    #[schemars(
+        description = "Collapse the recursive calls of a function into a single frame (default: false)."
+    )]
+    pub collapse_recursion: Option<bool>,
+    #[schemars(
+        description = "Fold the consecutive frames of the same module (Java package, C++ namespace, Rust crate or the kernel) into a single frame named after the module (default: false)."
+    )]
+    pub fold_modules: Option<bool>,
+    #[schemars(
+        description = "Frames to keep: 'all' (default), 'user' (userspace frames only) or 'kernel' (kernel frames only)."
+    )]
+    pub view: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -936,6 +960,24 @@ impl AperfMcpServer {
 
         let limit = req.limit.unwrap_or(30);
 
+        let transforms = match ProfileTransforms::new(
+            req.view.as_deref().unwrap_or("all"),
+            req.drop_frames.as_deref().unwrap_or_default(),
+            req.fold_modules.unwrap_or(false),
+            req.collapse_recursion.unwrap_or(false),
+        ) {
+            Ok(transforms) => transforms,
+            Err(e) => {
+                return ok_json(json!({"status": "error", "message": format!("Error: {}", e)}))
+            }
+        };
+        // The stacks can only be transformed in the profile, so use the profile of the whole
+        // system instead of the flamegraph SVGs.
+        let profiler = req
+            .profiler
+            .clone()
+            .or_else(|| (!transforms.is_empty()).then(|| SYSTEM_PROFILER_KEY.to_string()));
+
         // Compile regex filter if provided
         let filter_re = if let Some(ref pattern) = req.filter {
             match Regex::new(&format!("(?i){}", pattern)) {
@@ -957,7 +999,10 @@ impl AperfMcpServer {
             // Resolve the two run IDs for diff. When omitted, default to the first two runs that
             // have flamegraph SVGs (passing None to find_flamegraph_svg would resolve both sides
             // to the same first-alphabetical SVG).
-            let default_ids = find_all_flamegraph_run_ids(loaded, svg_type);
+            let default_ids = match &profiler {
+                Some(_) => loaded.metadata.run_ids.clone().unwrap_or_default(),
+                None => find_all_flamegraph_run_ids(loaded, svg_type),
+            };
             let (rid1, rid2) = match &req.run_id {
                 Some(ids) if ids.len() == 2 => (Some(ids[0].as_str()), Some(ids[1].as_str())),
                 Some(ids) if ids.len() > 2 => {
@@ -980,40 +1025,78 @@ impl AperfMcpServer {
                 }
             };
 
-            // Find both SVGs
-            let svg1 = find_flamegraph_svg(loaded, rid1, svg_type);
-            let svg1 = match svg1 {
-                Ok(p) => p,
-                Err(msg) => {
+            let (frames1, frames2) = if let Some(profiler) = &profiler {
+                if rid1 == rid2 {
                     return ok_json(
-                        json!({"status": "error", "message": format!("Base run: {}", msg)}),
-                    )
+                        json!({"status": "error", "message": "Error: Both run IDs are the same. Provide two different run IDs in the run_id array."}),
+                    );
                 }
-            };
-            let svg2 = find_flamegraph_svg(loaded, rid2, svg_type);
-            let svg2 = match svg2 {
-                Ok(p) => p,
-                Err(msg) => {
+                let frames1 = match profiler_flamegraph_frames(
+                    loaded,
+                    rid1.unwrap(),
+                    profiler,
+                    svg_type,
+                    &transforms,
+                ) {
+                    Ok(f) => f,
+                    Err(msg) => {
+                        return ok_json(
+                            json!({"status": "error", "message": format!("Base run: {}", msg)}),
+                        )
+                    }
+                };
+                let frames2 = match profiler_flamegraph_frames(
+                    loaded,
+                    rid2.unwrap(),
+                    profiler,
+                    svg_type,
+                    &transforms,
+                ) {
+                    Ok(f) => f,
+                    Err(msg) => {
+                        return ok_json(
+                            json!({"status": "error", "message": format!("Comparison run: {}", msg)}),
+                        )
+                    }
+                };
+                (frames1, frames2)
+            } else {
+                // Find both SVGs
+                let svg1 = find_flamegraph_svg(loaded, rid1, svg_type);
+                let svg1 = match svg1 {
+                    Ok(p) => p,
+                    Err(msg) => {
+                        return ok_json(
+                            json!({"status": "error", "message": format!("Base run: {}", msg)}),
+                        )
+                    }
+                };
+                let svg2 = find_flamegraph_svg(loaded, rid2, svg_type);
+                let svg2 = match svg2 {
+                    Ok(p) => p,
+                    Err(msg) => {
+                        return ok_json(
+                            json!({"status": "error", "message": format!("Comparison run: {}", msg)}),
+                        )
+                    }
+                };
+
+                if svg1 == svg2 {
                     return ok_json(
-                        json!({"status": "error", "message": format!("Comparison run: {}", msg)}),
-                    )
+                        json!({"status": "error", "message": "Error: Both run IDs resolve to the same flamegraph. Provide two different run IDs in the run_id array."}),
+                    );
                 }
-            };
-
-            if svg1 == svg2 {
-                return ok_json(
-                    json!({"status": "error", "message": "Error: Both run IDs resolve to the same flamegraph. Provide two different run IDs in the run_id array."}),
-                );
-            }
 
-            // Parse both
-            let frames1 = match parse_flamegraph_svg(&svg1) {
-                Ok(f) => f,
-                Err(msg) => return ok_json(json!({"status": "error", "message": msg})),
-            };
-            let frames2 = match parse_flamegraph_svg(&svg2) {
-                Ok(f) => f,
-                Err(msg) => return ok_json(json!({"status": "error", "message": msg})),
+                // Parse both
+                let frames1 = match parse_flamegraph_svg(&svg1) {
+                    Ok(f) => f,
+                    Err(msg) => return ok_json(json!({"status": "error", "message": msg})),
+                };
+                let frames2 = match parse_flamegraph_svg(&svg2) {
+                    Ok(f) => f,
+                    Err(msg) => return ok_json(json!({"status": "error", "message": msg})),
+                };
+                (frames1, frames2)
             };
 
             // Build function → pct maps
@@ -1088,8 +1171,9 @@ impl AperfMcpServer {
         let min_pct = req.min_pct.unwrap_or(0.1);
 
         // Determine which runs to process
-        let run_ids: Vec<String> = match &req.run_id {
-            Some(ids) if !ids.is_empty() => ids.clone(),
+        let run_ids: Vec<String> = match (&req.run_id, &profiler) {
+            (Some(ids), _) if !ids.is_empty() => ids.clone(),
+            (_, Some(_)) => loaded.metadata.run_ids.clone().unwrap_or_default(),
             _ => find_all_flamegraph_run_ids(loaded, fg_type),
         };
 
@@ -1102,16 +1186,21 @@ impl AperfMcpServer {
         let mut all_run_results: Vec<Value> = Vec::new();
 
         for rid in &run_ids {
-            let svg_path = find_flamegraph_svg(loaded, Some(rid.as_str()), fg_type);
-            let svg_path = match svg_path {
-                Ok(p) => p,
-                Err(msg) => {
-                    log::warn!("Skipping run '{}': {}", rid, msg);
-                    continue;
-                }
+            let frames = if let Some(profiler) = &profiler {
+                profiler_flamegraph_frames(loaded, rid, profiler, fg_type, &transforms)
+            } else {
+                let svg_path = find_flamegraph_svg(loaded, Some(rid.as_str()), fg_type);
+                let svg_path = match svg_path {
+                    Ok(p) => p,
+                    Err(msg) => {
+                        log::warn!("Skipping run '{}': {}", rid, msg);
+                        continue;
+                    }
+                };
+                parse_flamegraph_svg(&svg_path)
             };
 
-            let frames = match parse_flamegraph_svg(&svg_path) {
+            let frames = match frames {
                 Ok(f) => f,
                 Err(msg) => {
                     all_run_results.push(json!({
@@ -1612,11 +1701,72 @@ fn find_all_flamegraph_run_ids(loaded: &LoadedReport, fg_type: &str) -> Vec<Stri
 /// Parse a flamegraph SVG and extract all frames.
 fn parse_flamegraph_svg(path: &std::path::Path) -> Result<Vec<FlamegraphFrame>, String> {
     let content = std::fs::read_to_string(path).map_err(|e| format!("Error reading SVG: {}", e))?;
+    parse_flamegraph_svg_content(&content, &path.display().to_string())
+}
 
+/// Render the flamegraph of a profiler in the Perf profile of a run, such as the one of a
+/// process or a CPU, and parse its frames.
+fn profiler_flamegraph_frames(
+    loaded: &LoadedReport,
+    run_id: &str,
+    profiler: &str,
+    fg_type: &str,
+    transforms: &ProfileTransforms,
+) -> Result<Vec<FlamegraphFrame>, String> {
+    let path = loaded.data_dir.join("perf_profile.js");
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| format!("Error reading file: {}", e))?;
+    let json_str = super::js_parser::extract_json_from_js(&content)
+        .ok_or_else(|| format!("Error: No data found in {}", path.display()))?;
+    // The integer keys in the maps of a Profile cannot be deserialized through the untagged
+    // AperfData, so only deserialize the Profiler.
+    let processed_data: Value = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Error deserializing ProcessedData: {}", e))?;
+    let not_found_message = || {
+        format!(
+            "Error: No CPU profile of profiler '{}' found for run '{}'",
+            profiler, run_id
+        )
+    };
+    let profiler_data = processed_data
+        .get("runs")
+        .and_then(|runs| runs.get(run_id))
+        .and_then(|run| run.get("profilers"))
+        .and_then(|profilers| profilers.get(profiler))
+        .ok_or_else(not_found_message)?;
+    let profiler_data = Profiler::deserialize(profiler_data)
+        .map_err(|e| format!("Error deserializing Profiler: {}", e))?;
+    let profile = profiler_data
+        .profiles
+        .get("cpu")
+        .ok_or_else(not_found_message)?;
+    let transformed_profile;
+    let profile = if transforms.is_empty() {
+        profile
+    } else {
+        transformed_profile = profile
+            .transformed(transforms)
+            .map_err(|e| format!("Error transforming the profile: {}", e))?;
+        &transformed_profile
+    };
+    let mut svg = Vec::new();
+    write_flamegraph_svg(profile, fg_type == "reverse", &mut svg)
+        .map_err(|e| format!("Error rendering flamegraph: {}", e))?;
+    parse_flamegraph_svg_content(
+        &String::from_utf8_lossy(&svg),
+        &format!("profiler '{}' of run '{}'", profiler, run_id),
+    )
+}
+
+/// Parse the frames of a flamegraph SVG, where source describes the SVG in error messages.
+fn parse_flamegraph_svg_content(
+    content: &str,
+    source: &str,
+) -> Result<Vec<FlamegraphFrame>, String> {
     // Extract total_samples from: <svg id="frames" ... total_samples="N">
     let total_samples_re = Regex::new(r#"total_samples="(\d+)""#).unwrap();
     let total_samples: u64 = total_samples_re
-        .captures(&content)
+        .captures(content)
         .and_then(|c| c.get(1))
         .and_then(|m| m.as_str().parse().ok())
         .unwrap_or(0);
@@ -1634,7 +1784,7 @@ fn parse_flamegraph_svg(path: &std::path::Path) -> Result<Vec<FlamegraphFrame>,
 
     let mut frames: Vec<FlamegraphFrame> = Vec::new();
 
-    for cap in frame_re.captures_iter(&content) {
+    for cap in frame_re.captures_iter(content) {
         let name = cap[1].to_string();
         let samples: u64 = cap[2].replace(',', "").parse().unwrap_or(0);
         let pct: f64 = cap[3].parse().unwrap_or(0.0);
@@ -1658,10 +1808,7 @@ fn parse_flamegraph_svg(path: &std::path::Path) -> Result<Vec<FlamegraphFrame>,
     }
 
     if frames.is_empty() {
-        return Err(format!(
-            "Error: No frames found in SVG at {}",
-            path.display()
-        ));
+        return Err(format!("Error: No frames found in SVG at {}", source));
     }
 
     Ok(frames)
@@ -2761,6 +2908,95 @@ mod tests {
         assert!(top.pct > 10.0);
     }
 
+    #[test]
+    fn test_profiler_flamegraph_frames() {
+        use crate::data::common::data_formats::ProfilingData;
+        use crate::profiling::ThreadState;
+
+        let mut profiler = Profiler::new(0);
+        for (frames, count) in [
+            (["worker [101]", "main", "compute"], 3),
+            (["worker [101]", "main", "read"], 1),
+        ] {
+            let frames: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+            profiler.insert_stack("cpu", 0, ThreadState::None, &frames, count);
+        }
+        let mut profiling_data = ProfilingData::default();
+        profiling_data
+            .profilers
+            .insert("process: server [100]".to_string(), profiler);
+        let mut processed_data = ProcessedData::new("perf_profile".to_string());
+        processed_data
+            .runs
+            .insert("run1".to_string(), AperfData::Profile(profiling_data));
+
+        let data_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            data_dir.path().join("perf_profile.js"),
+            format!(
+                "processed_perf_profile_data = {};",
+                serde_json::to_string(&processed_data).unwrap()
+            ),
+        )
+        .unwrap();
+        let loaded = LoadedReport {
+            report_path: String::new(),
+            data_dir: data_dir.path().to_path_buf(),
+            metadata: Default::default(),
+            metrics: HashMap::new(),
+        };
+
+        let no_transforms = ProfileTransforms::default();
+        let frames = profiler_flamegraph_frames(
+            &loaded,
+            "run1",
+            "process: server [100]",
+            "normal",
+            &no_transforms,
+        )
+        .unwrap();
+        let compute = frames.iter().find(|f| f.name == "compute").unwrap();
+        assert_eq!(compute.samples, 3);
+        assert_eq!(compute.pct, 75.0);
+        assert!(frames.iter().any(|f| f.name == "worker [101]"));
+
+        assert!(
+            profiler_flamegraph_frames(&loaded, "run1", "CPU 0", "normal", &no_transforms).is_err()
+        );
+        assert!(profiler_flamegraph_frames(
+            &loaded,
+            "run2",
+            "process: server [100]",
+            "normal",
+            &no_transforms
+        )
+        .is_err());
+
+        // The thread and main frames are dropped from the transformed profile
+        let transforms = ProfileTransforms::new(
+            "all",
+            &[r"\[\d+\]$".to_string(), "^main$".to_string()],
+            false,
+            false,
+        )
+        .unwrap();
+        let frames = profiler_flamegraph_frames(
+            &loaded,
+            "run1",
+            "process: server [100]",
+            "normal",
+            &transforms,
+        )
+        .unwrap();
+        assert!(!frames
+            .iter()
+            .any(|f| f.name == "worker [101]" || f.name == "main"));
+        assert_eq!(
+            frames.iter().find(|f| f.name == "compute").unwrap().pct,
+            75.0
+        );
+    }
+
     #[test]
     fn test_svg_matches_run_and_type() {
         // Reports name graphs `<run_id>-flamegraph.svg` / `<run_id>-reverse-flamegraph.svg`.
@@ -3,6 +3,7 @@ pub mod test_key_value_key_expected_rule;
 pub mod test_key_value_key_run_comparison_rule;
 pub mod test_profile_metadata_comparison_rule;
 pub mod test_profile_metadata_expected_rule;
+pub mod test_profile_stack_frame_run_comparison_rule;
 pub mod test_profile_stack_frame_threshold_rule;
 pub mod test_time_series_data_point_threshold_rule;
 pub mod test_time_series_stat_intra_run_comparison_rule;
@@ -0,0 +1,114 @@
+use aperf::analytics::profile_stack_frame_run_comparison_rule::ProfileStackFrameRunComparisonRule;
+use aperf::analytics::{Analyze, DataFindings, Score, BASE_RUN_NAME};
+use aperf::data::common::data_formats::{AperfData, Profiler, ProfilingData};
+use aperf::data::common::processed_data_accessor::ProcessedDataAccessor;
+use aperf::profiling::ThreadState;
+use std::collections::HashMap;
+
+use super::test_helpers::{create_processed_data, DataFindingsExt};
+
+fn set_base_run(name: &str) {
+    *BASE_RUN_NAME.lock().unwrap() = name.to_string();
+}
+
+fn create_profiler_data(stacks: &[(&[&str], u64)]) -> AperfData {
+    let mut profiler = Profiler::new(0);
+    for (frames, count) in stacks {
+        let frames: Vec<String> = frames.iter().map(|frame| frame.to_string()).collect();
+        profiler.insert_stack("cpu", 0, ThreadState::AsyncDefault, &frames, *count);
+    }
+    let mut profilers = HashMap::new();
+    profilers.insert("profile_0".to_string(), profiler);
+    AperfData::Profile(ProfilingData { profilers })
+}
+
+fn create_rule(threshold: f64) -> ProfileStackFrameRunComparisonRule {
+    ProfileStackFrameRunComparisonRule {
+        rule_name: "test_rule",
+        profile_type: "cpu",
+        profiler_key: None,
+        thread_states: &[ThreadState::AsyncDefault],
+        total_samples: false,
+        threshold,
+        score: Score::Bad.as_f64(),
+        message: "Test message",
+    }
+}
+
+/// Base:    main;parse 20, main;compute 80
+/// Current: main;parse 120, main;compute 180 (parse grew from 20% to 40%)
+fn create_runs() -> Vec<(&'static str, AperfData)> {
+    vec![
+        (
+            "run1",
+            create_profiler_data(&[(&["main", "parse"], 20), (&["main", "compute"], 80)]),
+        ),
+        (
+            "run2",
+            create_profiler_data(&[(&["main", "parse"], 120), (&["main", "compute"], 180)]),
+        ),
+    ]
+}
+
+#[test]
+fn test_growth_below_threshold() {
+    set_base_run("run1");
+    let mut processed_data = create_processed_data("test_data", create_runs());
+
+    let mut findings = DataFindings::default();
+    create_rule(25.0).analyze(
+        &mut findings,
+        &mut processed_data,
+        &mut ProcessedDataAccessor::new(),
+    );
+    assert_eq!(findings.num_runs_with_findings(), 0);
+}
+
+#[test]
+fn test_growth_above_threshold() {
+    set_base_run("run1");
+    let mut processed_data = create_processed_data("test_data", create_runs());
+
+    let mut findings = DataFindings::default();
+    create_rule(10.0).analyze(
+        &mut findings,
+        &mut processed_data,
+        &mut ProcessedDataAccessor::new(),
+    );
+    // Only parse grew, while compute shrank and main has no self samples.
+    assert_eq!(findings.num_runs_with_findings(), 1);
+    assert!(findings.has_findings_for_run("run2"));
+    assert!(findings.has_findings_for_metric("run2", "profile_0"));
+}
+
+#[test]
+fn test_missing_profile_in_run() {
+    set_base_run("run1");
+    let mut runs = create_runs();
+    runs[1].1 = AperfData::Profile(ProfilingData::default());
+    let mut processed_data = create_processed_data("test_data", runs);
+
+    let mut findings = DataFindings::default();
+    create_rule(10.0).analyze(
+        &mut findings,
+        &mut processed_data,
+        &mut ProcessedDataAccessor::new(),
+    );
+    assert_eq!(findings.num_runs_with_findings(), 0);
+}
+
+#[test]
+fn test_growth_in_other_profiler() {
+    set_base_run("run1");
+    let mut processed_data = create_processed_data("test_data", create_runs());
+
+    let mut findings = DataFindings::default();
+    let mut rule = create_rule(10.0);
+    rule.profiler_key = Some("profile_1");
+    rule.analyze(
+        &mut findings,
+        &mut processed_data,
+        &mut ProcessedDataAccessor::new(),
+    );
+    assert_eq!(findings.num_runs_with_findings(), 0);
+}
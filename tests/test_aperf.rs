@@ -1,4 +1,8 @@
 use anyhow::Result;
+use aperf::export::{export, Export};
+use aperf::export_profile::{export_profile, ExportProfile};
+use aperf::import::{import, Import};
+use aperf::redact::RedactOptions;
 use aperf::report::{report, Report};
 use chrono::Utc;
 use flate2::read::GzDecoder;
@@ -200,6 +204,10 @@ fn test_record_and_report() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -230,6 +238,10 @@ fn test_record_and_report_dot_in_run_name() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -278,6 +290,10 @@ fn test_report_with_empty_data_bin() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -307,6 +323,10 @@ fn test_report_single_run() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -343,6 +363,10 @@ fn test_report_multiple_runs() {
                 ("test_run_1".to_string(), Some(2), None),
                 ("test_run_2".to_string(), None, Some(8)),
             ],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -375,6 +399,10 @@ fn test_report_from_report() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -411,6 +439,10 @@ fn test_report_renamed_run() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -441,6 +473,10 @@ fn test_report_backward_compatibility() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -544,6 +580,10 @@ fn test_duplicate_run_names() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
 
         assert!(report(&rep, &tmp_dir).is_ok());
@@ -581,6 +621,10 @@ fn test_duplicate_run_names() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
 
         assert!(report(&rev_rep, &tmp_dir).is_ok());
@@ -610,6 +654,10 @@ fn test_duplicate_run_names() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
 
         assert!(report(&combined_rep, &tmp_dir).is_ok());
@@ -660,6 +708,10 @@ fn test_report_with_time_ranges() {
                 (run_name_1.clone(), Some(5), Some(30)),
                 (run_name_2.clone(), Some(-8), Some(-2)),
             ],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -687,6 +739,10 @@ fn test_report_already_exists() {
             run: vec![run_path.into_os_string().into_string().unwrap()],
             name: Some(report_path_str.clone()),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         assert!(report(&rep, &tmp_dir).is_ok());
 
@@ -697,6 +753,10 @@ fn test_report_already_exists() {
             run: vec![another_run_path.into_os_string().into_string().unwrap()],
             name: Some(report_path_str.clone()),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error = report(&rep_with_same_name, &tmp_dir).unwrap_err();
         assert_eq!(
@@ -732,6 +792,10 @@ fn test_run_data_not_exists() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error = report(&rep, &tmp_dir).unwrap_err();
         assert_eq!(
@@ -746,6 +810,155 @@ fn test_run_data_not_exists() {
     })
 }
 
+#[test]
+fn test_export() {
+    run_test(|work_dir, tmp_dir| {
+        let run_path = get_test_data_path("test_run_1.tar.gz");
+        let output_path = work_dir.join("test_export");
+        export(
+            &Export {
+                run: vec![run_path.clone().into_os_string().into_string().unwrap()],
+                output: Some(output_path.clone().into_os_string().into_string().unwrap()),
+                format: String::from("csv"),
+                time_range: vec![(String::new(), Some(5), Some(10))],
+            },
+            &tmp_dir,
+        )?;
+
+        let time_series = fs::read_to_string(output_path.join("time_series.csv"))?;
+        let mut lines = time_series.lines();
+        assert_eq!(lines.next(), Some("run,data,metric,series,time,value"));
+        let mut num_rows = 0;
+        for line in lines {
+            let columns: Vec<&str> = line.split(',').collect();
+            assert_eq!(columns[0], "test_run_1");
+            let time: u64 = columns[columns.len() - 2].parse()?;
+            assert!((5..=10).contains(&time), "{line}");
+            num_rows += 1;
+        }
+        assert!(num_rows > 0);
+        assert!(fs::read_to_string(output_path.join("key_values.csv"))?
+            .starts_with("run,data,group,key,value\n"));
+        assert!(output_path.join("text.csv").exists());
+        let findings: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(output_path.join("findings.json"))?)?;
+        assert!(findings.is_object());
+
+        // The output directory is not overwritten
+        let error = export(
+            &Export {
+                run: vec![run_path.into_os_string().into_string().unwrap()],
+                output: Some(output_path.into_os_string().into_string().unwrap()),
+                format: String::from("jsonl"),
+                time_range: vec![],
+            },
+            &tmp_dir,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_export_profile_without_profile() {
+    run_test(|work_dir, tmp_dir| {
+        let run_path = get_test_data_path("test_run_1.tar.gz");
+        let output_path = work_dir.join("test_run_1.pb.gz");
+        let export = ExportProfile {
+            run: run_path.into_os_string().into_string().unwrap(),
+            data: String::from("perf_profile"),
+            profiler: None,
+            profile_type: String::from("cpu"),
+            format: String::from("pprof"),
+            output: Some(output_path.clone().into_os_string().into_string().unwrap()),
+            time_range: None,
+        };
+        let error = export_profile(&export, &tmp_dir).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Profile not found: run test_run_1 does not have any perf_profile data"
+        );
+        assert!(!output_path.exists());
+        assert!(!tmp_dir.join("export_profile").exists());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_import_folded_and_export_profile() {
+    run_test(|work_dir, tmp_dir| {
+        let folded_path = work_dir.join("stacks.txt");
+        fs::write(
+            &folded_path,
+            "main;compute;multiply 30\nmain;compute 5\n\nmain;io;read 10\n",
+        )?;
+        let run_path = work_dir.join("imported_run");
+        import(&Import {
+            run: run_path.clone().into_os_string().into_string().unwrap(),
+            perf: None,
+            jfr: Vec::new(),
+            folded: Some(folded_path.into_os_string().into_string().unwrap()),
+        })?;
+        assert!(run_path.join("perf_profiler_data.json").exists());
+        assert!(run_path.join("metadata.json").exists());
+
+        let output_path = work_dir.join("imported_run.folded");
+        export_profile(
+            &ExportProfile {
+                run: run_path.into_os_string().into_string().unwrap(),
+                data: String::from("perf_profile"),
+                profiler: None,
+                profile_type: String::from("cpu"),
+                format: String::from("folded"),
+                output: Some(output_path.clone().into_os_string().into_string().unwrap()),
+                time_range: None,
+            },
+            &tmp_dir,
+        )?;
+        let mut stacks: Vec<String> = fs::read_to_string(&output_path)?
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        stacks.sort();
+        assert_eq!(
+            stacks,
+            vec![
+                "main;compute 5",
+                "main;compute;multiply 30",
+                "main;io;read 10"
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_import_invalid_folded() {
+    run_test(|work_dir, _tmp_dir| {
+        let folded_path = work_dir.join("stacks.txt");
+        fs::write(&folded_path, "main;compute 30\nmain;io;read\n")?;
+        let run_path = work_dir.join("imported_run");
+        let error = import(&Import {
+            run: run_path.clone().into_os_string().into_string().unwrap(),
+            perf: None,
+            jfr: Vec::new(),
+            folded: Some(folded_path.into_os_string().into_string().unwrap()),
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid collapsed stack at line 2: main;io;read"
+        );
+        assert!(!run_path.exists());
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_report_duplicate_run_path() {
     run_test(|work_dir, tmp_dir| {
@@ -767,6 +980,10 @@ fn test_report_duplicate_run_path() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error = report(&rep, &tmp_dir).unwrap_err();
         assert_eq!(
@@ -797,6 +1014,10 @@ fn test_report_duplicate_run_path() {
                     .unwrap(),
             ),
             time_range: vec![],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error_alt = report(&rep_alt, &tmp_dir).unwrap_err();
         assert!(
@@ -833,6 +1054,10 @@ fn test_report_with_time_range_invalid_run_name() {
             ),
             // Specify a time range for a run name that doesn't exist in the report
             time_range: vec![("nonexistent_run".to_string(), Some(0), Some(10))],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error = report(&rep, &tmp_dir).unwrap_err();
         assert!(
@@ -870,6 +1095,10 @@ fn test_report_with_time_range_from_greater_than_to() {
             ),
             // from_time (60) > to_time (10) — should fail
             time_range: vec![(run_name.clone(), Some(60), Some(10))],
+            profile_drop_frames: vec![],
+            profile_collapse_recursion: false,
+            profile_fold_modules: false,
+            profile_view: "all".to_string(),
         };
         let error = report(&rep, &tmp_dir).unwrap_err();
         assert!(
@@ -907,14 +1136,25 @@ fn record_with_name(
         run_name: Some(run_path_str.clone()),
         interval: 1,
         period: 2,
+        checkpoint_interval: 10,
         dont_collect,
         collect_only,
         profile: false,
         perf_frequency: 99,
+        call_graph: String::from("fp"),
+        profile_offcpu: false,
+        profile_events: Vec::new(),
+        profile_event_period: 100000,
+        symbol_bundle_size: 0,
         save_profile_events: false,
         profile_java: None,
+        java_profile_events: Vec::new(),
+        asprof_args: Vec::new(),
+        profile_ext: Vec::new(),
         pmu_config: None,
         ungroup_pmu_events: false,
+        redact: false,
+        redact_options: RedactOptions::default(),
         hotline_frequency: 1000,
         num_to_report: 5000,
     };
@@ -924,14 +1164,25 @@ fn record_with_name(
         run_name: Some(run_path_str.clone()),
         interval: 1,
         period: 2,
+        checkpoint_interval: 10,
         dont_collect,
         collect_only,
         profile: false,
         perf_frequency: 99,
+        call_graph: String::from("fp"),
+        profile_offcpu: false,
+        profile_events: Vec::new(),
+        profile_event_period: 100000,
+        symbol_bundle_size: 0,
         save_profile_events: false,
         profile_java: None,
+        java_profile_events: Vec::new(),
+        asprof_args: Vec::new(),
+        profile_ext: Vec::new(),
         pmu_config: None,
         ungroup_pmu_events: false,
+        redact: false,
+        redact_options: RedactOptions::default(),
     };
 
     let runlog = work_dir.join("aperf_runlog");
@@ -4,6 +4,7 @@ mod aperf_stats_tests {
     use aperf::data::common::data_formats::AperfData;
     use aperf::data::{ProcessData, TimeEnum};
     use aperf::data_processing::ReportParams;
+    use aperf::profiling::transform::ProfileTransforms;
     use aperf::{data_file_path, get_data_name_from_type};
     use chrono::Utc;
     use std::collections::HashMap;
@@ -26,6 +27,7 @@ mod aperf_stats_tests {
             pmu_counter_mode: String::new(),
             aperf_process_pids: vec![4242],
             page_size: 0,
+            profile_transforms: ProfileTransforms::default(),
         }
     }
 
@@ -2,6 +2,7 @@ use aperf::data::common::data_formats::AperfData;
 use aperf::data::java_profile::JavaProfile;
 use aperf::data::ProcessData;
 use aperf::data_processing::ReportParams;
+use aperf::profiling::transform::ProfileTransforms;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
@@ -23,6 +24,7 @@ fn setup_test_env() -> (TempDir, PathBuf, PathBuf, ReportParams) {
         pmu_counter_mode: String::new(),
         aperf_process_pids: Vec::new(),
         page_size: 0,
+        profile_transforms: ProfileTransforms::default(),
     };
 
     (temp_dir, data_dir, report_dir, params)
@@ -0,0 +1,184 @@
+use aperf::data::common::data_formats::{AperfData, Series, TimeSeriesData, TimeSeriesMetric};
+use aperf::data::jvm_stat::{jvm_stat_values, JvmStat, JvmStatRaw};
+use aperf::data::{Data, ProcessData, TimeEnum};
+use aperf::data_processing::ReportParams;
+use aperf::profiling::jvm::PerfDataValue;
+use chrono::Utc;
+use std::collections::HashMap;
+
+/// Build raw data in the common time-series format, with one component per JVM.
+fn make_jvm_stat_raw_data(samples: &[Vec<(&str, Vec<(&str, f64)>)>]) -> Vec<Data> {
+    let base = Utc::now();
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, jvms)| {
+            let mut data = String::new();
+            for (jvm, stats) in jvms {
+                data.push_str(&format!("{}:\n", jvm));
+                for (stat, value) in stats {
+                    data.push_str(&format!("{} {}\n", stat, value));
+                }
+            }
+            Data::JvmStatRaw(JvmStatRaw {
+                hsperfdata_paths: HashMap::new(),
+                last_discovery: None,
+                time: TimeEnum::DateTime(base + chrono::Duration::seconds(i as i64)),
+                data,
+            })
+        })
+        .collect()
+}
+
+fn unwrap_time_series(result: AperfData) -> TimeSeriesData {
+    match result {
+        AperfData::TimeSeries(ts) => ts,
+        _ => panic!("Expected TimeSeries data"),
+    }
+}
+
+fn find_series<'a>(metric: &'a TimeSeriesMetric, series_name: &str) -> &'a Series {
+    metric
+        .series
+        .iter()
+        .find(|s| !s.is_aggregate && s.series_name == series_name)
+        .unwrap()
+}
+
+#[test]
+fn test_jvm_stat_values() {
+    let counters: HashMap<String, PerfDataValue> = [
+        ("sun.os.hrt.frequency", 1_000_000_000),
+        ("sun.gc.collector.0.invocations", 10),
+        ("sun.gc.collector.0.time", 200_000_000),
+        ("sun.gc.collector.1.invocations", 1),
+        ("sun.gc.collector.1.time", 300_000_000),
+        ("sun.gc.generation.0.capacity", 1000),
+        ("sun.gc.generation.0.space.0.used", 100),
+        ("sun.gc.generation.0.space.0.capacity", 600),
+        ("sun.gc.generation.0.space.1.used", 50),
+        ("sun.gc.generation.1.capacity", 4000),
+        ("sun.gc.generation.1.maxCapacity", 8000),
+        ("sun.gc.generation.1.space.0.used", 2000),
+        ("java.cls.loadedClasses", 500),
+        ("java.cls.sharedLoadedClasses", 100),
+        ("java.cls.unloadedClasses", 20),
+        ("java.threads.live", 30),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), PerfDataValue::Long(value)))
+    .chain([(
+        "sun.gc.collector.0.name".to_string(),
+        PerfDataValue::String("G1 young collection pauses".to_string()),
+    )])
+    .collect();
+
+    let values: HashMap<&str, f64> = jvm_stat_values(&counters).into_iter().collect();
+    assert_eq!(values["gc_count"], 11.0);
+    assert_eq!(values["gc_time"], 500.0);
+    assert_eq!(values["full_gc_count"], 1.0);
+    assert_eq!(values["full_gc_time"], 300.0);
+    assert_eq!(values["young_gen_used"], 150.0);
+    assert_eq!(values["old_gen_used"], 2000.0);
+    assert_eq!(values["heap_used"], 2150.0);
+    assert_eq!(values["heap_committed"], 5000.0);
+    assert_eq!(values["old_gen_max_capacity"], 8000.0);
+    assert_eq!(values["loaded_classes"], 580.0);
+    assert_eq!(values["class_loads"], 600.0);
+    assert_eq!(values["live_threads"], 30.0);
+    // The counters not published by the JVM are omitted
+    assert!(!values.contains_key("metaspace_used"));
+    assert!(!values.contains_key("safepoint_time"));
+}
+
+#[test]
+fn test_jvm_stat_empty_data() {
+    let mut jvm_stat = JvmStat::new();
+    let ts = unwrap_time_series(
+        jvm_stat
+            .process_raw_data(&ReportParams::new(), vec![])
+            .unwrap(),
+    );
+    assert!(ts.metrics.is_empty());
+}
+
+#[test]
+fn test_jvm_stat_gc_time_percent_and_rates() {
+    let raw = make_jvm_stat_raw_data(&[
+        vec![
+            ("Kafka (100)", vec![("gc_count", 10.0), ("gc_time", 1000.0)]),
+            ("app.jar (200)", vec![("gc_count", 5.0), ("gc_time", 100.0)]),
+        ],
+        vec![
+            ("Kafka (100)", vec![("gc_count", 14.0), ("gc_time", 1300.0)]),
+            ("app.jar (200)", vec![("gc_count", 6.0), ("gc_time", 120.0)]),
+        ],
+    ]);
+
+    let mut jvm_stat = JvmStat::new();
+    let ts = unwrap_time_series(
+        jvm_stat
+            .process_raw_data(&ReportParams::new(), raw)
+            .unwrap(),
+    );
+
+    assert_eq!(ts.sorted_metric_names[0], "gc_time_percent");
+    let gc_count = &ts.metrics["gc_count"];
+    assert_eq!(find_series(gc_count, "Kafka (100)").values, vec![0.0, 4.0]);
+    assert_eq!(
+        find_series(gc_count, "app.jar (200)").values,
+        vec![0.0, 1.0]
+    );
+
+    let gc_time_percent = &ts.metrics["gc_time_percent"];
+    assert_eq!(
+        find_series(gc_time_percent, "Kafka (100)").values,
+        vec![0.0, 30.0]
+    );
+    assert_eq!(
+        find_series(gc_time_percent, "app.jar (200)").values,
+        vec![0.0, 2.0]
+    );
+    // The stats are computed from the JVM with the highest average
+    assert_eq!(
+        gc_time_percent.series[gc_time_percent.stats_series_idx].series_name,
+        "Kafka (100)"
+    );
+}
+
+#[test]
+fn test_jvm_stat_old_gen_used_percent() {
+    let raw = make_jvm_stat_raw_data(&[vec![
+        (
+            "Kafka (100)",
+            vec![
+                ("old_gen_used", 900.0),
+                ("old_gen_max_capacity", 1000.0),
+                ("heap_used", 1200.0),
+            ],
+        ),
+        // The non-generational JVMs have no old generation
+        ("app.jar (200)", vec![("heap_used", 300.0)]),
+    ]]);
+
+    let mut jvm_stat = JvmStat::new();
+    let ts = unwrap_time_series(
+        jvm_stat
+            .process_raw_data(&ReportParams::new(), raw)
+            .unwrap(),
+    );
+
+    let old_gen_used_percent = &ts.metrics["old_gen_used_percent"];
+    assert_eq!(
+        find_series(old_gen_used_percent, "Kafka (100)").values,
+        vec![90.0]
+    );
+    assert!(old_gen_used_percent
+        .series
+        .iter()
+        .all(|s| s.series_name != "app.jar (200)"));
+    assert_eq!(
+        find_series(&ts.metrics["heap_used"], "app.jar (200)").values,
+        vec![300.0]
+    );
+}
@@ -0,0 +1,92 @@
+#![cfg(target_os = "linux")]
+
+use aperf::record::{record, Record};
+use aperf::redact::RedactOptions;
+use flate2::read::GzDecoder;
+use log::LevelFilter;
+use log4rs::append::file::FileAppender;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::encode::pattern::PatternEncoder;
+use regex::Regex;
+use std::fs;
+use std::io::Read;
+use tar::Archive;
+use tempfile::TempDir;
+
+/// The generated key of record --redact is not logged to the runlog, which is archived with the
+/// redacted run, since the key would make the pseudonyms reversible.
+#[test]
+fn test_record_redact_does_not_archive_generated_key() {
+    let work_dir = TempDir::with_prefix("aperf").unwrap();
+    let tmp_dir = TempDir::with_prefix("tmp_aperf").unwrap();
+    let runlog = work_dir.path().join("aperf_runlog");
+
+    // Log every level from Debug up to the runlog, as aperf does. The pattern has no date, which
+    // cannot be formatted in the logs of the thread-local destructors at the end of the test.
+    let config = Config::builder()
+        .appender(
+            Appender::builder().build(
+                "aperflog",
+                Box::new(
+                    FileAppender::builder()
+                        .encoder(Box::new(PatternEncoder::new("{l} {M}] {m}{n}")))
+                        .build(&runlog)
+                        .unwrap(),
+                ),
+            ),
+        )
+        .logger(
+            Logger::builder()
+                .appender("aperflog")
+                .build("aperf", LevelFilter::Debug),
+        )
+        .build(Root::builder().build(LevelFilter::Warn))
+        .unwrap();
+    log4rs::init_config(config).unwrap();
+
+    let run_dir = work_dir.path().join("test_record_redact");
+    let rec = Record {
+        run_name: Some(run_dir.display().to_string()),
+        interval: 1,
+        period: 2,
+        checkpoint_interval: 10,
+        dont_collect: None,
+        collect_only: Some(vec!["systeminfo".to_string()]),
+        profile: false,
+        perf_frequency: 99,
+        call_graph: String::from("fp"),
+        profile_offcpu: false,
+        profile_events: Vec::new(),
+        profile_event_period: 100000,
+        symbol_bundle_size: 0,
+        save_profile_events: false,
+        profile_java: None,
+        java_profile_events: Vec::new(),
+        asprof_args: Vec::new(),
+        profile_ext: Vec::new(),
+        pmu_config: None,
+        ungroup_pmu_events: false,
+        redact: true,
+        redact_options: RedactOptions::default(),
+        #[cfg(feature = "hotline")]
+        hotline_frequency: 1000,
+        #[cfg(feature = "hotline")]
+        num_to_report: 5000,
+    };
+    record(&rec, tmp_dir.path(), &runlog).unwrap();
+
+    let archive = fs::File::open(run_dir.with_extension("tar.gz")).unwrap();
+    let mut archive = Archive::new(GzDecoder::new(archive));
+    let mut archived_runlog = String::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        if entry.path().unwrap().ends_with("aperf_runlog") {
+            entry.read_to_string(&mut archived_runlog).unwrap();
+        }
+    }
+    assert!(archived_runlog.contains("Starting Data collection"));
+    // The generated key is 32 hex digits.
+    assert!(!Regex::new(r"\b[0-9a-f]{32}\b")
+        .unwrap()
+        .is_match(&archived_runlog));
+}